@@ -0,0 +1,159 @@
+//! Benchmarks for building the compositor's vertex data and encoding its
+//! passes. Run with `cargo bench --features bench`.
+
+use std::num::NonZeroU32;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use ratatui::buffer::Buffer;
+use ratatui::style::Color;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::style::Stylize;
+use ratatui::widgets::Block;
+use ratatui::Terminal;
+use ratatui_wgpu::shaders::DefaultPostProcessor;
+use ratatui_wgpu::Builder;
+use ratatui_wgpu::Dimensions;
+use ratatui_wgpu::Font;
+use ratatui_wgpu::HeadlessSurface;
+use ratatui_wgpu::WgpuBackend;
+
+const GRIDS: [(u16, u16); 3] = [(80, 24), (200, 50), (400, 100)];
+
+/// How many distinct glyphs and styles the grid contains.
+#[derive(Clone, Copy, Debug)]
+enum Diversity {
+    /// The same glyph in every cell.
+    Uniform,
+    /// All printable ascii characters.
+    Ascii,
+    /// Printable ascii in varying colors, weights and slants.
+    Styled,
+}
+
+impl Diversity {
+    const ALL: [Diversity; 3] = [Diversity::Uniform, Diversity::Ascii, Diversity::Styled];
+
+    fn fill(
+        self,
+        buffer: &mut Buffer,
+    ) {
+        let area = buffer.area;
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let index = y as usize * area.width as usize + x as usize;
+                let ch = match self {
+                    Diversity::Uniform => 'x',
+                    Diversity::Ascii | Diversity::Styled => (b'!' + (index % 94) as u8) as char,
+                };
+                let style = match self {
+                    Diversity::Uniform | Diversity::Ascii => Style::new(),
+                    Diversity::Styled => Style::new()
+                        .fg(Color::Indexed((index % 16) as u8))
+                        .bg(Color::Indexed((index / 16 % 16) as u8))
+                        .add_modifier(match index % 4 {
+                            0 => Modifier::empty(),
+                            1 => Modifier::BOLD,
+                            2 => Modifier::ITALIC,
+                            _ => Modifier::BOLD | Modifier::ITALIC,
+                        }),
+                };
+                buffer[(x, y)].set_char(ch).set_style(style);
+            }
+        }
+    }
+}
+
+type HeadlessBackend = WgpuBackend<'static, 'static, DefaultPostProcessor, HeadlessSurface>;
+
+/// Build a headless terminal of exactly `columns` x `rows` cells filled with
+/// content of the given diversity.
+fn terminal(
+    columns: u16,
+    rows: u16,
+    diversity: Diversity,
+) -> Terminal<HeadlessBackend> {
+    let mut backend = futures_lite::future::block_on(
+        Builder::<DefaultPostProcessor>::from_font(
+            Font::new(include_bytes!(
+                "../src/backend/fonts/CascadiaMono-Regular.ttf"
+            ))
+            .expect("Invalid font file"),
+        )
+        .with_width_and_height(Dimensions {
+            width: NonZeroU32::new(4096).unwrap(),
+            height: NonZeroU32::new(4096).unwrap(),
+        })
+        .build_headless(),
+    )
+    .expect("Headless backend");
+
+    let cell = backend.cell_bounds(0, 0);
+    backend.resize(
+        columns as u32 * cell.width as u32,
+        rows as u32 * cell.height as u32,
+    );
+
+    let mut terminal = Terminal::new(backend).unwrap();
+    terminal.hide_cursor().unwrap();
+    terminal.draw(|f| diversity.fill(f.buffer_mut())).unwrap();
+    assert_eq!(terminal.size().unwrap(), (columns, rows).into());
+    terminal
+}
+
+fn prepare_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prepare_frame");
+    for (columns, rows) in GRIDS {
+        for diversity in Diversity::ALL {
+            let mut terminal = terminal(columns, rows, diversity);
+            group.bench_function(
+                BenchmarkId::new(format!("{diversity:?}"), format!("{columns}x{rows}")),
+                |b| b.iter(|| terminal.backend_mut().bench_prepare_frame().unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    for (columns, rows) in GRIDS {
+        for diversity in Diversity::ALL {
+            let mut terminal = terminal(columns, rows, diversity);
+            terminal.backend_mut().bench_prepare_frame().unwrap();
+            group.bench_function(
+                BenchmarkId::new(format!("{diversity:?}"), format!("{columns}x{rows}")),
+                |b| b.iter(|| terminal.backend_mut().bench_render().unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn full_screen_bg(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full_screen_bg");
+    for (columns, rows) in GRIDS {
+        let mut terminal = terminal(columns, rows, Diversity::Uniform);
+        let mut frame = 0;
+        group.bench_function(format!("{columns}x{rows}"), |b| {
+            b.iter(|| {
+                let color = if frame % 2 == 0 {
+                    Color::Blue
+                } else {
+                    Color::Red
+                };
+                frame += 1;
+                terminal
+                    .draw(|f| f.render_widget(Block::new().bg(color), f.area()))
+                    .unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, prepare_frame, render, full_screen_bg);
+criterion_main!(benches);
@@ -1,6 +1,10 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::num::NonZeroU32;
 use std::num::NonZeroU64;
+use std::sync::Arc;
 
 use bitvec::vec::BitVec;
 use ratatui::style::Color;
@@ -11,13 +15,13 @@ use wgpu::include_wgsl;
 use wgpu::util::BufferInitDescriptor;
 use wgpu::util::DeviceExt;
 use wgpu::vertex_attr_array;
+use wgpu::Adapter;
 use wgpu::AddressMode;
 use wgpu::Backends;
 use wgpu::BindGroupDescriptor;
 use wgpu::BindGroupEntry;
 use wgpu::BindGroupLayoutDescriptor;
 use wgpu::BindGroupLayoutEntry;
-use wgpu::BindingResource;
 use wgpu::BindingType;
 use wgpu::BlendState;
 use wgpu::Buffer;
@@ -27,7 +31,8 @@ use wgpu::BufferUsages;
 use wgpu::ColorTargetState;
 use wgpu::ColorWrites;
 use wgpu::Device;
-use wgpu::Extent3d;
+use wgpu::DownlevelFlags;
+use wgpu::Features;
 use wgpu::FilterMode;
 use wgpu::FragmentState;
 use wgpu::Instance;
@@ -38,71 +43,167 @@ use wgpu::MipmapFilterMode;
 use wgpu::MultisampleState;
 use wgpu::PipelineCompilationOptions;
 use wgpu::PipelineLayoutDescriptor;
+use wgpu::PowerPreference;
 use wgpu::PresentMode;
 use wgpu::PrimitiveState;
 use wgpu::PrimitiveTopology;
+use wgpu::QuerySetDescriptor;
+use wgpu::QueryType;
+use wgpu::Queue;
 use wgpu::RenderPipelineDescriptor;
 use wgpu::Sampler;
 use wgpu::SamplerBindingType;
 use wgpu::SamplerDescriptor;
 use wgpu::ShaderStages;
 use wgpu::Surface;
+use wgpu::SurfaceConfiguration;
 use wgpu::SurfaceTarget;
-use wgpu::TextureDescriptor;
-use wgpu::TextureDimension;
 use wgpu::TextureFormat;
+use wgpu::TextureFormatFeatureFlags;
+use wgpu::TextureFormatFeatures;
 use wgpu::TextureSampleType;
 use wgpu::TextureUsages;
 use wgpu::TextureView;
-use wgpu::TextureViewDescriptor;
 use wgpu::TextureViewDimension;
 use wgpu::VertexBufferLayout;
 use wgpu::VertexState;
 use wgpu::VertexStepMode;
 
+use crate::backend::build_atlas_bindings;
+use crate::backend::build_atlas_page;
+use crate::backend::build_multisample_target;
+use crate::backend::build_viewport_target;
 use crate::backend::build_wgpu_state;
+use crate::backend::fixed_grid_layout;
+use crate::backend::fixed_grid_viewport;
+use crate::backend::grid_layout;
+use crate::backend::post_process_inputs;
 use crate::backend::private::Token;
+use crate::backend::select_present_mode;
+use crate::backend::wgpu_backend::physical_font_size;
 use crate::backend::wgpu_backend::WgpuBackend;
+use crate::backend::wgpu_backend::RASTERIZATION_BUDGET;
+use crate::backend::AtlasPage;
+use crate::backend::BaselineMode;
+use crate::backend::CursorStyle;
 use crate::backend::Dimensions;
+use crate::backend::EmojiMode;
+use crate::backend::FrameStats;
+use crate::backend::GpuTimer;
+use crate::backend::HeadlessSurface;
+use crate::backend::ImageCompositor;
+use crate::backend::ImageVertex;
 use crate::backend::PostProcessor;
 use crate::backend::RenderSurface;
-use crate::backend::TextBgVertexMember;
+use crate::backend::SubpixelLayout;
+use crate::backend::TextBgInstance;
 use crate::backend::TextCacheBgPipeline;
 use crate::backend::TextCacheFgPipeline;
 use crate::backend::TextVertexMember;
+use crate::backend::TextureSurface;
+use crate::backend::UnderlineStyle;
 use crate::backend::Viewport;
+use crate::backend::ViewportBlit;
 use crate::colors::named;
 use crate::colors::ColorTable;
+use crate::colors::Colors;
+use crate::colors::Rgb;
+use crate::colors::XTERM_PALETTE;
 use crate::fonts::Font;
 use crate::fonts::Fonts;
 use crate::shaders::DefaultPostProcessor;
+use crate::utils::gpu_buffer::GrowableBuffer;
 use crate::utils::plan_cache::PlanCache;
 use crate::utils::text_atlas::Atlas;
 use crate::Error;
+use crate::RandomState;
 use crate::Result;
+use crate::Tag;
 
 const CACHE_WIDTH: u32 = 1800;
 const CACHE_HEIGHT: u32 = 1200;
+/// Atlas coordinates are packed into 13 bits, see `UNDERLINE_STYLE_SHIFT`.
+const MAX_CACHE_SIZE: u32 = 8192;
+/// The smallest atlas which can be requested with [`Builder::with_atlas_size`].
+const MIN_CACHE_SIZE: u32 = 256;
 
 /// Builds a [`WgpuBackend`] instance.
 ///
 /// Height and width will default to 1x1, so don't forget to call
 /// [`Builder::with_dimensions`] to configure the backend presentation
 /// dimensions.
+///
+/// Most options combine freely. Building fails with
+/// [`Error::ConflictingOptions`] for the combinations which can't be honored:
+///
+/// | Option                        | Conflicts with                                           |
+/// |-------------------------------|----------------------------------------------------------|
+/// | [`Builder::with_subpixel_aa`] | [`Builder::with_transparent_background`]                 |
+/// | [`Builder::with_subpixel_aa`] | [`Builder::with_background_alpha`] below `255`           |
+/// | [`Builder::with_fixed_grid`]  | [`Builder::with_viewport`] other than [`Viewport::Full`] |
+/// | [`Builder::with_fixed_grid`]  | [`Builder::with_min_cells`] other than `(1, 1)`          |
+///
+/// Other options fall back when the adapter or surface doesn't support them,
+/// e.g. [`Builder::with_hdr`] without a high bit depth surface format or
+/// [`Builder::with_msaa`] with an unsupported sample count, and only log a
+/// warning.
 pub struct Builder<'a, P: PostProcessor = DefaultPostProcessor> {
     user_data: P::UserData,
     fonts: Fonts<'a>,
     instance: Option<Instance>,
+    backends: Backends,
+    power_preference: PowerPreference,
+    force_fallback_adapter: bool,
     limits: Option<Limits>,
     present_mode: Option<PresentMode>,
     width: NonZeroU32,
     height: NonZeroU32,
     viewport: Viewport,
     colors: ColorTable,
+    palette: [Rgb; 256],
     reset_fg: Color,
     reset_bg: Color,
     fast_blink: Duration,
     slow_blink: Duration,
+    text_blink: bool,
+    synthetic_bold: bool,
+    synthetic_italic: bool,
+    italic_overhang: bool,
+    ligatures: bool,
+    builtin_box_drawing: bool,
+    builtin_powerline: bool,
+    budgeted_rasterization: bool,
+    subpixel_aa: Option<SubpixelLayout>,
+    subpixel_positioning: bool,
+    transparent_background: bool,
+    hdr: bool,
+    msaa: u32,
+    text_format: TextureFormat,
+    background_alpha: HashMap<Color, u8, RandomState>,
+    cell_bleed: f32,
+    text_gamma: f32,
+    stem_darkening: f32,
+    premultiplied_alpha: bool,
+    underline_style: UnderlineStyle,
+    underline_thickness: Option<f32>,
+    underline_offset: Option<f32>,
+    baseline_mode: BaselineMode,
+    min_cells: (u16, u16),
+    fixed_grid: Option<(u16, u16)>,
+    scale_factor: f64,
+    emoji_mode: EmojiMode,
+    dim_factor: f32,
+    tab_width: u8,
+    cursor_style: CursorStyle,
+    cursor_color: Color,
+    cursor_blink: Option<Duration>,
+    selection_color: Color,
+    selection_alpha: u8,
+    min_frame_interval: Option<Duration>,
+    atlas_size: Option<u32>,
+    atlas_pages: u32,
+    atlas_filter: FilterMode,
+    clear_color: Color,
 }
 
 impl<'a, P: PostProcessor> Builder<'a, P>
@@ -115,6 +216,9 @@ where
         Self {
             user_data: Default::default(),
             instance: None,
+            backends: Backends::default(),
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: false,
             fonts: Fonts::new(font, 24),
             limits: None,
             present_mode: None,
@@ -122,10 +226,50 @@ where
             height: NonZeroU32::new(1).unwrap(),
             viewport: Viewport::Full,
             colors: named::DEFAULT_COLORS,
+            palette: XTERM_PALETTE,
             reset_fg: Color::Black,
             reset_bg: Color::White,
             fast_blink: Duration::from_millis(200),
             slow_blink: Duration::from_millis(1000),
+            text_blink: true,
+            synthetic_bold: true,
+            synthetic_italic: true,
+            italic_overhang: false,
+            ligatures: false,
+            builtin_box_drawing: false,
+            builtin_powerline: false,
+            budgeted_rasterization: false,
+            subpixel_aa: None,
+            subpixel_positioning: false,
+            transparent_background: false,
+            hdr: false,
+            msaa: 1,
+            text_format: TextureFormat::Rgba8Unorm,
+            background_alpha: HashMap::default(),
+            cell_bleed: 0.0,
+            text_gamma: 2.2,
+            stem_darkening: 0.0,
+            premultiplied_alpha: false,
+            underline_style: UnderlineStyle::Straight,
+            underline_thickness: None,
+            underline_offset: None,
+            baseline_mode: BaselineMode::PerFont,
+            min_cells: (1, 1),
+            fixed_grid: None,
+            scale_factor: 1.0,
+            emoji_mode: EmojiMode::Color,
+            dim_factor: 0.5,
+            tab_width: 8,
+            cursor_style: CursorStyle::Block,
+            cursor_color: Color::Reset,
+            cursor_blink: None,
+            selection_color: Color::Reset,
+            selection_alpha: 96,
+            min_frame_interval: None,
+            atlas_size: None,
+            atlas_pages: 1,
+            atlas_filter: FilterMode::Nearest,
+            clear_color: Color::Rgb(0, 0, 0),
         }
     }
 }
@@ -140,6 +284,9 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         Self {
             user_data,
             instance: None,
+            backends: Backends::default(),
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: false,
             fonts: Fonts::new(font, 24),
             limits: None,
             present_mode: None,
@@ -147,10 +294,50 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
             height: NonZeroU32::new(1).unwrap(),
             viewport: Viewport::Full,
             colors: named::DEFAULT_COLORS,
+            palette: XTERM_PALETTE,
             reset_fg: Color::Black,
             reset_bg: Color::White,
             fast_blink: Duration::from_millis(200),
             slow_blink: Duration::from_millis(1000),
+            text_blink: true,
+            synthetic_bold: true,
+            synthetic_italic: true,
+            italic_overhang: false,
+            ligatures: false,
+            builtin_box_drawing: false,
+            builtin_powerline: false,
+            budgeted_rasterization: false,
+            subpixel_aa: None,
+            subpixel_positioning: false,
+            transparent_background: false,
+            hdr: false,
+            msaa: 1,
+            text_format: TextureFormat::Rgba8Unorm,
+            background_alpha: HashMap::default(),
+            cell_bleed: 0.0,
+            text_gamma: 2.2,
+            stem_darkening: 0.0,
+            premultiplied_alpha: false,
+            underline_style: UnderlineStyle::Straight,
+            underline_thickness: None,
+            underline_offset: None,
+            baseline_mode: BaselineMode::PerFont,
+            min_cells: (1, 1),
+            fixed_grid: None,
+            scale_factor: 1.0,
+            emoji_mode: EmojiMode::Color,
+            dim_factor: 0.5,
+            tab_width: 8,
+            cursor_style: CursorStyle::Block,
+            cursor_color: Color::Reset,
+            cursor_blink: None,
+            selection_color: Color::Reset,
+            selection_alpha: 96,
+            min_frame_interval: None,
+            atlas_size: None,
+            atlas_pages: 1,
+            atlas_filter: FilterMode::Nearest,
+            clear_color: Color::Rgb(0, 0, 0),
         }
     }
 
@@ -164,6 +351,45 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Only request adapters from the specified wgpu backends, e.g.
+    /// [`Backends::VULKAN`] to make rendering reproducible across machines.
+    /// This has no effect if an instance is supplied with
+    /// [`Builder::with_instance`]. Defaults to [`Backends::all`].
+    #[must_use]
+    pub fn with_backends(
+        mut self,
+        backends: Backends,
+    ) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    /// Prefer adapters with the specified power usage, e.g.
+    /// [`PowerPreference::LowPower`] to render with an integrated gpu and save
+    /// battery. Defaults to [`PowerPreference::None`], which leaves the choice
+    /// to wgpu.
+    #[must_use]
+    pub fn with_power_preference(
+        mut self,
+        power_preference: PowerPreference,
+    ) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Only accept a fallback adapter, usually a software renderer, e.g. for
+    /// deterministic output in CI. Building the backend fails with
+    /// [`Error::AdapterRequestFailed`] if no such adapter is available.
+    /// Defaults to `false`.
+    #[must_use]
+    pub fn with_force_fallback_adapter(
+        mut self,
+        force: bool,
+    ) -> Self {
+        self.force_fallback_adapter = force;
+        self
+    }
+
     /// Use the supplied [`Viewport`] for rendering. Defaults to
     /// [`Viewport::Full`].
     #[must_use]
@@ -175,7 +401,54 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Keep the grid at least `(columns, rows)` cells large, however small the
+    /// surface gets, so that widgets never have to lay out a degenerate area.
+    /// When the drawable area of the [`Viewport`] is too small for the grid,
+    /// the middle of the grid is shown and its edges are cropped. The cropped
+    /// grid fills the drawable area, which is then placed on the surface as
+    /// usual, e.g. centered and scaled for [`Viewport::IntegerScale`].
+    /// [`WgpuBackend::pixel_to_cell`] and [`WgpuBackend::cell_bounds`] account
+    /// for the cropping. Zero is treated as one. Defaults to `(1, 1)`.
+    #[must_use]
+    pub fn with_min_cells(
+        mut self,
+        (columns, rows): (u16, u16),
+    ) -> Self {
+        self.min_cells = (columns.max(1), rows.max(1));
+        self
+    }
+
+    /// Render a grid of exactly `(columns, rows)` cells, whatever the size of
+    /// the surface, e.g. for deterministic snapshot tests or kiosks with a
+    /// fixed layout. The text is rendered at its natural size and scaled up
+    /// by the largest integer factor that fits the surface, as with
+    /// [`Viewport::IntegerScale`], with the margin around it filled with the
+    /// color set by [`Builder::with_clear_color`]. Resizing the surface only
+    /// changes the scale and the margin, while changing the font size changes
+    /// the natural size of the text. If the surface is smaller than the text,
+    /// the text is drawn unscaled from the top left corner and cropped.
+    /// [`WgpuBackend::pixel_to_cell`] and [`WgpuBackend::cell_bounds`] account
+    /// for the scaling.
+    ///
+    /// This takes the place of [`Builder::with_viewport`] and
+    /// [`Builder::with_min_cells`], building fails with
+    /// [`Error::ConflictingOptions`] if either is set as well. Zero is treated
+    /// as one.
+    #[must_use]
+    pub fn with_fixed_grid(
+        mut self,
+        (columns, rows): (u16, u16),
+    ) -> Self {
+        self.fixed_grid = Some((columns.max(1), rows.max(1)));
+        self
+    }
+
     /// Use the specified font size in pixels. Defaults to 24px.
+    ///
+    /// The size is in logical pixels, which are multiplied by
+    /// [`Builder::with_scale_factor`] to get the size glyphs are rasterized
+    /// at. With the default scale factor of `1.0`, they are the same as
+    /// physical pixels.
     #[must_use]
     pub fn with_font_size_px(
         mut self,
@@ -185,6 +458,60 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Render for a display with the given scale factor, e.g.
+    /// `winit::window::Window::scale_factor`. The font size is multiplied by
+    /// it, so that fonts specified in logical pixels with
+    /// [`Builder::with_font_size_px`] stay crisp on HiDPI displays. The
+    /// surface size, [`Builder::with_width_and_height`], and the positions
+    /// passed to [`WgpuBackend::pixel_to_cell`] remain in physical pixels, and
+    /// the grid is computed from those.
+    ///
+    /// Values which aren't finite and positive are treated as `1.0`, which is
+    /// the default.
+    ///
+    /// See also [`WgpuBackend::set_scale_factor`].
+    #[must_use]
+    pub fn with_scale_factor(
+        mut self,
+        scale_factor: f64,
+    ) -> Self {
+        self.scale_factor = if scale_factor.is_finite() && scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+        self
+    }
+
+    /// Scale the height of each cell relative to the font size, e.g. `1.2`
+    /// for 20% taller cells. Glyphs are vertically centered in their cell.
+    /// Defaults to `1.0`.
+    ///
+    /// See also [`Fonts::set_line_height_scale`].
+    #[must_use]
+    pub fn with_line_height_scale(
+        mut self,
+        scale: f32,
+    ) -> Self {
+        self.fonts.set_line_height_scale(scale);
+        self
+    }
+
+    /// Scale the width of each cell relative to the font's advance, e.g. `1.1`
+    /// for 10% wider columns. Glyphs keep their size and are horizontally
+    /// centered in their cell. This composes with
+    /// [`Builder::with_line_height_scale`]. Defaults to `1.0`.
+    ///
+    /// See also [`Fonts::set_cell_width_scale`].
+    #[must_use]
+    pub fn with_cell_width_scale(
+        mut self,
+        scale: f32,
+    ) -> Self {
+        self.fonts.set_cell_width_scale(scale);
+        self
+    }
+
     /// Use the specified list of fonts for rendering. You may call this
     /// multiple times to extend the list of fallback fonts. Note that this will
     /// automatically organize fonts by relative width in order to optimize
@@ -200,6 +527,27 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Parse the provided font data and add it to the list of fonts used for
+    /// rendering, as with [`Builder::with_fonts`]. `face_index` selects the
+    /// face to use from a font collection (.ttc/.otc), use `0` otherwise.
+    ///
+    /// Borrowed data is used directly. Owned data is leaked and kept alive for
+    /// the remainder of the program, see [`Font::from_owned_bytes`].
+    ///
+    /// Returns [`Error::FontParsingFailed`] if the data isn't a valid font.
+    pub fn with_font_bytes(
+        mut self,
+        data: impl Into<Cow<'a, [u8]>>,
+        face_index: u32,
+    ) -> Result<Self> {
+        let font = match data.into() {
+            Cow::Borrowed(data) => Font::from_bytes(data, face_index)?,
+            Cow::Owned(data) => Font::from_owned_bytes(data, face_index)?,
+        };
+        self.fonts.add_fonts([font]);
+        Ok(self)
+    }
+
     /// Use the specified list of regular fonts for rendering. You may call this
     /// multiple times to extend the list of fallback fonts.
     ///
@@ -252,6 +600,576 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Use the specified list of fallback fonts for rendering. These are
+    /// consulted for all styles after the style specific fonts when a glyph is
+    /// missing. You may call this multiple times to extend the list of
+    /// fallback fonts.
+    ///
+    /// See also [`Fonts::add_fallback_fonts`].
+    #[must_use]
+    pub fn with_fallback_fonts<I: IntoIterator<Item = Font<'a>>>(
+        mut self,
+        fonts: I,
+    ) -> Self {
+        self.fonts.add_fallback_fonts(fonts);
+        self
+    }
+
+    /// Synthesize bold text by stroking glyph outlines when no bold font is
+    /// available for a bold cell. Fonts supplied via
+    /// [`Builder::with_bold_fonts`] are unaffected. Defaults to `true`.
+    #[must_use]
+    pub fn with_synthetic_bold(
+        mut self,
+        synthetic_bold: bool,
+    ) -> Self {
+        self.synthetic_bold = synthetic_bold;
+        self
+    }
+
+    /// Synthesize italic text by slanting glyph outlines when no italic font is
+    /// available for an italic cell. Fonts supplied via
+    /// [`Builder::with_italic_fonts`] are unaffected. Defaults to `true`.
+    #[must_use]
+    pub fn with_synthetic_italic(
+        mut self,
+        synthetic_italic: bool,
+    ) -> Self {
+        self.synthetic_italic = synthetic_italic;
+        self
+    }
+
+    /// Allow synthesized italic glyphs to overhang into the following cell
+    /// rather than being shifted back and clipped to their own cell. Only
+    /// applies to single width glyphs. Defaults to `false`.
+    #[must_use]
+    pub fn with_synthetic_italic_overhang(
+        mut self,
+        overhang: bool,
+    ) -> Self {
+        self.italic_overhang = overhang;
+        self
+    }
+
+    /// Substitute ligatures, e.g. for `=>` or `!=` in programming fonts. The
+    /// ligature is drawn over the cells of the characters it replaces, which
+    /// keep their content and position for the cursor and
+    /// [`WgpuBackend::pixel_to_cell`]. Ligatures are clipped to two cells.
+    /// Contextual alternates, which swap glyphs without merging cells, are
+    /// applied either way.
+    /// Defaults to `false`.
+    #[must_use]
+    pub fn with_ligatures(
+        mut self,
+        ligatures: bool,
+    ) -> Self {
+        self.ligatures = ligatures;
+        self
+    }
+
+    /// Draw box drawing characters (`U+2500` to `U+257F`) and block elements
+    /// (`U+2580` to `U+259F`) procedurally at the exact size of the cell
+    /// instead of using the font's glyphs, so that lines and blocks join up
+    /// seamlessly with neighboring cells. Line weight scales with the cell
+    /// size. Defaults to `false`.
+    #[must_use]
+    pub fn with_builtin_box_drawing(
+        mut self,
+        enabled: bool,
+    ) -> Self {
+        self.builtin_box_drawing = enabled;
+        self
+    }
+
+    /// Draw the powerline separators (`U+E0B0` to `U+E0B3`) procedurally at the
+    /// exact size of the cell instead of using the glyphs of a patched font.
+    /// The separators are drawn in the foreground color over the cell
+    /// background, so setting the foreground to the background of one
+    /// neighbor and the background to that of the other joins them seamlessly.
+    /// Independent of [`Builder::with_builtin_box_drawing`]. Defaults to
+    /// `false`.
+    #[must_use]
+    pub fn with_builtin_powerline(
+        mut self,
+        enabled: bool,
+    ) -> Self {
+        self.builtin_powerline = enabled;
+        self
+    }
+
+    /// Limit the time each [`Backend::flush`](ratatui::backend::Backend::flush)
+    /// spends rasterizing new glyphs, so that a screen full of new text doesn't
+    /// stall the frame. Glyphs which don't fit in a frame are drawn blank and
+    /// filled in by the following flushes, with [`WgpuBackend::needs_update`]
+    /// returning `true` until they're done.
+    ///
+    /// This isn't asynchronous: the flush spreads the glyphs over a worker
+    /// thread per core and waits for them, so it still blocks for up to the
+    /// budget of a few milliseconds plus the glyphs being rasterized when it
+    /// runs out. Fonts borrow their data, so the workers can't outlive the
+    /// flush.
+    ///
+    /// When disabled, every glyph is rasterized before the flush completes,
+    /// which keeps frames deterministic, e.g. for snapshot tests. Defaults to
+    /// `false`.
+    #[must_use]
+    pub fn with_budgeted_rasterization(
+        mut self,
+        enabled: bool,
+    ) -> Self {
+        self.budgeted_rasterization = enabled;
+        self
+    }
+
+    /// Rasterize glyphs with separate coverage for each subpixel of the
+    /// display, which gives sharper text on LCD panels. Color glyphs are
+    /// unaffected. Defaults to grayscale antialiasing.
+    ///
+    /// Subpixel rendering blends against the cell background, so it is only
+    /// correct for opaque backgrounds and doesn't suit displays which may be
+    /// rotated or scaled after rendering. Building fails with
+    /// [`Error::ConflictingOptions`] if it is combined with
+    /// [`Builder::with_transparent_background`] or a translucent
+    /// [`Builder::with_background_alpha`].
+    #[must_use]
+    pub fn with_subpixel_aa(
+        mut self,
+        layout: SubpixelLayout,
+    ) -> Self {
+        self.subpixel_aa = Some(layout);
+        self
+    }
+
+    /// Rasterize glyphs at one of three horizontal subpixel phases, picking
+    /// the one nearest to where the glyph would be placed exactly, instead of
+    /// snapping them to whole pixels. Cells are always a whole number of
+    /// pixels wide, so this mostly helps glyphs which sit between pixels
+    /// within their cell: glyphs centered in cells an odd number of pixels
+    /// wider than the font, e.g. with [`Builder::with_cell_width_scale`], and
+    /// marks and the glyphs of ligatures, which are offset from the start of
+    /// the cell. Color glyphs and builtin box drawing are always drawn at
+    /// whole pixels.
+    ///
+    /// A glyph may take up an atlas entry for each phase it is drawn at, so
+    /// this costs atlas space. Defaults to `false`.
+    #[must_use]
+    pub fn with_subpixel_positioning(
+        mut self,
+        enabled: bool,
+    ) -> Self {
+        self.subpixel_positioning = enabled;
+        self
+    }
+
+    /// Render cells using the default ([`Color::Reset`]) background color as
+    /// fully transparent, so that whatever is behind the window shows through.
+    /// The surface is configured with a [`wgpu::CompositeAlphaMode`] which
+    /// supports transparency if one is available. Defaults to `false`.
+    ///
+    /// Whether this is honored depends on the platform and compositor. It
+    /// generally works on macOS, Wayland, X11 with a compositing window
+    /// manager, and the web. Most Windows configurations only offer opaque
+    /// surfaces, in which case the background renders as black.
+    ///
+    /// The [`PostProcessor`] must preserve alpha for this to have an effect,
+    /// which [`DefaultPostProcessor`] does.
+    #[must_use]
+    pub fn with_transparent_background(
+        mut self,
+        transparent: bool,
+    ) -> Self {
+        self.transparent_background = transparent;
+        self
+    }
+
+    /// Present to a surface format with more than 8 bits per channel, such as
+    /// [`TextureFormat::Rgba16Float`] or [`TextureFormat::Rgb10a2Unorm`], if
+    /// the surface supports one. Falls back to the default format otherwise.
+    /// Only applies to window surfaces, headless and texture surfaces use the
+    /// format they were created with. Defaults to `false`.
+    ///
+    /// This only selects the surface format. Colors keep the standard range
+    /// with white at `1.0`, so nothing is drawn brighter than on an 8-bit
+    /// surface, and the extra precision only reduces banding, e.g. in the
+    /// gradients of post processors.
+    #[must_use]
+    pub fn with_hdr(
+        mut self,
+        hdr: bool,
+    ) -> Self {
+        self.hdr = hdr;
+        self
+    }
+
+    /// Let the [`PostProcessor`] render with `sample_count` samples per pixel
+    /// and resolve the result before presenting, which antialiases the edges
+    /// of geometry it draws, e.g. a curved screen. Only processors which
+    /// support [`PostProcessor::enable_multisampling`] are affected, the text
+    /// itself is sampled from a texture and doesn't change. Falls back to a
+    /// single sample if the format of the post processor's target doesn't
+    /// support the requested count. Defaults to `1`.
+    #[must_use]
+    pub fn with_msaa(
+        mut self,
+        sample_count: u32,
+    ) -> Self {
+        self.msaa = sample_count;
+        self
+    }
+
+    /// Composite text into a texture of the given format rather than
+    /// [`TextureFormat::Rgba8Unorm`]. A float format such as
+    /// [`TextureFormat::Rgba16Float`] keeps more precision for post processing,
+    /// an srgb format such as [`TextureFormat::Rgba8UnormSrgb`] blends in
+    /// linear space. The format must have four channels and be renderable,
+    /// blendable and filterable, building the backend returns
+    /// [`Error::UnsupportedTextFormat`] otherwise. Srgb formats also require
+    /// [`wgpu::DownlevelFlags::VIEW_FORMATS`], which some GL adapters lack.
+    ///
+    /// The [`PostProcessor`] and [`WgpuBackend::text_texture_view`] get a view
+    /// of the non-srgb equivalent of the format, so they read srgb encoded
+    /// colors either way.
+    #[must_use]
+    pub fn with_text_format(
+        mut self,
+        format: TextureFormat,
+    ) -> Self {
+        self.text_format = format;
+        self
+    }
+
+    /// Composite text with premultiplied alpha blending, so that
+    /// [`WgpuBackend::text_texture_view`] and the targets of
+    /// [`WgpuBackend::render_text`] hold premultiplied colors throughout. This
+    /// matters for color glyphs (emoji), which are otherwise multiplied with
+    /// their alpha twice and get dark fringes over translucent or transparent
+    /// backgrounds. Defaults to `false`.
+    ///
+    /// Callers compositing the text over their own rendering should blend it
+    /// with [`BlendState::PREMULTIPLIED_ALPHA_BLENDING`], i.e. a source factor
+    /// of [`wgpu::BlendFactor::One`] and a destination factor of
+    /// [`wgpu::BlendFactor::OneMinusSrcAlpha`]. [`BlendState::ALPHA_BLENDING`]
+    /// multiplies the color with alpha a second time, darkening the edges of
+    /// the text.
+    #[must_use]
+    pub fn with_premultiplied_alpha(
+        mut self,
+        premultiplied: bool,
+    ) -> Self {
+        self.premultiplied_alpha = premultiplied;
+        self
+    }
+
+    /// Render cells with the given background color with the given opacity,
+    /// from `0` for fully transparent to `255` for opaque. Text over these
+    /// cells is blended with the background rather than against it. The alpha
+    /// is kept in [`WgpuBackend::text_texture_view`] with premultiplied color,
+    /// and shows what's behind the window when
+    /// [`Builder::with_transparent_background`] is enabled. May be called
+    /// multiple times for different colors.
+    ///
+    /// See also [`WgpuBackend::set_background_alpha`].
+    #[must_use]
+    pub fn with_background_alpha(
+        mut self,
+        color: Color,
+        alpha: u8,
+    ) -> Self {
+        self.background_alpha.insert(color, alpha);
+        self
+    }
+
+    /// Extend the background of every cell by `bleed` pixels on each side, so
+    /// that the backgrounds of neighboring cells overlap. This hides the seams
+    /// which can show between solid backgrounds and borders when the grid is
+    /// stretched by a fraction of a pixel, e.g. by rendering it into a target
+    /// of a different size with [`WgpuBackend::render_text`]. Glyphs,
+    /// including builtin box drawing, keep their size. Where backgrounds meet,
+    /// the cell that comes later in reading order is drawn over the bleed of
+    /// the earlier one, so translucent backgrounds don't double up.
+    ///
+    /// The bleed is clamped to `0.0..=1.0`, values which aren't finite are
+    /// treated as `0.0`. Defaults to `0.0`.
+    #[must_use]
+    pub fn with_cell_bleed(
+        mut self,
+        bleed: f32,
+    ) -> Self {
+        self.cell_bleed = if bleed.is_finite() {
+            bleed.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// Use the specified gamma when blending text with its background. Blending
+    /// in linear space keeps light text on dark backgrounds from looking too
+    /// thin. `1.0` blends the stored colors directly. Defaults to `2.2`.
+    ///
+    /// Values which aren't finite and positive are treated as `1.0`.
+    #[must_use]
+    pub fn with_text_gamma(
+        mut self,
+        gamma: f32,
+    ) -> Self {
+        self.text_gamma = if gamma.is_finite() && gamma > 0.0 {
+            gamma
+        } else {
+            1.0
+        };
+        self
+    }
+
+    /// Darken the antialiased edges of glyphs by `amount` when they are
+    /// rasterized, which keeps thin strokes from disappearing, especially on
+    /// dark backgrounds. Unlike [`Builder::with_text_gamma`], this changes the
+    /// glyph coverage stored in the atlas. Defaults to `0.0`, which leaves the
+    /// coverage unchanged.
+    ///
+    /// Values which aren't finite and positive are treated as `0.0`.
+    #[must_use]
+    pub fn with_stem_darkening(
+        mut self,
+        amount: f32,
+    ) -> Self {
+        self.stem_darkening = if amount.is_finite() && amount > 0.0 {
+            amount
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// Draw [`Modifier::DIM`](ratatui::style::Modifier::DIM) text with its
+    /// foreground color blended toward the background by `factor`, where
+    /// `0.0` makes the text invisible and `1.0` doesn't dim it at all. Text
+    /// over a transparent background is faded by the same factor instead.
+    /// Defaults to `0.5`.
+    ///
+    /// The factor is clamped to between `0.0` and `1.0`, and values which
+    /// aren't finite are treated as `0.5`.
+    #[must_use]
+    pub fn with_dim_factor(
+        mut self,
+        factor: f32,
+    ) -> Self {
+        self.dim_factor = if factor.is_finite() {
+            factor.clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        self
+    }
+
+    /// Place tab stops every `width` columns. ratatui usually drops tabs from
+    /// text, but a cell whose symbol is a tab advances to the next tab stop
+    /// and covers the cells in between with blanks. Text which is already
+    /// expanded to spaces is unaffected. Defaults to `8`, and `0` is treated
+    /// as `1`.
+    #[must_use]
+    pub fn with_tab_width(
+        mut self,
+        width: u8,
+    ) -> Self {
+        self.tab_width = width;
+        self
+    }
+
+    /// Use the specified [`UnderlineStyle`] for underlined cells. ratatui
+    /// doesn't track an underline style per cell, so this applies to all
+    /// underlined text. Defaults to [`UnderlineStyle::Straight`].
+    #[must_use]
+    pub fn with_underline_style(
+        mut self,
+        style: UnderlineStyle,
+    ) -> Self {
+        self.underline_style = style;
+        self
+    }
+
+    /// Draw underlines `thickness` pixels thick instead of using the font's
+    /// suggested thickness. Curly and double underlines take up three times
+    /// this height. Like the font size, this is multiplied by
+    /// [`Builder::with_scale_factor`]. Values below one pixel draw a single
+    /// pixel line.
+    #[must_use]
+    pub fn with_underline_thickness(
+        mut self,
+        thickness: f32,
+    ) -> Self {
+        self.underline_thickness = Some(thickness);
+        self
+    }
+
+    /// Draw the top of underlines `offset` pixels below the baseline instead
+    /// of on it, e.g. to keep them clear of descenders. Negative values move
+    /// them up. Like the font size, this is multiplied by
+    /// [`Builder::with_scale_factor`]. Underlines start within their cell.
+    #[must_use]
+    pub fn with_underline_offset(
+        mut self,
+        offset: f32,
+    ) -> Self {
+        self.underline_offset = Some(offset);
+        self
+    }
+
+    /// Use the specified [`BaselineMode`] to position glyphs from fonts with
+    /// different metrics. [`BaselineMode::Primary`] keeps e.g. CJK and Latin
+    /// fallbacks on the same baseline as the primary font. Defaults to
+    /// [`BaselineMode::PerFont`].
+    #[must_use]
+    pub fn with_baseline_alignment(
+        mut self,
+        mode: BaselineMode,
+    ) -> Self {
+        self.baseline_mode = mode;
+        self
+    }
+
+    /// Use the specified [`EmojiMode`] to draw glyphs from color fonts.
+    /// [`EmojiMode::Monochrome`] tints emoji with the foreground color for a
+    /// uniform palette. Defaults to [`EmojiMode::Color`].
+    #[must_use]
+    pub fn with_emoji_mode(
+        mut self,
+        mode: EmojiMode,
+    ) -> Self {
+        self.emoji_mode = mode;
+        self
+    }
+
+    /// Use the specified [`CursorStyle`] when drawing the cursor. Defaults to
+    /// [`CursorStyle::Block`].
+    #[must_use]
+    pub fn with_cursor_style(
+        mut self,
+        style: CursorStyle,
+    ) -> Self {
+        self.cursor_style = style;
+        self
+    }
+
+    /// Use the specified color when drawing the cursor. [`Color::Reset`] uses
+    /// the foreground color of the cell under the cursor. Defaults to
+    /// [`Color::Reset`].
+    #[must_use]
+    pub fn with_cursor_color(
+        mut self,
+        color: Color,
+    ) -> Self {
+        self.cursor_color = color;
+        self
+    }
+
+    /// Use the specified color and opacity for the highlight drawn by
+    /// [`WgpuBackend::set_selection`]. [`Color::Reset`] uses the foreground
+    /// color of each highlighted cell. Defaults to [`Color::Reset`] with an
+    /// alpha of `96`.
+    #[must_use]
+    pub fn with_selection_color(
+        mut self,
+        color: Color,
+        alpha: u8,
+    ) -> Self {
+        self.selection_color = color;
+        self.selection_alpha = alpha;
+        self
+    }
+
+    /// Blink the cursor at the specified interval, or never if `None`. The
+    /// cursor is always shown immediately after it moves. As with
+    /// [`Builder::with_rapid_blink_millis`], you must ensure that a call to
+    /// `flush` is made frequently enough, see [`WgpuBackend::needs_update`].
+    /// Defaults to `None`.
+    #[must_use]
+    pub fn with_cursor_blink(
+        mut self,
+        interval: Option<Duration>,
+    ) -> Self {
+        self.cursor_blink = interval;
+        self
+    }
+
+    /// Present at most `fps` frames per second. A flush made sooner than
+    /// `1 / fps` seconds after the last presented frame is skipped, the
+    /// changes it would have drawn are kept and drawn by the next flush which
+    /// isn't, and [`WgpuBackend::needs_update`] returns `true` until then.
+    /// This is useful with animated [`PostProcessor`]s which request an
+    /// update on every frame. Zero disables the limit, which is the default.
+    #[must_use]
+    pub fn with_max_fps(
+        mut self,
+        fps: u32,
+    ) -> Self {
+        self.min_frame_interval = (fps > 0).then(|| Duration::from_secs_f64(1.0 / fps as f64));
+        self
+    }
+
+    /// Limit the glyph atlas to `size` x `size` pixels. When the atlas is full,
+    /// the least recently used glyphs are evicted and rasterized again the
+    /// next time they are drawn. The atlas must be able to hold every glyph
+    /// visible at once, otherwise some glyphs will be drawn incorrectly.
+    ///
+    /// The size is clamped to between 256 and 8192 pixels, and to the largest
+    /// texture the device supports. Defaults to an 1800x1200 atlas.
+    #[must_use]
+    pub fn with_atlas_size(
+        mut self,
+        size: u32,
+    ) -> Self {
+        self.atlas_size = Some(size);
+        self
+    }
+
+    /// Allow the glyph atlas to grow to `pages` pages, each the size set by
+    /// [`Builder::with_atlas_size`]. A page is only allocated once the ones
+    /// before it are full, and glyphs are only evicted once every page is full.
+    /// This is useful for apps with huge glyph sets, e.g. CJK text.
+    ///
+    /// The number of pages is clamped to between 1 and the device's maximum
+    /// number of texture array layers. Defaults to 1.
+    #[must_use]
+    pub fn with_atlas_pages(
+        mut self,
+        pages: u32,
+    ) -> Self {
+        self.atlas_pages = pages;
+        self
+    }
+
+    /// Sample the glyph atlas with the given filter. Glyphs are laid out
+    /// aligned to whole pixels, so this only makes a difference when text is
+    /// stretched, e.g. by [`WgpuBackend::render_text`] into a target of a
+    /// different size than [`WgpuBackend::text_texture_view`].
+    /// [`FilterMode::Linear`] smooths the glyphs in that case, while
+    /// [`FilterMode::Nearest`] keeps their edges hard. Defaults to
+    /// [`FilterMode::Nearest`].
+    #[must_use]
+    pub fn with_atlas_filter(
+        mut self,
+        filter: FilterMode,
+    ) -> Self {
+        self.atlas_filter = filter;
+        self
+    }
+
+    /// Instantiate variable fonts at the specified axis coordinates, e.g.
+    /// `&[(Tag::from_bytes(b"wght"), 350.0)]`. This applies to all fonts,
+    /// including ones provided after this call. Axes not supported by a font
+    /// are ignored.
+    ///
+    /// See also [`Fonts::set_variable_axes`].
+    #[must_use]
+    pub fn with_variable_axes(
+        mut self,
+        axes: &[(Tag, f32)],
+    ) -> Self {
+        self.fonts.set_variable_axes(axes);
+        self
+    }
+
     /// Use the specified [`wgpu::Limits`]. Defaults to
     /// [`wgpu::Adapter::limits`].
     #[must_use]
@@ -263,7 +1181,9 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
-    /// Use the specified [`wgpu::PresentMode`].
+    /// Use the specified [`wgpu::PresentMode`]. If the surface doesn't support
+    /// it, [`PresentMode::Mailbox`] and then [`PresentMode::Fifo`] are used
+    /// instead. Defaults to the surface's default present mode.
     #[must_use]
     pub fn with_present_mode(
         mut self,
@@ -307,6 +1227,18 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Use the specified palette for the 256 indexed colors
+    /// ([`Color::Indexed`]). Named colors are controlled by
+    /// [`Builder::with_color_table`]. Defaults to [`XTERM_PALETTE`].
+    #[must_use]
+    pub fn with_palette(
+        mut self,
+        palette: [Rgb; 256],
+    ) -> Self {
+        self.palette = palette;
+        self
+    }
+
     /// Use the specified [`ratatui::style::Color`] for the default foreground
     /// color. Defaults to Black.
     #[must_use]
@@ -329,6 +1261,25 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self
     }
 
+    /// Use the specified [`ratatui::style::Color`] to clear the area of the
+    /// surface which isn't covered by text, e.g. the margin around
+    /// [`Viewport::Centered`], [`Viewport::IntegerScale`] or
+    /// [`Viewport::Padded`] and the area excluded by [`Viewport::Shrink`].
+    /// This is independent of the default cell background, except that
+    /// [`Color::Reset`] uses the default background color, or leaves the area
+    /// transparent with [`Builder::with_transparent_background`]. Defaults to
+    /// opaque black.
+    ///
+    /// See also [`WgpuBackend::set_clear_color`].
+    #[must_use]
+    pub fn with_clear_color(
+        mut self,
+        color: Color,
+    ) -> Self {
+        self.clear_color = color;
+        self
+    }
+
     /// Use the specified interval in milliseconds as the rapid blink speed.
     /// Note that this library doesn't spin off rendering into a separate thread
     /// for you. If you want text to blink, you must ensure that a call to
@@ -347,11 +1298,25 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
     /// for you. If you want text to blink, you must ensure that a call to
     /// `flush` is made frequently enough. Defaults to 1000ms.
     #[must_use]
-    pub fn with_slow_blink_millis(
+    pub fn with_slow_blink_millis(
+        mut self,
+        millis: u64,
+    ) -> Self {
+        self.slow_blink = Duration::from_millis(millis);
+        self
+    }
+
+    /// Blink text with
+    /// [`Modifier::SLOW_BLINK`](ratatui::style::Modifier::SLOW_BLINK)
+    /// or [`Modifier::RAPID_BLINK`](ratatui::style::Modifier::RAPID_BLINK).
+    /// When disabled, blinking text is always shown and never causes a redraw.
+    /// Defaults to `true`.
+    #[must_use]
+    pub fn with_text_blink(
         mut self,
-        millis: u64,
+        enabled: bool,
     ) -> Self {
-        self.slow_blink = Duration::from_millis(millis);
+        self.text_blink = enabled;
         self
     }
 }
@@ -365,7 +1330,7 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
     ) -> Result<WgpuBackend<'a, 's, P>> {
         let instance = self.instance.get_or_insert_with(|| {
             wgpu::Instance::new(&InstanceDescriptor {
-                backends: Backends::default(),
+                backends: self.backends,
                 flags: InstanceFlags::default(),
                 ..Default::default()
             })
@@ -388,30 +1353,121 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
         self.build_with_render_surface(surface).await
     }
 
-    #[cfg(test)]
-    pub(crate) async fn build_headless(
-        self
-    ) -> Result<WgpuBackend<'a, 'static, P, super::HeadlessSurface>> {
-        self.build_with_render_surface(super::HeadlessSurface::default())
+    /// Build a new backend which renders to `surface` using a device and queue
+    /// owned by the caller, e.g. to draw a terminal overlay inside an existing
+    /// wgpu application. `surface` is usually a [`Surface`] created from the
+    /// same device's instance, but a [`HeadlessSurface`] may also be used.
+    ///
+    /// No adapter or device is requested. The surface is configured with
+    /// `config` as-is, so [`Builder::with_width_and_height`] and
+    /// [`Builder::with_present_mode`] have no effect. Resizing the backend
+    /// only changes the dimensions of this config. The backend never polls
+    /// the device, so the caller remains responsible for doing so.
+    ///
+    /// Returns [`Error::UnsupportedTextFormat`] if the format set with
+    /// [`Builder::with_text_format`] isn't guaranteed to be supported by the
+    /// device, since there's no adapter to query. This includes all srgb
+    /// formats, which need a downlevel capability only the adapter reports.
+    pub fn build_with_device<'s, S: RenderSurface<'s> + 's>(
+        self,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        surface: S,
+        config: SurfaceConfiguration,
+    ) -> Result<WgpuBackend<'a, 's, P, S>> {
+        self.validate()?;
+        if !supports_text_format(
+            self.text_format,
+            self.text_format
+                .guaranteed_format_features(device.features()),
+            false,
+        ) {
+            return Err(Error::UnsupportedTextFormat(self.text_format));
+        }
+
+        Ok(self.build_with_state(
+            Device::clone(&device),
+            Queue::clone(&queue),
+            surface,
+            config,
+            vec![],
+            None,
+        ))
+    }
+
+    /// Build a new backend which renders into the caller's texture described
+    /// by `surface`. As with [`Builder::build_with_device`], the device and
+    /// queue are owned by the caller and the texture must have been created
+    /// from them. Flushing the backend renders into the texture without
+    /// presenting anything.
+    pub fn build_with_texture(
+        self,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        surface: TextureSurface,
+    ) -> Result<WgpuBackend<'a, 'static, P, TextureSurface>> {
+        let config = surface.config();
+        self.build_with_device(device, queue, surface, config)
+    }
+
+    /// Build a new backend which renders to an offscreen [`HeadlessSurface`]
+    /// using the dimensions supplied to [`Builder::with_width_and_height`].
+    /// The rendered image can be retrieved with
+    /// [`WgpuBackend::read_pixels`].
+    pub async fn build_headless(self) -> Result<WgpuBackend<'a, 'static, P, HeadlessSurface>> {
+        self.build_with_render_surface(HeadlessSurface::default())
             .await
     }
 
-    #[cfg(test)]
-    pub(crate) async fn build_headless_with_format(
+    /// Build a new headless backend as with [`Builder::build_headless`], using
+    /// the specified format for the surface. Readback supports
+    /// [`TextureFormat::Rgba8Unorm`], [`TextureFormat::Bgra8Unorm`], their
+    /// srgb variants, [`TextureFormat::Rgb10a2Unorm`] and
+    /// [`TextureFormat::Rgba16Float`]. Other formats return
+    /// [`Error::UnsupportedSurfaceFormat`].
+    pub async fn build_headless_with_format(
         self,
         format: TextureFormat,
-    ) -> Result<WgpuBackend<'a, 'static, P, super::HeadlessSurface>> {
-        self.build_with_render_surface(super::HeadlessSurface::new(format))
+    ) -> Result<WgpuBackend<'a, 'static, P, HeadlessSurface>> {
+        if !HeadlessSurface::supports_format(format) {
+            return Err(Error::UnsupportedSurfaceFormat(format));
+        }
+
+        self.build_with_render_surface(HeadlessSurface::new(format))
             .await
     }
 
+    /// Build a new headless backend as with
+    /// [`Builder::build_headless_with_format`], forcing wgpu's fallback
+    /// adapter. This is intended for CI machines without a GPU, where the
+    /// fallback is a software rasterizer such as Mesa's lavapipe (Vulkan) or
+    /// llvmpipe (GL), or WARP on Windows. The rendered image can be retrieved
+    /// with [`WgpuBackend::read_pixels`].
+    ///
+    /// The `WGPU_BACKEND` environment variable (e.g. `WGPU_BACKEND=gl` or
+    /// `WGPU_BACKEND=vulkan`) overrides the backends set with
+    /// [`Builder::with_backends`]. On Linux the software drivers are usually
+    /// provided by the `mesa-vulkan-drivers` and `libgl1-mesa-dri` packages;
+    /// the GL path additionally requires wgpu's `gles` feature, which is
+    /// enabled by default. Returns [`Error::AdapterRequestFailed`] if no
+    /// fallback adapter is available.
+    pub async fn build_headless_software(
+        mut self,
+        format: TextureFormat,
+    ) -> Result<WgpuBackend<'a, 'static, P, HeadlessSurface>> {
+        self.backends = Backends::from_env().unwrap_or(self.backends);
+        self.force_fallback_adapter = true;
+        self.build_headless_with_format(format).await
+    }
+
     async fn build_with_render_surface<'s, S: RenderSurface<'s> + 's>(
         mut self,
-        mut surface: S,
+        surface: S,
     ) -> Result<WgpuBackend<'a, 's, P, S>> {
+        self.validate()?;
         let instance = self.instance.get_or_insert_with(|| {
             wgpu::Instance::new(&InstanceDescriptor {
-                backends: Backends::default(),
+                backends: self.backends,
                 flags: InstanceFlags::default(),
                 ..Default::default()
             })
@@ -419,13 +1475,14 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                force_fallback_adapter: self.force_fallback_adapter,
                 compatible_surface: surface.wgpu_surface(Token),
-                ..Default::default()
             })
             .await
             .map_err(Error::AdapterRequestFailed)?;
 
-        let limits = if let Some(limits) = self.limits {
+        let limits = if let Some(limits) = self.limits.take() {
             min_limits(&adapter, limits)
         } else {
             adapter.limits()
@@ -433,81 +1490,167 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
 
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
+                required_features: adapter.features() & Features::TIMESTAMP_QUERY,
                 required_limits: limits.clone(),
                 ..Default::default()
             })
             .await
             .map_err(Error::DeviceRequestFailed)?;
 
+        let max_dimension = limits.max_texture_dimension_2d;
+        if self.width.get() > max_dimension || self.height.get() > max_dimension {
+            warn!(
+                "Surface of {}x{} exceeds the device limit of {max_dimension}, clamping.",
+                self.width, self.height
+            );
+        }
+
         let mut surface_config = surface
             .get_default_config(
                 &adapter,
-                self.width.get().min(limits.max_texture_dimension_2d),
-                self.height.get().min(limits.max_texture_dimension_2d),
+                self.width.get().min(max_dimension),
+                self.height.get().min(max_dimension),
+                self.transparent_background,
+                self.hdr,
                 Token,
             )
             .ok_or(Error::SurfaceConfigurationRequestFailed)?;
 
+        // Creating the pipelines would fail validation, which panics.
+        if !adapter
+            .get_texture_format_features(surface_config.format)
+            .allowed_usages
+            .contains(TextureUsages::RENDER_ATTACHMENT)
+        {
+            return Err(Error::UnsupportedSurfaceFormat(surface_config.format));
+        }
+
+        if !supports_text_format(
+            self.text_format,
+            adapter.get_texture_format_features(self.text_format),
+            adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(DownlevelFlags::VIEW_FORMATS),
+        ) {
+            return Err(Error::UnsupportedTextFormat(self.text_format));
+        }
+
+        let present_modes = surface.present_modes(&adapter, Token);
         if let Some(mode) = self.present_mode {
-            surface_config.present_mode = mode;
+            surface_config.present_mode = select_present_mode(mode, &present_modes);
+        }
+
+        Ok(self.build_with_state(
+            device,
+            queue,
+            surface,
+            surface_config,
+            present_modes,
+            Some(&adapter),
+        ))
+    }
+
+    /// Reject combinations of options which can't be honored together, see
+    /// the table on [`Builder`].
+    fn validate(&self) -> Result<()> {
+        if self.subpixel_aa.is_some() {
+            if self.transparent_background {
+                return Err(Error::ConflictingOptions(
+                    "subpixel antialiasing can't blend against a transparent background",
+                ));
+            }
+            if self.background_alpha.values().any(|&alpha| alpha < u8::MAX) {
+                return Err(Error::ConflictingOptions(
+                    "subpixel antialiasing can't blend against a translucent background",
+                ));
+            }
+        }
+
+        if self.fixed_grid.is_some() {
+            if !matches!(self.viewport, Viewport::Full) {
+                return Err(Error::ConflictingOptions(
+                    "a fixed grid sets its own viewport",
+                ));
+            }
+            if self.min_cells != (1, 1) {
+                return Err(Error::ConflictingOptions(
+                    "a fixed grid can't have a minimum size",
+                ));
+            }
         }
 
+        Ok(())
+    }
+
+    fn build_with_state<'s, S: RenderSurface<'s> + 's>(
+        mut self,
+        device: Device,
+        queue: Queue,
+        mut surface: S,
+        surface_config: SurfaceConfiguration,
+        present_modes: Vec<PresentMode>,
+        adapter: Option<&Adapter>,
+    ) -> WgpuBackend<'a, 's, P, S> {
         surface.configure(&device, &surface_config, Token);
 
-        let (inset_width, inset_height) = match self.viewport {
-            Viewport::Full => (0, 0),
-            Viewport::Shrink { width, height } => (width, height),
-        };
+        let font_size = self.fonts.height_px() as f32;
+        self.fonts
+            .set_size_px(physical_font_size(font_size, self.scale_factor));
+        let cell_size = (self.fonts.cell_width_px(), self.fonts.cell_height_px());
+        let fixed_layout = self
+            .fixed_grid
+            .map(|grid| fixed_grid_layout(grid, cell_size));
+        if let Some(layout) = &fixed_layout {
+            self.viewport = fixed_grid_viewport(layout);
+        }
 
-        let drawable_width = surface_config.width - inset_width;
-        let drawable_height = surface_config.height - inset_height;
+        let (drawable_width, drawable_height) = self
+            .viewport
+            .drawable_size(surface_config.width, surface_config.height);
 
         info!(
             "char width x height: {}x{}",
-            self.fonts.min_width_px(),
-            self.fonts.height_px()
+            self.fonts.cell_width_px(),
+            self.fonts.cell_height_px()
         );
 
-        let text_cache = device.create_texture(&TextureDescriptor {
-            label: Some("Text Atlas"),
-            size: Extent3d {
-                width: CACHE_WIDTH,
-                height: CACHE_HEIGHT,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        let text_cache_view = text_cache.create_view(&TextureViewDescriptor::default());
-
-        let text_mask = device.create_texture(&TextureDescriptor {
-            label: Some("Text Mask"),
-            size: Extent3d {
-                width: CACHE_WIDTH,
-                height: CACHE_HEIGHT,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::R8Unorm,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        let (cache_width, cache_height) = match self.atlas_size {
+            Some(size) => {
+                let clamped = size.clamp(MIN_CACHE_SIZE, MAX_CACHE_SIZE.min(max_dimension));
+                if size > max_dimension {
+                    warn!(
+                        "Atlas size {size} exceeds the device limit of {max_dimension}, clamping."
+                    );
+                }
+                (clamped, clamped)
+            }
+            None => {
+                if CACHE_WIDTH > max_dimension || CACHE_HEIGHT > max_dimension {
+                    warn!(
+                        "Default atlas of {CACHE_WIDTH}x{CACHE_HEIGHT} exceeds the device limit \
+                         of {max_dimension}, clamping."
+                    );
+                }
+                (
+                    CACHE_WIDTH.min(max_dimension),
+                    CACHE_HEIGHT.min(max_dimension),
+                )
+            }
+        };
 
-        let text_mask_view = text_mask.create_view(&TextureViewDescriptor::default());
+        let max_pages = self
+            .atlas_pages
+            .clamp(1, device.limits().max_texture_array_layers);
+        let atlas_page = build_atlas_page(&device, cache_width, cache_height);
 
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             address_mode_w: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Nearest,
-            min_filter: FilterMode::Nearest,
+            mag_filter: self.atlas_filter,
+            min_filter: self.atlas_filter,
             mipmap_filter: MipmapFilterMode::Nearest,
             ..Default::default()
         });
@@ -521,82 +1664,368 @@ impl<'a, P: PostProcessor> Builder<'a, P> {
 
         let atlas_size_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Atlas Size buffer"),
-            contents: bytemuck::cast_slice(&[CACHE_WIDTH as f32, CACHE_HEIGHT as f32, 0.0, 0.0]),
+            contents: bytemuck::cast_slice(&[
+                cache_width as f32,
+                cache_height as f32,
+                self.text_gamma,
+                0.0,
+            ]),
             usage: BufferUsages::UNIFORM,
         });
 
-        let text_bg_compositor = build_text_bg_compositor(&device, &text_screen_size_buffer);
+        let text_bg_compositor =
+            build_text_bg_compositor(&device, &text_screen_size_buffer, self.text_format);
 
         let text_fg_compositor = build_text_fg_compositor(
             &device,
             &text_screen_size_buffer,
             &atlas_size_buffer,
-            &text_cache_view,
-            &text_mask_view,
+            &atlas_page,
             &sampler,
+            self.subpixel_aa.is_some(),
+            self.text_format,
+            self.premultiplied_alpha,
         );
 
-        let wgpu_state = build_wgpu_state(
+        let image_compositor = build_image_compositor(
             &device,
-            (drawable_width / self.fonts.min_width_px()) * self.fonts.min_width_px(),
-            (drawable_height / self.fonts.height_px()) * self.fonts.height_px(),
+            &text_screen_size_buffer,
+            self.text_format,
+            self.premultiplied_alpha,
+        );
+
+        let layout = fixed_layout.unwrap_or_else(|| {
+            grid_layout((drawable_width, drawable_height), cell_size, self.min_cells)
+        });
+        let wgpu_state = build_wgpu_state(&device, self.text_format, layout.width, layout.height);
+
+        let viewport_blit = self
+            .viewport
+            .blit_format(surface_config.format, self.text_format)
+            .map(|format| {
+                build_viewport_blit(
+                    &device,
+                    format,
+                    self.viewport,
+                    &surface_config,
+                    &wgpu_state.text_dest_view,
+                    self.text_format,
+                )
+            });
+
+        let colors = Colors {
+            table: self.colors,
+            palette: self.palette,
+        };
+        let reset_fg = colors.c2c(self.reset_fg, [0, 0, 0]);
+        let reset_bg = colors.c2c(self.reset_bg, [255, 255, 255]);
+
+        let (text_view, post_process_config) = post_process_inputs(
+            self.viewport,
+            viewport_blit.as_ref(),
+            &wgpu_state.text_dest_view,
+            &surface_config,
+        );
+
+        let mut post_process = P::compile(&device, text_view, post_process_config, self.user_data);
+        let features = adapter.map_or_else(
+            || {
+                post_process_config
+                    .format
+                    .guaranteed_format_features(device.features())
+            },
+            |adapter| adapter.get_texture_format_features(post_process_config.format),
         );
+        let sample_count = match supported_sample_count(self.msaa, features) {
+            1 => 1,
+            count if post_process.enable_multisampling(&device, post_process_config, count) => {
+                count
+            }
+            _ => 1,
+        };
+        let multisample_target = (sample_count > 1)
+            .then(|| build_multisample_target(&device, post_process_config, sample_count));
 
-        let reset_fg = self.colors.c2c(self.reset_fg, [0, 0, 0]);
-        let reset_bg = self.colors.c2c(self.reset_bg, [255, 255, 255]);
+        let gpu_timer = build_gpu_timer(&device, &queue);
 
-        Ok(WgpuBackend {
-            post_process: P::compile(
-                &device,
-                &wgpu_state.text_dest_view,
-                &surface_config,
-                self.user_data,
-            ),
+        WgpuBackend {
+            post_process,
+            sample_count,
+            multisample_target,
             cells: vec![],
+            laid_out: vec![],
             dirty_rows: vec![],
             dirty_cells: BitVec::new(),
             rendered: vec![],
             sourced: vec![],
             fast_blinking: BitVec::new(),
             slow_blinking: BitVec::new(),
+            overhanging: BitVec::new(),
             cursor: (0, 0),
             surface,
             _surface: PhantomData,
             surface_config,
+            present_modes,
+            adapter_info: adapter.map(Adapter::get_info),
             device,
             queue,
-            plan_cache: PlanCache::new(self.fonts.count().max(2)),
+            plan_cache: PlanCache::new(self.fonts.count().max(2), self.ligatures),
             buffer: UnicodeBuffer::new(),
             row: String::new(),
             rowmap: vec![],
             viewport: self.viewport,
-            cached: Atlas::new(&self.fonts, CACHE_WIDTH, CACHE_HEIGHT),
-            text_cache,
-            text_mask,
-            bg_vertices: vec![],
-            text_indices: vec![],
+            viewport_blit,
+            clear_color: self.clear_color,
+            text_format: self.text_format,
+            cached: Atlas::new(&self.fonts, cache_width, cache_height, max_pages),
+            atlas_pages: vec![atlas_page],
+            bg_instances: vec![],
+            cell_instances: 0,
+            text_indices: vec![vec![]],
             text_vertices: vec![],
+            bg_instance_buffer: GrowableBuffer::new("Text Bg Instances", BufferUsages::VERTEX),
+            text_vertex_buffer: GrowableBuffer::new("Text Vertices", BufferUsages::VERTEX),
+            index_buffer: GrowableBuffer::new("Text Indices", BufferUsages::INDEX),
             text_screen_size_buffer,
             text_bg_compositor,
             text_fg_compositor,
+            image_compositor,
+            images: vec![],
+            layer_images: vec![],
+            gpu_timer,
+            frame_stats: FrameStats::default(),
+            atlas_uploads: 0,
+            image_vertices: vec![],
+            image_vertex_buffer: GrowableBuffer::new("Image Vertices", BufferUsages::VERTEX),
+            repaint: vec![],
             wgpu_state,
             fonts: self.fonts,
-            colors: self.colors,
+            synthetic_bold: self.synthetic_bold,
+            synthetic_italic: self.synthetic_italic,
+            italic_overhang: self.italic_overhang,
+            builtin_box_drawing: self.builtin_box_drawing,
+            builtin_powerline: self.builtin_powerline,
+            budgeted_rasterization: self.budgeted_rasterization,
+            rasterization_budget: RASTERIZATION_BUDGET,
+            unrasterized: HashSet::default(),
+            subpixel_aa: self.subpixel_aa,
+            transparent_background: self.transparent_background,
+            background_alpha: self.background_alpha,
+            cell_bleed: self.cell_bleed,
+            underline_style: self.underline_style,
+            underline_thickness: self.underline_thickness,
+            underline_offset: self.underline_offset,
+            baseline_mode: self.baseline_mode,
+            min_cells: self.min_cells,
+            fixed_grid: self.fixed_grid,
+            scale_factor: self.scale_factor,
+            font_size,
+            emoji_mode: self.emoji_mode,
+            stem_darkening: self.stem_darkening,
+            subpixel_positioning: self.subpixel_positioning,
+            dim_factor: self.dim_factor,
+            tab_width: self.tab_width.max(1) as usize,
+            cursor_visible: true,
+            cursor_style: self.cursor_style,
+            cursor_color: self.cursor_color,
+            rendered_cursor: None,
+            extra_cursors: vec![],
+            rendered_extra_cursors: vec![],
+            selection: None,
+            pre_process_callback: None,
+            overlay_callback: None,
+            selection_color: self.selection_color,
+            selection_alpha: self.selection_alpha,
+            rendered_selection: BitVec::default(),
+            cursor_blink: self.cursor_blink,
+            last_cursor_toggle: Instant::now(),
+            show_cursor: true,
+            min_frame_interval: self.min_frame_interval,
+            last_present: None,
+            frame_time: None,
+            frame_skipped: false,
+            colors,
+            colors_dirty: false,
             reset_fg,
             reset_bg,
             fast_duration: self.fast_blink,
             last_fast_toggle: Instant::now(),
             show_fast: true,
             slow_duration: self.slow_blink,
+            text_blink: self.text_blink,
             last_slow_toggle: Instant::now(),
             show_slow: true,
-        })
+        }
+    }
+}
+
+fn build_viewport_blit(
+    device: &Device,
+    format: TextureFormat,
+    viewport: Viewport,
+    surface_config: &SurfaceConfiguration,
+    text_dest_view: &TextureView,
+    text_format: TextureFormat,
+) -> ViewportBlit {
+    let shader = device.create_shader_module(include_wgsl!("shaders/viewport.wgsl"));
+
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("Viewport Blit Binding Layout"),
+        entries: &[BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Viewport Blit Layout"),
+        bind_group_layouts: &[&layout],
+        immediate_size: 0,
+    });
+
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Viewport Blit Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview_mask: None,
+        cache: None,
+    });
+
+    let target = build_viewport_target(
+        device,
+        &layout,
+        viewport,
+        surface_config,
+        text_dest_view,
+        text_format,
+    );
+
+    ViewportBlit {
+        pipeline,
+        layout,
+        target,
+    }
+}
+
+/// Whether text can be composited into a texture with the given format and
+/// features. Srgb formats additionally need `view_formats` to be sampled
+/// through their non-srgb equivalent.
+fn supports_text_format(
+    format: TextureFormat,
+    features: TextureFormatFeatures,
+    view_formats: bool,
+) -> bool {
+    format.components() == 4
+        && (view_formats || !format.is_srgb())
+        && features
+            .allowed_usages
+            .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING)
+        && features
+            .flags
+            .contains(TextureFormatFeatureFlags::BLENDABLE | TextureFormatFeatureFlags::FILTERABLE)
+}
+
+/// Create the timer for [`FrameStats::gpu_time`], or `None` if the device
+/// doesn't support timestamp queries.
+fn build_gpu_timer(
+    device: &Device,
+    queue: &Queue,
+) -> Option<GpuTimer> {
+    if !device.features().contains(Features::TIMESTAMP_QUERY) {
+        return None;
+    }
+
+    let size = 2 * size_of::<u64>() as u64;
+    Some(GpuTimer {
+        queries: device.create_query_set(&QuerySetDescriptor {
+            label: Some("Frame Timestamps"),
+            ty: QueryType::Timestamp,
+            count: 2,
+        }),
+        resolve: device.create_buffer(&BufferDescriptor {
+            label: Some("Frame Timestamps Resolve"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        }),
+        readback: device.create_buffer(&BufferDescriptor {
+            label: Some("Frame Timestamps Readback"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }),
+        period: queue.get_timestamp_period(),
+        pending: None,
+    })
+}
+
+/// The number of samples per pixel for [`Builder::with_msaa`], falling back
+/// to a single sample if a format with the given features can't render and
+/// resolve `requested` samples.
+fn supported_sample_count(
+    requested: u32,
+    features: TextureFormatFeatures,
+) -> u32 {
+    if requested <= 1 {
+        return 1;
+    }
+    if features
+        .flags
+        .contains(TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+        && features.flags.sample_count_supported(requested)
+    {
+        requested
+    } else {
+        warn!("{requested}x multisampling isn't supported, falling back to 1x.");
+        1
     }
 }
 
+/// The pipeline overrides telling the compositor shaders whether the text
+/// format encodes srgb, in which case they have to output linear colors.
+fn srgb_target_constants(format: TextureFormat) -> [(&'static str, f64); 1] {
+    [("SRGB_TARGET", if format.is_srgb() { 1.0 } else { 0.0 })]
+}
+
+fn text_fg_constants(
+    format: TextureFormat,
+    premultiplied_alpha: bool,
+) -> [(&'static str, f64); 2] {
+    let [srgb_target] = srgb_target_constants(format);
+    [
+        srgb_target,
+        ("PREMULTIPLIED", if premultiplied_alpha { 1.0 } else { 0.0 }),
+    ]
+}
+
 fn build_text_bg_compositor(
     device: &Device,
     screen_size: &Buffer,
+    text_format: TextureFormat,
 ) -> TextCacheBgPipeline {
     let shader = device.create_shader_module(include_wgsl!("shaders/composite_bg.wgsl"));
 
@@ -629,52 +2058,84 @@ fn build_text_bg_compositor(
         immediate_size: 0,
     });
 
-    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("Text Bg Compositor Pipeline"),
-        layout: Some(&pipeline_layout),
-        vertex: VertexState {
-            module: &shader,
-            entry_point: Some("vs_main"),
-            compilation_options: PipelineCompilationOptions::default(),
-            buffers: &[VertexBufferLayout {
-                array_stride: size_of::<TextBgVertexMember>() as u64,
-                step_mode: VertexStepMode::Vertex,
-                attributes: &vertex_attr_array![0 => Float32x2, 1 => Uint32],
-            }],
-        },
-        primitive: PrimitiveState {
-            topology: PrimitiveTopology::TriangleList,
-            ..Default::default()
-        },
-        depth_stencil: None,
-        multisample: MultisampleState::default(),
-        fragment: Some(FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
-            compilation_options: PipelineCompilationOptions::default(),
-            targets: &[Some(ColorTargetState {
-                format: TextureFormat::Rgba8Unorm,
-                blend: None,
-                write_mask: ColorWrites::ALL,
-            })],
-        }),
-        multiview_mask: None,
-        cache: None,
+    let build_pipeline = |blend| {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Text Bg Compositor Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[
+                    VertexBufferLayout {
+                        array_stride: size_of::<[f32; 2]>() as u64,
+                        step_mode: VertexStepMode::Vertex,
+                        attributes: &vertex_attr_array![0 => Float32x2],
+                    },
+                    VertexBufferLayout {
+                        array_stride: size_of::<TextBgInstance>() as u64,
+                        step_mode: VertexStepMode::Instance,
+                        attributes: &vertex_attr_array![1 => Float32x2, 2 => Float32x2, 3 => Uint32],
+                    },
+                ],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions {
+                    constants: &srgb_target_constants(text_format),
+                    ..Default::default()
+                },
+                targets: &[Some(ColorTargetState {
+                    format: text_format,
+                    blend,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview_mask: None,
+            cache: None,
+        })
+    };
+    let pipeline = build_pipeline(None);
+    let blended_pipeline = build_pipeline(Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING));
+
+    let quad_vertices = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Text Bg Quad Vertices"),
+        contents: bytemuck::cast_slice(&[[0.0f32, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]),
+        usage: BufferUsages::VERTEX,
+    });
+
+    let quad_indices = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Text Bg Quad Indices"),
+        contents: bytemuck::cast_slice(&[0u32, 1, 2, 2, 3, 1]),
+        usage: BufferUsages::INDEX,
     });
 
     TextCacheBgPipeline {
         pipeline,
+        blended_pipeline,
         fs_uniforms,
+        quad_vertices,
+        quad_indices,
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn build_text_fg_compositor(
     device: &Device,
     screen_size: &Buffer,
     atlas_size: &Buffer,
-    cache_view: &TextureView,
-    mask_view: &TextureView,
+    atlas_page: &AtlasPage,
     sampler: &Sampler,
+    subpixel_aa: bool,
+    text_format: TextureFormat,
+    premultiplied_alpha: bool,
 ) -> TextCacheFgPipeline {
     let shader = device.create_shader_module(include_wgsl!("shaders/composite_fg.wgsl"));
 
@@ -743,46 +2204,144 @@ fn build_text_fg_compositor(
         }],
     });
 
-    let atlas_bindings = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("Text Compositor Fragment Binding"),
-        layout: &fragment_shader_layout,
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Text Compositor Layout"),
+        bind_group_layouts: &[&vertex_shader_layout, &fragment_shader_layout],
+        immediate_size: 0,
+    });
+
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Text Compositor Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[VertexBufferLayout {
+                array_stride: size_of::<TextVertexMember>() as u64,
+                step_mode: VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32, 3 => Uint32, 4 => Uint32, 5 => Uint32, 6 => Uint32],
+            }],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some(if subpixel_aa {
+                "fs_main_subpixel"
+            } else {
+                "fs_main"
+            }),
+            compilation_options: PipelineCompilationOptions {
+                constants: &text_fg_constants(text_format, premultiplied_alpha),
+                ..Default::default()
+            },
+            targets: &[Some(ColorTargetState {
+                format: text_format,
+                blend: Some(if premultiplied_alpha {
+                    BlendState::PREMULTIPLIED_ALPHA_BLENDING
+                } else {
+                    BlendState::ALPHA_BLENDING
+                }),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview_mask: None,
+        cache: None,
+    });
+
+    let atlas_bindings = build_atlas_bindings(
+        device,
+        &fragment_shader_layout,
+        atlas_page,
+        sampler,
+        atlas_size,
+    );
+
+    TextCacheFgPipeline {
+        pipeline,
+        fs_uniforms,
+        atlas_layout: fragment_shader_layout,
+        atlas_bindings: vec![atlas_bindings],
+        sampler: sampler.clone(),
+        atlas_size: atlas_size.clone(),
+    }
+}
+
+fn build_image_compositor(
+    device: &Device,
+    screen_size: &Buffer,
+    text_format: TextureFormat,
+    premultiplied_alpha: bool,
+) -> ImageCompositor {
+    let shader = device.create_shader_module(include_wgsl!("shaders/composite_image.wgsl"));
+
+    let vertex_shader_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("Image Compositor Uniforms Binding Layout"),
+        entries: &[BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: Some(NonZeroU64::new(size_of::<[f32; 4]>() as u64).unwrap()),
+            },
+            count: None,
+        }],
+    });
+
+    let fragment_shader_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("Image Compositor Fragment Binding Layout"),
         entries: &[
-            BindGroupEntry {
+            BindGroupLayoutEntry {
                 binding: 0,
-                resource: BindingResource::TextureView(cache_view),
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
             },
-            BindGroupEntry {
+            BindGroupLayoutEntry {
                 binding: 1,
-                resource: BindingResource::TextureView(mask_view),
-            },
-            BindGroupEntry {
-                binding: 2,
-                resource: BindingResource::Sampler(sampler),
-            },
-            BindGroupEntry {
-                binding: 3,
-                resource: atlas_size.as_entire_binding(),
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
             },
         ],
     });
 
+    let fs_uniforms = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Image Compositor Uniforms Binding"),
+        layout: &vertex_shader_layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: screen_size.as_entire_binding(),
+        }],
+    });
+
     let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-        label: Some("Text Compositor Layout"),
+        label: Some("Image Compositor Layout"),
         bind_group_layouts: &[&vertex_shader_layout, &fragment_shader_layout],
         immediate_size: 0,
     });
 
     let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("Text Compositor Pipeline"),
+        label: Some("Image Compositor Pipeline"),
         layout: Some(&pipeline_layout),
         vertex: VertexState {
             module: &shader,
             entry_point: Some("vs_main"),
             compilation_options: PipelineCompilationOptions::default(),
             buffers: &[VertexBufferLayout {
-                array_stride: size_of::<TextVertexMember>() as u64,
+                array_stride: size_of::<ImageVertex>() as u64,
                 step_mode: VertexStepMode::Vertex,
-                attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32, 3 => Uint32, 4 => Uint32],
+                attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x2],
             }],
         },
         primitive: PrimitiveState {
@@ -794,10 +2353,17 @@ fn build_text_fg_compositor(
         fragment: Some(FragmentState {
             module: &shader,
             entry_point: Some("fs_main"),
-            compilation_options: PipelineCompilationOptions::default(),
+            compilation_options: PipelineCompilationOptions {
+                constants: &text_fg_constants(text_format, premultiplied_alpha),
+                ..Default::default()
+            },
             targets: &[Some(ColorTargetState {
-                format: TextureFormat::Rgba8Unorm,
-                blend: Some(BlendState::ALPHA_BLENDING),
+                format: text_format,
+                blend: Some(if premultiplied_alpha {
+                    BlendState::PREMULTIPLIED_ALPHA_BLENDING
+                } else {
+                    BlendState::ALPHA_BLENDING
+                }),
                 write_mask: ColorWrites::ALL,
             })],
         }),
@@ -805,10 +2371,22 @@ fn build_text_fg_compositor(
         cache: None,
     });
 
-    TextCacheFgPipeline {
+    // Images are scaled to their cells with nearest neighbor sampling.
+    let sampler = device.create_sampler(&SamplerDescriptor {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Nearest,
+        min_filter: FilterMode::Nearest,
+        mipmap_filter: MipmapFilterMode::Nearest,
+        ..Default::default()
+    });
+
+    ImageCompositor {
         pipeline,
         fs_uniforms,
-        atlas_bindings,
+        image_layout: fragment_shader_layout,
+        sampler,
     }
 }
 
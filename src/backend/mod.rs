@@ -2,24 +2,49 @@ pub(crate) mod builder;
 pub(crate) mod wgpu_backend;
 
 use std::num::NonZeroU32;
+use std::ops::Range;
+use std::sync::mpsc;
+use std::time::Duration;
 
+use bitvec::vec::BitVec;
+use ratatui::layout::Position;
+use ratatui::layout::Rect;
+use ratatui::style::Color;
 use wgpu::Adapter;
 use wgpu::BindGroup;
-#[cfg(test)]
+use wgpu::BindGroupDescriptor;
+use wgpu::BindGroupEntry;
+use wgpu::BindGroupLayout;
+use wgpu::BindingResource;
 use wgpu::Buffer;
-#[cfg(test)]
+use wgpu::BufferAsyncError;
 use wgpu::BufferDescriptor;
-#[cfg(test)]
 use wgpu::BufferUsages;
 use wgpu::CommandEncoder;
+use wgpu::CommandEncoderDescriptor;
+use wgpu::CompositeAlphaMode;
 use wgpu::Device;
 use wgpu::Extent3d;
+use wgpu::LoadOp;
+use wgpu::MapMode;
+use wgpu::Operations;
+use wgpu::Origin3d;
+use wgpu::PollType;
+use wgpu::PresentMode;
+use wgpu::QuerySet;
 use wgpu::Queue;
+use wgpu::RenderPassColorAttachment;
+use wgpu::RenderPassDescriptor;
+use wgpu::RenderPassTimestampWrites;
 use wgpu::RenderPipeline;
+use wgpu::Sampler;
+use wgpu::StoreOp;
 use wgpu::Surface;
 use wgpu::SurfaceConfiguration;
+use wgpu::SurfaceError;
 use wgpu::SurfaceTexture;
-#[cfg(test)]
+use wgpu::TexelCopyBufferInfo;
+use wgpu::TexelCopyBufferLayout;
 use wgpu::Texture;
 use wgpu::TextureDescriptor;
 use wgpu::TextureDimension;
@@ -28,6 +53,8 @@ use wgpu::TextureUsages;
 use wgpu::TextureView;
 use wgpu::TextureViewDescriptor;
 
+use crate::colors::linear_to_srgb;
+
 /// A pipeline for post-processing rendered text.
 pub trait PostProcessor {
     /// Custom user data which will be supplied during creation of the post
@@ -38,6 +65,13 @@ pub trait PostProcessor {
     /// Called during initialization of the backend. This should fully
     /// initialize the post processor for rendering. Note that you are expected
     /// to render to the final surface during [`PostProcessor::process`].
+    ///
+    /// `text_view` holds srgb encoded, premultiplied colors. Its format is
+    /// `Rgba8Unorm` unless overridden with
+    /// [`Builder::with_text_format`](crate::Builder::with_text_format), in
+    /// which case it's the non-srgb equivalent of the chosen format. If the
+    /// surface format is srgb, convert the colors to linear before writing
+    /// them out so the surface encodes them only once.
     fn compile(
         device: &Device,
         text_view: &TextureView,
@@ -73,6 +107,27 @@ pub trait PostProcessor {
         surface_view: &TextureView,
     );
 
+    /// Called after [`PostProcessor::compile`] if multisampling was requested
+    /// with [`Builder::with_msaa`](crate::Builder::with_msaa) and the format
+    /// of the surface view supports it. Processors which draw geometry whose
+    /// edges should be antialiased can rebuild the pipelines rendering to the
+    /// surface view with `sample_count` samples, keep doing so in
+    /// [`PostProcessor::resize`], and return true. The `surface_view` passed
+    /// to [`PostProcessor::process`] is then a multisampled texture, which the
+    /// backend resolves to the surface afterwards. This doesn't affect the
+    /// text, which is sampled from `text_view` either way.
+    ///
+    /// The default returns false, so the processor keeps rendering to the
+    /// surface directly.
+    fn enable_multisampling(
+        &mut self,
+        _device: &Device,
+        _surface_config: &SurfaceConfiguration,
+        _sample_count: u32,
+    ) -> bool {
+        false
+    }
+
     /// Called to see if this post processor wants to update the screen. By
     /// default, the backend only runs the compositor and post processor when
     /// the text changes. Returning true from this will override that behavior
@@ -104,18 +159,340 @@ pub enum Viewport {
     #[default]
     Full,
     /// Render to a reduced area starting at the top right and rendering up to
-    /// the bottom left - (width, height).
+    /// the bottom left - (width, height). The excluded area is filled with the
+    /// color set by
+    /// [`Builder::with_clear_color`](crate::Builder::with_clear_color).
     Shrink { width: u32, height: u32 },
+    /// Render to an area of (width, height) in the middle of the surface. The
+    /// margin around it is filled with the color set by
+    /// [`Builder::with_clear_color`](crate::Builder::with_clear_color). The
+    /// area is clamped to the size of the surface.
+    Centered { width: u32, height: u32 },
+    /// Render the text at a natural resolution of (width, height) and scale it
+    /// up by the largest integer factor that fits the surface, e.g. for bitmap
+    /// fonts. The scaled text is centered and the margin around it is filled
+    /// with the color set by
+    /// [`Builder::with_clear_color`](crate::Builder::with_clear_color).
+    /// Scaling happens before the [`PostProcessor`] runs. The resolution is
+    /// clamped to the size of the surface.
+    IntegerScale { width: u32, height: u32 },
+    /// Render to the surface minus the given padding in pixels on each side.
+    /// The padding is filled with the color set by
+    /// [`Builder::with_clear_color`](crate::Builder::with_clear_color). If
+    /// the padding leaves no room for text, the grid is clamped to a single
+    /// cell.
+    Padded {
+        top: u32,
+        right: u32,
+        bottom: u32,
+        left: u32,
+    },
+}
+
+impl Viewport {
+    /// Pad the text by the same amount of pixels on every side.
+    pub const fn uniform_padding(padding: u32) -> Self {
+        Viewport::Padded {
+            top: padding,
+            right: padding,
+            bottom: padding,
+            left: padding,
+        }
+    }
+
+    /// The size of the area text is rendered to for a surface of the given
+    /// size.
+    fn drawable_size(
+        &self,
+        surface_width: u32,
+        surface_height: u32,
+    ) -> (u32, u32) {
+        match *self {
+            Viewport::Full => (surface_width, surface_height),
+            Viewport::Shrink { width, height } => (
+                surface_width.saturating_sub(width),
+                surface_height.saturating_sub(height),
+            ),
+            Viewport::Centered { width, height } | Viewport::IntegerScale { width, height } => {
+                (width.min(surface_width), height.min(surface_height))
+            }
+            Viewport::Padded {
+                top,
+                right,
+                bottom,
+                left,
+            } => (
+                surface_width.saturating_sub(left.saturating_add(right)),
+                surface_height.saturating_sub(top.saturating_add(bottom)),
+            ),
+        }
+    }
+
+    /// Place content of `width` by `height` pixels on the surface. Returns the
+    /// position of its top left corner and the factor it is scaled by.
+    fn placement(
+        &self,
+        surface_width: u32,
+        surface_height: u32,
+        width: u32,
+        height: u32,
+    ) -> (u32, u32, u32) {
+        match *self {
+            Viewport::Full | Viewport::Shrink { .. } => (0, 0, 1),
+            Viewport::Centered { .. } => {
+                let (width, height) = self.drawable_size(surface_width, surface_height);
+                (
+                    (surface_width - width) / 2,
+                    (surface_height - height) / 2,
+                    1,
+                )
+            }
+            Viewport::IntegerScale { .. } => {
+                let width = width.clamp(1, surface_width);
+                let height = height.clamp(1, surface_height);
+                let scale = (surface_width / width).min(surface_height / height);
+                (
+                    (surface_width - width * scale) / 2,
+                    (surface_height - height * scale) / 2,
+                    scale,
+                )
+            }
+            Viewport::Padded { top, left, .. } => {
+                // Keep the single cell left when the padding is too large on
+                // the surface.
+                let (width, height) = self.drawable_size(surface_width, surface_height);
+                (
+                    left.min(surface_width - width.max(1)),
+                    top.min(surface_height - height.max(1)),
+                    1,
+                )
+            }
+        }
+    }
+
+    /// The format of the intermediate texture the viewport needs, if any.
+    fn blit_format(
+        &self,
+        surface_format: TextureFormat,
+        text_format: TextureFormat,
+    ) -> Option<TextureFormat> {
+        match self {
+            Viewport::Full => None,
+            Viewport::Shrink { .. } | Viewport::Centered { .. } | Viewport::Padded { .. } => {
+                Some(surface_format)
+            }
+            Viewport::IntegerScale { .. } => Some(text_format.remove_srgb_suffix()),
+        }
+    }
+}
+
+/// The order of the subpixels of the display, used for subpixel antialiasing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SubpixelLayout {
+    /// Red, green, blue from left to right. This is the most common layout.
+    Rgb,
+    /// Blue, green, red from left to right.
+    Bgr,
+}
+
+/// The shape used to draw the cursor.
+///
+/// A cursor placed on any cell of a wide grapheme cluster, such as a CJK
+/// character or an emoji, is drawn at the cluster's first cell and covers the
+/// whole cluster. The cursor position itself is left as it was set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CursorStyle {
+    /// Fill the cell, drawing its text in the cell's background color.
+    #[default]
+    Block,
+    /// A thin vertical bar at the left edge of the cell.
+    Bar,
+    /// A thin horizontal line at the bottom of the cell.
+    Underline,
+}
+
+/// A callback which encodes additional rendering into a frame, see
+/// [`WgpuBackend::set_pre_process_callback`](crate::WgpuBackend::set_pre_process_callback)
+/// and
+/// [`WgpuBackend::set_overlay_callback`](crate::WgpuBackend::set_overlay_callback).
+pub type RenderCallback = Box<dyn FnMut(&mut CommandEncoder, &TextureView) + Send>;
+
+/// The cells covered by a selection highlight, see
+/// [`WgpuBackend::set_selection`](crate::WgpuBackend::set_selection).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Selection {
+    /// Every cell inside of the rectangle.
+    Block(Rect),
+    /// Every cell from `start` to `end`, both inclusive, in reading order.
+    /// Rows in between are selected entirely, as in a text editor.
+    Lines { start: Position, end: Position },
+}
+
+/// Where an image of the image layer is placed, see [`ImageDesc`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImagePlacement {
+    /// Stretched over the cells of the rectangle. The image follows the cells
+    /// when the font size changes.
+    Cells(Rect),
+    /// Stretched over `width` x `height` pixels of the text starting at `x`,
+    /// `y`, relative to the top left corner of the grid. Parts outside of the
+    /// grid are clipped.
+    Pixels {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// An image of the image layer, see
+/// [`WgpuBackend::add_image`](crate::WgpuBackend::add_image).
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDesc<'a> {
+    /// `width` x `height` pixels of 8 bit rgba with straight alpha, row by
+    /// row.
+    pub rgba: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub placement: ImagePlacement,
+    /// Images with a negative z index are drawn over the cell backgrounds but
+    /// under the text, all others over the text. Images with a higher z index
+    /// are drawn over those with a lower one, and images with the same z index
+    /// in the order they were added.
+    pub z_index: i32,
+}
+
+/// Statistics about the last frame rendered by [`Backend::flush`], see
+/// [`WgpuBackend::frame_stats`](crate::WgpuBackend::frame_stats).
+///
+/// [`Backend::flush`]: ratatui::backend::Backend::flush
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameStats {
+    /// The time the cpu spent building the frame, from updating the changed
+    /// cells to submitting the encoded passes.
+    pub cpu_time: Duration,
+    /// The time the gpu spent rendering a frame, measured with timestamp
+    /// queries. Reading the measurement back doesn't wait for the gpu, so
+    /// this is the most recent frame whose measurement completed, usually an
+    /// earlier one. `None` if the device doesn't support
+    /// [`wgpu::Features::TIMESTAMP_QUERY`] or no measurement completed yet.
+    pub gpu_time: Option<Duration>,
+    /// The glyphs rasterized and uploaded to the atlas since the previous
+    /// frame.
+    pub atlas_uploads: usize,
+    /// The cells which were composited again.
+    pub dirty_cells: usize,
+}
+
+/// The metrics the backend lays out text with, in physical pixels, see
+/// [`WgpuBackend::font_metrics`](crate::WgpuBackend::font_metrics). Vertical
+/// metrics are those of the primary font, the first regular font or the font
+/// the [`Builder`](crate::Builder) was created with if there are none.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct FontMetrics {
+    /// The distance from the baseline to the top of the font's line box.
+    pub ascent: f32,
+    /// The distance from the baseline to the bottom of the font's line box,
+    /// positive below the baseline.
+    pub descent: f32,
+    /// The gap the font suggests between lines. Cells are sized by the font
+    /// size and the line height scale instead.
+    pub line_gap: f32,
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// The distance from the top of a cell to the baseline of its text.
+    pub baseline: f32,
+    /// The scale factor the logical font size was multiplied by, see
+    /// [`Builder::with_scale_factor`](crate::Builder::with_scale_factor).
+    pub scale_factor: f64,
+}
+
+/// The style used to draw underlines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnderlineStyle {
+    /// A single straight line.
+    #[default]
+    Straight,
+    /// A wavy line, commonly used to highlight spelling mistakes or
+    /// diagnostics.
+    Curly,
+    /// Two thin straight lines.
+    Double,
+    /// A line of dots.
+    Dotted,
+    /// A line of short dashes.
+    Dashed,
+}
+
+/// How glyphs from different fonts are positioned vertically within a cell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BaselineMode {
+    /// Every font sits on its own baseline, derived from its ascender.
+    #[default]
+    PerFont,
+    /// Glyphs from fallback fonts are moved onto the primary font's baseline,
+    /// and underlines use the primary font's metrics. The primary font is the
+    /// first regular font, or the font the [`Builder`](crate::Builder) was
+    /// created with if there are none.
+    Primary,
+}
+
+/// How glyphs from color fonts, such as emoji, are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EmojiMode {
+    /// Color glyphs are drawn with their own colors.
+    #[default]
+    Color,
+    /// Color glyphs are converted to grayscale coverage and tinted with the
+    /// cell's foreground color, like any other glyph.
+    Monochrome,
+}
+
+/// What [`WgpuBackend::render_text`](crate::WgpuBackend::render_text) does
+/// with the previous contents of its target. Either way, cells with a
+/// transparent or translucent background, see
+/// [`Builder::with_transparent_background`](crate::Builder::with_transparent_background)
+/// and
+/// [`Builder::with_background_alpha`](crate::Builder::with_background_alpha),
+/// are blended over what's below them as premultiplied colors. Text over them
+/// is premultiplied as well with
+/// [`Builder::with_premultiplied_alpha`](crate::Builder::with_premultiplied_alpha),
+/// which keeps the target premultiplied throughout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClearPolicy {
+    /// Clear the target to the opaque color before drawing the grid.
+    /// [`Color::Reset`] clears to transparent, which leaves only the grid in
+    /// the target. This is the default.
+    Clear(Color),
+    /// Draw the grid over the target's contents, e.g. for an overlay over the
+    /// caller's scene. The target should hold premultiplied or opaque colors.
+    Load,
+}
+
+impl Default for ClearPolicy {
+    fn default() -> Self {
+        Self::Clear(Color::Reset)
+    }
 }
 
 mod private {
     use wgpu::Surface;
 
-    #[cfg(test)]
     use crate::backend::HeadlessSurface;
-    #[cfg(test)]
     use crate::backend::HeadlessTarget;
     use crate::backend::RenderTarget;
+    use crate::backend::TextureSurface;
+    use crate::backend::TextureTarget;
 
     pub trait Sealed {}
 
@@ -124,11 +501,13 @@ mod private {
     impl Sealed for Surface<'_> {}
     impl Sealed for RenderTarget {}
 
-    #[cfg(test)]
     impl Sealed for HeadlessTarget {}
 
-    #[cfg(test)]
     impl Sealed for HeadlessSurface {}
+
+    impl Sealed for TextureTarget {}
+
+    impl Sealed for TextureSurface {}
 }
 
 /// A Texture target that can be rendered to.
@@ -162,7 +541,6 @@ impl RenderTexture for RenderTarget {
     }
 }
 
-#[cfg(test)]
 impl RenderTexture for HeadlessTarget {
     fn get_view(
         &self,
@@ -186,6 +564,8 @@ pub trait RenderSurface<'s>: private::Sealed {
         adapter: &Adapter,
         width: u32,
         height: u32,
+        transparent: bool,
+        hdr: bool,
         _token: private::Token,
     ) -> Option<SurfaceConfiguration>;
 
@@ -196,10 +576,72 @@ pub trait RenderSurface<'s>: private::Sealed {
         _token: private::Token,
     );
 
+    /// Acquire the texture for the next frame. Surfaces which have become
+    /// outdated, e.g. because the window changed size before
+    /// [`WgpuBackend::resize`](crate::WgpuBackend::resize) was called, are
+    /// reconfigured with `config` and acquired once more. Returns `None` if
+    /// no texture is available for this frame, and an error only if the
+    /// failure is fatal.
     fn get_current_texture(
+        &mut self,
+        device: &Device,
+        config: &SurfaceConfiguration,
+        _token: private::Token,
+    ) -> crate::Result<Option<Self::Target>>;
+
+    /// Read back the pixels of `region`, which must lie within the surface.
+    fn read_pixels(
         &self,
+        device: &Device,
+        queue: &Queue,
+        region: Rect,
+        _token: private::Token,
+    ) -> Option<crate::Result<Vec<u8>>>;
+
+    fn present_modes(
+        &self,
+        adapter: &Adapter,
         _token: private::Token,
-    ) -> Option<Self::Target>;
+    ) -> Vec<PresentMode>;
+}
+
+/// Surface formats with more than 8 bits per channel, in order of preference.
+/// Colors are written in the standard range on these as well.
+const HDR_FORMATS: [TextureFormat; 2] = [TextureFormat::Rgba16Float, TextureFormat::Rgb10a2Unorm];
+
+/// Whether the surface expects linear color values, either because the
+/// hardware encodes them to sRGB on write or because it is a floating point
+/// (extended range sRGB) format.
+pub(crate) fn expects_linear(format: TextureFormat) -> bool {
+    format.is_srgb()
+        || matches!(
+            format,
+            TextureFormat::Rgba16Float | TextureFormat::Rgba32Float
+        )
+}
+
+/// Pick `requested` if the surface supports it, otherwise fall back to
+/// [`PresentMode::Mailbox`] and then [`PresentMode::Fifo`], which is always
+/// supported. An empty list of `supported` modes accepts any mode.
+pub(crate) fn select_present_mode(
+    requested: PresentMode,
+    supported: &[PresentMode],
+) -> PresentMode {
+    let mode = [requested, PresentMode::Mailbox]
+        .into_iter()
+        .find(|mode| {
+            supported.is_empty()
+                || supported.contains(mode)
+                || matches!(mode, PresentMode::AutoVsync | PresentMode::AutoNoVsync)
+        })
+        .unwrap_or(PresentMode::Fifo);
+
+    if mode != requested {
+        warn!("Present mode {requested:?} is not supported by the surface, falling back.");
+    }
+    info!("Using present mode {mode:?}");
+
+    mode
 }
 
 pub struct RenderTarget {
@@ -222,9 +664,44 @@ impl<'s> RenderSurface<'s> for Surface<'s> {
         adapter: &Adapter,
         width: u32,
         height: u32,
+        transparent: bool,
+        hdr: bool,
         _token: private::Token,
     ) -> Option<SurfaceConfiguration> {
-        self.get_default_config(adapter, width, height)
+        let mut config = self.get_default_config(adapter, width, height)?;
+
+        if hdr {
+            let capabilities = self.get_capabilities(adapter);
+            if let Some(format) = HDR_FORMATS
+                .into_iter()
+                .find(|format| capabilities.formats.contains(format))
+            {
+                config.format = format;
+                info!("Using HDR surface format {format:?}");
+            } else {
+                warn!("Surface doesn't support an HDR format, falling back to 8-bit.");
+            }
+        }
+
+        if transparent {
+            // The text is composited with premultiplied alpha, but compositors which
+            // only support postmultiplied alpha still give a reasonable result.
+            let capabilities = self.get_capabilities(adapter);
+            if let Some(mode) = [
+                CompositeAlphaMode::PreMultiplied,
+                CompositeAlphaMode::PostMultiplied,
+                CompositeAlphaMode::Inherit,
+            ]
+            .into_iter()
+            .find(|mode| capabilities.alpha_modes.contains(mode))
+            {
+                config.alpha_mode = mode;
+            } else {
+                warn!("Surface doesn't support transparency, rendering will be opaque.");
+            }
+        }
+
+        Some(config)
     }
 
     fn configure(
@@ -237,14 +714,28 @@ impl<'s> RenderSurface<'s> for Surface<'s> {
     }
 
     fn get_current_texture(
-        &self,
+        &mut self,
+        device: &Device,
+        config: &SurfaceConfiguration,
         _token: private::Token,
-    ) -> Option<Self::Target> {
-        let output = match self.get_current_texture() {
+    ) -> crate::Result<Option<Self::Target>> {
+        let output = match Surface::get_current_texture(self) {
+            Err(err @ (SurfaceError::Outdated | SurfaceError::Lost)) => {
+                warn!("{err}, reconfiguring the surface.");
+                Surface::configure(self, device, config);
+                Surface::get_current_texture(self)
+            }
+            output => output,
+        };
+
+        let output = match output {
             Ok(output) => output,
+            Err(err @ SurfaceError::OutOfMemory) => {
+                return Err(crate::Error::SurfaceTextureAcquisitionFailed(err));
+            }
             Err(err) => {
                 error!("{err}");
-                return None;
+                return Ok(None);
             }
         };
 
@@ -252,29 +743,54 @@ impl<'s> RenderSurface<'s> for Surface<'s> {
             .texture
             .create_view(&TextureViewDescriptor::default());
 
-        Some(RenderTarget {
+        Ok(Some(RenderTarget {
             texture: output,
             view,
-        })
+        }))
+    }
+
+    fn read_pixels(
+        &self,
+        _device: &Device,
+        _queue: &Queue,
+        _region: Rect,
+        _token: private::Token,
+    ) -> Option<crate::Result<Vec<u8>>> {
+        None
+    }
+
+    fn present_modes(
+        &self,
+        adapter: &Adapter,
+        _token: private::Token,
+    ) -> Vec<PresentMode> {
+        self.get_capabilities(adapter).present_modes
     }
 }
 
-#[cfg(test)]
-pub(crate) struct HeadlessTarget {
+/// The render target of a [`HeadlessSurface`].
+pub struct HeadlessTarget {
     view: TextureView,
 }
 
-#[cfg(test)]
-pub(crate) struct HeadlessSurface {
+/// An offscreen surface which can be read back from the cpu, e.g. for snapshot
+/// testing or server-side rendering.
+///
+/// Constructed using
+/// [`Builder::build_headless`](crate::Builder::build_headless).
+/// See [`WgpuBackend::read_pixels`](crate::WgpuBackend::read_pixels) for
+/// retrieving the rendered image.
+pub struct HeadlessSurface {
     pub(crate) texture: Option<Texture>,
     pub(crate) buffer: Option<Buffer>,
+    /// The stride of each row in the readback buffer, which is padded to
+    /// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
     pub(crate) buffer_width: u32,
     pub(crate) width: u32,
     pub(crate) height: u32,
     pub(crate) format: TextureFormat,
 }
 
-#[cfg(test)]
 impl HeadlessSurface {
     fn new(format: TextureFormat) -> Self {
         Self {
@@ -282,9 +798,148 @@ impl HeadlessSurface {
             ..Default::default()
         }
     }
+
+    /// Whether frames rendered to `format` can be read back.
+    pub(crate) fn supports_format(format: TextureFormat) -> bool {
+        matches!(
+            format,
+            TextureFormat::Rgba8Unorm
+                | TextureFormat::Rgba8UnormSrgb
+                | TextureFormat::Bgra8Unorm
+                | TextureFormat::Bgra8UnormSrgb
+                | TextureFormat::Rgb10a2Unorm
+                | TextureFormat::Rgba16Float
+        )
+    }
+
+    fn bytes_per_pixel(&self) -> u32 {
+        self.format.block_copy_size(None).unwrap_or(4)
+    }
+
+    fn read_back(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        region: Rect,
+    ) -> crate::Result<Vec<u8>> {
+        let (Some(texture), Some(buffer)) = (&self.texture, &self.buffer) else {
+            return Ok(vec![]);
+        };
+        if region.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // The region is never larger than the surface, so its padded rows fit in the
+        // readback buffer.
+        let width = region.width as u32;
+        let height = region.height as u32;
+        let buffer_width =
+            (width * self.bytes_per_pixel()).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                origin: Origin3d {
+                    x: region.x as u32,
+                    y: region.y as u32,
+                    z: 0,
+                },
+                ..texture.as_image_copy()
+            },
+            TexelCopyBufferInfo {
+                buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(buffer_width),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        let submission = queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..(buffer_width * height) as u64);
+        let (send, recv) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = send.send(result);
+        });
+        device
+            .poll(PollType::Wait {
+                submission_index: Some(submission),
+                timeout: None,
+            })
+            .map_err(crate::Error::DevicePollFailed)?;
+        recv.recv()
+            .expect("Map callback dropped without being called")
+            .map_err(crate::Error::BufferMapFailed)?;
+
+        let row_bytes = width as usize * self.bytes_per_pixel() as usize;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(buffer_width as usize) {
+                let row = &row[..row_bytes];
+                match self.format {
+                    // Wider formats are converted to 8 bits per channel.
+                    TextureFormat::Rgb10a2Unorm => {
+                        for px in row.chunks_exact(4) {
+                            let px = u32::from_le_bytes(px.try_into().unwrap());
+                            let channel = |shift: u32| ((px >> shift & 0x3ff) * 255 + 511) / 1023;
+                            pixels.extend(
+                                [channel(0), channel(10), channel(20), (px >> 30) * 85]
+                                    .map(|c| c as u8),
+                            );
+                        }
+                    }
+                    TextureFormat::Rgba16Float => {
+                        for px in row.chunks_exact(8) {
+                            let channel =
+                                |idx: usize| f16_to_f32(u16::from_le_bytes([px[idx], px[idx + 1]]));
+                            // Float surfaces hold linear values.
+                            let [r, g, b] =
+                                [0, 2, 4].map(|idx| linear_to_srgb(channel(idx).clamp(0.0, 1.0)));
+                            pixels.extend(
+                                [r, g, b, channel(6).clamp(0.0, 1.0)]
+                                    .map(|c| (c * 255.0).round() as u8),
+                            );
+                        }
+                    }
+                    _ => pixels.extend_from_slice(row),
+                }
+            }
+        }
+        buffer.unmap();
+
+        if matches!(
+            self.format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        ) {
+            for px in pixels.chunks_exact_mut(4) {
+                px.swap(0, 2);
+            }
+        }
+
+        Ok(pixels)
+    }
+}
+
+/// Convert the bits of an IEEE 754 half precision float to an `f32`.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f32::from(bits & 0x3ff);
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
 }
 
-#[cfg(test)]
 impl Default for HeadlessSurface {
     fn default() -> Self {
         Self {
@@ -298,7 +953,6 @@ impl Default for HeadlessSurface {
     }
 }
 
-#[cfg(test)]
 impl RenderSurface<'static> for HeadlessSurface {
     type Target = HeadlessTarget;
 
@@ -314,6 +968,8 @@ impl RenderSurface<'static> for HeadlessSurface {
         _adapter: &Adapter,
         width: u32,
         height: u32,
+        _transparent: bool,
+        _hdr: bool,
         _token: private::Token,
     ) -> Option<SurfaceConfiguration> {
         Some(SurfaceConfiguration {
@@ -349,7 +1005,8 @@ impl RenderSurface<'static> for HeadlessSurface {
             view_formats: &[],
         }));
 
-        self.buffer_width = config.width * 4;
+        self.buffer_width = (config.width * self.bytes_per_pixel())
+            .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
         self.buffer = Some(device.create_buffer(&BufferDescriptor {
             label: None,
             size: (self.buffer_width * config.height) as u64,
@@ -361,23 +1018,176 @@ impl RenderSurface<'static> for HeadlessSurface {
     }
 
     fn get_current_texture(
+        &mut self,
+        device: &Device,
+        config: &SurfaceConfiguration,
+        token: private::Token,
+    ) -> crate::Result<Option<Self::Target>> {
+        // A missing texture is the headless equivalent of a lost surface.
+        if self.texture.is_none() {
+            warn!("Headless texture is missing, reconfiguring the surface.");
+            self.configure(device, config, token);
+        }
+
+        Ok(self.texture.as_ref().map(|t| HeadlessTarget {
+            view: t.create_view(&TextureViewDescriptor::default()),
+        }))
+    }
+
+    fn read_pixels(
         &self,
+        device: &Device,
+        queue: &Queue,
+        region: Rect,
         _token: private::Token,
-    ) -> Option<Self::Target> {
-        self.texture.as_ref().map(|t| HeadlessTarget {
-            view: t.create_view(&TextureViewDescriptor::default()),
-        })
+    ) -> Option<crate::Result<Vec<u8>>> {
+        Some(self.read_back(device, queue, region))
+    }
+
+    fn present_modes(
+        &self,
+        _adapter: &Adapter,
+        _token: private::Token,
+    ) -> Vec<PresentMode> {
+        // Nothing is presented, so any mode is acceptable.
+        vec![]
+    }
+}
+
+/// The render target of a [`TextureSurface`].
+pub struct TextureTarget {
+    view: TextureView,
+}
+
+impl RenderTexture for TextureTarget {
+    fn get_view(
+        &self,
+        _token: private::Token,
+    ) -> &TextureView {
+        &self.view
     }
 }
 
+/// A surface which renders into a texture owned by the caller rather than
+/// presenting to a window, e.g. to composite the terminal as a layer inside a
+/// larger scene. Flushing the backend renders into the view, but nothing is
+/// presented.
+///
+/// The texture must be created from the device passed to
+/// [`Builder::build_with_texture`](crate::Builder::build_with_texture) and
+/// must support [`TextureUsages::RENDER_ATTACHMENT`].
+pub struct TextureSurface {
+    view: TextureView,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+}
+
+impl TextureSurface {
+    /// Create a new surface rendering to `view`. `width`, `height`, and
+    /// `format` must match the texture the view was created from.
+    pub fn new(
+        view: TextureView,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Self {
+        Self {
+            view,
+            width,
+            height,
+            format,
+        }
+    }
+
+    fn config(&self) -> SurfaceConfiguration {
+        SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            present_mode: wgpu::PresentMode::Immediate,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        }
+    }
+}
+
+impl RenderSurface<'static> for TextureSurface {
+    type Target = TextureTarget;
+
+    fn wgpu_surface(
+        &self,
+        _token: private::Token,
+    ) -> Option<&Surface<'static>> {
+        None
+    }
+
+    fn get_default_config(
+        &self,
+        _adapter: &Adapter,
+        _width: u32,
+        _height: u32,
+        _transparent: bool,
+        _hdr: bool,
+        _token: private::Token,
+    ) -> Option<SurfaceConfiguration> {
+        Some(self.config())
+    }
+
+    fn configure(
+        &mut self,
+        _device: &Device,
+        config: &SurfaceConfiguration,
+        _token: private::Token,
+    ) {
+        self.width = config.width;
+        self.height = config.height;
+    }
+
+    fn get_current_texture(
+        &mut self,
+        _device: &Device,
+        _config: &SurfaceConfiguration,
+        _token: private::Token,
+    ) -> crate::Result<Option<Self::Target>> {
+        Ok(Some(TextureTarget {
+            view: self.view.clone(),
+        }))
+    }
+
+    fn read_pixels(
+        &self,
+        _device: &Device,
+        _queue: &Queue,
+        _region: Rect,
+        _token: private::Token,
+    ) -> Option<crate::Result<Vec<u8>>> {
+        None
+    }
+
+    fn present_modes(
+        &self,
+        _adapter: &Adapter,
+        _token: private::Token,
+    ) -> Vec<PresentMode> {
+        // Nothing is presented, so any mode is acceptable.
+        vec![]
+    }
+}
+
+// Position + Size + Background, one per background quad. The quad itself is a
+// shared unit square scaled by the vertex shader.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct TextBgVertexMember {
-    vertex: [f32; 2],
+struct TextBgInstance {
+    position: [f32; 2],
+    size: [f32; 2],
     bg_color: u32,
 }
 
-// Vertex + UVCoord + Color
+// Vertex + UVCoord + Color + Decorations + Background
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct TextVertexMember {
@@ -386,44 +1196,475 @@ struct TextVertexMember {
     fg_color: u32,
     underline_pos: u32,
     underline_color: u32,
+    strikethrough_pos: u32,
+    bg_color: u32,
 }
 
 struct TextCacheBgPipeline {
     pipeline: RenderPipeline,
+    /// Blends backgrounds over the target instead of replacing it, for
+    /// [`ClearPolicy::Load`].
+    blended_pipeline: RenderPipeline,
     fs_uniforms: BindGroup,
+    quad_vertices: Buffer,
+    quad_indices: Buffer,
 }
 
 struct TextCacheFgPipeline {
     pipeline: RenderPipeline,
     fs_uniforms: BindGroup,
-    atlas_bindings: BindGroup,
+    atlas_layout: BindGroupLayout,
+    /// One per [`AtlasPage`].
+    atlas_bindings: Vec<BindGroup>,
+    sampler: Sampler,
+    atlas_size: Buffer,
+}
+
+// Vertex + UVCoord
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ImageVertex {
+    vertex: [f32; 2],
+    uv: [f32; 2],
+}
+
+/// Draws the images placed with
+/// [`WgpuBackend::draw_image`](crate::WgpuBackend::draw_image) over the cells
+/// they cover.
+struct ImageCompositor {
+    pipeline: RenderPipeline,
+    fs_uniforms: BindGroup,
+    image_layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+/// An image placed with
+/// [`WgpuBackend::draw_image`](crate::WgpuBackend::draw_image).
+struct InlineImage {
+    area: Rect,
+    /// The cells of `area` which still show the image, row by row.
+    covered: BitVec,
+    bindings: BindGroup,
+    /// The vertices drawing the image's cells in the current frame.
+    vertices: Range<u32>,
+}
+
+/// An image of the image layer, added with
+/// [`WgpuBackend::add_image`](crate::WgpuBackend::add_image).
+struct LayerImage {
+    id: u32,
+    placement: ImagePlacement,
+    z_index: i32,
+    bindings: BindGroup,
+    /// The vertices drawing the image's cells in the current frame.
+    vertices: Range<u32>,
+}
+
+/// Create the multisampled texture a post processor renders into when it
+/// supports [`PostProcessor::enable_multisampling`], matching a post process
+/// target described by `config`.
+fn build_multisample_target(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+) -> TextureView {
+    device
+        .create_texture(&TextureDescriptor {
+            label: Some("Multisampled Post Process Target"),
+            size: Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: TextureDimension::D2,
+            format: config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&TextureViewDescriptor::default())
+}
+
+/// Measures how long the gpu takes to render a frame with timestamp queries,
+/// see [`FrameStats::gpu_time`].
+struct GpuTimer {
+    queries: QuerySet,
+    resolve: Buffer,
+    readback: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Receives the result of mapping `readback` once the measured frame
+    /// completed.
+    pending: Option<mpsc::Receiver<Result<(), BufferAsyncError>>>,
+}
+
+impl GpuTimer {
+    /// The measurement of an earlier frame, if it completed since the last
+    /// call.
+    fn finished(&mut self) -> Option<Duration> {
+        let result = self.pending.as_ref()?.try_recv().ok()?;
+        self.pending = None;
+        result.ok()?;
+
+        let ticks = {
+            let data = self.readback.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            timestamps[1].saturating_sub(timestamps[0])
+        };
+        self.readback.unmap();
+        Some(Duration::from_nanos(
+            (ticks as f64 * self.period as f64) as u64,
+        ))
+    }
+
+    /// Whether the readback buffer is free for a new measurement.
+    fn is_idle(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Write the first (`index` 0) or second (`index` 1) timestamp with an
+    /// empty pass over `view`.
+    fn write_timestamp(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        index: u32,
+    ) {
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Timestamp Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            timestamp_writes: Some(RenderPassTimestampWrites {
+                query_set: &self.queries,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+            ..Default::default()
+        });
+    }
+
+    /// Copy both timestamps out for reading them back with
+    /// [`GpuTimer::map`].
+    fn resolve(
+        &self,
+        encoder: &mut CommandEncoder,
+    ) {
+        encoder.resolve_query_set(&self.queries, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, None);
+    }
+
+    /// Start reading back the timestamps after the resolving encoder was
+    /// submitted.
+    fn map(&mut self) {
+        let (send, recv) = mpsc::channel();
+        self.readback
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = send.send(result);
+            });
+        self.pending = Some(recv);
+    }
+}
+
+/// Copies the output of the post processor into its place on the surface for
+/// [`Viewport::Centered`] and [`Viewport::Padded`], or the text into a scaled
+/// up copy for [`Viewport::IntegerScale`].
+struct ViewportBlit {
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    target: ViewportTarget,
+}
+
+/// The intermediate texture of a [`ViewportBlit`]. The post processor renders
+/// to it for [`Viewport::Centered`] and [`Viewport::Padded`] and reads from it
+/// for [`Viewport::IntegerScale`].
+struct ViewportTarget {
+    /// The size and format of the texture.
+    config: SurfaceConfiguration,
+    view: TextureView,
+    /// The source of the blit.
+    bindings: BindGroup,
+}
+
+/// How the grid of cells maps onto the text texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct GridLayout {
+    /// The number of columns and rows.
+    columns: u16,
+    rows: u16,
+    /// The size of the text texture in pixels.
+    width: u32,
+    height: u32,
+    /// The position of the text texture's top left corner within the grid. The
+    /// grid is cropped to the drawable area when it is only this large because
+    /// of the minimum grid size.
+    crop_x: u32,
+    crop_y: u32,
+}
+
+/// Lay out a grid of cells of `cell_width` by `cell_height` pixels on a
+/// drawable area of `drawable_width` by `drawable_height` pixels. The grid has
+/// at least `min_columns` by `min_rows` cells, and is centered on the drawable
+/// area if that makes it larger.
+fn grid_layout(
+    (drawable_width, drawable_height): (u32, u32),
+    (cell_width, cell_height): (u32, u32),
+    (min_columns, min_rows): (u16, u16),
+) -> GridLayout {
+    let axis = |drawable: u32, cell: u32, min: u16| {
+        let natural = (drawable / cell).clamp(1, u16::MAX as u32) as u16;
+        let cells = natural.max(min);
+        if cells > natural {
+            let size = drawable.max(1);
+            (cells, size, (cells as u32 * cell - size) / 2)
+        } else {
+            (cells, cells as u32 * cell, 0)
+        }
+    };
+    let (columns, width, crop_x) = axis(drawable_width, cell_width, min_columns);
+    let (rows, height, crop_y) = axis(drawable_height, cell_height, min_rows);
+    GridLayout {
+        columns,
+        rows,
+        width,
+        height,
+        crop_x,
+        crop_y,
+    }
+}
+
+/// The layout of a grid of exactly `columns` x `rows` cells, see
+/// [`Builder::with_fixed_grid`](crate::Builder::with_fixed_grid).
+fn fixed_grid_layout(
+    (columns, rows): (u16, u16),
+    (cell_width, cell_height): (u32, u32),
+) -> GridLayout {
+    GridLayout {
+        columns,
+        rows,
+        width: columns as u32 * cell_width,
+        height: rows as u32 * cell_height,
+        crop_x: 0,
+        crop_y: 0,
+    }
+}
+
+/// The viewport showing a fixed grid of the given layout, scaled by the
+/// largest integer factor that fits the surface.
+fn fixed_grid_viewport(layout: &GridLayout) -> Viewport {
+    Viewport::IntegerScale {
+        width: layout.width,
+        height: layout.height,
+    }
 }
 
 struct WgpuState {
+    /// The view the text is sampled through. For srgb formats this is the
+    /// non-srgb equivalent, so it always reads srgb encoded colors.
     text_dest_view: TextureView,
+    /// The view the text is rendered to.
+    text_target_view: TextureView,
 }
 
 fn build_wgpu_state(
     device: &Device,
+    format: TextureFormat,
     drawable_width: u32,
     drawable_height: u32,
 ) -> WgpuState {
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if drawable_width > max_dimension || drawable_height > max_dimension {
+        warn!(
+            "Text target of {drawable_width}x{drawable_height} exceeds the device limit of \
+             {max_dimension}, clamping."
+        );
+    }
+
+    let sample_format = format.remove_srgb_suffix();
     let text_dest = device.create_texture(&TextureDescriptor {
         label: Some("Text Compositor Out"),
         size: Extent3d {
-            width: drawable_width.max(1),
-            height: drawable_height.max(1),
+            width: drawable_width.clamp(1, max_dimension),
+            height: drawable_height.clamp(1, max_dimension),
             depth_or_array_layers: 1,
         },
         mip_level_count: 1,
         sample_count: 1,
         dimension: TextureDimension::D2,
-        format: TextureFormat::Rgba8Unorm,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+        view_formats: if format.is_srgb() {
+            std::slice::from_ref(&sample_format)
+        } else {
+            &[]
+        },
+    });
+
+    let text_target_view = text_dest.create_view(&TextureViewDescriptor::default());
+    let text_dest_view = text_dest.create_view(&TextureViewDescriptor {
+        format: Some(sample_format),
+        ..Default::default()
+    });
+
+    WgpuState {
+        text_dest_view,
+        text_target_view,
+    }
+}
+
+fn build_viewport_target(
+    device: &Device,
+    layout: &BindGroupLayout,
+    viewport: Viewport,
+    surface_config: &SurfaceConfiguration,
+    text_dest_view: &TextureView,
+    text_format: TextureFormat,
+) -> ViewportTarget {
+    let (width, height) = match viewport {
+        Viewport::IntegerScale { .. } => (surface_config.width, surface_config.height),
+        _ => viewport.drawable_size(surface_config.width, surface_config.height),
+    };
+    let config = SurfaceConfiguration {
+        width: width.max(1),
+        height: height.max(1),
+        format: viewport
+            .blit_format(surface_config.format, text_format)
+            .unwrap_or(surface_config.format),
+        ..surface_config.clone()
+    };
+
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("Viewport Target"),
+        size: Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: config.format,
         usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
         view_formats: &[],
     });
 
-    let text_dest_view = text_dest.create_view(&TextureViewDescriptor::default());
+    let view = texture.create_view(&TextureViewDescriptor::default());
+
+    let bindings = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Viewport Blit Bindings"),
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::TextureView(match viewport {
+                Viewport::IntegerScale { .. } => text_dest_view,
+                _ => &view,
+            }),
+        }],
+    });
+
+    ViewportTarget {
+        config,
+        view,
+        bindings,
+    }
+}
+
+/// The text view and surface configuration to hand to the post processor.
+fn post_process_inputs<'a>(
+    viewport: Viewport,
+    blit: Option<&'a ViewportBlit>,
+    text_dest_view: &'a TextureView,
+    surface_config: &'a SurfaceConfiguration,
+) -> (&'a TextureView, &'a SurfaceConfiguration) {
+    match (viewport, blit) {
+        (Viewport::IntegerScale { .. }, Some(blit)) => (&blit.target.view, surface_config),
+        (_, Some(blit)) => (text_dest_view, &blit.target.config),
+        (_, None) => (text_dest_view, surface_config),
+    }
+}
+
+/// A page of the glyph atlas and the mask marking which of its glyphs are
+/// color glyphs.
+struct AtlasPage {
+    cache: Texture,
+    mask: Texture,
+}
 
-    WgpuState { text_dest_view }
+fn build_atlas_page(
+    device: &Device,
+    width: u32,
+    height: u32,
+) -> AtlasPage {
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let cache = device.create_texture(&TextureDescriptor {
+        label: Some("Text Atlas"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    let mask = device.create_texture(&TextureDescriptor {
+        label: Some("Text Mask"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::R8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    AtlasPage { cache, mask }
+}
+
+fn build_atlas_bindings(
+    device: &Device,
+    layout: &BindGroupLayout,
+    page: &AtlasPage,
+    sampler: &Sampler,
+    atlas_size: &Buffer,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Text Compositor Fragment Binding"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(
+                    &page.cache.create_view(&TextureViewDescriptor::default()),
+                ),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(
+                    &page.mask.create_view(&TextureViewDescriptor::default()),
+                ),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(sampler),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: atlas_size.as_entire_binding(),
+            },
+        ],
+    })
 }
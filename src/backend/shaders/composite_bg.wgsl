@@ -3,15 +3,20 @@ struct VertexOutput {
     @builtin(position) gl_Position: vec4<f32>,
 }
 
+// The size of the text texture, and the offset of its top left corner within the
+// grid when the grid is cropped to fit the surface.
 @group(0) @binding(0)
 var<uniform> ScreenSize: vec4<f32>;
 
 @vertex
 fn vs_main(
-    @location(0) VertexCoord: vec2<f32>,
-    @location(1) BgColor: u32,
+    @location(0) QuadCoord: vec2<f32>,
+    @location(1) Position: vec2<f32>,
+    @location(2) Size: vec2<f32>,
+    @location(3) BgColor: u32,
 ) -> VertexOutput {
-    let gl_Position = vec4<f32>((2.0 * VertexCoord / ScreenSize.xy - 1.0) * vec2(1.0, -1.0), 0.0, 1.0);
+    let VertexCoord = Position + QuadCoord * Size;
+    let gl_Position = vec4<f32>((2.0 * (VertexCoord - ScreenSize.zw) / ScreenSize.xy - 1.0) * vec2(1.0, -1.0), 0.0, 1.0);
     return VertexOutput(BgColor, gl_Position);
 }
 
@@ -28,9 +33,21 @@ fn unpack_color(color: u32) -> vec4<f32> {
     );
 }
 
+// Set when the text texture has an srgb format. It encodes what's written to it,
+// so colors have to be output linear.
+override SRGB_TARGET: bool = false;
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(pow((color + 0.055) / 1.055, vec3(2.4)), color / 12.92, color <= vec3(0.04045));
+}
+
+fn to_target(color: vec4<f32>) -> vec4<f32> {
+    return select(color, vec4(srgb_to_linear(color.rgb), color.a), SRGB_TARGET);
+}
+
 
 @fragment
 fn fs_main(@location(0) @interpolate(flat) BgColor: u32) -> FragmentOutput {
     let bgColorUnpacked = unpack_color(BgColor);
-    return FragmentOutput(bgColorUnpacked);
+    return FragmentOutput(to_target(bgColorUnpacked));
 }
\ No newline at end of file
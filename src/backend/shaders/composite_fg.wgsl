@@ -3,9 +3,13 @@ struct VertexOutput {
     @location(1) @interpolate(flat) FgColor: u32,
     @location(2) @interpolate(flat) UnderlinePos: u32,
     @location(3) @interpolate(flat) UnderlineColor: u32,
+    @location(4) @interpolate(flat) StrikethroughPos: u32,
+    @location(5) @interpolate(flat) BgColor: u32,
     @builtin(position) gl_Position: vec4<f32>,
 }
 
+// The size of the text texture, and the offset of its top left corner within the
+// grid when the grid is cropped to fit the surface.
 @group(0) @binding(0)
 var<uniform> ScreenSize: vec4<f32>;
 
@@ -16,9 +20,11 @@ fn vs_main(
     @location(2) FgColor: u32,
     @location(3) UnderlinePos: u32,
     @location(4) UnderlineColor: u32,
+    @location(5) StrikethroughPos: u32,
+    @location(6) BgColor: u32,
 ) -> VertexOutput {
-    let gl_Position = vec4<f32>((2.0 * VertexCoord / ScreenSize.xy - 1.0) * vec2(1.0, -1.0), 0.0, 1.0);
-    return VertexOutput(UV, FgColor, UnderlinePos, UnderlineColor, gl_Position);
+    let gl_Position = vec4<f32>((2.0 * (VertexCoord - ScreenSize.zw) / ScreenSize.xy - 1.0) * vec2(1.0, -1.0), 0.0, 1.0);
+    return VertexOutput(UV, FgColor, UnderlinePos, UnderlineColor, StrikethroughPos, BgColor, gl_Position);
 }
 
 struct FragmentOutput {
@@ -44,6 +50,154 @@ fn unpack_color(color: u32) -> vec4<f32> {
     );
 }
 
+// Set when the text texture has an srgb format. It encodes what's written to it,
+// so colors have to be output linear.
+override SRGB_TARGET: bool = false;
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(pow((color + 0.055) / 1.055, vec3(2.4)), color / 12.92, color <= vec3(0.04045));
+}
+
+fn to_target(color: vec4<f32>) -> vec4<f32> {
+    return select(color, vec4(srgb_to_linear(color.rgb), color.a), SRGB_TARGET);
+}
+
+// Set when the output is blended with premultiplied alpha rather than by the
+// fixed function blending multiplying the color with alpha.
+override PREMULTIPLIED: bool = false;
+
+// AtlasSize.z holds the gamma used when blending text with its background.
+fn to_linear(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color, vec3<f32>(0.0)), vec3<f32>(AtlasSize.z));
+}
+
+fn from_linear(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color, vec3<f32>(0.0)), vec3<f32>(1.0 / AtlasSize.z));
+}
+
+// Blending each channel independently (for subpixel coverage) or in linear space
+// isn't possible with fixed function blending, so instead we blend against the known
+// cell background and emit a color which produces the same result under regular
+// alpha blending. This is exact as long as the destination is the (opaque) cell
+// background. For srgb targets the fixed function blending happens in linear space,
+// so the color is solved for there.
+fn blend_coverage(fg: vec3<f32>, srgb_bg: vec3<f32>, coverage: vec3<f32>) -> vec4<f32> {
+    let srgb_blended = from_linear(mix(to_linear(srgb_bg), to_linear(fg), coverage));
+    let blended = to_target(vec4(srgb_blended, 1.0)).rgb;
+    let bg = to_target(vec4(srgb_bg, 1.0)).rgb;
+
+    // The smallest alpha which can still reach the blended color from the background.
+    let delta = blended - bg;
+    let room = select(bg, 1.0 - bg, delta > vec3<f32>(0.0));
+    let needed = select(vec3<f32>(0.0), abs(delta) / room, room > vec3<f32>(0.0));
+    let alpha = min(
+        max(max(coverage.r, max(coverage.g, coverage.b)), max(needed.r, max(needed.g, needed.b))),
+        1.0,
+    );
+    let color = select(
+        vec3<f32>(0.0),
+        (blended - bg * (1.0 - alpha)) / alpha,
+        alpha > 0.0,
+    );
+    return vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), alpha);
+}
+
+const UNDERLINE_STRAIGHT: u32 = 0u;
+const UNDERLINE_CURLY: u32 = 1u;
+const UNDERLINE_DOUBLE: u32 = 2u;
+const UNDERLINE_DOTTED: u32 = 3u;
+const UNDERLINE_DASHED: u32 = 4u;
+
+// The underline band spans [yMin, yMax) in atlas coordinates, with the style packed
+// into the top bits. Patterns are based on the screen position so that they line up
+// across adjacent cells.
+fn is_underline(UnderlinePos: u32, UV: vec2<f32>, FragCoord: vec4<f32>) -> bool {
+    let style = UnderlinePos >> 29u;
+    let yMax = UnderlinePos & 0x1FFFu;
+    let yMin = (UnderlinePos >> 16u) & 0x1FFFu;
+    if u32(UV.y) < yMin || u32(UV.y) >= yMax {
+        return false;
+    }
+
+    // Patterns repeat in whole pixels from the left edge of the screen.
+    let x = u32(FragCoord.x);
+    switch style {
+        case UNDERLINE_DOUBLE: {
+            // The band is three times the line thickness, a line at either edge.
+            let thickness = max(1u, (yMax - yMin) / 3u);
+            return u32(UV.y) < yMin + thickness || u32(UV.y) >= yMax - thickness;
+        }
+        case UNDERLINE_DOTTED: {
+            let dot = max(2u, yMax - yMin);
+            return (x / dot) % 2u == 0u;
+        }
+        case UNDERLINE_DASHED: {
+            let dash = max(3u, (yMax - yMin) * 4u);
+            return (x / dash) % 3u != 2u;
+        }
+        case UNDERLINE_CURLY: {
+            // The band is three times the line thickness, the wave fills the remainder.
+            let height = f32(yMax - yMin);
+            let thickness = max(1.0, height / 3.0);
+            let amplitude = (height - thickness) / 2.0;
+            let period = max(4.0, height * 2.0);
+            let center = f32(yMin) + height / 2.0
+                + amplitude * sin(FragCoord.x * 6.28318530718 / period);
+            return abs(UV.y - center) <= thickness / 2.0;
+        }
+        default: {
+            return true;
+        }
+    }
+}
+
+fn composite(
+    UV: vec2<f32>,
+    FragCoord: vec4<f32>,
+    FgColor: u32,
+    UnderlinePos: u32,
+    UnderlineColor: u32,
+    StrikethroughPos: u32,
+    BgColor: u32,
+    coverage: vec3<f32>,
+    textureColor: vec4<f32>,
+) -> vec4<f32> {
+    let underLineColorUnpacked = unpack_color(UnderlineColor);
+    let fgColorUnpacked = unpack_color(FgColor);
+    let bgColorUnpacked = unpack_color(BgColor);
+    let mask = textureSample(Mask, Sampler, UV / AtlasSize.xy);
+
+    // There's nothing to blend against on a transparent or translucent background,
+    // so coverage is used directly as alpha.
+    let alpha = max(coverage.r, max(coverage.g, coverage.b)) * fgColorUnpacked.a;
+    var fgColor = select(
+        blend_coverage(fgColorUnpacked.rgb, bgColorUnpacked.rgb, coverage * fgColorUnpacked.a),
+        to_target(vec4<f32>(fgColorUnpacked.rgb, alpha)),
+        bgColorUnpacked.a < 1.0,
+    );
+    // Color glyphs are stored premultiplied, so with premultiplied output they are
+    // unpremultiplied first to only be multiplied with their alpha once.
+    let glyphColor = select(
+        textureColor.rgb,
+        select(vec3<f32>(0.0), textureColor.rgb / textureColor.a, textureColor.a > 0.0),
+        PREMULTIPLIED,
+    );
+    fgColor = select(
+        fgColor,
+        to_target(vec4<f32>(glyphColor, textureColor.a * fgColorUnpacked.a)),
+        mask.r >= 0.5,
+    );
+
+    let decorationColor = to_target(underLineColorUnpacked);
+    fgColor = select(fgColor, decorationColor, is_underline(UnderlinePos, UV, FragCoord));
+
+    // Strikethrough uses the same decoration color as the underline.
+    let strikeMax = StrikethroughPos & 0xFFFFu;
+    let strikeMin = StrikethroughPos >> 16u;
+    fgColor = select(fgColor, decorationColor, u32(UV.y) >= strikeMin && u32(UV.y) < strikeMax);
+
+    return select(fgColor, vec4<f32>(fgColor.rgb * fgColor.a, fgColor.a), PREMULTIPLIED);
+}
 
 @fragment
 fn fs_main(
@@ -51,23 +205,45 @@ fn fs_main(
     @location(1) @interpolate(flat) FgColor: u32,
     @location(2) @interpolate(flat) UnderlinePos: u32,
     @location(3) @interpolate(flat) UnderlineColor: u32,
+    @location(4) @interpolate(flat) StrikethroughPos: u32,
+    @location(5) @interpolate(flat) BgColor: u32,
+    @builtin(position) FragCoord: vec4<f32>,
 ) -> FragmentOutput {
-    let underLineColorUnpacked = unpack_color(UnderlineColor);
-
-    var fgColorUnpacked = unpack_color(FgColor);
-    var textureColor = textureSample(Atlas, Sampler, UV / AtlasSize.xy);
-
-    let alpha = textureColor.a * fgColorUnpacked.a;
-    textureColor.a = alpha;
-    fgColorUnpacked.a = alpha;
-
-    let mask = textureSample(Mask, Sampler, UV / AtlasSize.xy);
-
-    var fgColor = select(fgColorUnpacked, textureColor, mask.r == 1.0);
-
-    let yMax = UnderlinePos & 0xFFFFu;
-    let yMin = UnderlinePos >> 16u;
-    fgColor = select(fgColor, underLineColorUnpacked, u32(UV.y) >= yMin && u32(UV.y) < yMax);
+    let textureColor = textureSample(Atlas, Sampler, UV / AtlasSize.xy);
+    return FragmentOutput(composite(
+        UV,
+        FragCoord,
+        FgColor,
+        UnderlinePos,
+        UnderlineColor,
+        StrikethroughPos,
+        BgColor,
+        vec3<f32>(textureColor.a),
+        textureColor,
+    ));
+}
 
-    return FragmentOutput(fgColor);
-}
\ No newline at end of file
+// The atlas stores separate coverage for each subpixel in the rgb channels.
+@fragment
+fn fs_main_subpixel(
+    @location(0) UV: vec2<f32>,
+    @location(1) @interpolate(flat) FgColor: u32,
+    @location(2) @interpolate(flat) UnderlinePos: u32,
+    @location(3) @interpolate(flat) UnderlineColor: u32,
+    @location(4) @interpolate(flat) StrikethroughPos: u32,
+    @location(5) @interpolate(flat) BgColor: u32,
+    @builtin(position) FragCoord: vec4<f32>,
+) -> FragmentOutput {
+    let textureColor = textureSample(Atlas, Sampler, UV / AtlasSize.xy);
+    return FragmentOutput(composite(
+        UV,
+        FragCoord,
+        FgColor,
+        UnderlinePos,
+        UnderlineColor,
+        StrikethroughPos,
+        BgColor,
+        textureColor.rgb,
+        textureColor,
+    ));
+}
@@ -0,0 +1,50 @@
+struct VertexOutput {
+    @location(0) UV: vec2<f32>,
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+// The size of the text texture, and the offset of its top left corner within the
+// grid when the grid is cropped to fit the surface.
+@group(0) @binding(0)
+var<uniform> ScreenSize: vec4<f32>;
+
+@vertex
+fn vs_main(
+    @location(0) VertexCoord: vec2<f32>,
+    @location(1) UV: vec2<f32>,
+) -> VertexOutput {
+    let gl_Position = vec4<f32>((2.0 * (VertexCoord - ScreenSize.zw) / ScreenSize.xy - 1.0) * vec2(1.0, -1.0), 0.0, 1.0);
+    return VertexOutput(UV, gl_Position);
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var Image: texture_2d<f32>;
+@group(1) @binding(1)
+var Sampler: sampler;
+
+// Set when the text texture has an srgb format. It encodes what's written to it,
+// so colors have to be output linear.
+override SRGB_TARGET: bool = false;
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(pow((color + 0.055) / 1.055, vec3(2.4)), color / 12.92, color <= vec3(0.04045));
+}
+
+fn to_target(color: vec4<f32>) -> vec4<f32> {
+    return select(color, vec4(srgb_to_linear(color.rgb), color.a), SRGB_TARGET);
+}
+
+// Set when the output is blended with premultiplied alpha rather than by the
+// fixed function blending multiplying the color with alpha.
+override PREMULTIPLIED: bool = false;
+
+@fragment
+fn fs_main(@location(0) UV: vec2<f32>) -> FragmentOutput {
+    // Images are stored with straight alpha.
+    let color = to_target(textureSample(Image, Sampler, UV));
+    return FragmentOutput(select(color, vec4(color.rgb * color.a, color.a), PREMULTIPLIED));
+}
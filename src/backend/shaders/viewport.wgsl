@@ -0,0 +1,24 @@
+struct VertexOutput {
+    @location(0) UV: vec2<f32>,
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+// A triangle covering the viewport, which is set to the centered area.
+@vertex
+fn vs_main(@builtin(vertex_index) Index: u32) -> VertexOutput {
+    let vertex = vec2(f32((Index << 1u) & 2u), f32(Index & 2u));
+    return VertexOutput(vertex, vec4(vertex * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var Texture: texture_2d<f32>;
+
+@fragment
+fn fs_main(@location(0) UV: vec2<f32>) -> FragmentOutput {
+    let texel = vec2<u32>(UV * vec2<f32>(textureDimensions(Texture)));
+    return FragmentOutput(textureLoad(Texture, texel, 0));
+}
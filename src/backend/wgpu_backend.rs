@@ -1,8 +1,13 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 use bitvec::order::Lsb0;
 use bitvec::slice::BitSlice;
@@ -18,7 +23,9 @@ use ratatui::backend::ClearType;
 use ratatui::backend::WindowSize;
 use ratatui::buffer::Cell;
 use ratatui::layout::Position;
+use ratatui::layout::Rect;
 use ratatui::layout::Size;
+use ratatui::style::Color;
 use ratatui::style::Modifier;
 use rustybuzz::shape_with_plan;
 use rustybuzz::ttf_parser::GlyphId;
@@ -29,17 +36,17 @@ use rustybuzz::GlyphBuffer;
 use rustybuzz::UnicodeBuffer;
 use unicode_bidi::Level;
 use unicode_bidi::ParagraphBidiInfo;
-use unicode_properties::GeneralCategoryGroup;
-use unicode_properties::UnicodeEmoji;
-use unicode_properties::UnicodeGeneralCategory;
 use unicode_width::UnicodeWidthChar;
 use unicode_width::UnicodeWidthStr;
 use web_time::Duration;
 use web_time::Instant;
-use wgpu::util::BufferInitDescriptor;
-use wgpu::util::DeviceExt;
+use wgpu::AdapterInfo;
+use wgpu::BindGroup;
+use wgpu::BindGroupDescriptor;
+use wgpu::BindGroupEntry;
+use wgpu::BindingResource;
 use wgpu::Buffer;
-use wgpu::BufferUsages;
+use wgpu::CommandEncoder;
 use wgpu::CommandEncoderDescriptor;
 use wgpu::Device;
 use wgpu::Extent3d;
@@ -47,48 +54,115 @@ use wgpu::IndexFormat;
 use wgpu::LoadOp;
 use wgpu::Operations;
 use wgpu::Origin3d;
+use wgpu::PresentMode;
 use wgpu::Queue;
+use wgpu::RenderPass;
 use wgpu::RenderPassColorAttachment;
 use wgpu::RenderPassDescriptor;
 use wgpu::StoreOp;
 use wgpu::Surface;
 use wgpu::SurfaceConfiguration;
-use wgpu::Texture;
 use wgpu::TextureAspect;
-
+use wgpu::TextureDescriptor;
+use wgpu::TextureDimension;
+use wgpu::TextureFormat;
+use wgpu::TextureUsages;
+use wgpu::TextureView;
+use wgpu::TextureViewDescriptor;
+
+use crate::backend::build_atlas_bindings;
+use crate::backend::build_atlas_page;
+use crate::backend::build_multisample_target;
+use crate::backend::build_viewport_target;
 use crate::backend::build_wgpu_state;
+use crate::backend::expects_linear;
+use crate::backend::fixed_grid_layout;
+use crate::backend::fixed_grid_viewport;
+use crate::backend::grid_layout;
+use crate::backend::post_process_inputs;
 use crate::backend::private::Token;
+use crate::backend::select_present_mode;
+use crate::backend::AtlasPage;
+use crate::backend::BaselineMode;
+use crate::backend::ClearPolicy;
+use crate::backend::CursorStyle;
+use crate::backend::EmojiMode;
+use crate::backend::FontMetrics;
+use crate::backend::FrameStats;
+use crate::backend::GpuTimer;
+use crate::backend::GridLayout;
+use crate::backend::ImageCompositor;
+use crate::backend::ImageDesc;
+use crate::backend::ImagePlacement;
+use crate::backend::ImageVertex;
+use crate::backend::InlineImage;
+use crate::backend::LayerImage;
 use crate::backend::PostProcessor;
+use crate::backend::RenderCallback;
 use crate::backend::RenderSurface;
 use crate::backend::RenderTexture;
-use crate::backend::TextBgVertexMember;
+use crate::backend::Selection;
+use crate::backend::SubpixelLayout;
+use crate::backend::TextBgInstance;
 use crate::backend::TextCacheBgPipeline;
 use crate::backend::TextCacheFgPipeline;
 use crate::backend::TextVertexMember;
+use crate::backend::TextureSurface;
+use crate::backend::UnderlineStyle;
 use crate::backend::Viewport;
+use crate::backend::ViewportBlit;
 use crate::backend::WgpuState;
+use crate::colors::srgb_to_linear;
 use crate::colors::ColorTable;
+use crate::colors::Colors;
 use crate::colors::Rgb;
+use crate::fonts::is_color_glyph;
 use crate::fonts::Font;
 use crate::fonts::Fonts;
 use crate::shaders::DefaultPostProcessor;
+use crate::utils::box_drawing;
+use crate::utils::gpu_buffer::GrowableBuffer;
 use crate::utils::plan_cache::PlanCache;
+#[cfg(feature = "testing")]
+use crate::utils::snapshot;
 use crate::utils::text_atlas::Atlas;
 use crate::utils::text_atlas::CacheRect;
 use crate::utils::text_atlas::Entry;
 use crate::utils::text_atlas::Key;
 use crate::utils::Outline;
 use crate::utils::Painter;
+use crate::Error;
 use crate::RandomState;
+use crate::Result;
+use crate::Tag;
 
 const NULL_CELL: Cell = Cell::new("");
 
 pub(super) struct RenderInfo {
     cell: usize,
+    key: Key,
     cached: CacheRect,
     underline_pos_min: u16,
     underline_pos_max: u16,
+    strikethrough_pos_min: u16,
+    strikethrough_pos_max: u16,
+    /// The glyph extends one cell past the cell(s) it occupies.
+    overhang: bool,
+}
+
+/// The background of a cell, after block cursors and the selection are
+/// applied.
+struct CellBackground {
+    color: Rgb,
+    alpha: u8,
+    /// The premultiplied color, with the selection blended over it.
+    packed: u32,
+    /// The text color under a block cursor.
+    cursor_fg: Option<Rgb>,
+    /// The selection color and its opacity, if the cell is selected.
+    selection: Option<(Rgb, u8)>,
 }
+
 /// Map from (x, y, glyph) -> (cell index, cache entry).
 /// We use an IndexMap because we want a consistent rendering order for
 /// vertices.
@@ -106,7 +180,6 @@ type Sourced = HashSet<(i32, i32, GlyphId, u32), RandomState>;
 /// referenced [`Surface`] (typically the lifetime of your window object).
 ///
 /// Limitations:
-/// - The cursor is tracked but not rendered.
 /// - No builtin accessibilty, although [`WgpuBackend::get_text`] is provided to
 ///   access the screen's contents.
 pub struct WgpuBackend<
@@ -116,22 +189,64 @@ pub struct WgpuBackend<
     S: RenderSurface<'s> = Surface<'s>,
 > {
     pub(super) post_process: P,
+    /// The samples per pixel the post processor renders with, see
+    /// [`PostProcessor::enable_multisampling`].
+    pub(super) sample_count: u32,
+    /// The multisampled texture the post processor renders into when
+    /// `sample_count` is more than one, resolved to its actual target.
+    pub(super) multisample_target: Option<TextureView>,
 
     pub(super) cells: Vec<Cell>,
+    /// The cells as they're drawn, laid out from `cells` whenever their row
+    /// changes.
+    pub(super) laid_out: Vec<Cell>,
     pub(super) dirty_rows: Vec<bool>,
     pub(super) dirty_cells: BitVec,
     pub(super) rendered: Vec<Rendered>,
     pub(super) sourced: Vec<Sourced>,
     pub(super) fast_blinking: BitVec,
     pub(super) slow_blinking: BitVec,
+    pub(super) overhanging: BitVec,
 
     pub(super) cursor: (u16, u16),
+    pub(super) cursor_visible: bool,
+    pub(super) cursor_style: CursorStyle,
+    pub(super) cursor_color: Color,
+    /// The cell the cursor was drawn in during the last flush.
+    pub(super) rendered_cursor: Option<usize>,
+    /// Decorative cursors set with [`WgpuBackend::set_extra_cursors`].
+    pub(super) extra_cursors: Vec<(u16, u16, CursorStyle, Color)>,
+    /// The cells, styles and colors of the extra cursors drawn during the last
+    /// flush.
+    pub(super) rendered_extra_cursors: Vec<(usize, CursorStyle, Color)>,
+    /// The selection highlight set with [`WgpuBackend::set_selection`].
+    pub(super) selection: Option<Selection>,
+    pub(super) pre_process_callback: Option<RenderCallback>,
+    pub(super) overlay_callback: Option<RenderCallback>,
+    pub(super) selection_color: Color,
+    pub(super) selection_alpha: u8,
+    /// The cells highlighted during the last flush.
+    pub(super) rendered_selection: BitVec,
+    pub(super) cursor_blink: Option<Duration>,
+    pub(super) last_cursor_toggle: Instant,
+    pub(super) show_cursor: bool,
 
     pub(super) viewport: Viewport,
+    /// The intermediate target and blit for viewports with a margin.
+    pub(super) viewport_blit: Option<ViewportBlit>,
+    /// The color the area of the surface outside of the viewport is cleared
+    /// to.
+    pub(super) clear_color: Color,
+    pub(super) text_format: TextureFormat,
 
     pub(super) surface: S,
     pub(super) _surface: PhantomData<&'s S>,
     pub(super) surface_config: SurfaceConfiguration,
+    /// The present modes supported by the surface, or empty if unknown.
+    pub(super) present_modes: Vec<PresentMode>,
+    /// The adapter the device was requested from, or `None` if the device is
+    /// owned by the caller.
+    pub(super) adapter_info: Option<AdapterInfo>,
     pub(super) device: Device,
     pub(super) queue: Queue,
 
@@ -141,26 +256,102 @@ pub struct WgpuBackend<
     pub(super) rowmap: Vec<u16>,
 
     pub(super) cached: Atlas,
-    pub(super) text_cache: Texture,
-    pub(super) text_mask: Texture,
-    pub(super) bg_vertices: Vec<TextBgVertexMember>,
-    pub(super) text_indices: Vec<[u32; 6]>,
+    pub(super) atlas_pages: Vec<AtlasPage>,
+    pub(super) bg_instances: Vec<TextBgInstance>,
+    /// How many of `bg_instances` are cell backgrounds. The rest are cursors
+    /// drawn over the text.
+    pub(super) cell_instances: usize,
+    /// Indices into `text_vertices`, grouped by the atlas page each quad
+    /// samples from.
+    pub(super) text_indices: Vec<Vec<[u32; 6]>>,
     pub(super) text_vertices: Vec<TextVertexMember>,
+    /// Persistent gpu copies of the vertex and index data of the dirty cells.
+    pub(super) bg_instance_buffer: GrowableBuffer,
+    pub(super) text_vertex_buffer: GrowableBuffer,
+    pub(super) index_buffer: GrowableBuffer,
     pub(super) text_bg_compositor: TextCacheBgPipeline,
     pub(super) text_fg_compositor: TextCacheFgPipeline,
+    pub(super) image_compositor: ImageCompositor,
+    /// The images placed with [`WgpuBackend::draw_image`], from bottom to
+    /// top.
+    pub(super) images: Vec<InlineImage>,
+    /// The images of the image layer, ordered by their z index.
+    pub(super) layer_images: Vec<LayerImage>,
+    pub(super) gpu_timer: Option<GpuTimer>,
+    pub(super) frame_stats: FrameStats,
+    /// The glyphs uploaded to the atlas since the last frame.
+    pub(super) atlas_uploads: usize,
+    pub(super) image_vertices: Vec<ImageVertex>,
+    pub(super) image_vertex_buffer: GrowableBuffer,
+    /// Areas whose cells are repainted during the next flush, because an
+    /// image was placed or removed there.
+    pub(super) repaint: Vec<Rect>,
     pub(super) text_screen_size_buffer: Buffer,
 
     pub(super) wgpu_state: WgpuState,
 
     pub(super) fonts: Fonts<'f>,
-    pub(super) colors: ColorTable,
+    pub(super) synthetic_bold: bool,
+    pub(super) synthetic_italic: bool,
+    pub(super) italic_overhang: bool,
+    pub(super) builtin_box_drawing: bool,
+    pub(super) builtin_powerline: bool,
+    pub(super) budgeted_rasterization: bool,
+    pub(super) rasterization_budget: Duration,
+    /// Glyphs which didn't fit in the last frame's rasterization budget.
+    pub(super) unrasterized: HashSet<Key, RandomState>,
+    pub(super) subpixel_aa: Option<SubpixelLayout>,
+    pub(super) transparent_background: bool,
+    pub(super) background_alpha: HashMap<Color, u8, RandomState>,
+    /// How far cell backgrounds extend past the cell on each side, in pixels.
+    pub(super) cell_bleed: f32,
+    pub(super) underline_style: UnderlineStyle,
+    /// Overrides the font's underline thickness, in logical pixels.
+    pub(super) underline_thickness: Option<f32>,
+    /// Moves underlines below the baseline, in logical pixels.
+    pub(super) underline_offset: Option<f32>,
+    pub(super) baseline_mode: BaselineMode,
+    /// The smallest grid, in columns and rows, regardless of the surface size.
+    pub(super) min_cells: (u16, u16),
+    /// The exact size of the grid, in columns and rows, if it doesn't depend
+    /// on the surface size.
+    pub(super) fixed_grid: Option<(u16, u16)>,
+    /// The scale factor of the display, which converts logical to physical
+    /// pixels.
+    pub(super) scale_factor: f64,
+    /// The font size in logical pixels.
+    pub(super) font_size: f32,
+    pub(super) emoji_mode: EmojiMode,
+    /// How much the coverage of glyph edges is increased when they are
+    /// rasterized.
+    pub(super) stem_darkening: f32,
+    /// Glyphs are rasterized at the subpixel phase closest to their exact
+    /// horizontal position.
+    pub(super) subpixel_positioning: bool,
+    /// The number of columns between tab stops.
+    pub(super) tab_width: usize,
+    /// How far [`Modifier::DIM`] text is blended from the background toward
+    /// its foreground color.
+    pub(super) dim_factor: f32,
+    pub(super) colors: Colors,
+    /// Every cell needs to be repainted because the colors changed.
+    pub(super) colors_dirty: bool,
     pub(super) reset_fg: Rgb,
     pub(super) reset_bg: Rgb,
 
+    pub(super) min_frame_interval: Option<Duration>,
+    pub(super) last_present: Option<Instant>,
+    /// The time between the last two presented frames.
+    pub(super) frame_time: Option<Duration>,
+    /// A flush was skipped because it came too soon after the last frame.
+    pub(super) frame_skipped: bool,
+
     pub(super) fast_duration: Duration,
     pub(super) last_fast_toggle: Instant,
     pub(super) show_fast: bool,
     pub(super) slow_duration: Duration,
+    /// Whether blinking text blinks at all.
+    pub(super) text_blink: bool,
     pub(super) last_slow_toggle: Instant,
     pub(super) show_slow: bool,
 }
@@ -177,73 +368,476 @@ impl<'f, 's, P: PostProcessor, S: RenderSurface<'s>> WgpuBackend<'f, 's, P, S> {
         &mut self.post_process
     }
 
+    /// Get the view of the composited text before post processing, e.g. to
+    /// sample the rendered terminal in your own shaders. This is the same view
+    /// passed to the [`PostProcessor`].
+    ///
+    /// The view is [`wgpu::TextureFormat::Rgba8Unorm`], or the non-srgb
+    /// equivalent of the format set with
+    /// [`Builder::with_text_format`](crate::Builder::with_text_format). The
+    /// texture is sized to a whole number of cells, so it may be slightly
+    /// smaller than the surface. It contains the terminal contents as of
+    /// the most recent call to [`WgpuBackend::flush`]. The view is replaced
+    /// whenever the backend is resized or the cell size changes, so don't
+    /// retain it across those calls.
+    pub fn text_texture_view(&self) -> &TextureView {
+        &self.wgpu_state.text_dest_view
+    }
+
+    /// Get information about the adapter (GPU and driver) the backend renders
+    /// with, e.g. to log it when diagnosing rendering differences between
+    /// machines. Returns `None` if the backend was built with a device owned
+    /// by the caller, see
+    /// [`Builder::build_with_device`](crate::Builder::build_with_device).
+    pub fn adapter_info(&self) -> Option<&AdapterInfo> {
+        self.adapter_info.as_ref()
+    }
+
+    /// Get the largest width or height of a texture the device supports. The
+    /// surface, text target and glyph atlas are clamped to this size, which
+    /// may be as small as 2048 pixels on some mobile and GL devices.
+    pub fn max_texture_dimension(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// Change the [`wgpu::PresentMode`] and reconfigure the surface. If the
+    /// surface doesn't support `mode`, this falls back to
+    /// [`PresentMode::Mailbox`] and then [`PresentMode::Fifo`]. Returns the
+    /// mode which was selected.
+    ///
+    /// See also [`Builder::with_present_mode`](crate::Builder::with_present_mode).
+    pub fn set_present_mode(
+        &mut self,
+        mode: PresentMode,
+    ) -> PresentMode {
+        let mode = select_present_mode(mode, &self.present_modes);
+        if mode != self.surface_config.present_mode {
+            self.surface_config.present_mode = mode;
+            self.surface
+                .configure(&self.device, &self.surface_config, Token);
+        }
+
+        mode
+    }
+
     /// Resize the rendering surface. This should be called e.g. to keep the
-    /// backend in sync with your window size.
+    /// backend in sync with your window size, as soon as the window reports
+    /// its new size. This reconfigures the surface, rebuilds the text target,
+    /// resizes the [`PostProcessor`] and recalculates the grid immediately.
+    /// Resizing to the current size, or to a zero width or height, does
+    /// nothing.
+    ///
+    /// Returns the new size of the grid in cells, which can be passed on to
+    /// [`ratatui::Terminal::resize`] to resize its buffers in lockstep. If the
+    /// grid changes size, every cell is cleared and must be drawn again.
     pub fn resize(
         &mut self,
         width: u32,
         height: u32,
-    ) {
-        let limits = self.device.limits();
-        let width = width.min(limits.max_texture_dimension_2d);
-        let height = height.min(limits.max_texture_dimension_2d);
+    ) -> Size {
+        let max_dimension = self.max_texture_dimension();
+        if width > max_dimension || height > max_dimension {
+            warn!(
+                "Surface of {width}x{height} exceeds the device limit of {max_dimension}, \
+                 clamping."
+            );
+        }
+        let width = width.min(max_dimension);
+        let height = height.min(max_dimension);
 
         if width == self.surface_config.width && height == self.surface_config.height
             || width == 0
             || height == 0
         {
-            return;
+            return self.grid_size();
         }
 
-        let (inset_width, inset_height) = match self.viewport {
-            Viewport::Full => (0, 0),
-            Viewport::Shrink { width, height } => (width, height),
-        };
-
-        let dims = self.size().unwrap();
-        let current_width = dims.width;
-        let current_height = dims.height;
+        let current = self.grid_size();
 
         self.surface_config.width = width;
         self.surface_config.height = height;
         self.surface
             .configure(&self.device, &self.surface_config, Token);
 
-        let width = width - inset_width;
-        let height = height - inset_height;
-
-        let chars_wide = width / self.fonts.min_width_px();
-        let chars_high = height / self.fonts.height_px();
-
-        if chars_wide != current_width as u32 || chars_high != current_height as u32 {
-            self.cells.clear();
-            self.rendered.clear();
-            self.sourced.clear();
-            self.fast_blinking.clear();
-            self.slow_blinking.clear();
+        let new = self.grid_size();
+        if new != current {
+            self.clear_grid();
         }
 
         // This always needs to be cleared because the surface is cleared when it is
         // resized. If we don't re-render the rows, we end up with a blank surface when
         // the resize is less than a character dimension.
         self.dirty_rows.clear();
-
-        self.wgpu_state = build_wgpu_state(
-            &self.device,
-            chars_wide * self.fonts.min_width_px(),
-            chars_high * self.fonts.height_px(),
-        );
-
-        self.post_process.resize(
-            &self.device,
-            &self.wgpu_state.text_dest_view,
-            &self.surface_config,
-        );
+        self.rebuild_text_target();
 
         info!(
             "Resized from {}x{} to {}x{}",
-            current_width, current_height, chars_wide, chars_high,
+            current.width, current.height, new.width, new.height,
         );
+
+        new
+    }
+
+    /// The current size of the grid in columns and rows. This is always at
+    /// least 1x1, or the size set with
+    /// [`Builder::with_min_cells`](crate::Builder::with_min_cells), and is the
+    /// same as [`Backend::size`].
+    pub fn grid_size(&self) -> Size {
+        let layout = self.grid_layout();
+        Size {
+            width: layout.columns,
+            height: layout.rows,
+        }
+    }
+
+    /// Change the height of all fonts to the specified size in pixels without
+    /// rebuilding the backend, e.g. to implement zooming. This clears the glyph
+    /// cache and recalculates the grid for the current surface size. The grid
+    /// always contains at least one cell, even if a cell is larger than the
+    /// surface.
+    ///
+    /// Returns the new size of the grid in cells. The next call to
+    /// [`ratatui::Terminal::draw`] will pick it up and re-layout.
+    ///
+    /// Like [`Builder::with_font_size_px`](crate::Builder::with_font_size_px),
+    /// the size is in logical pixels and multiplied by the scale factor.
+    pub fn set_font_size(
+        &mut self,
+        px: f32,
+    ) -> Size {
+        self.font_size = px;
+        self.fonts
+            .set_size_px(physical_font_size(self.font_size, self.scale_factor));
+        self.fonts_changed();
+        self.grid_size()
+    }
+
+    /// Change the scale factor of the display, e.g. when the window moves to
+    /// a monitor with a different one. The font size is rescaled from its
+    /// logical size, which clears the glyph cache and recalculates the grid.
+    /// The surface size isn't changed, so this is usually followed by a call
+    /// to [`WgpuBackend::resize`] with the new physical size of the window.
+    ///
+    /// Returns the new size of the grid in cells. Values which aren't finite
+    /// and positive are treated as `1.0`.
+    ///
+    /// See also [`Builder::with_scale_factor`](crate::Builder::with_scale_factor).
+    pub fn set_scale_factor(
+        &mut self,
+        scale_factor: f64,
+    ) -> Size {
+        self.scale_factor = if scale_factor.is_finite() && scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+        self.fonts
+            .set_size_px(physical_font_size(self.font_size, self.scale_factor));
+        self.fonts_changed();
+        self.grid_size()
+    }
+
+    /// The scale factor of the display, set with
+    /// [`Builder::with_scale_factor`](crate::Builder::with_scale_factor) or
+    /// [`WgpuBackend::set_scale_factor`].
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// The font and cell metrics text is currently laid out with, e.g. to
+    /// align native widgets to the text or to debug the layout.
+    pub fn font_metrics(&self) -> FontMetrics {
+        let font = self.fonts.primary().font();
+        let scale = self.fonts.height_px() as f32 / font.height() as f32;
+        let ascent = font.ascender() as f32 * scale;
+        FontMetrics {
+            ascent,
+            descent: -font.descender() as f32 * scale,
+            line_gap: font.line_gap() as f32 * scale,
+            cell_width: self.fonts.cell_width_px(),
+            cell_height: self.fonts.cell_height_px(),
+            baseline: ascent + self.fonts.padding_top_px() as f32,
+            scale_factor: self.scale_factor,
+        }
+    }
+
+    /// Scale the height of each cell relative to the font size. Returns the
+    /// new size of the grid in cells.
+    ///
+    /// See also [`Builder::with_line_height_scale`](crate::Builder::with_line_height_scale).
+    pub fn set_line_height_scale(
+        &mut self,
+        scale: f32,
+    ) -> Size {
+        self.fonts.set_line_height_scale(scale);
+        self.fonts_changed();
+        self.grid_size()
+    }
+
+    /// Scale the width of each cell relative to the font's advance. Returns
+    /// the new size of the grid in cells.
+    ///
+    /// See also [`Builder::with_cell_width_scale`](crate::Builder::with_cell_width_scale).
+    pub fn set_cell_width_scale(
+        &mut self,
+        scale: f32,
+    ) -> Size {
+        self.fonts.set_cell_width_scale(scale);
+        self.fonts_changed();
+        self.grid_size()
+    }
+
+    /// Change the [`CursorStyle`] used to draw the cursor.
+    pub fn set_cursor_style(
+        &mut self,
+        style: CursorStyle,
+    ) {
+        self.cursor_style = style;
+        self.redraw_cursor();
+    }
+
+    /// Change the color used to draw the cursor. [`Color::Reset`] uses the
+    /// foreground color of the cell under the cursor.
+    pub fn set_cursor_color(
+        &mut self,
+        color: Color,
+    ) {
+        self.cursor_color = color;
+        self.redraw_cursor();
+    }
+
+    /// Draw additional cursors at the given `(column, row)` cells, e.g. for
+    /// editors with multiple carets. These are purely decorative: they don't
+    /// affect the terminal cursor, don't blink, and are drawn even while the
+    /// terminal cursor is hidden. [`Color::Reset`] uses the foreground color
+    /// of the cell under the cursor, and positions outside of the grid are
+    /// ignored. Pass an empty slice to remove them.
+    pub fn set_extra_cursors(
+        &mut self,
+        cursors: &[(u16, u16, CursorStyle, Color)],
+    ) {
+        self.extra_cursors.clear();
+        self.extra_cursors.extend_from_slice(cursors);
+    }
+
+    /// Highlight the cells covered by `selection`, or remove the highlight if
+    /// `None`. The highlight is drawn over the text and under the cursor
+    /// without modifying the cells themselves. Wide characters are highlighted
+    /// entirely if either half is selected.
+    pub fn set_selection(
+        &mut self,
+        selection: Option<Selection>,
+    ) {
+        self.selection = selection;
+    }
+
+    /// Run `callback` right before the [`PostProcessor`] on every rendered
+    /// frame, with the frame's encoder and the text texture the post
+    /// processor reads from. Whatever it draws is post processed along with
+    /// the text, e.g. to put a debug HUD under the same effects. `None`
+    /// removes the callback.
+    ///
+    /// The text texture keeps its contents between frames and only the cells
+    /// which changed are drawn again, so anything drawn into it stays there
+    /// until the cells below it are redrawn. Content which changes from frame
+    /// to frame fits [`WgpuBackend::set_overlay_callback`] better.
+    ///
+    /// The callback only runs for frames which are rendered, not during
+    /// [`WgpuBackend::render_text`]. The view is only valid for the duration of
+    /// the call. A panic in the callback propagates out of
+    /// [`Backend::flush`], and the frame isn't presented.
+    pub fn set_pre_process_callback(
+        &mut self,
+        callback: Option<RenderCallback>,
+    ) {
+        self.pre_process_callback = callback;
+    }
+
+    /// Run `callback` after the [`PostProcessor`] on every rendered frame,
+    /// with the frame's encoder and the view of the surface texture about to
+    /// be presented, e.g. to draw an FPS counter on top of everything. The
+    /// encoder is submitted once the callback returns. `None` removes the
+    /// callback.
+    ///
+    /// The surface texture is presented right after the call and a new one is
+    /// acquired for the next frame, so the view must not be kept: rendering
+    /// to it later fails wgpu's validation, which panics by default. Frames
+    /// are only rendered when something changed, see
+    /// [`WgpuBackend::needs_redraw`], and the overlay is drawn again with each
+    /// of them. A panic in the callback propagates out of
+    /// [`Backend::flush`], and the frame isn't presented.
+    pub fn set_overlay_callback(
+        &mut self,
+        callback: Option<RenderCallback>,
+    ) {
+        self.overlay_callback = callback;
+    }
+
+    /// Change the color and opacity of the selection highlight.
+    ///
+    /// See also [`Builder::with_selection_color`](crate::Builder::with_selection_color).
+    pub fn set_selection_color(
+        &mut self,
+        color: Color,
+        alpha: u8,
+    ) {
+        self.selection_color = color;
+        self.selection_alpha = alpha;
+        // Forgetting the highlighted cells repaints all of them.
+        self.rendered_selection.fill(false);
+    }
+
+    /// Whether the next call to `flush` will update the screen even if nothing
+    /// was drawn, e.g. because the cursor is due to blink or the
+    /// [`PostProcessor`] requested an update. This is useful for deciding
+    /// whether to schedule another frame.
+    pub fn needs_update(&self) -> bool {
+        self.frame_skipped
+            || self.post_process.needs_update()
+            || !self.unrasterized.is_empty()
+            || self.cursor_needs_update()
+            || (self.text_blink
+                && self.fast_blinking.any()
+                && self.last_fast_toggle.elapsed() >= self.fast_duration)
+            || (self.text_blink
+                && self.slow_blinking.any()
+                && self.last_slow_toggle.elapsed() >= self.slow_duration)
+    }
+
+    /// Whether the event loop should schedule another frame, e.g. with
+    /// winit's `Window::request_redraw`. This is the case when cells, colors,
+    /// the cursor or the selection changed since the last frame, or when
+    /// [`WgpuBackend::needs_update`] is `true`.
+    ///
+    /// Blinking only needs a frame once it is due to toggle, see
+    /// [`WgpuBackend::next_redraw_at`] for when that is.
+    pub fn needs_redraw(&self) -> bool {
+        self.needs_update() || self.has_pending_changes()
+    }
+
+    /// When the next frame should be drawn, or `None` if nothing is due until
+    /// the app draws something new. This combines the cursor and text blink
+    /// intervals, [`PostProcessor`]s which request updates, glyphs still
+    /// being rasterized with
+    /// [`Builder::with_budgeted_rasterization`](crate::Builder::with_budgeted_rasterization),
+    /// and pending changes. Frames which are due immediately return the
+    /// current time, or the end of the frame interval set with
+    /// [`Builder::with_max_fps`](crate::Builder::with_max_fps) if that is
+    /// later.
+    ///
+    /// This is useful with winit's `ControlFlow::WaitUntil`, rather than
+    /// requesting redraws in a busy loop.
+    pub fn next_redraw_at(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let immediate = (self.frame_skipped
+            || self.post_process.needs_update()
+            || !self.unrasterized.is_empty()
+            || self.has_pending_changes())
+        .then_some(now);
+        let cursor = self
+            .cursor_blink
+            .filter(|_| self.cursor_visible)
+            .map(|interval| self.last_cursor_toggle + interval);
+        let fast = (self.text_blink && self.fast_blinking.any())
+            .then(|| self.last_fast_toggle + self.fast_duration);
+        let slow = (self.text_blink && self.slow_blinking.any())
+            .then(|| self.last_slow_toggle + self.slow_duration);
+
+        let next = [immediate, cursor, fast, slow]
+            .into_iter()
+            .flatten()
+            .min()?;
+        // Frames flushed before the interval passed are skipped.
+        let earliest = self
+            .min_frame_interval
+            .zip(self.last_present)
+            .map(|(interval, last)| last + interval);
+        Some(earliest.map_or(next, |earliest| next.max(earliest)))
+    }
+
+    /// Statistics about the last frame rendered by [`Backend::flush`], e.g. to
+    /// profile an application. Flushes which don't render a frame, because
+    /// nothing changed or the frame was skipped, leave them unchanged.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// The time between the last two presented frames, or `None` if fewer
+    /// than two frames have been presented.
+    pub fn frame_time(&self) -> Option<Duration> {
+        self.frame_time
+    }
+
+    /// Map a physical pixel position on the surface, e.g. from winit's
+    /// `WindowEvent::CursorMoved`, to the `(column, row)` of the cell drawn
+    /// there. Returns `None` if the position falls outside of the grid,
+    /// including the area excluded by [`Viewport::Shrink`] and the margin
+    /// around [`Viewport::Centered`], [`Viewport::IntegerScale`] and
+    /// [`Viewport::Padded`].
+    ///
+    /// This assumes the [`PostProcessor`] draws the text at its original size
+    /// from the top left corner of the drawable area, as
+    /// [`AspectPreservingDefaultPostProcessor`](crate::shaders::AspectPreservingDefaultPostProcessor)
+    /// does.
+    pub fn pixel_to_cell(
+        &self,
+        x: f64,
+        y: f64,
+    ) -> Option<(u16, u16)> {
+        let (offset_x, offset_y, scale) = self.viewport_placement();
+        let layout = self.grid_layout();
+        let x = (x - offset_x as f64) / scale as f64;
+        let y = (y - offset_y as f64) / scale as f64;
+
+        // Also rejects NaN.
+        if !(x >= 0.0 && y >= 0.0) {
+            return None;
+        }
+        let x = x + layout.crop_x as f64;
+        let y = y + layout.crop_y as f64;
+
+        let bounds = self.grid_size();
+        let column = (x / self.fonts.cell_width_px() as f64) as u32;
+        let row = (y / self.fonts.cell_height_px() as f64) as u32;
+
+        (column < bounds.width as u32 && row < bounds.height as u32)
+            .then_some((column as u16, row as u16))
+    }
+
+    /// The pixel rectangle the cell at `(column, row)` occupies on the surface,
+    /// e.g. for placing an IME candidate window. Cells holding a double width
+    /// character report a doubled width. This is the inverse of
+    /// [`WgpuBackend::pixel_to_cell`] and makes the same assumptions about the
+    /// [`PostProcessor`].
+    pub fn cell_bounds(
+        &self,
+        column: u16,
+        row: u16,
+    ) -> Rect {
+        let bounds = self.grid_size();
+        let columns = self
+            .cells
+            .get(row as usize * bounds.width as usize + column as usize)
+            .filter(|_| column < bounds.width)
+            .map_or(1, |cell| cell.symbol().width().clamp(1, 2)) as u32;
+
+        let (offset_x, offset_y, scale) = self.viewport_placement();
+        let layout = self.grid_layout();
+        let cell_width = self.fonts.cell_width_px() * scale;
+        let cell_height = self.fonts.cell_height_px() * scale;
+
+        // Cells cropped off the surface are clamped to its edge.
+        let to_u16 = |value: i64| value.clamp(0, u16::MAX as i64) as u16;
+        let x =
+            offset_x as i64 + (column as i64 * cell_width as i64) - (layout.crop_x * scale) as i64;
+        let y =
+            offset_y as i64 + (row as i64 * cell_height as i64) - (layout.crop_y * scale) as i64;
+        Rect {
+            x: to_u16(x),
+            y: to_u16(y),
+            width: to_u16((columns * cell_width) as i64),
+            height: to_u16(cell_height as i64),
+        }
     }
 
     /// Get the text currently displayed on the screen.
@@ -270,233 +864,699 @@ impl<'f, 's, P: PostProcessor, S: RenderSurface<'s>> WgpuBackend<'f, 's, P, S> {
         new_colors: ColorTable,
     ) {
         self.dirty_rows.clear();
-        self.colors = new_colors;
+        self.colors.table = new_colors;
+    }
+
+    /// Update the 256 color palette used for [`Color::Indexed`] colors. Every
+    /// cell is repainted the next time [`WgpuBackend::flush`] is called, but
+    /// the glyph cache is retained.
+    ///
+    /// See also [`Builder::with_palette`](crate::Builder::with_palette).
+    pub fn update_palette(
+        &mut self,
+        palette: [Rgb; 256],
+    ) {
+        self.colors.palette = palette;
+        self.colors_dirty = true;
+    }
+
+    /// Set the color the area of the surface which isn't covered by text is
+    /// cleared to. Every cell is repainted the next time
+    /// [`WgpuBackend::flush`] is called.
+    ///
+    /// See also [`Builder::with_clear_color`](crate::Builder::with_clear_color).
+    pub fn set_clear_color(
+        &mut self,
+        color: Color,
+    ) {
+        self.clear_color = color;
+        self.colors_dirty = true;
+    }
+
+    /// Set the opacity of cells with the given background color, from `0` for
+    /// fully transparent to `255` for opaque. Every cell is repainted the next
+    /// time [`WgpuBackend::flush`] is called.
+    ///
+    /// See also
+    /// [`Builder::with_background_alpha`](crate::Builder::with_background_alpha).
+    pub fn set_background_alpha(
+        &mut self,
+        color: Color,
+        alpha: u8,
+    ) {
+        self.background_alpha.insert(color, alpha);
+        self.colors_dirty = true;
     }
 
     /// Update the fonts used for rendering. This will cause a full repaint of
-    /// the screen the next time [`WgpuBackend::flush`] is called.
+    /// the screen the next time [`WgpuBackend::flush`] is called. The size of
+    /// the new fonts is in logical pixels, like [`WgpuBackend::set_font_size`].
+    ///
+    /// See also [`WgpuBackend::set_fonts`], which loads a new font from its
+    /// data and returns the new grid size.
     pub fn update_fonts(
         &mut self,
         new_fonts: Fonts<'f>,
     ) {
-        self.dirty_rows.clear();
-        self.cached.match_fonts(&new_fonts);
         self.fonts = new_fonts;
+        self.font_size = self.fonts.height_px() as f32;
+        self.fonts
+            .set_size_px(physical_font_size(self.font_size, self.scale_factor));
+        self.fonts_changed();
     }
 
-    fn render(&mut self) {
-        let bounds = self.window_size().unwrap();
-
-        let mut encoder = self
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor {
-                label: Some("Draw Encoder"),
-            });
+    /// Swap the fonts used for rendering for the font in `data`, e.g. when the
+    /// user picks a different font, without rebuilding the backend. The font
+    /// keeps the current font size. This clears the glyph cache, takes the
+    /// cell size from the new font and recalculates the grid for the current
+    /// surface size. The pipelines and atlas pages are kept.
+    ///
+    /// Returns the new size of the grid in cells, which can be passed on to
+    /// [`ratatui::Terminal::resize`]. If the grid changes size, every cell is
+    /// cleared and must be drawn again.
+    ///
+    /// Returns [`Error::FontParsingFailed`] if the data isn't a valid font, in
+    /// which case the current fonts are kept.
+    ///
+    /// <div class="warning">
+    ///
+    /// Like [`Font::from_owned_bytes`](crate::Font::from_owned_bytes), owned
+    /// data is leaked and isn't freed when the font is replaced. Apps letting
+    /// the user switch fonts should load each font's data once and pass it
+    /// borrowed rather than loading it again on every switch.
+    ///
+    /// </div>
+    pub fn set_fonts(
+        &mut self,
+        data: impl Into<Cow<'f, [u8]>>,
+        face_index: u32,
+    ) -> Result<Size> {
+        let font = match data.into() {
+            Cow::Borrowed(data) => Font::from_bytes(data, face_index)?,
+            Cow::Owned(data) => Font::from_owned_bytes(data, face_index)?,
+        };
+        self.fonts = Fonts::new(font, physical_font_size(self.font_size, self.scale_factor));
+        self.fonts_changed();
+        Ok(self.grid_size())
+    }
 
-        if !self.text_vertices.is_empty() {
-            {
-                let mut uniforms = self
-                    .queue
-                    .write_buffer_with(
-                        &self.text_screen_size_buffer,
-                        0,
-                        NonZeroU64::new(size_of::<[f32; 4]>() as u64).unwrap(),
-                    )
-                    .unwrap();
-                uniforms.copy_from_slice(bytemuck::cast_slice(&[
-                    bounds.columns_rows.width as f32 * self.fonts.min_width_px() as f32,
-                    bounds.columns_rows.height as f32 * self.fonts.height_px() as f32,
-                    0.0,
-                    0.0,
-                ]));
-            }
-
-            let bg_vertices = self.device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("Text Bg Vertices"),
-                contents: bytemuck::cast_slice(&self.bg_vertices),
-                usage: BufferUsages::VERTEX,
-            });
+    /// Update the variation axis coordinates used to instantiate variable
+    /// fonts. This invalidates the glyph cache and will cause a full repaint of
+    /// the screen the next time [`WgpuBackend::flush`] is called.
+    ///
+    /// See also [`Fonts::set_variable_axes`].
+    pub fn set_variable_axes(
+        &mut self,
+        axes: &[(Tag, f32)],
+    ) {
+        self.fonts.set_variable_axes(axes);
+        self.fonts_changed();
+    }
 
-            let fg_vertices = self.device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("Text Vertices"),
-                contents: bytemuck::cast_slice(&self.text_vertices),
-                usage: BufferUsages::VERTEX,
-            });
+    /// Invalidate everything derived from the current fonts. If the cell size
+    /// changed, the grid is rebuilt for the current surface size.
+    fn fonts_changed(&mut self) {
+        self.cached.match_fonts(&self.fonts);
+        self.dirty_rows.clear();
 
-            let indices = self.device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("Text Indices"),
-                contents: bytemuck::cast_slice(&self.text_indices),
-                usage: BufferUsages::INDEX,
-            });
+        let layout = self.grid_layout();
+        if self.fixed_grid.is_some() {
+            self.viewport = fixed_grid_viewport(&layout);
+        }
+        if self.cells.len() != layout.columns as usize * layout.rows as usize
+            || self.wgpu_state.text_dest_view.texture().size()
+                != (Extent3d {
+                    width: layout.width,
+                    height: layout.height,
+                    depth_or_array_layers: 1,
+                })
+        {
+            self.clear_grid();
+            self.rebuild_text_target();
+        }
+    }
 
-            {
-                let mut text_render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                    label: Some("Text Render Pass"),
-                    color_attachments: &[Some(RenderPassColorAttachment {
-                        view: &self.wgpu_state.text_dest_view,
-                        resolve_target: None,
-                        ops: Operations {
-                            load: LoadOp::Load,
-                            store: StoreOp::Store,
-                        },
-                        depth_slice: None,
-                    })],
-                    ..Default::default()
-                });
+    /// Drop all cell contents, e.g. because the dimensions of the grid changed.
+    fn clear_grid(&mut self) {
+        self.rendered_cursor = None;
+        self.rendered_extra_cursors.clear();
+        self.rendered_selection.clear();
+        self.cells.clear();
+        self.laid_out.clear();
+        self.rendered.clear();
+        self.sourced.clear();
+        self.fast_blinking.clear();
+        self.slow_blinking.clear();
+        self.overhanging.clear();
+        self.dirty_rows.clear();
+        self.images.clear();
+        self.repaint.clear();
+    }
 
-                text_render_pass.set_index_buffer(indices.slice(..), IndexFormat::Uint32);
+    /// Whether the blinking cursor is due to toggle.
+    fn cursor_needs_update(&self) -> bool {
+        self.cursor_visible
+            && self
+                .cursor_blink
+                .is_some_and(|interval| self.last_cursor_toggle.elapsed() >= interval)
+    }
 
-                text_render_pass.set_pipeline(&self.text_bg_compositor.pipeline);
-                text_render_pass.set_bind_group(0, &self.text_bg_compositor.fs_uniforms, &[]);
-                text_render_pass.set_vertex_buffer(0, bg_vertices.slice(..));
-                text_render_pass.draw_indexed(0..(self.bg_vertices.len() as u32 / 4) * 6, 0, 0..1);
+    /// Restart the blink cycle with the cursor shown.
+    fn reset_cursor_blink(&mut self) {
+        self.last_cursor_toggle = Instant::now();
+        self.show_cursor = true;
+    }
 
-                text_render_pass.set_pipeline(&self.text_fg_compositor.pipeline);
-                text_render_pass.set_bind_group(0, &self.text_fg_compositor.fs_uniforms, &[]);
-                text_render_pass.set_bind_group(1, &self.text_fg_compositor.atlas_bindings, &[]);
+    /// Redraw the row containing the cursor during the next flush.
+    fn redraw_cursor(&mut self) {
+        let width = self.grid_size().width as usize;
+        if let Some(row) = self
+            .rendered_cursor
+            .and_then(|cursor| self.dirty_rows.get_mut(cursor / width))
+        {
+            *row = true;
+        }
+    }
 
-                text_render_pass.set_vertex_buffer(0, fg_vertices.slice(..));
-                text_render_pass.draw_indexed(
-                    0..(self.text_vertices.len() as u32 / 4) * 6,
-                    0,
-                    0..1,
-                );
+    /// Stop showing images in the `width` cells starting at `x`, `y`, e.g.
+    /// because text was drawn there. Images which no longer show in any cell
+    /// are removed.
+    fn uncover_images(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+    ) {
+        for image in &mut self.images {
+            let area = image.area;
+            for x in x..x.saturating_add(width) {
+                if area.contains(Position { x, y }) {
+                    let index = (y - area.y) as usize * area.width as usize + (x - area.x) as usize;
+                    image.covered.set(index, false);
+                    self.repaint.push(Rect::new(x, y, 1, 1));
+                }
             }
         }
+        self.images.retain(|image| image.covered.any());
+    }
 
-        let Some(texture) = self.surface.get_current_texture(Token) else {
-            return;
-        };
+    /// The cell the cursor is drawn in, or `None` if it is hidden or blinked
+    /// off.
+    fn cursor_cell(
+        &self,
+        bounds: Size,
+    ) -> Option<usize> {
+        let cursor = self.cursor.1 as usize * bounds.width as usize + self.cursor.0 as usize;
+        (self.cursor_visible && self.show_cursor)
+            .then_some(cursor)
+            .filter(|cursor| *cursor < self.laid_out.len())
+            .map(|cursor| self.cluster_start(cursor, self.cursor.0 as usize))
+    }
 
-        self.post_process.process(
-            &mut encoder,
-            &self.queue,
-            &self.wgpu_state.text_dest_view,
-            &self.surface_config,
-            texture.get_view(Token),
-        );
+    /// Mark the cells a cursor in the cell at `index` is drawn over, and the
+    /// one before it, as dirty.
+    fn mark_cursor_dirty(
+        &mut self,
+        index: usize,
+    ) {
+        let end =
+            (index + self.laid_out.get(index).map_or(1, cluster_width)).min(self.dirty_cells.len());
+        for index in index.saturating_sub(1)..end {
+            self.dirty_cells.set(index, true);
+        }
+    }
 
-        self.queue.submit(Some(encoder.finish()));
-        texture.present(Token);
+    /// The cell holding the grapheme cluster drawn over the cell at `index`,
+    /// which is `column` cells into its row. Wide clusters, such as CJK
+    /// characters and emoji, are stored in their first cell and cover the
+    /// following ones, so a cursor placed on any cell of a cluster snaps back
+    /// to its start and covers the whole cluster.
+    fn cluster_start(
+        &self,
+        index: usize,
+        column: usize,
+    ) -> usize {
+        (index - column..index)
+            .rev()
+            .find(|&start| cluster_width(&self.laid_out[start]) > index - start)
+            .unwrap_or(index)
     }
-}
 
-impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's, P, S> {
-    fn draw<'a, I>(
-        &mut self,
-        content: I,
-    ) -> std::io::Result<()>
-    where
-        I: Iterator<Item = (u16, u16, &'a Cell)>,
-    {
-        let bounds = self.size()?;
+    /// The cells the extra cursors within the grid are drawn in.
+    fn extra_cursor_cells(
+        &self,
+        bounds: Size,
+    ) -> Vec<(usize, CursorStyle, Color)> {
+        self.extra_cursors
+            .iter()
+            .filter(|(x, y, ..)| *x < bounds.width && *y < bounds.height)
+            .map(|&(x, y, style, color)| {
+                (
+                    y as usize * bounds.width as usize + x as usize,
+                    x as usize,
+                    style,
+                    color,
+                )
+            })
+            .filter(|(index, ..)| *index < self.laid_out.len())
+            .map(|(index, column, style, color)| (self.cluster_start(index, column), style, color))
+            .collect()
+    }
 
-        self.cells
-            .resize(bounds.height as usize * bounds.width as usize, Cell::EMPTY);
-        self.sourced.resize_with(
-            bounds.height as usize * bounds.width as usize,
-            Sourced::default,
-        );
-        self.rendered.resize_with(
-            bounds.height as usize * bounds.width as usize,
-            Rendered::default,
-        );
-        self.fast_blinking
-            .resize(bounds.height as usize * bounds.width as usize, false);
-        self.slow_blinking
-            .resize(bounds.height as usize * bounds.width as usize, false);
-        self.dirty_rows.resize(bounds.height as usize, true);
+    /// Whether anything was drawn or changed since the last frame which the
+    /// next flush will render.
+    fn has_pending_changes(&self) -> bool {
+        let bounds = self.grid_size();
+        self.colors_dirty
+            || !self.repaint.is_empty()
+            || self.dirty_rows.len() != bounds.height as usize
+            || self.dirty_rows.contains(&true)
+            || self.cursor_cell(bounds) != self.rendered_cursor
+            || self.extra_cursor_cells(bounds) != self.rendered_extra_cursors
+            || self.selected_cells(bounds) != self.rendered_selection
+    }
 
-        for (x, y, cell) in content {
-            let index = y as usize * bounds.width as usize + x as usize;
+    /// The cells covered by the current selection, with wide characters
+    /// covered entirely.
+    fn selected_cells(
+        &self,
+        bounds: Size,
+    ) -> BitVec {
+        let mut selected = BitVec::repeat(false, self.laid_out.len());
+        let width = bounds.width as usize;
+        match self.selection {
+            None => return selected,
+            Some(Selection::Block(rect)) => {
+                let rect = rect.intersection(Rect::new(0, 0, bounds.width, bounds.height));
+                for y in rect.top()..rect.bottom() {
+                    let row = y as usize * width;
+                    let range = row + rect.left() as usize..row + rect.right() as usize;
+                    if range.end <= selected.len() {
+                        selected[range].fill(true);
+                    }
+                }
+            }
+            Some(Selection::Lines { start, end }) => {
+                let (start, end) = if (start.y, start.x) <= (end.y, end.x) {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+                let index = |pos: Position| {
+                    pos.y as usize * width + pos.x.min(bounds.width.saturating_sub(1)) as usize
+                };
+                let (start, end) = (index(start), (index(end) + 1).min(selected.len()));
+                if start < end {
+                    selected[start..end].fill(true);
+                }
+            }
+        }
 
-            self.fast_blinking
-                .set(index, cell.modifier.contains(Modifier::RAPID_BLINK));
-            self.slow_blinking
-                .set(index, cell.modifier.contains(Modifier::SLOW_BLINK));
+        for index in 0..selected.len().saturating_sub(1) {
+            if index % width != width - 1
+                && self.laid_out[index].symbol().width() > 1
+                && (selected[index] || selected[index + 1])
+            {
+                selected.set(index, true);
+                selected.set(index + 1, true);
+            }
+        }
 
-            self.cells[index] = cell.clone();
+        selected
+    }
 
-            let width = cell.symbol().width().max(1);
-            let start = (index + 1).min(self.cells.len());
-            let end = (index + width).min(self.cells.len());
-            self.cells[start..end].fill(NULL_CELL);
-            self.dirty_rows[y as usize] = true;
+    /// Build the background quad for a cursor drawn on top of the cell at
+    /// `index`.
+    fn cursor_instance(
+        &self,
+        index: usize,
+        style: CursorStyle,
+        color: Color,
+        grid_width: u16,
+    ) -> TextBgInstance {
+        let cell = &self.laid_out[index];
+        let fg_color = if cell.modifier.contains(Modifier::REVERSED) {
+            self.colors.c2c(cell.bg, self.reset_bg)
+        } else {
+            self.colors.c2c(cell.fg, self.reset_fg)
+        };
+        let [r, g, b] = self.colors.c2c(color, fg_color);
+        let bg_color = u32::from_be_bytes([r, g, b, 255]);
+
+        let column = index % grid_width as usize;
+        let row = index / grid_width as usize;
+        // Wide clusters get a cursor as wide as they are, clipped at the edge of
+        // the grid.
+        let cells_wide = cluster_width(cell).min(grid_width as usize - column);
+        let cell_width = self.fonts.cell_width_px() as f32;
+        let cell_height = self.fonts.cell_height_px() as f32;
+        let thickness = (self.fonts.height_px() / 12).max(1) as f32;
+        let x = column as f32 * cell_width;
+        let y = row as f32 * cell_height;
+        let cursor_width = cells_wide as f32 * cell_width;
+        let (x, y, width, height) = match style {
+            CursorStyle::Block => (x, y, cursor_width, cell_height),
+            CursorStyle::Bar => (x, y, thickness.min(cell_width), cell_height),
+            CursorStyle::Underline => (
+                x,
+                y + (cell_height - thickness).max(0.0),
+                cursor_width,
+                thickness.min(cell_height),
+            ),
+        };
+
+        TextBgInstance {
+            position: [x, y],
+            size: [width, height],
+            bg_color,
         }
+    }
 
-        Ok(())
+    /// Recreate the text render target to match the current grid.
+    fn rebuild_text_target(&mut self) {
+        let layout = self.grid_layout();
+        self.wgpu_state =
+            build_wgpu_state(&self.device, self.text_format, layout.width, layout.height);
+
+        if let Some(blit) = &mut self.viewport_blit {
+            blit.target = build_viewport_target(
+                &self.device,
+                &blit.layout,
+                self.viewport,
+                &self.surface_config,
+                &self.wgpu_state.text_dest_view,
+                self.text_format,
+            );
+        }
+
+        let (text_view, post_process_config) = post_process_inputs(
+            self.viewport,
+            self.viewport_blit.as_ref(),
+            &self.wgpu_state.text_dest_view,
+            &self.surface_config,
+        );
+        self.post_process
+            .resize(&self.device, text_view, post_process_config);
+        if self.sample_count > 1 {
+            self.multisample_target = Some(build_multisample_target(
+                &self.device,
+                post_process_config,
+                self.sample_count,
+            ));
+        }
     }
 
-    fn hide_cursor(&mut self) -> std::io::Result<()> {
-        Ok(())
+    /// The position of the top left corner of the text on the surface and the
+    /// factor it is scaled by.
+    fn viewport_placement(&self) -> (u32, u32, u32) {
+        let text = self.wgpu_state.text_dest_view.texture().size();
+        self.viewport.placement(
+            self.surface_config.width,
+            self.surface_config.height,
+            text.width,
+            text.height,
+        )
     }
 
-    fn show_cursor(&mut self) -> std::io::Result<()> {
-        Ok(())
+    /// The size of the drawable area in pixels, excluding any viewport inset or
+    /// margin.
+    fn drawable_size(&self) -> (u32, u32) {
+        self.viewport
+            .drawable_size(self.surface_config.width, self.surface_config.height)
     }
 
-    fn get_cursor_position(&mut self) -> std::io::Result<Position> {
-        Ok(Position::new(self.cursor.0, self.cursor.1))
+    /// How the grid for the current surface and cell size maps onto the text
+    /// texture.
+    fn grid_layout(&self) -> GridLayout {
+        let cell_size = (self.fonts.cell_width_px(), self.fonts.cell_height_px());
+        match self.fixed_grid {
+            Some(grid) => fixed_grid_layout(grid, cell_size),
+            None => grid_layout(self.drawable_size(), cell_size, self.min_cells),
+        }
     }
 
-    fn set_cursor_position<Pos: Into<Position>>(
+    /// Allocate atlas pages until there are `pages` of them.
+    fn add_atlas_pages(
         &mut self,
-        position: Pos,
-    ) -> std::io::Result<()> {
-        let bounds = self.size()?;
-        let pos: Position = position.into();
-        self.cursor = (pos.x.min(bounds.width - 1), pos.y.min(bounds.height - 1));
-        Ok(())
+        pages: usize,
+    ) {
+        while self.atlas_pages.len() < pages {
+            debug!("Adding glyph atlas page {}", self.atlas_pages.len() + 1);
+
+            let page = build_atlas_page(
+                &self.device,
+                self.atlas_pages[0].cache.width(),
+                self.atlas_pages[0].cache.height(),
+            );
+            self.text_fg_compositor
+                .atlas_bindings
+                .push(build_atlas_bindings(
+                    &self.device,
+                    &self.text_fg_compositor.atlas_layout,
+                    &page,
+                    &self.text_fg_compositor.sampler,
+                    &self.text_fg_compositor.atlas_size,
+                ));
+            self.atlas_pages.push(page);
+        }
+        self.text_indices
+            .resize_with(self.atlas_pages.len(), Vec::new);
     }
 
-    fn clear(&mut self) -> std::io::Result<()> {
-        self.cells.clear();
-        self.dirty_rows.clear();
-        self.cursor = (0, 0);
+    /// Rasterize the glyphs for `jobs` and upload them to the atlas. Returns
+    /// the glyphs which weren't rasterized before the deadline passed.
+    fn upload_glyphs(
+        &mut self,
+        jobs: HashMap<Key, RasterJob, RandomState>,
+        deadline: Option<Instant>,
+    ) -> Vec<Key> {
+        let rasterized = rasterize_jobs(
+            &self.fonts,
+            self.subpixel_aa,
+            self.emoji_mode,
+            self.stem_darkening,
+            jobs,
+            deadline,
+        );
+        let mut unrasterized = vec![];
+        let mut uploads = Vec::with_capacity(rasterized.len());
+        for (key, image) in rasterized {
+            match image {
+                Some(image) => {
+                    self.cached.mark_ready(&key);
+                    uploads.push(image);
+                }
+                None => {
+                    unrasterized.push(key);
+                }
+            }
+        }
 
-        Ok(())
+        self.add_atlas_pages(self.cached.pages() as usize);
+
+        self.atlas_uploads += uploads.len();
+        for (cached, image, mask) in uploads {
+            self.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.atlas_pages[cached.page as usize].cache,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: cached.x,
+                        y: cached.y,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                bytemuck::cast_slice(&image),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(cached.width * size_of::<u32>() as u32),
+                    rows_per_image: Some(cached.height),
+                },
+                Extent3d {
+                    width: cached.width,
+                    height: cached.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            self.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.atlas_pages[cached.page as usize].mask,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: cached.x,
+                        y: cached.y,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &vec![if mask { 255 } else { 0 }; (cached.width * cached.height) as usize],
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(cached.width),
+                    rows_per_image: Some(cached.height),
+                },
+                Extent3d {
+                    width: cached.width,
+                    height: cached.height,
+                    depth_or_array_layers: 1,
+                },
+            )
+        }
+
+        unrasterized
     }
 
-    fn size(&self) -> std::io::Result<Size> {
-        let (inset_width, inset_height) = match self.viewport {
-            Viewport::Full => (0, 0),
-            Viewport::Shrink { width, height } => (width, height),
+    /// The background the cell at `index` is drawn with, after the block
+    /// cursors and the selection are applied.
+    fn cell_background(
+        &self,
+        index: usize,
+        cursor: Option<usize>,
+    ) -> CellBackground {
+        let cell = &self.laid_out[index];
+        let reverse = cell.modifier.contains(Modifier::REVERSED);
+        let (fg_color, bg_color) = if reverse {
+            (
+                self.colors.c2c(cell.bg, self.reset_bg),
+                self.colors.c2c(cell.fg, self.reset_fg),
+            )
+        } else {
+            (
+                self.colors.c2c(cell.fg, self.reset_fg),
+                self.colors.c2c(cell.bg, self.reset_bg),
+            )
         };
-        let width = self.surface_config.width - inset_width;
-        let height = self.surface_config.height - inset_height;
 
-        Ok(Size {
-            width: (width / self.fonts.min_width_px()) as u16,
-            height: (height / self.fonts.height_px()) as u16,
-        })
+        // A block cursor swaps the colors of the cell it covers so the text remains
+        // readable. The terminal cursor takes precedence over extra cursors.
+        let block_color = if cursor == Some(index) && self.cursor_style == CursorStyle::Block {
+            Some(self.cursor_color)
+        } else {
+            self.rendered_extra_cursors
+                .iter()
+                .find(|(i, style, _)| *i == index && *style == CursorStyle::Block)
+                .map(|(_, _, color)| *color)
+        }
+        .filter(|_| !self.rendered[index].is_empty());
+        let block_cursor = block_color.is_some();
+        let cursor_fg = block_cursor.then_some(bg_color);
+        let bg_color = match block_color {
+            Some(color) => self.colors.c2c(color, fg_color),
+            None => bg_color,
+        };
+
+        // Transparent cells are cleared to transparent black so that text drawn
+        // over them ends up premultiplied. Cells replace whatever was drawn
+        // before, so translucent backgrounds are premultiplied here rather than
+        // blended. Reversed cells are filled with the foreground color, which
+        // stays opaque even if it's the default.
+        let bg = if reverse { cell.fg } else { cell.bg };
+        let bg_alpha = if block_cursor {
+            255
+        } else if self.transparent_background && !reverse && bg == Color::Reset {
+            0
+        } else {
+            self.background_alpha.get(&bg).copied().unwrap_or(255)
+        };
+        // Selected cells are tinted toward the selection color, which is the same as
+        // blending it over the finished cell. Block cursors stay on top.
+        let selection = (self.rendered_selection[index] && !block_cursor).then(|| {
+            (
+                self.colors.c2c(self.selection_color, fg_color),
+                self.selection_alpha,
+            )
+        });
+        let packed = {
+            let [r, g, b] = bg_color.map(|c| (u32::from(c) * u32::from(bg_alpha) / 255) as u8);
+            let bg = [r, g, b, bg_alpha];
+            let bg = selection.map_or(bg, |(color, alpha)| blend_over(bg, color, alpha));
+            u32::from_be_bytes(bg)
+        };
+
+        CellBackground {
+            color: bg_color,
+            alpha: bg_alpha,
+            packed,
+            cursor_fg,
+            selection,
+        }
     }
 
-    fn window_size(&mut self) -> std::io::Result<WindowSize> {
-        let (inset_width, inset_height) = match self.viewport {
-            Viewport::Full => (0, 0),
-            Viewport::Shrink { width, height } => (width, height),
+    /// The background quads of the cell quad at `position` when backgrounds
+    /// bleed. A full redraw paints the cells in order, so each background
+    /// covers the edges of the cells before it and has its own right and
+    /// bottom edges covered by the cells after it. Rather than overlap, the
+    /// quad is split along those edges and each part takes the background
+    /// which ends up on top, so redrawing any cell leaves its neighbors
+    /// untouched. The cells along the edge of the grid bleed past it.
+    fn bled_backgrounds(
+        &self,
+        [x, y]: [f32; 2],
+        own: u32,
+        cursor: Option<usize>,
+        bounds: Size,
+    ) -> impl Iterator<Item = TextBgInstance> {
+        let (cell_width, cell_height) = (
+            self.fonts.cell_width_px() as f32,
+            self.fonts.cell_height_px() as f32,
+        );
+        let bleed = self.cell_bleed;
+        let (columns, rows) = (bounds.width as usize, bounds.height as usize);
+        let column = ((x.max(0.0) / cell_width) as usize).min(columns - 1);
+        let row = ((y.max(0.0) / cell_height) as usize).min(rows - 1);
+
+        // The part of the quad's span the cell keeps, and the part covered by the
+        // next cell, if there is one.
+        let split = |start: f32, size: f32, first: bool, last: bool| {
+            let (kept, end) = (if first { start - bleed } else { start }, start + size);
+            if last {
+                [(kept, end + bleed), (0.0, 0.0)]
+            } else {
+                [(kept, end - bleed), (end - bleed, end)]
+            }
         };
-        let width = self.surface_config.width - inset_width;
-        let height = self.surface_config.height - inset_height;
+        let spans_x = split(x, cell_width, column == 0, column + 1 == columns);
+        let spans_y = split(y, cell_height, row == 0, row + 1 == rows);
 
-        Ok(WindowSize {
-            columns_rows: Size {
-                width: (width / self.fonts.min_width_px()) as u16,
-                height: (height / self.fonts.height_px()) as u16,
-            },
-            pixels: Size {
-                width: width as u16,
-                height: height as u16,
-            },
-        })
+        let mut backgrounds: [TextBgInstance; 4] = [bytemuck::Zeroable::zeroed(); 4];
+        for (instance, (dx, dy)) in backgrounds.iter_mut().zip([(0, 0), (1, 0), (0, 1), (1, 1)]) {
+            let ((left, right), (top, bottom)) = (spans_x[dx], spans_y[dy]);
+            if right <= left || bottom <= top {
+                continue;
+            }
+
+            let bg_color = if (dx, dy) == (0, 0) {
+                own
+            } else {
+                let index = (row + dy) * columns + column + dx;
+                self.cell_background(self.cluster_start(index, column + dx), cursor)
+                    .packed
+            };
+            *instance = TextBgInstance {
+                position: [left, top],
+                size: [right - left, bottom - top],
+                bg_color,
+            };
+        }
+
+        backgrounds
+            .into_iter()
+            .filter(|instance| instance.size != [0.0, 0.0])
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    /// Consume the changes since the last frame, rasterizing new glyphs and
+    /// rebuilding the background instances and text vertices. Returns whether
+    /// the frame needs to be rendered.
+    fn prepare_frame(&mut self) -> std::io::Result<bool> {
         let bounds = self.size()?;
         self.dirty_cells.clear();
-        self.dirty_cells.resize(self.cells.len(), false);
+        self.dirty_cells
+            .resize(self.laid_out.len(), std::mem::take(&mut self.colors_dirty));
 
-        let fast_toggle_dirty = self.last_fast_toggle.elapsed() >= self.fast_duration;
+        let fast_toggle_dirty =
+            self.text_blink && self.last_fast_toggle.elapsed() >= self.fast_duration;
         if fast_toggle_dirty {
             self.last_fast_toggle = Instant::now();
             self.show_fast = !self.show_fast;
@@ -506,7 +1566,8 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
             }
         }
 
-        let slow_toggle_dirty = self.last_slow_toggle.elapsed() >= self.slow_duration;
+        let slow_toggle_dirty =
+            self.text_blink && self.last_slow_toggle.elapsed() >= self.slow_duration;
         if slow_toggle_dirty {
             self.last_slow_toggle = Instant::now();
             self.show_slow = !self.show_slow;
@@ -516,10 +1577,433 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
             }
         }
 
+        if self.cursor_needs_update() {
+            self.last_cursor_toggle = Instant::now();
+            self.show_cursor = !self.show_cursor;
+        }
+
+        // Redraw the cells the cursor is leaving and entering. The cell before the
+        // cursor is included in case it holds a glyph overhanging into the cursor,
+        // and the cells after it in case the cursor covers a wide cluster.
+        let cursor = self.cursor_cell(bounds);
+        if cursor != self.rendered_cursor {
+            for index in self.rendered_cursor.into_iter().chain(cursor) {
+                self.mark_cursor_dirty(index);
+            }
+            self.rendered_cursor = cursor;
+        }
+
+        let extra_cursors = self.extra_cursor_cells(bounds);
+        if extra_cursors != self.rendered_extra_cursors {
+            for index in self
+                .rendered_extra_cursors
+                .iter()
+                .chain(&extra_cursors)
+                .map(|&(index, ..)| index)
+                .collect::<Vec<_>>()
+            {
+                self.mark_cursor_dirty(index);
+            }
+            self.rendered_extra_cursors = extra_cursors;
+        }
+
+        let selected = self.selected_cells(bounds);
+        for index in 0..selected.len() {
+            if selected[index] != self.rendered_selection.get(index).is_some_and(|b| *b) {
+                self.dirty_cells.set(index, true);
+            }
+        }
+        self.rendered_selection = selected;
+
+        for area in std::mem::take(&mut self.repaint) {
+            let area = area.intersection(Rect::from((Position::ORIGIN, bounds)));
+            for y in area.top()..area.bottom() {
+                for x in area.left()..area.right() {
+                    let index = y as usize * bounds.width as usize + x as usize;
+                    if index < self.dirty_cells.len() {
+                        self.dirty_cells.set(index, true);
+                    }
+                }
+            }
+        }
+
         let mut pending_cache_updates = HashMap::<_, _, RandomState>::default();
 
+        self.cached.next_frame();
+        loop {
+            self.shape_dirty_rows(bounds, &mut pending_cache_updates);
+
+            // Glyphs from earlier frames may have been evicted from the atlas to make room
+            // for new ones. Rows still showing them are shaped again so the glyphs are
+            // rasterized into their new entries.
+            if !self.cached.take_evicted() {
+                break;
+            }
+
+            let mut stale = false;
+            for info in self.rendered.iter().flat_map(IndexMap::values) {
+                if !self.cached.contains(&info.key, &info.cached) {
+                    self.dirty_rows[info.cell / bounds.width as usize] = true;
+                    stale = true;
+                }
+            }
+
+            if !stale {
+                break;
+            }
+        }
+
+        // With bleed, each cell also paints the edges of its own area which the
+        // backgrounds of the cells to the right of and below it bleed over, so those
+        // edges look the same as when every cell is drawn. The cells whose edges a
+        // changed cell bleeds over are redrawn along with it.
+        if self.cell_bleed > 0.0 {
+            let width = bounds.width as usize;
+            for index in self.dirty_cells.iter_ones().collect::<Vec<_>>() {
+                let (column, row) = (index % width, index / width);
+                for (dx, dy) in [(1, 0), (0, 1), (1, 1)] {
+                    if column >= dx && row >= dy {
+                        self.dirty_cells.set(index - dy * width - dx, true);
+                    }
+                }
+            }
+        }
+
+        // Overhanging glyphs are drawn with the cell they belong to, and painting the
+        // background of the next cell clears the overhang. Make sure that both cells
+        // are always redrawn together.
+        for index in 0..self.dirty_cells.len().saturating_sub(1) {
+            if self.dirty_cells[index] && self.overhanging[index] {
+                self.dirty_cells.set(index + 1, true);
+            }
+        }
+        for index in (1..self.dirty_cells.len()).rev() {
+            if self.dirty_cells[index] && self.overhanging[index - 1] {
+                self.dirty_cells.set(index - 1, true);
+            }
+        }
+
+        // Glyphs which aren't rasterized within the frame budget are left blank and
+        // their rows are shaped again during the next flush.
+        let deadline = self
+            .budgeted_rasterization
+            .then(|| Instant::now() + self.rasterization_budget);
+        let unrasterized = self.upload_glyphs(pending_cache_updates, deadline);
+        self.unrasterized.clear();
+        self.unrasterized.extend(unrasterized);
+        if !self.unrasterized.is_empty() {
+            for info in self.rendered.iter().flat_map(IndexMap::values) {
+                if self.unrasterized.contains(&info.key) {
+                    self.dirty_rows[info.cell / bounds.width as usize] = true;
+                }
+            }
+        }
+
+        if !self.post_process.needs_update() && !self.dirty_cells.any() {
+            return Ok(false);
+        }
+
+        self.bg_instances.clear();
+        self.text_vertices.clear();
+        self.text_indices.iter_mut().for_each(Vec::clear);
+
+        let underline_style = match self.underline_style {
+            UnderlineStyle::Straight => 0,
+            UnderlineStyle::Curly => 1,
+            UnderlineStyle::Double => 2,
+            UnderlineStyle::Dotted => 3,
+            UnderlineStyle::Dashed => 4,
+        };
+
+        let mut index_offset = 0;
+        for index in self.dirty_cells.iter_ones() {
+            let to_render = &self.rendered[index];
+            let CellBackground {
+                color: bg_color,
+                alpha: bg_alpha,
+                packed: bg_color_u32,
+                cursor_fg,
+                selection,
+            } = self.cell_background(index, cursor);
+
+            for (
+                (x, y, _),
+                RenderInfo {
+                    cell,
+                    key,
+                    cached,
+                    underline_pos_min,
+                    underline_pos_max,
+                    strikethrough_pos_min,
+                    strikethrough_pos_max,
+                    overhang,
+                    ..
+                },
+            ) in to_render.iter()
+            {
+                let cell = &self.laid_out[*cell];
+                let reverse = cell.modifier.contains(Modifier::REVERSED);
+                let fg_color = if let Some(fg_color) = cursor_fg {
+                    fg_color
+                } else if reverse {
+                    self.colors.c2c(cell.bg, self.reset_bg)
+                } else {
+                    self.colors.c2c(cell.fg, self.reset_fg)
+                };
+
+                // Dim text is blended toward the background it's drawn on. Transparent
+                // backgrounds have no color to blend toward, so the text is faded instead.
+                let dim = cell.modifier.contains(Modifier::DIM);
+                let fg_color = if dim && bg_alpha != 0 {
+                    let mut fg_color = fg_color;
+                    for (fg, bg) in fg_color.iter_mut().zip(bg_color) {
+                        *fg = (f32::from(bg) + (f32::from(*fg) - f32::from(bg)) * self.dim_factor)
+                            .round() as u8;
+                    }
+                    fg_color
+                } else {
+                    fg_color
+                };
+
+                // Glyphs which haven't been rasterized yet are drawn blank.
+                let alpha = if self.unrasterized.contains(key)
+                    | (cell.modifier.contains(Modifier::RAPID_BLINK) & !self.show_fast)
+                    | (cell.modifier.contains(Modifier::SLOW_BLINK) & !self.show_slow)
+                {
+                    0
+                } else if dim && bg_alpha == 0 {
+                    (255.0 * self.dim_factor).round() as u8
+                } else {
+                    255
+                };
+
+                // The cell's underline color is used as is, unless the underline is
+                // drawn under a block cursor.
+                #[cfg(feature = "underline-color")]
+                let underline_color = match cell.underline_color {
+                    Color::Reset => fg_color,
+                    _ if cursor_fg.is_some() => fg_color,
+                    color => self.colors.c2c(color, fg_color),
+                };
+                #[cfg(not(feature = "underline-color"))]
+                let underline_color = fg_color;
+                let (fg_color, underline_color) = match selection {
+                    Some((color, alpha)) => {
+                        let tint = |[r, g, b]: [u8; 3]| {
+                            let [r, g, b, _] = blend_over([r, g, b, 255], color, alpha);
+                            [r, g, b]
+                        };
+                        (tint(fg_color), tint(underline_color))
+                    }
+                    None => (fg_color, underline_color),
+                };
+                let [r, g, b] = fg_color;
+                let fg_color: u32 = u32::from_be_bytes([r, g, b, alpha]);
+
+                let [r, g, b] = underline_color;
+                let underline_color = u32::from_be_bytes([r, g, b, alpha]);
+
+                // Hidden text only draws its background. The vertices are still
+                // pushed to keep them in step with the background instances.
+                let hidden = cell.modifier.contains(Modifier::HIDDEN);
+
+                for offset_x in (0..cached.width).step_by(self.fonts.cell_width_px() as usize) {
+                    if !hidden {
+                        self.text_indices[cached.page as usize].push([
+                            index_offset,     // x, y
+                            index_offset + 1, // x + w, y
+                            index_offset + 2, // x, y + h
+                            index_offset + 2, // x, y + h
+                            index_offset + 3, // x + w, y + h
+                            index_offset + 1, // x + w y
+                        ]);
+                    }
+                    index_offset += 4;
+
+                    let x = *x as f32 + offset_x as f32;
+                    let y = *y as f32;
+                    let uvx = cached.x + offset_x;
+                    let uvy = cached.y;
+
+                    // The overhanging part of a glyph belongs to the next cell, so it
+                    // doesn't get a background or underline. The background quad is
+                    // left degenerate to keep indices shared between both passes.
+                    let overhang_quad =
+                        *overhang && offset_x + self.fonts.cell_width_px() >= cached.width;
+                    if overhang_quad {
+                        self.bg_instances.push(TextBgInstance {
+                            position: [x, y],
+                            size: [0.0, self.fonts.cell_height_px() as f32],
+                            bg_color: bg_color_u32,
+                        });
+                    } else if self.cell_bleed > 0.0 {
+                        let backgrounds =
+                            self.bled_backgrounds([x, y], bg_color_u32, cursor, bounds);
+                        self.bg_instances.extend(backgrounds);
+                    } else {
+                        self.bg_instances.push(TextBgInstance {
+                            position: [x, y],
+                            size: [
+                                self.fonts.cell_width_px() as f32,
+                                self.fonts.cell_height_px() as f32,
+                            ],
+                            bg_color: bg_color_u32,
+                        });
+                    }
+
+                    let underline_pos = if overhang_quad {
+                        0
+                    } else {
+                        (underline_style << UNDERLINE_STYLE_SHIFT)
+                            | ((*underline_pos_min as u32 + uvy) << 16)
+                            | (*underline_pos_max as u32 + uvy)
+                    };
+                    let strikethrough_pos = if overhang_quad {
+                        0
+                    } else {
+                        ((*strikethrough_pos_min as u32 + uvy) << 16)
+                            | (*strikethrough_pos_max as u32 + uvy)
+                    };
+
+                    self.text_vertices.push(TextVertexMember {
+                        vertex: [x, y],
+                        uv: [uvx as f32, uvy as f32],
+                        fg_color,
+                        underline_pos,
+                        underline_color,
+                        strikethrough_pos,
+                        bg_color: bg_color_u32,
+                    });
+                    self.text_vertices.push(TextVertexMember {
+                        vertex: [x + self.fonts.cell_width_px() as f32, y],
+                        uv: [uvx as f32 + self.fonts.cell_width_px() as f32, uvy as f32],
+                        fg_color,
+                        underline_pos,
+                        underline_color,
+                        strikethrough_pos,
+                        bg_color: bg_color_u32,
+                    });
+                    self.text_vertices.push(TextVertexMember {
+                        vertex: [x, y + self.fonts.cell_height_px() as f32],
+                        uv: [uvx as f32, uvy as f32 + self.fonts.cell_height_px() as f32],
+                        fg_color,
+                        underline_pos,
+                        underline_color,
+                        strikethrough_pos,
+                        bg_color: bg_color_u32,
+                    });
+                    self.text_vertices.push(TextVertexMember {
+                        vertex: [
+                            x + self.fonts.cell_width_px() as f32,
+                            y + self.fonts.cell_height_px() as f32,
+                        ],
+                        uv: [
+                            uvx as f32 + self.fonts.cell_width_px() as f32,
+                            uvy as f32 + self.fonts.cell_height_px() as f32,
+                        ],
+                        fg_color,
+                        underline_pos,
+                        underline_color,
+                        strikethrough_pos,
+                        bg_color: bg_color_u32,
+                    });
+                }
+            }
+        }
+
+        // Images are drawn over the repainted cells they cover, clipped to each cell,
+        // since the cells which weren't repainted still show them from earlier frames.
+        self.image_vertices.clear();
+        let (cell_width, cell_height) = (
+            self.fonts.cell_width_px() as f32,
+            self.fonts.cell_height_px() as f32,
+        );
+        let cell_pixels = |x: u16, y: u16| {
+            let (left, top) = (x as f32 * cell_width, y as f32 * cell_height);
+            [left, top, left + cell_width, top + cell_height]
+        };
+        let grid = Rect::from((Position::ORIGIN, bounds));
+        let is_dirty = |dirty_cells: &BitSlice, position: Position| {
+            dirty_cells[position.y as usize * bounds.width as usize + position.x as usize]
+        };
+
+        for image in &mut self.images {
+            let start = self.image_vertices.len() as u32;
+            let image_pixels = [
+                image.area.left() as f32 * cell_width,
+                image.area.top() as f32 * cell_height,
+                image.area.right() as f32 * cell_width,
+                image.area.bottom() as f32 * cell_height,
+            ];
+            for position in image.area.intersection(grid).positions() {
+                let index = (position.y - image.area.y) as usize * image.area.width as usize
+                    + (position.x - image.area.x) as usize;
+                if image.covered[index] && is_dirty(&self.dirty_cells, position) {
+                    push_image_quad(
+                        &mut self.image_vertices,
+                        cell_pixels(position.x, position.y),
+                        image_pixels,
+                    );
+                }
+            }
+            image.vertices = start..self.image_vertices.len() as u32;
+        }
+
+        let layer_placements = self
+            .layer_images
+            .iter()
+            .map(|image| {
+                (
+                    self.placement_pixels(image.placement),
+                    self.placement_cells(image.placement),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (image, (image_pixels, cells)) in self.layer_images.iter_mut().zip(layer_placements) {
+            let start = self.image_vertices.len() as u32;
+            for position in cells.intersection(grid).positions() {
+                if is_dirty(&self.dirty_cells, position) {
+                    push_image_quad(
+                        &mut self.image_vertices,
+                        cell_pixels(position.x, position.y),
+                        image_pixels,
+                    );
+                }
+            }
+            image.vertices = start..self.image_vertices.len() as u32;
+        }
+
+        // Cursors which don't cover the cell are drawn on top of the text. These quads
+        // only exist in the background pass. They're redrawn every frame since
+        // repainting a neighboring cell may paint over them. Extra cursors go first
+        // so the terminal cursor ends up on top.
+        let cursors = self
+            .rendered_extra_cursors
+            .iter()
+            .copied()
+            .chain(cursor.map(|index| (index, self.cursor_style, self.cursor_color)))
+            .filter(|&(index, style, _)| {
+                style != CursorStyle::Block
+                    || !self.dirty_cells[index]
+                    || self.rendered[index].is_empty()
+            })
+            .map(|(index, style, color)| self.cursor_instance(index, style, color, bounds.width))
+            .collect::<Vec<_>>();
+        self.cell_instances = self.bg_instances.len();
+        self.bg_instances.extend(cursors);
+
+        Ok(true)
+    }
+
+    /// Shape the dirty rows into `rendered`, queueing the glyphs which aren't
+    /// in the atlas yet in `pending_cache_updates`.
+    fn shape_dirty_rows(
+        &mut self,
+        bounds: Size,
+        pending_cache_updates: &mut HashMap<Key, RasterJob, RandomState>,
+    ) {
         for (y, (row, sourced)) in self
-            .cells
+            .laid_out
             .chunks(bounds.width as usize)
             .zip(self.sourced.chunks_mut(bounds.width as usize))
             .enumerate()
@@ -530,6 +2014,7 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
 
             self.dirty_rows[y] = false;
             let mut new_sourced = vec![Sourced::default(); bounds.width as usize];
+            let mut new_overhanging = BitVec::<usize>::repeat(false, bounds.width as usize);
 
             // This block concatenates the strings for the row into one string for bidi
             // resolution, then maps bytes for the string to their associated cell index. It
@@ -546,22 +2031,34 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
             }
 
             let mut x = 0;
-            // rustbuzz provides a non-zero x-advance for the first character in a cluster
-            // with combining characters. The remainder of the cluster doesn't account for
-            // this advance, so if we advance prior to rendering them, we end up with all of
-            // the associated characters being offset by a cell. To combat this, we only
-            // bump the x-advance after we've finished processing all of the characters in a
-            // cell. This assumes that we 1) always get a non-zero advance at the beginning
-            // of a cluster and 2) the next cluster in the sequence starts with a non-zero
-            // advance.
+            // A cell's symbol may shape to several glyphs, e.g. a base and its combining
+            // marks, or a glyph per code point when the font has no glyph for a ZWJ
+            // sequence. The pen only moves to the next cell once all glyphs of a cell are
+            // placed, so the glyphs are laid out relative to the start of their cell.
             let mut next_advance = 0;
+            let mut current_cell = None;
+            let mut pen = 0;
+            let mut origin = 0;
+            let mut exact_pen = 0.0;
+            let mut exact_origin = 0.0;
             let mut shape = |font: &Font,
                              fake_bold,
                              fake_italic,
-                             buffer: GlyphBuffer|
+                             buffer: GlyphBuffer,
+                             end: usize|
              -> UnicodeBuffer {
                 let metrics = font.font();
                 let advance_scale = self.fonts.height_px() as f32 / metrics.height() as f32;
+                let (underline_metrics, underline_scale, baseline_shift) =
+                    baseline_metrics(&self.fonts, self.baseline_mode, metrics);
+
+                let mut clusters = buffer
+                    .glyph_infos()
+                    .iter()
+                    .map(|info| info.cluster as usize)
+                    .collect::<Vec<_>>();
+                clusters.sort_unstable();
+                clusters.dedup();
 
                 for (info, position) in buffer
                     .glyph_infos()
@@ -570,58 +2067,110 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                 {
                     let cell_idx = self.rowmap[info.cluster as usize] as usize;
                     let cell = &row[cell_idx];
-                    let max_width = cell.symbol().width();
+                    // A ligature merges the clusters of the characters it replaces and is drawn
+                    // over all of their cells.
+                    let cluster_end = clusters
+                        .get(clusters.partition_point(|&c| c <= info.cluster as usize))
+                        .map_or(end, |&c| c);
+                    let last_cell = self.rowmap[cluster_end - 1] as usize;
+                    let max_width = row[cell_idx..=last_cell]
+                        .iter()
+                        .map(|cell| cell.symbol().width())
+                        .sum::<usize>();
                     let sourced = &mut new_sourced[cell_idx];
 
-                    let basey = y as i32 * self.fonts.height_px() as i32
-                        + (position.y_offset as f32 * advance_scale) as i32;
-                    let mut advance = (position.x_advance as f32 * advance_scale) as i32;
-                    if advance != 0 {
+                    let first_in_cell = current_cell != Some(cell_idx);
+                    if first_in_cell {
+                        current_cell = Some(cell_idx);
                         x += next_advance;
-                        advance =
-                            max_width as i32 * advance.signum() * self.fonts.min_width_px() as i32;
-                        next_advance = advance;
+                        next_advance = max_width as i32 * self.fonts.cell_width_px() as i32;
+                        pen = 0;
+                        origin = 0;
+                        exact_pen = 0.0;
+                        exact_origin = 0.0;
+                    }
+
+                    // Zero width glyphs such as combining marks are positioned relative to the
+                    // glyph they follow.
+                    let exact_advance = position.x_advance as f32 * advance_scale;
+                    let advance = exact_advance as i32;
+                    if advance != 0 {
+                        origin = pen;
+                        pen += advance;
+                        exact_origin = exact_pen;
+                        exact_pen += exact_advance;
+                    }
+                    // Glyphs which would start past the end of the cell are dropped rather than
+                    // drawn over the following cells.
+                    if advance != 0 && origin > 0 && origin >= next_advance {
+                        continue;
                     }
-                    let basex = x + (position.x_offset as f32 * advance_scale) as i32;
+
+                    let basey = y as i32 * self.fonts.cell_height_px() as i32
+                        + (position.y_offset as f32 * advance_scale) as i32;
+                    let basex = x + origin + (position.x_offset as f32 * advance_scale) as i32;
+                    let first_base = advance != 0 && origin == 0;
+
+                    let ch = self.row[info.cluster as usize..].chars().next().unwrap();
+                    // Box drawing and block elements which fill a cell on their own are
+                    // drawn to the exact size of the cell instead of taken from the font.
+                    let builtin = ((self.builtin_box_drawing && box_drawing::is_box_drawing(ch))
+                        || (self.builtin_powerline && box_drawing::is_powerline(ch)))
+                        && cell.symbol().chars().count() == 1;
+                    let (is_color, chars_wide) =
+                        glyph_cells(metrics, info, ch, builtin, max_width, last_cell > cell_idx);
+                    let fake_bold = fake_bold & self.synthetic_bold & !is_color & !builtin;
+                    let fake_italic = fake_italic & self.synthetic_italic & !is_color & !builtin;
+
+                    // Slanted glyphs may lean into the following cell. Atlas entries are at
+                    // most two cells wide, so this is only possible for
+                    // single width glyphs.
+                    let overhang = fake_italic & self.italic_overhang & (chars_wide == 1);
+
+                    // The glyph is drawn at whole pixels, with the remainder of its exact
+                    // position rasterized into the atlas entry.
+                    let (shift, phase) = if self.subpixel_positioning && !is_color && !builtin {
+                        let exact = exact_origin
+                            + position.x_offset as f32 * advance_scale
+                            + chars_wide as f32 * self.fonts.exact_padding_left_px();
+                        let snapped = basex - x + chars_wide as i32 * self.fonts.padding_left_px();
+                        subpixel_phase(exact - snapped as f32)
+                    } else {
+                        (0, 0)
+                    };
+                    let basex = basex + shift;
 
                     // This assumes that we only want to underline the first character in the
                     // cluster, and that the remaining characters are all combining characters
                     // which don't need an underline.
-                    let set = if advance != 0 {
+                    let mut set = if first_base {
                         Modifier::BOLD | Modifier::ITALIC | Modifier::UNDERLINED
                     } else {
                         Modifier::BOLD | Modifier::ITALIC
                     };
-
-                    let key = Key {
-                        style: cell.modifier.intersection(set),
-                        glyph: info.glyph_id,
-                        font: font.id(),
-                    };
-
-                    let ch = self.row[info.cluster as usize..].chars().next().unwrap();
-                    let width = (metrics
-                        .glyph_hor_advance(GlyphId(info.glyph_id as _))
-                        .unwrap_or_default() as f32
-                        * advance_scale) as u32;
-                    let chars_wide = ch.width().unwrap_or(max_width) as u32;
-                    let chars_wide = if chars_wide == 0 { 1 } else { chars_wide };
-                    let width = if width == 0 {
-                        chars_wide * self.fonts.min_width_px()
-                    } else {
-                        width
-                    };
+                    // Glyphs which aren't synthesized can share an atlas entry with the regular
+                    // style.
+                    set.set(Modifier::BOLD, fake_bold);
+                    set.set(Modifier::ITALIC, fake_italic);
+
+                    let key = glyph_key(
+                        font,
+                        info,
+                        builtin.then_some(ch),
+                        cell.modifier.intersection(set),
+                        phase,
+                    );
 
                     let cached = self.cached.get(
                         &key,
-                        chars_wide * self.fonts.min_width_px(),
-                        self.fonts.height_px(),
+                        (chars_wide + u32::from(overhang)) * self.fonts.cell_width_px(),
+                        self.fonts.cell_height_px(),
                     );
 
-                    let offset = (basey.max(0) as usize / self.fonts.height_px() as usize)
+                    let offset = (basey.max(0) as usize / self.fonts.cell_height_px() as usize)
                         .min(bounds.height as usize - 1)
                         * bounds.width as usize
-                        + (basex.max(0) as usize / self.fonts.min_width_px() as usize)
+                        + (basex.max(0) as usize / self.fonts.cell_width_px() as usize)
                             .min(bounds.width as usize - 1);
 
                     sourced.insert((basex, basey, GlyphId(info.glyph_id as _), chars_wide));
@@ -629,26 +2178,82 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                     let mut underline_pos_min = 0;
                     let mut underline_pos_max = 0;
                     if key.style.contains(Modifier::UNDERLINED) {
-                        let underline_position = (metrics.ascender() as f32 * advance_scale) as u16;
-                        let underline_thickness = metrics
-                            .underline_metrics()
-                            .map(|m| (m.thickness as f32 * advance_scale) as u16)
-                            .unwrap_or(1);
+                        let scale_factor = self.scale_factor as f32;
+                        let underline_position = (underline_metrics.ascender() as f32
+                            * underline_scale
+                            + self.fonts.padding_top_px() as f32
+                            + self.underline_offset.unwrap_or_default() * scale_factor)
+                            .clamp(0.0, self.fonts.cell_height_px().saturating_sub(1) as f32)
+                            as u16;
+                        let underline_thickness = match self.underline_thickness {
+                            Some(thickness) => (thickness * scale_factor).round().max(1.0) as u16,
+                            None => underline_metrics
+                                .underline_metrics()
+                                .map(|m| (m.thickness as f32 * underline_scale) as u16)
+                                .unwrap_or(1),
+                        };
+                        let underline_height = match self.underline_style {
+                            // Leave room for the wave or second line, but keep it within the
+                            // cell.
+                            UnderlineStyle::Curly | UnderlineStyle::Double => {
+                                (underline_thickness.max(1) * 3).min(
+                                    (self.fonts.cell_height_px() as u16)
+                                        .saturating_sub(underline_position),
+                                )
+                            }
+                            UnderlineStyle::Straight
+                            | UnderlineStyle::Dotted
+                            | UnderlineStyle::Dashed => underline_thickness,
+                        };
                         underline_pos_min = underline_position;
-                        underline_pos_max = underline_pos_min + underline_thickness;
+                        underline_pos_max = underline_pos_min + underline_height;
+                    }
+
+                    let mut strikethrough_pos_min = 0;
+                    let mut strikethrough_pos_max = 0;
+                    if first_base && cell.modifier.contains(Modifier::CROSSED_OUT) {
+                        // Fonts without strikeout metrics get a line through the middle of
+                        // the x-height.
+                        let (position, thickness) = metrics
+                            .strikeout_metrics()
+                            .map(|m| (m.position as f32, m.thickness as f32))
+                            .unwrap_or_else(|| {
+                                (
+                                    metrics.x_height().unwrap_or(metrics.ascender() / 2) as f32
+                                        / 2.0,
+                                    metrics
+                                        .underline_metrics()
+                                        .map(|m| m.thickness as f32)
+                                        .unwrap_or_default(),
+                                )
+                            });
+                        strikethrough_pos_min = ((metrics.ascender() as f32 - position)
+                            * advance_scale
+                            + (self.fonts.padding_top_px() + baseline_shift) as f32)
+                            .max(0.0) as u16;
+                        strikethrough_pos_max =
+                            strikethrough_pos_min + ((thickness * advance_scale) as u16).max(1);
                     }
 
                     self.rendered[offset].insert(
                         (basex, basey, GlyphId(info.glyph_id as _)),
                         RenderInfo {
                             cell: y * bounds.width as usize + cell_idx,
+                            key,
                             cached: *cached,
                             underline_pos_min,
                             underline_pos_max,
+                            strikethrough_pos_min,
+                            strikethrough_pos_max,
+                            overhang,
                         },
                     );
-                    for x_offset in 0..chars_wide as usize {
-                        self.dirty_cells.set(offset + x_offset, true);
+                    // A wide glyph in the last column is clipped at the edge of the grid.
+                    let row_end = (offset / bounds.width as usize + 1) * bounds.width as usize;
+                    self.dirty_cells[offset..(offset + chars_wide as usize).min(row_end)]
+                        .fill(true);
+                    if overhang {
+                        new_overhanging.set(cell_idx, true);
                     }
 
                     if cached.cached() {
@@ -656,19 +2261,20 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                     }
 
                     pending_cache_updates.entry(key).or_insert_with(|| {
-                        let is_emoji = ch.is_emoji_char()
-                            && !matches!(ch.general_category_group(), GeneralCategoryGroup::Number);
-
-                        let (rect, image) = rasterize_glyph(
-                            cached,
-                            metrics,
+                        raster_job(
+                            &self.fonts,
+                            font,
                             info,
-                            fake_italic & !is_emoji,
-                            fake_bold & !is_emoji,
-                            advance_scale,
-                            width,
-                        );
-                        (rect, image, is_emoji)
+                            &key,
+                            cached,
+                            Synthetic {
+                                bold: fake_bold,
+                                italic: fake_italic,
+                                italic_overhang: overhang,
+                            },
+                            chars_wide,
+                            baseline_shift,
+                        )
                     });
                 }
 
@@ -680,6 +2286,7 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
 
             let (mut current_font, mut current_fake_bold, mut current_fake_italic) = fontmap[0];
             let mut current_level = Level::ltr();
+            let mut buffer_end = 0;
 
             for (level, range) in runs.into_iter().map(|run| (levels[run.start], run)) {
                 let chars = &self.row[range.clone()];
@@ -704,6 +2311,7 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                                 self.plan_cache.get(current_font, &mut buffer),
                                 buffer,
                             ),
+                            buffer_end,
                         );
 
                         current_font = font;
@@ -713,6 +2321,7 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                     }
 
                     self.buffer.add(ch, (range.start + idx) as u32);
+                    buffer_end = range.start + idx + ch.len_utf8();
                 }
             }
 
@@ -726,15 +2335,16 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                     self.plan_cache.get(current_font, &mut buffer),
                     buffer,
                 ),
+                buffer_end,
             );
 
             for (new, old) in new_sourced.into_iter().zip(sourced.iter_mut()) {
                 if new != *old {
                     for (x, y, glyph, width) in old.difference(&new) {
-                        let cell = ((*y).max(0) as usize / self.fonts.height_px() as usize)
+                        let cell = ((*y).max(0) as usize / self.fonts.cell_height_px() as usize)
                             .min(bounds.height as usize - 1)
                             * bounds.width as usize
-                            + ((*x).max(0) as usize / self.fonts.min_width_px() as usize)
+                            + ((*x).max(0) as usize / self.fonts.cell_width_px() as usize)
                                 .min(bounds.width as usize - 1);
 
                         for offset_x in 0..*width as usize {
@@ -750,656 +2360,7913 @@ impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's,
                     *old = new;
                 }
             }
-        }
 
-        for (_, (cached, image, mask)) in pending_cache_updates {
-            self.queue.write_texture(
-                wgpu::TexelCopyTextureInfo {
-                    texture: &self.text_cache,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: cached.x,
-                        y: cached.y,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
-                },
-                bytemuck::cast_slice(&image),
-                wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(cached.width * size_of::<u32>() as u32),
-                    rows_per_image: Some(cached.height),
-                },
-                Extent3d {
-                    width: cached.width,
-                    height: cached.height,
-                    depth_or_array_layers: 1,
-                },
-            );
-
-            self.queue.write_texture(
-                wgpu::TexelCopyTextureInfo {
-                    texture: &self.text_mask,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: cached.x,
-                        y: cached.y,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
-                },
-                &vec![if mask { 255 } else { 0 }; (cached.width * cached.height) as usize],
-                wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(cached.width),
-                    rows_per_image: Some(cached.height),
-                },
-                Extent3d {
-                    width: cached.width,
-                    height: cached.height,
-                    depth_or_array_layers: 1,
-                },
-            )
+            // The area a glyph overhangs into must be repainted when the glyph is added or
+            // removed.
+            let row_start = y * bounds.width as usize;
+            let row_overhanging =
+                &mut self.overhanging[row_start..row_start + bounds.width as usize];
+            for idx in row_overhanging
+                .iter_ones()
+                .chain(new_overhanging.iter_ones())
+            {
+                if idx + 1 < bounds.width as usize {
+                    self.dirty_cells.set(row_start + idx + 1, true);
+                }
+            }
+            row_overhanging.copy_from_bitslice(&new_overhanging);
         }
+    }
 
-        if self.post_process.needs_update() || self.dirty_cells.any() {
-            self.bg_vertices.clear();
-            self.text_vertices.clear();
-            self.text_indices.clear();
+    /// Encode the background and text passes for the most recently prepared
+    /// frame, drawing into `target`. `load` is applied to the target first,
+    /// and `blend_backgrounds` blends the cell backgrounds over it instead of
+    /// replacing it.
+    fn encode_text(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        load: LoadOp<wgpu::Color>,
+        blend_backgrounds: bool,
+    ) {
+        if self.bg_instances.is_empty() {
+            return;
+        }
 
-            let mut index_offset = 0;
-            for index in self.dirty_cells.iter_ones() {
-                let cell = &self.cells[index];
-                let to_render = &self.rendered[index];
+        let layout = self.grid_layout();
+        {
+            let mut uniforms = self
+                .queue
+                .write_buffer_with(
+                    &self.text_screen_size_buffer,
+                    0,
+                    NonZeroU64::new(size_of::<[f32; 4]>() as u64).unwrap(),
+                )
+                .unwrap();
+            uniforms.copy_from_slice(bytemuck::cast_slice(&[
+                layout.width as f32,
+                layout.height as f32,
+                layout.crop_x as f32,
+                layout.crop_y as f32,
+            ]));
+        }
 
-                let reverse = cell.modifier.contains(Modifier::REVERSED);
-                let bg_color = if reverse {
-                    self.colors.c2c(cell.fg, self.reset_fg)
-                } else {
-                    self.colors.c2c(cell.bg, self.reset_bg)
-                };
+        let bg_instances = self.bg_instance_buffer.write(
+            &self.device,
+            &self.queue,
+            bytemuck::cast_slice(&self.bg_instances),
+        );
+        // Hidden text has vertices but no indices, and empty buffers can't be bound.
+        let fg = self
+            .text_indices
+            .iter()
+            .any(|indices| !indices.is_empty())
+            .then(|| {
+                (
+                    self.text_vertex_buffer.write(
+                        &self.device,
+                        &self.queue,
+                        bytemuck::cast_slice(&self.text_vertices),
+                    ),
+                    self.index_buffer.write(
+                        &self.device,
+                        &self.queue,
+                        bytemuck::cast_slice(&self.text_indices.concat()),
+                    ),
+                )
+            });
 
-                let [r, g, b] = bg_color;
-                let bg_color_u32: u32 = u32::from_be_bytes([r, g, b, 255]);
+        let image_vertices = (!self.image_vertices.is_empty()).then(|| {
+            self.image_vertex_buffer.write(
+                &self.device,
+                &self.queue,
+                bytemuck::cast_slice(&self.image_vertices),
+            )
+        });
 
-                for (
-                    (x, y, _),
-                    RenderInfo {
-                        cell,
-                        cached,
-                        underline_pos_min,
-                        underline_pos_max,
+        {
+            let mut text_render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Text Render Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load,
+                        store: StoreOp::Store,
                     },
-                ) in to_render.iter()
-                {
-                    let cell = &self.cells[*cell];
-                    let reverse = cell.modifier.contains(Modifier::REVERSED);
-                    let fg_color = if reverse {
-                        self.colors.c2c(cell.bg, self.reset_bg)
-                    } else {
-                        self.colors.c2c(cell.fg, self.reset_fg)
-                    };
-
-                    let alpha = if cell.modifier.contains(Modifier::HIDDEN)
-                        | (cell.modifier.contains(Modifier::RAPID_BLINK) & !self.show_fast)
-                        | (cell.modifier.contains(Modifier::SLOW_BLINK) & !self.show_slow)
-                    {
-                        0
-                    } else if cell.modifier.contains(Modifier::DIM) {
-                        127
-                    } else {
-                        255
-                    };
-
-                    let underline_color = fg_color;
-                    let [r, g, b] = fg_color;
-                    let fg_color: u32 = u32::from_be_bytes([r, g, b, alpha]);
-
-                    let [r, g, b] = underline_color;
-                    let underline_color = u32::from_be_bytes([r, g, b, alpha]);
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
 
-                    for offset_x in (0..cached.width).step_by(self.fonts.min_width_px() as usize) {
-                        self.text_indices.push([
-                            index_offset,     // x, y
-                            index_offset + 1, // x + w, y
-                            index_offset + 2, // x, y + h
-                            index_offset + 2, // x, y + h
-                            index_offset + 3, // x + w, y + h
-                            index_offset + 1, // x + w y
-                        ]);
-                        index_offset += 4;
+            let cell_quads = self.cell_instances as u32;
+            let all_quads = self.bg_instances.len() as u32;
+            let bg_compositor = &self.text_bg_compositor;
+            let bg_pipeline = if blend_backgrounds {
+                &bg_compositor.blended_pipeline
+            } else {
+                &bg_compositor.pipeline
+            };
+            let draw_bg = |pass: &mut RenderPass, instances: Range<u32>| {
+                pass.set_pipeline(bg_pipeline);
+                pass.set_bind_group(0, &bg_compositor.fs_uniforms, &[]);
+                pass.set_vertex_buffer(0, bg_compositor.quad_vertices.slice(..));
+                pass.set_vertex_buffer(1, bg_instances);
+                pass.set_index_buffer(bg_compositor.quad_indices.slice(..), IndexFormat::Uint32);
+                pass.draw_indexed(0..6, 0, instances);
+            };
 
-                        let x = *x as f32 + offset_x as f32;
-                        let y = *y as f32;
-                        let uvx = cached.x + offset_x;
-                        let uvy = cached.y;
+            if cell_quads > 0 {
+                draw_bg(&mut text_render_pass, 0..cell_quads);
+            }
 
-                        self.bg_vertices.push(TextBgVertexMember {
-                            vertex: [x, y],
-                            bg_color: bg_color_u32,
-                        });
-                        self.bg_vertices.push(TextBgVertexMember {
-                            vertex: [x + self.fonts.min_width_px() as f32, y],
-                            bg_color: bg_color_u32,
-                        });
-                        self.bg_vertices.push(TextBgVertexMember {
-                            vertex: [x, y + self.fonts.height_px() as f32],
-                            bg_color: bg_color_u32,
-                        });
-                        self.bg_vertices.push(TextBgVertexMember {
-                            vertex: [
-                                x + self.fonts.min_width_px() as f32,
-                                y + self.fonts.height_px() as f32,
-                            ],
-                            bg_color: bg_color_u32,
-                        });
+            // The image layer's images with a negative z index go between the
+            // backgrounds and the text.
+            if let Some(image_vertices) = image_vertices {
+                let mut under = self
+                    .layer_images
+                    .iter()
+                    .take_while(|image| image.z_index < 0)
+                    .filter(|image| !image.vertices.is_empty())
+                    .peekable();
+                if under.peek().is_some() {
+                    text_render_pass.set_pipeline(&self.image_compositor.pipeline);
+                    text_render_pass.set_bind_group(0, &self.image_compositor.fs_uniforms, &[]);
+                    text_render_pass.set_vertex_buffer(0, image_vertices);
+                }
+                for image in under {
+                    text_render_pass.set_bind_group(1, &image.bindings, &[]);
+                    text_render_pass.draw(image.vertices.clone(), 0..1);
+                }
+            }
 
-                        let underline_pos = ((*underline_pos_min as u32 + uvy) << 16)
-                            | (*underline_pos_max as u32 + uvy);
+            if let Some((fg_vertices, indices)) = fg {
+                text_render_pass.set_pipeline(&self.text_fg_compositor.pipeline);
+                text_render_pass.set_bind_group(0, &self.text_fg_compositor.fs_uniforms, &[]);
+                text_render_pass.set_vertex_buffer(0, fg_vertices);
+                text_render_pass.set_index_buffer(indices, IndexFormat::Uint32);
 
-                        self.text_vertices.push(TextVertexMember {
-                            vertex: [x, y],
-                            uv: [uvx as f32, uvy as f32],
-                            fg_color,
-                            underline_pos,
-                            underline_color,
-                        });
-                        self.text_vertices.push(TextVertexMember {
-                            vertex: [x + self.fonts.min_width_px() as f32, y],
-                            uv: [uvx as f32 + self.fonts.min_width_px() as f32, uvy as f32],
-                            fg_color,
-                            underline_pos,
-                            underline_color,
-                        });
-                        self.text_vertices.push(TextVertexMember {
-                            vertex: [x, y + self.fonts.height_px() as f32],
-                            uv: [uvx as f32, uvy as f32 + self.fonts.height_px() as f32],
-                            fg_color,
-                            underline_pos,
-                            underline_color,
-                        });
-                        self.text_vertices.push(TextVertexMember {
-                            vertex: [
-                                x + self.fonts.min_width_px() as f32,
-                                y + self.fonts.height_px() as f32,
-                            ],
-                            uv: [
-                                uvx as f32 + self.fonts.min_width_px() as f32,
-                                uvy as f32 + self.fonts.height_px() as f32,
-                            ],
-                            fg_color,
-                            underline_pos,
-                            underline_color,
-                        });
+                let mut start = 0;
+                for (indices, atlas_bindings) in self
+                    .text_indices
+                    .iter()
+                    .zip(&self.text_fg_compositor.atlas_bindings)
+                {
+                    let end = start + indices.len() as u32 * 6;
+                    if end > start {
+                        text_render_pass.set_bind_group(1, atlas_bindings, &[]);
+                        text_render_pass.draw_indexed(start..end, 0, 0..1);
                     }
+                    start = end;
                 }
             }
 
-            self.render();
-        }
+            // Inline images and the image layer's images with a non negative z index.
+            if let Some(image_vertices) = image_vertices {
+                text_render_pass.set_pipeline(&self.image_compositor.pipeline);
+                text_render_pass.set_bind_group(0, &self.image_compositor.fs_uniforms, &[]);
+                text_render_pass.set_vertex_buffer(0, image_vertices);
+                let images = self
+                    .images
+                    .iter()
+                    .map(|image| (&image.bindings, &image.vertices))
+                    .chain(
+                        self.layer_images
+                            .iter()
+                            .filter(|image| image.z_index >= 0)
+                            .map(|image| (&image.bindings, &image.vertices)),
+                    );
+                for (bindings, vertices) in images.filter(|(_, vertices)| !vertices.is_empty()) {
+                    text_render_pass.set_bind_group(1, bindings, &[]);
+                    text_render_pass.draw(vertices.clone(), 0..1);
+                }
+            }
 
-        Ok(())
+            // Anything past the text is a cursor drawn over the top of it.
+            if all_quads > cell_quads {
+                draw_bg(&mut text_render_pass, cell_quads..all_quads);
+            }
+        }
     }
 
-    fn clear_region(
-        &mut self,
-        clear_type: ClearType,
-    ) -> std::io::Result<()> {
-        let bounds = self.size()?;
-        let line_start = self.cursor.1 as usize * bounds.width as usize;
-        let idx = line_start + self.cursor.0 as usize;
+    fn render(&mut self) -> crate::Result<()> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Draw Encoder"),
+            });
 
-        match clear_type {
-            ClearType::All => self.clear(),
-            ClearType::AfterCursor => {
-                self.cells.truncate(idx + 1);
-                Ok(())
-            }
-            ClearType::BeforeCursor => {
-                self.cells[..idx].fill(Cell::EMPTY);
-                Ok(())
-            }
-            ClearType::CurrentLine => {
-                self.cells[line_start..line_start + bounds.width as usize].fill(Cell::EMPTY);
-                Ok(())
-            }
-            ClearType::UntilNewLine => {
-                let remain = (bounds.width - self.cursor.0) as usize;
-                self.cells[idx..idx + remain].fill(Cell::EMPTY);
-                Ok(())
+        let text_target = self.wgpu_state.text_target_view.clone();
+        let timer = self.gpu_timer.as_mut().and_then(|timer| {
+            if let Some(gpu_time) = timer.finished() {
+                self.frame_stats.gpu_time = Some(gpu_time);
             }
+            timer.is_idle().then_some(&*timer)
+        });
+        let measured = timer.is_some();
+        if let Some(timer) = timer {
+            timer.write_timestamp(&mut encoder, &text_target, 0);
         }
-    }
-}
+        // Only changed cells are drawn, over the previous frame.
+        self.encode_text(&mut encoder, &text_target, LoadOp::Load, false);
+
+        let Some(texture) =
+            self.surface
+                .get_current_texture(&self.device, &self.surface_config, Token)?
+        else {
+            return Ok(());
+        };
 
-fn rasterize_glyph(
-    cached: Entry,
-    metrics: &rustybuzz::Face,
-    info: &rustybuzz::GlyphInfo,
-    fake_italic: bool,
-    fake_bold: bool,
-    advance_scale: f32,
-    actual_width: u32,
-) -> (CacheRect, Vec<u32>) {
-    let scale = cached.width as f32 / actual_width as f32;
-    let computed_offset_x = -(cached.width as f32 * (1.0 - scale));
-    let computed_offset_y = cached.height as f32 * (1.0 - scale);
-    let scale = scale * advance_scale * 2.0;
+        let (text_view, post_process_config) = post_process_inputs(
+            self.viewport,
+            self.viewport_blit.as_ref(),
+            &self.wgpu_state.text_dest_view,
+            &self.surface_config,
+        );
 
-    let skew = if fake_italic {
-        Transform::new(
-            /* scale x */ 1.0,
-            /* skew x */ 0.0,
-            /* skew y */ -0.25,
-            /* scale y */ 1.0,
-            /* translate x */ -0.25 * cached.width as f32,
-            /* translate y */ 0.0,
-        )
-    } else {
-        Transform::default()
-    };
+        let Some(blit) = &self.viewport_blit else {
+            if let Some(callback) = &mut self.pre_process_callback {
+                callback(&mut encoder, text_view);
+            }
+            post_process_into(
+                &mut self.post_process,
+                self.multisample_target.as_ref(),
+                &mut encoder,
+                &self.queue,
+                text_view,
+                post_process_config,
+                texture.get_view(Token),
+            );
+            if let Some(callback) = &mut self.overlay_callback {
+                callback(&mut encoder, texture.get_view(Token));
+            }
 
-    let mut image = vec![0u32; cached.width as usize * 2 * cached.height as usize * 2];
-    let mut target = DrawTarget::from_backing(
-        cached.width as i32 * 2,
-        cached.height as i32 * 2,
-        &mut image[..],
-    );
+            self.submit_frame(encoder, measured);
+            texture.present(Token);
+            return Ok(());
+        };
 
-    let mut painter = Painter::new(
-        metrics,
-        &mut target,
-        skew,
-        scale,
-        metrics.ascender() as f32 * scale + computed_offset_y,
-        computed_offset_x,
-    );
-    if metrics
-        .paint_color_glyph(
-            GlyphId(info.glyph_id as _),
-            0,
-            RgbaColor::new(255, 255, 255, 255),
-            &mut painter,
-        )
-        .is_some()
-    {
-        let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
-        final_image.draw_image_with_size_at(
-            cached.width as f32,
-            cached.height as f32,
-            0.,
-            0.,
-            &raqote::Image {
-                width: cached.width as i32 * 2,
-                height: cached.height as i32 * 2,
-                data: &image,
-            },
-            &DrawOptions {
-                blend_mode: raqote::BlendMode::Src,
-                antialias: raqote::AntialiasMode::None,
+        // The text texture holds srgb values as is, only the surface might need
+        // them linearized.
+        let clear_color = (!self.transparent_background || self.clear_color != Color::Reset)
+            .then(|| self.colors.c2c(self.clear_color, self.reset_bg));
+        let margin = |linearize: bool| clear_value(clear_color, linearize);
+
+        let (x, y, scale) = self.viewport_placement();
+        let blit_to = |encoder: &mut CommandEncoder,
+                       view: &TextureView,
+                       clear: wgpu::Color,
+                       (width, height): (u32, u32)| {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Viewport Blit Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(clear),
+                        store: StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
                 ..Default::default()
-            },
-        );
+            });
 
-        let mut final_image = final_image.into_vec();
-        for argb in final_image.iter_mut() {
-            let [a, r, g, b] = argb.to_be_bytes();
-            *argb = u32::from_le_bytes([r, g, b, a]);
+            pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            pass.set_pipeline(&blit.pipeline);
+            pass.set_bind_group(0, &blit.target.bindings, &[]);
+            pass.draw(0..3, 0..1);
+        };
+
+        if let Viewport::IntegerScale { .. } = self.viewport {
+            let text = self.wgpu_state.text_dest_view.texture().size();
+            blit_to(
+                &mut encoder,
+                &blit.target.view,
+                margin(false),
+                (text.width * scale, text.height * scale),
+            );
+            if let Some(callback) = &mut self.pre_process_callback {
+                callback(&mut encoder, text_view);
+            }
+            post_process_into(
+                &mut self.post_process,
+                self.multisample_target.as_ref(),
+                &mut encoder,
+                &self.queue,
+                text_view,
+                post_process_config,
+                texture.get_view(Token),
+            );
+        } else {
+            if let Some(callback) = &mut self.pre_process_callback {
+                callback(&mut encoder, text_view);
+            }
+            post_process_into(
+                &mut self.post_process,
+                self.multisample_target.as_ref(),
+                &mut encoder,
+                &self.queue,
+                text_view,
+                post_process_config,
+                &blit.target.view,
+            );
+            blit_to(
+                &mut encoder,
+                texture.get_view(Token),
+                margin(expects_linear(self.surface_config.format)),
+                (blit.target.config.width, blit.target.config.height),
+            );
+        }
+        if let Some(callback) = &mut self.overlay_callback {
+            callback(&mut encoder, texture.get_view(Token));
         }
 
-        return (*cached, final_image);
+        self.submit_frame(encoder, measured);
+        texture.present(Token);
+        Ok(())
     }
 
-    if let Some(raster) = metrics.glyph_raster_image(GlyphId(info.glyph_id as _), u16::MAX) {
-        if let Some(value) = extract_color_image(&mut image, raster, cached, advance_scale) {
-            return value;
+    /// Submit the frame's passes, finishing its gpu time measurement if
+    /// `measured`.
+    fn submit_frame(
+        &mut self,
+        mut encoder: CommandEncoder,
+        measured: bool,
+    ) {
+        let timer = self.gpu_timer.as_mut().filter(|_| measured);
+        if let Some(timer) = &timer {
+            timer.write_timestamp(&mut encoder, &self.wgpu_state.text_target_view, 1);
+            timer.resolve(&mut encoder);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        if let Some(timer) = timer {
+            timer.map();
         }
     }
 
-    let mut render = Outline::default();
-    if let Some(bounds) = metrics.outline_glyph(GlyphId(info.glyph_id as _), &mut render) {
-        let path = render.finish();
+    /// Draw `content` and encode the text compositor passes into `encoder`,
+    /// rendering the whole grid into `target`. Unlike [`Backend::flush`], this
+    /// doesn't acquire or present the surface and skips the [`PostProcessor`],
+    /// so the terminal can be layered into the caller's own rendering. Only
+    /// the queue's buffer and texture writes are performed, submitting the
+    /// encoder is up to the caller.
+    ///
+    /// `target` must have the format of [`WgpuBackend::text_texture_view`], or
+    /// its srgb equivalent if the backend was built with an srgb
+    /// [`Builder::with_text_format`](crate::Builder::with_text_format). The
+    /// grid covers the whole target, so it should be the size of
+    /// [`WgpuBackend::text_texture_view`] to avoid stretching the cells. See
+    /// [`Builder::with_premultiplied_alpha`](crate::Builder::with_premultiplied_alpha)
+    /// for how to blend the result over other content.
+    ///
+    /// `clear` decides whether the target is cleared first or the grid is
+    /// layered over its contents, see [`ClearPolicy`].
+    ///
+    /// Backends which only render this way never need to call
+    /// [`Backend::flush`]. Mixing both is fine, the next flush redraws the
+    /// whole grid into the backend's own texture.
+    pub fn render_text<'a, I>(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear: ClearPolicy,
+        content: I,
+    ) -> std::io::Result<()>
+    where
+        I: IntoIterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.draw(content.into_iter())?;
+
+        // The target's previous contents are unknown, so every cell is drawn.
+        self.colors_dirty = true;
+        if self.prepare_frame()? {
+            // Backgrounds are blended, which only replaces the transparent black a
+            // reset clear leaves.
+            let load = match clear {
+                ClearPolicy::Clear(color) => {
+                    let color =
+                        (color != Color::Reset).then(|| self.colors.c2c(color, self.reset_bg));
+                    LoadOp::Clear(clear_value(color, target.texture().format().is_srgb()))
+                }
+                ClearPolicy::Load => LoadOp::Load,
+            };
+            self.encode_text(encoder, target, load, true);
+        }
+        self.colors_dirty = true;
 
-        // Some fonts return bounds that are entirely negative. I'm not sure why this
-        // is, but it means the glyph won't render at all. We check for this here and
-        // offset it if so. This seems to let those fonts render correctly.
-        let x_off = if bounds.x_max < 0 {
-            -bounds.x_min as f32
-        } else {
+        Ok(())
+    }
+
+    /// Rasterize the glyphs for the characters of `text` and upload them to the
+    /// atlas ahead of time, e.g. the printable ascii range right after the
+    /// backend is built, so the first frames showing them don't have to. Each
+    /// character is shaped on its own in the regular style, with the fonts and
+    /// fallbacks a cell holding it would use. Characters whose glyphs are
+    /// already cached are skipped.
+    ///
+    /// The glyphs are rasterized immediately, regardless of
+    /// [`Builder::with_budgeted_rasterization`](crate::Builder::with_budgeted_rasterization).
+    /// Like any other glyphs, they may be evicted once the atlas fills up.
+    pub fn preload_glyphs(
+        &mut self,
+        text: &str,
+    ) {
+        let mut jobs = HashMap::<_, _, RandomState>::default();
+        let mut cell = Cell::default();
+        for ch in text.chars() {
+            // Control characters are never drawn.
+            let Some(cell_width) = ch.width() else {
+                continue;
+            };
+            cell.set_char(ch);
+            let (font, ..) = self.fonts.font_for_cell(&cell);
+            let metrics = font.font();
+            let (.., baseline_shift) = baseline_metrics(&self.fonts, self.baseline_mode, metrics);
+
+            let mut buffer = std::mem::take(&mut self.buffer);
+            buffer.add(ch, 0);
+            let buffer = shape_with_plan(metrics, self.plan_cache.get(font, &mut buffer), buffer);
+            for info in buffer.glyph_infos() {
+                // The same decisions as when shaping a row, for a cell on its own.
+                let builtin = (self.builtin_box_drawing && box_drawing::is_box_drawing(ch))
+                    || (self.builtin_powerline && box_drawing::is_powerline(ch));
+                let (is_color, chars_wide) =
+                    glyph_cells(metrics, info, ch, builtin, cell_width, false);
+                let phase = if self.subpixel_positioning && !is_color && !builtin {
+                    subpixel_phase(
+                        chars_wide as f32
+                            * (self.fonts.exact_padding_left_px()
+                                - self.fonts.padding_left_px() as f32),
+                    )
+                    .1
+                } else {
+                    0
+                };
+                let key = glyph_key(font, info, builtin.then_some(ch), Modifier::empty(), phase);
+
+                let cached = self.cached.get(
+                    &key,
+                    chars_wide * self.fonts.cell_width_px(),
+                    self.fonts.cell_height_px(),
+                );
+                if cached.cached() {
+                    continue;
+                }
+
+                jobs.entry(key).or_insert_with(|| {
+                    raster_job(
+                        &self.fonts,
+                        font,
+                        info,
+                        &key,
+                        cached,
+                        // Regular text is never synthesized.
+                        Synthetic {
+                            bold: false,
+                            italic: false,
+                            italic_overhang: false,
+                        },
+                        chars_wide,
+                        baseline_shift,
+                    )
+                });
+            }
+            self.buffer = buffer.clear();
+        }
+
+        self.upload_glyphs(jobs, None);
+    }
+
+    /// Draw an image over the cells of `area`, e.g. to display images sent
+    /// with a terminal graphics protocol such as sixel. `rgba` holds `width` x
+    /// `height` pixels of 8 bit rgba with straight alpha, row by row. The image
+    /// is scaled to the size of the area with nearest neighbor sampling and
+    /// blended over the backgrounds and text of its cells. Parts of the area
+    /// outside of the grid are clipped.
+    ///
+    /// The image stays across frames. A cell stops showing it once text other
+    /// than blanks is drawn into the cell, and the image is removed once it
+    /// doesn't show in any cell, when an image is drawn over its whole area,
+    /// by [`WgpuBackend::clear_images`] and [`Backend::clear`], and when the
+    /// grid changes size. It is composited into
+    /// [`WgpuBackend::text_texture_view`], and thus post processed along with
+    /// the text.
+    ///
+    /// Returns an error if `rgba` doesn't hold exactly `width * height`
+    /// pixels, if either is zero, or if the image is larger than
+    /// [`WgpuBackend::max_texture_dimension`].
+    pub fn draw_image(
+        &mut self,
+        area: Rect,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+    ) -> crate::Result<()> {
+        let bindings = self.create_image_bindings(rgba, width, height)?;
+        if area.is_empty() {
+            return Ok(());
+        }
+
+        // Images hidden entirely by the new one are replaced.
+        self.images
+            .retain(|image| area.intersection(image.area) != image.area);
+        self.images.push(InlineImage {
+            area,
+            covered: BitVec::repeat(true, area.area() as usize),
+            bindings,
+            vertices: 0..0,
+        });
+        self.repaint.push(area);
+
+        Ok(())
+    }
+
+    /// Remove all images placed with [`WgpuBackend::draw_image`]. Their cells
+    /// are repainted during the next flush.
+    pub fn clear_images(&mut self) {
+        self.repaint
+            .extend(self.images.drain(..).map(|image| image.area));
+    }
+
+    /// Upload an image for [`WgpuBackend::draw_image`] and
+    /// [`WgpuBackend::add_image`].
+    fn create_image_bindings(
+        &self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+    ) -> crate::Result<BindGroup> {
+        if width == 0 || height == 0 || rgba.len() as u64 != width as u64 * height as u64 * 4 {
+            return Err(Error::InvalidImage(format!(
+                "{} bytes aren't a {width}x{height} rgba image",
+                rgba.len()
+            )));
+        }
+        let max_dimension = self.max_texture_dimension();
+        if width > max_dimension || height > max_dimension {
+            return Err(Error::InvalidImage(format!(
+                "{width}x{height} exceeds the device limit of {max_dimension}"
+            )));
+        }
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("Image"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            rgba,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        let bindings = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Image Bindings"),
+            layout: &self.image_compositor.image_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(
+                        &texture.create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.image_compositor.sampler),
+                },
+            ],
+        });
+
+        Ok(bindings)
+    }
+
+    /// Add an image to the image layer, replacing the image with the same `id`
+    /// if there is one. Unlike images drawn with [`WgpuBackend::draw_image`],
+    /// images of the layer are independent of the text grid: they stay in
+    /// place when text is drawn over them, the terminal is cleared, or the grid
+    /// changes size, until they are removed with
+    /// [`WgpuBackend::remove_image`]. See [`ImageDesc`] for how they are
+    /// placed and stacked.
+    ///
+    /// Returns an error if the image's pixels don't match its dimensions, if
+    /// either is zero, or if it is larger than
+    /// [`WgpuBackend::max_texture_dimension`].
+    pub fn add_image(
+        &mut self,
+        id: u32,
+        desc: ImageDesc,
+    ) -> crate::Result<()> {
+        let bindings = self.create_image_bindings(desc.rgba, desc.width, desc.height)?;
+        self.remove_image(id);
+
+        let index = self
+            .layer_images
+            .partition_point(|image| image.z_index <= desc.z_index);
+        self.layer_images.insert(
+            index,
+            LayerImage {
+                id,
+                placement: desc.placement,
+                z_index: desc.z_index,
+                bindings,
+                vertices: 0..0,
+            },
+        );
+        self.repaint.push(self.placement_cells(desc.placement));
+
+        Ok(())
+    }
+
+    /// Remove the image with the given `id` from the image layer. Returns
+    /// whether there was such an image.
+    pub fn remove_image(
+        &mut self,
+        id: u32,
+    ) -> bool {
+        let Some(index) = self.layer_images.iter().position(|image| image.id == id) else {
+            return false;
+        };
+        let image = self.layer_images.remove(index);
+        self.repaint.push(self.placement_cells(image.placement));
+        true
+    }
+
+    /// The pixels of the text covered by an image as left, top, right and
+    /// bottom.
+    fn placement_pixels(
+        &self,
+        placement: ImagePlacement,
+    ) -> [f32; 4] {
+        match placement {
+            ImagePlacement::Cells(area) => {
+                let (cell_width, cell_height) = (
+                    self.fonts.cell_width_px() as f32,
+                    self.fonts.cell_height_px() as f32,
+                );
+                [
+                    area.left() as f32 * cell_width,
+                    area.top() as f32 * cell_height,
+                    area.right() as f32 * cell_width,
+                    area.bottom() as f32 * cell_height,
+                ]
+            }
+            ImagePlacement::Pixels {
+                x,
+                y,
+                width,
+                height,
+            } => [
+                x as f32,
+                y as f32,
+                x as f32 + width as f32,
+                y as f32 + height as f32,
+            ],
+        }
+    }
+
+    /// The cells an image overlaps, which may extend past the grid.
+    fn placement_cells(
+        &self,
+        placement: ImagePlacement,
+    ) -> Rect {
+        let [left, top, right, bottom] = self.placement_pixels(placement);
+        let (cell_width, cell_height) = (
+            self.fonts.cell_width_px() as f32,
+            self.fonts.cell_height_px() as f32,
+        );
+        let cell = |px: f32, size: f32| (px / size).clamp(0.0, u16::MAX as f32);
+        let (x, y) = (cell(left, cell_width) as u16, cell(top, cell_height) as u16);
+        Rect::new(
+            x,
+            y,
+            (cell(right, cell_width).ceil() as u16).saturating_sub(x),
+            (cell(bottom, cell_height).ceil() as u16).saturating_sub(y),
+        )
+    }
+
+    /// Rebuild the background instances and text vertices for every cell as
+    /// if all of them changed, without rendering. Only meant for the
+    /// benchmarks.
+    #[cfg(feature = "bench")]
+    #[doc(hidden)]
+    pub fn bench_prepare_frame(&mut self) -> std::io::Result<()> {
+        self.colors_dirty = true;
+        self.prepare_frame().map(|_| ())
+    }
+
+    /// Encode and submit the compositor and post processing passes for the
+    /// most recently prepared frame. Only meant for the benchmarks.
+    #[cfg(feature = "bench")]
+    #[doc(hidden)]
+    pub fn bench_render(&mut self) -> Result<()> {
+        self.render()
+    }
+
+    /// Read the most recently presented frame back from the gpu.
+    ///
+    /// The result is tightly packed RGBA data, four bytes per pixel and
+    /// `width * 4` bytes per row, regardless of the surface's texture format.
+    ///
+    /// This blocks until the gpu has finished rendering and the image has been
+    /// copied into cpu memory. Only backends built with
+    /// [`Builder::build_headless`](crate::Builder::build_headless) support
+    /// readback, other surfaces return [`Error::ReadbackUnsupported`].
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        let region = Rect::new(
+            0,
+            0,
+            self.surface_config.width.min(u16::MAX as u32) as u16,
+            self.surface_config.height.min(u16::MAX as u32) as u16,
+        );
+        self.surface
+            .read_pixels(&self.device, &self.queue, region, Token)
+            .unwrap_or(Err(Error::ReadbackUnsupported))
+    }
+
+    /// Read the pixels of `region` of the most recently presented frame back
+    /// from the gpu, e.g. to test a single widget. `region` is in pixels, see
+    /// [`WgpuBackend::cell_bounds`] to find the pixels of a cell.
+    ///
+    /// The result is tightly packed RGBA data as with
+    /// [`WgpuBackend::read_pixels`], `region.width * 4` bytes per row. Returns
+    /// [`Error::RegionOutOfBounds`] if `region` doesn't lie within the
+    /// surface.
+    pub fn capture_region(
+        &self,
+        region: Rect,
+    ) -> Result<Vec<u8>> {
+        if region.right() as u32 > self.surface_config.width
+            || region.bottom() as u32 > self.surface_config.height
+        {
+            return Err(Error::RegionOutOfBounds(region));
+        }
+
+        self.surface
+            .read_pixels(&self.device, &self.queue, region, Token)
+            .unwrap_or(Err(Error::ReadbackUnsupported))
+    }
+
+    /// Write the most recently presented frame to `path` as an RGBA png.
+    ///
+    /// This blocks in the same way as [`WgpuBackend::read_pixels`] and has the
+    /// same requirements on the surface.
+    #[cfg(feature = "png")]
+    pub fn save_png(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<()> {
+        write_png(
+            path.as_ref(),
+            self.surface_config.width,
+            self.surface_config.height,
+            &self.read_pixels()?,
+        )
+    }
+
+    /// Compare the most recently presented frame against the reference png at
+    /// `path` and panic if they differ, e.g. to snapshot test an application
+    /// after [`Terminal::draw`](ratatui::Terminal::draw). Each channel of
+    /// each pixel may differ from the reference by up to `tolerance`, which
+    /// absorbs the small differences in rasterization between gpus and
+    /// drivers.
+    ///
+    /// On a mismatch, the frame is written next to the reference as
+    /// `<name>.actual.png`, along with `<name>.diff.png`, which shows the
+    /// mismatched pixels in red over a faded copy of the frame. A missing
+    /// reference is a mismatch as well, so the first run of a new test
+    /// produces an image to review and rename to the reference.
+    ///
+    /// This blocks in the same way as [`WgpuBackend::read_pixels`] and has the
+    /// same requirements on the surface.
+    #[cfg(feature = "testing")]
+    #[track_caller]
+    pub fn assert_frame_matches(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        tolerance: u8,
+    ) {
+        let path = path.as_ref();
+        let actual = snapshot::RgbaImage {
+            width: self.surface_config.width,
+            height: self.surface_config.height,
+            pixels: self.read_pixels().expect("Failed to read back the frame"),
+        };
+        let sibling = |suffix| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path.with_file_name(format!("{stem}.{suffix}.png"))
+        };
+
+        let failure = match snapshot::read_png(path) {
+            Ok(reference) => match snapshot::diff(&actual, &reference, tolerance) {
+                None => return,
+                Some((mismatched, diff)) => {
+                    let diff_path = sibling("diff");
+                    write_png(&diff_path, diff.width, diff.height, &diff.pixels)
+                        .expect("Failed to write the diff image");
+                    format!(
+                        "{mismatched} pixels of the {}x{} frame differ from the {}x{} reference \
+                         {} by more than {tolerance}, see {}",
+                        actual.width,
+                        actual.height,
+                        reference.width,
+                        reference.height,
+                        path.display(),
+                        diff_path.display()
+                    )
+                }
+            },
+            Err(err) => format!("Failed to read the reference {}: {err}", path.display()),
+        };
+
+        let actual_path = sibling("actual");
+        write_png(&actual_path, actual.width, actual.height, &actual.pixels)
+            .expect("Failed to write the actual image");
+        panic!(
+            "{failure}. The frame was written to {}",
+            actual_path.display()
+        );
+    }
+}
+
+/// Write tightly packed RGBA `pixels` to `path` as a png.
+#[cfg(feature = "png")]
+fn write_png(
+    path: &std::path::Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .map_err(|e| Error::PngEncodingFailed(png::EncodingError::from(e)))?;
+
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(Error::PngEncodingFailed)
+}
+
+impl<P: PostProcessor> WgpuBackend<'_, 'static, P, TextureSurface> {
+    /// Replace the texture being rendered to, e.g. after the caller recreated
+    /// it at a new size. The new view must have the same format as the
+    /// previous one. `width` and `height` are the dimensions of the new
+    /// texture.
+    pub fn set_texture_view(
+        &mut self,
+        view: TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        self.surface.view = view;
+        self.resize(width, height);
+    }
+}
+
+impl<'s, P: PostProcessor, S: RenderSurface<'s>> Backend for WgpuBackend<'_, 's, P, S> {
+    fn draw<'a, I>(
+        &mut self,
+        content: I,
+    ) -> std::io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        let bounds = self.size()?;
+
+        self.cells
+            .resize(bounds.height as usize * bounds.width as usize, Cell::EMPTY);
+        self.laid_out
+            .resize(bounds.height as usize * bounds.width as usize, Cell::EMPTY);
+        self.sourced.resize_with(
+            bounds.height as usize * bounds.width as usize,
+            Sourced::default,
+        );
+        self.rendered.resize_with(
+            bounds.height as usize * bounds.width as usize,
+            Rendered::default,
+        );
+        self.fast_blinking
+            .resize(bounds.height as usize * bounds.width as usize, false);
+        self.slow_blinking
+            .resize(bounds.height as usize * bounds.width as usize, false);
+        self.overhanging
+            .resize(bounds.height as usize * bounds.width as usize, false);
+        self.dirty_rows.resize(bounds.height as usize, true);
+
+        for (x, y, cell) in content {
+            let index = y as usize * bounds.width as usize + x as usize;
+            self.fast_blinking
+                .set(index, cell.modifier.contains(Modifier::RAPID_BLINK));
+            self.slow_blinking
+                .set(index, cell.modifier.contains(Modifier::SLOW_BLINK));
+
+            self.dirty_rows[y as usize] = true;
+
+            self.cells[index] = cell.clone();
+
+            let width = cell.symbol().width().max(1);
+            if !self.images.is_empty() && !cell.symbol().trim().is_empty() {
+                self.uncover_images(x, y, width as u16);
+            }
+            let start = (index + 1).min(self.cells.len());
+            let end = (index + width).min(self.cells.len());
+            self.cells[start..end].fill(NULL_CELL);
+        }
+
+        // Rows are laid out again whenever any of their cells change, since tabs and
+        // zero width symbols change the cells around them.
+        for (y, row) in self.cells.chunks(bounds.width as usize).enumerate() {
+            if self.dirty_rows[y] {
+                let start = y * bounds.width as usize;
+                lay_out_row(
+                    row,
+                    &mut self.laid_out[start..start + row.len()],
+                    self.tab_width,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> std::io::Result<()> {
+        self.cursor_visible = false;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> std::io::Result<()> {
+        if !self.cursor_visible {
+            self.reset_cursor_blink();
+        }
+        self.cursor_visible = true;
+        Ok(())
+    }
+
+    fn get_cursor_position(&mut self) -> std::io::Result<Position> {
+        Ok(Position::new(self.cursor.0, self.cursor.1))
+    }
+
+    fn set_cursor_position<Pos: Into<Position>>(
+        &mut self,
+        position: Pos,
+    ) -> std::io::Result<()> {
+        let bounds = self.size()?;
+        let pos: Position = position.into();
+        let cursor = (pos.x.min(bounds.width - 1), pos.y.min(bounds.height - 1));
+        if cursor != self.cursor {
+            self.reset_cursor_blink();
+        }
+        self.cursor = cursor;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> std::io::Result<()> {
+        self.cells.clear();
+        self.laid_out.clear();
+        self.dirty_rows.clear();
+        self.cursor = (0, 0);
+        self.clear_images();
+
+        Ok(())
+    }
+
+    fn size(&self) -> std::io::Result<Size> {
+        Ok(self.grid_size())
+    }
+
+    fn window_size(&mut self) -> std::io::Result<WindowSize> {
+        let (width, height) = self.drawable_size();
+
+        Ok(WindowSize {
+            columns_rows: self.grid_size(),
+            pixels: Size {
+                width: width as u16,
+                height: height as u16,
+            },
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // Skipping before anything is consumed leaves the changes for the next
+        // flush.
+        self.frame_skipped = self
+            .min_frame_interval
+            .zip(self.last_present)
+            .is_some_and(|(interval, last)| last.elapsed() < interval);
+        if self.frame_skipped {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        if self.prepare_frame()? {
+            self.render().map_err(std::io::Error::other)?;
+
+            let now = Instant::now();
+            self.frame_time = self.last_present.map(|last| now - last);
+            self.last_present = Some(now);
+            self.frame_stats = FrameStats {
+                cpu_time: now - start,
+                gpu_time: self.frame_stats.gpu_time,
+                atlas_uploads: std::mem::take(&mut self.atlas_uploads),
+                dirty_cells: self.dirty_cells.count_ones(),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn clear_region(
+        &mut self,
+        clear_type: ClearType,
+    ) -> std::io::Result<()> {
+        let bounds = self.size()?;
+        let line_start = self.cursor.1 as usize * bounds.width as usize;
+        let idx = line_start + self.cursor.0 as usize;
+
+        match clear_type {
+            ClearType::All => self.clear(),
+            ClearType::AfterCursor => {
+                self.cells.truncate(idx + 1);
+                self.laid_out.truncate(idx + 1);
+                Ok(())
+            }
+            ClearType::BeforeCursor => {
+                self.cells[..idx].fill(Cell::EMPTY);
+                Ok(())
+            }
+            ClearType::CurrentLine => {
+                self.cells[line_start..line_start + bounds.width as usize].fill(Cell::EMPTY);
+                Ok(())
+            }
+            ClearType::UntilNewLine => {
+                let remain = (bounds.width - self.cursor.0) as usize;
+                self.cells[idx..idx + remain].fill(Cell::EMPTY);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The value to clear a target to `color` with, transparent for [`None`].
+/// Targets with an srgb format expect the value `linearize`d.
+fn clear_value(
+    color: Option<[u8; 3]>,
+    linearize: bool,
+) -> wgpu::Color {
+    color.map_or(wgpu::Color::TRANSPARENT, |[r, g, b]| {
+        let channel = |c: u8| {
+            let c = c as f32 / 255.0;
+            if linearize {
+                srgb_to_linear(c) as f64
+            } else {
+                c as f64
+            }
+        };
+        wgpu::Color {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+            a: 1.0,
+        }
+    })
+}
+
+/// Lay out a row of `cells` into `laid_out` as it's drawn. A tab advances to
+/// the next tab stop, covering the cells it skips with blanks in its style in
+/// the same way a wide character covers the cell after it. A zero width
+/// symbol on its own, such as a combining mark or a variation selector, is
+/// composed onto the cluster before it rather than drawn in a cell of its
+/// own, and its cell is left blank. `cells` keeps what ratatui drew, so
+/// changing either cell lays the row out from scratch.
+fn lay_out_row(
+    cells: &[Cell],
+    laid_out: &mut [Cell],
+    tab_width: usize,
+) {
+    laid_out.clone_from_slice(cells);
+    let mut x = 0;
+    while x < cells.len() {
+        let cell = &cells[x];
+        if cell.symbol() == "\t" {
+            let mut blank = cell.clone();
+            blank.set_symbol(" ");
+            let stop = ((x / tab_width + 1) * tab_width).min(cells.len());
+            laid_out[x..stop].fill(blank);
+            x = stop;
+            continue;
+        }
+
+        if x > 0 && !cell.symbol().is_empty() && cell.symbol().width() == 0 {
+            let base = (0..x - 1)
+                .rev()
+                .find(|&start| cluster_width(&laid_out[start]) > x - 1 - start)
+                .unwrap_or(x - 1);
+            let mut blank = cell.clone();
+            blank.set_symbol(" ");
+            laid_out[x] = blank;
+            let composed = format!("{}{}", laid_out[base].symbol(), cell.symbol());
+            laid_out[base].set_symbol(&composed);
+            // An emoji presentation selector may widen the cluster.
+            let end = (base + cluster_width(&laid_out[base])).min(cells.len());
+            laid_out[base + 1..end].fill(NULL_CELL);
+        }
+
+        x += 1;
+    }
+}
+
+/// The number of cells the grapheme cluster in `cell` covers.
+fn cluster_width(cell: &Cell) -> usize {
+    cell.symbol().width().max(1)
+}
+
+/// Run the post processor into `target`. Processors rendering multisampled go
+/// through `multisample_target`, which is resolved to `target` afterwards.
+fn post_process_into<P: PostProcessor>(
+    post_process: &mut P,
+    multisample_target: Option<&TextureView>,
+    encoder: &mut CommandEncoder,
+    queue: &Queue,
+    text_view: &TextureView,
+    surface_config: &SurfaceConfiguration,
+    target: &TextureView,
+) {
+    let Some(multisample_target) = multisample_target else {
+        post_process.process(encoder, queue, text_view, surface_config, target);
+        return;
+    };
+
+    post_process.process(
+        encoder,
+        queue,
+        text_view,
+        surface_config,
+        multisample_target,
+    );
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("Multisample Resolve Pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: multisample_target,
+            resolve_target: Some(target),
+            ops: Operations {
+                load: LoadOp::Load,
+                store: StoreOp::Discard,
+            },
+            depth_slice: None,
+        })],
+        ..Default::default()
+    });
+}
+
+/// Blend `color` with the given opacity over the premultiplied color `dest`.
+fn blend_over(
+    dest: [u8; 4],
+    color: [u8; 3],
+    alpha: u8,
+) -> [u8; 4] {
+    let alpha = u32::from(alpha);
+    let [r, g, b] = color;
+    let mut out = [0; 4];
+    for (out, (dest, src)) in out.iter_mut().zip(dest.into_iter().zip([r, g, b, 255])) {
+        *out = ((u32::from(src) * alpha + u32::from(dest) * (255 - alpha)) / 255) as u8;
+    }
+    out
+}
+
+/// The horizontal shear applied to synthesized italics, roughly 12 degrees.
+const SYNTHETIC_ITALIC_SKEW: f32 = 0.21;
+
+/// The number of horizontal positions within a pixel glyphs are rasterized at
+/// with [`Builder::with_subpixel_positioning`](crate::Builder::with_subpixel_positioning).
+const SUBPIXEL_PHASES: u8 = 3;
+
+/// The underline style is packed into the top bits of the underline position.
+/// Atlas coordinates must fit in the remaining 13 bits of each half.
+const UNDERLINE_STYLE_SHIFT: u32 = 29;
+
+/// How long a frame may spend rasterizing new glyphs with
+/// [`Builder::with_budgeted_rasterization`](crate::Builder::with_budgeted_rasterization).
+pub(super) const RASTERIZATION_BUDGET: Duration = Duration::from_millis(4);
+
+/// A glyph waiting to be rasterized into its atlas entry.
+#[derive(Debug, Clone, Copy)]
+struct RasterJob {
+    cached: Entry,
+    font: u64,
+    info: rustybuzz::GlyphInfo,
+    /// The character to draw procedurally instead of using the font.
+    builtin: Option<char>,
+    synthetic: Synthetic,
+    advance_scale: f32,
+    placement: Placement,
+}
+
+type RasterizedGlyph = (CacheRect, Vec<u32>, bool);
+
+/// Push the two triangles drawing the part of `image` inside of `cell`, both
+/// given in pixels as left, top, right and bottom.
+fn push_image_quad(
+    vertices: &mut Vec<ImageVertex>,
+    cell: [f32; 4],
+    image: [f32; 4],
+) {
+    let [left, top, right, bottom] = [
+        cell[0].max(image[0]),
+        cell[1].max(image[1]),
+        cell[2].min(image[2]),
+        cell[3].min(image[3]),
+    ];
+    if left >= right || top >= bottom {
+        return;
+    }
+
+    let u = |x: f32| (x - image[0]) / (image[2] - image[0]);
+    let v = |y: f32| (y - image[1]) / (image[3] - image[1]);
+    let corner = |x: f32, y: f32| ImageVertex {
+        vertex: [x, y],
+        uv: [u(x), v(y)],
+    };
+    vertices.extend([
+        corner(left, top),
+        corner(right, top),
+        corner(left, bottom),
+        corner(left, bottom),
+        corner(right, bottom),
+        corner(right, top),
+    ]);
+}
+
+/// Whether the glyph is a color glyph, and how many cells wide it's drawn.
+/// `max_width` is the width of the cells covered by the glyph's cluster, and
+/// `ligature` whether that cluster merges several cells. Both shaping rows and
+/// preloading glyphs decide this here, so they agree on the atlas entries.
+fn glyph_cells(
+    metrics: &rustybuzz::Face,
+    info: &rustybuzz::GlyphInfo,
+    ch: char,
+    builtin: bool,
+    max_width: usize,
+    ligature: bool,
+) -> (bool, u32) {
+    let is_color = !builtin && is_color_glyph(metrics, GlyphId(info.glyph_id as _));
+    let chars_wide = ch.width().unwrap_or(max_width) as u32;
+    let chars_wide = if chars_wide == 0 { 1 } else { chars_wide };
+    let chars_wide = if ligature {
+        max_width.clamp(1, 2) as u32
+    } else {
+        chars_wide
+    };
+    // Color glyphs (emoji) may be followed by a variation selector which widens
+    // the cell, but they should never extend past a double-width cell.
+    let chars_wide = if is_color {
+        chars_wide.max(max_width as u32).min(2)
+    } else if builtin {
+        1
+    } else {
+        chars_wide
+    };
+    (is_color, chars_wide)
+}
+
+/// The atlas key of a glyph shaped with `font`, or of the procedurally drawn
+/// `builtin` character.
+fn glyph_key(
+    font: &Font,
+    info: &rustybuzz::GlyphInfo,
+    builtin: Option<char>,
+    style: Modifier,
+    phase: u8,
+) -> Key {
+    Key {
+        style,
+        glyph: builtin.map_or(info.glyph_id, u32::from),
+        font: if builtin.is_some() {
+            box_drawing::BUILTIN_FONT_ID
+        } else {
+            font.id()
+        },
+        phase,
+    }
+}
+
+/// The job rasterizing the glyph for `key` into `cached`, scaled to
+/// `chars_wide` cells.
+#[allow(clippy::too_many_arguments)]
+fn raster_job(
+    fonts: &Fonts,
+    font: &Font,
+    info: &rustybuzz::GlyphInfo,
+    key: &Key,
+    cached: Entry,
+    synthetic: Synthetic,
+    chars_wide: u32,
+    baseline_shift: i32,
+) -> RasterJob {
+    let metrics = font.font();
+    let advance_scale = fonts.height_px() as f32 / metrics.height() as f32;
+    let width = (metrics
+        .glyph_hor_advance(GlyphId(info.glyph_id as _))
+        .unwrap_or_default() as f32
+        * advance_scale) as u32;
+    let width = if width == 0 {
+        chars_wide * fonts.min_width_px()
+    } else {
+        width
+    };
+
+    RasterJob {
+        cached,
+        font: font.id(),
+        info: *info,
+        builtin: char::from_u32(key.glyph).filter(|_| key.font == box_drawing::BUILTIN_FONT_ID),
+        synthetic,
+        advance_scale,
+        placement: Placement {
+            advance_width: width,
+            cell_width: chars_wide * fonts.min_width_px(),
+            box_width: (chars_wide + u32::from(synthetic.italic_overhang)) * fonts.min_width_px(),
+            font_height: fonts.height_px(),
+            padding_left: chars_wide as i32 * fonts.padding_left_px(),
+            padding_top: fonts.padding_top_px() + baseline_shift,
+            subpixel_offset: f32::from(key.phase) / f32::from(SUBPIXEL_PHASES),
+        },
+    }
+}
+
+/// Rasterize the glyphs for `jobs`. Without a deadline every glyph is
+/// rasterized on the current thread. With a deadline the glyphs are spread over
+/// a scoped worker per core, which are joined before returning, and glyphs
+/// which weren't started before the deadline passed are returned as `None`.
+fn rasterize_jobs(
+    fonts: &Fonts,
+    subpixel_aa: Option<SubpixelLayout>,
+    emoji_mode: EmojiMode,
+    stem_darkening: f32,
+    jobs: HashMap<Key, RasterJob, RandomState>,
+    deadline: Option<Instant>,
+) -> Vec<(Key, Option<RasterizedGlyph>)> {
+    // Fonts aren't shareable between threads, but their faces are.
+    let jobs = jobs
+        .into_iter()
+        .map(|(key, job)| {
+            let face = fonts
+                .font_by_id(job.font)
+                .expect("Glyphs are only shaped with fonts of the collection")
+                .font();
+            (key, job, face)
+        })
+        .collect::<Vec<_>>();
+
+    // Raising the coverage to a power below one darkens partially covered pixels
+    // while leaving empty and fully covered ones unchanged.
+    let darkening = (stem_darkening > 0.0).then(|| {
+        let exponent = 1.0 / (1.0 + stem_darkening);
+        std::array::from_fn::<u8, 256, _>(|c| {
+            ((c as f32 / 255.0).powf(exponent) * 255.0).round() as u8
+        })
+    });
+
+    let rasterize = |(_, job, face): &(Key, RasterJob, &rustybuzz::Face)| {
+        if let Some(image) = job
+            .builtin
+            .and_then(|ch| box_drawing::rasterize(ch, job.cached.width, job.cached.height))
+        {
+            return (*job.cached, image, false);
+        }
+        let (rect, mut image, is_color) = rasterize_glyph(
+            job.cached,
+            face,
+            &job.info,
+            job.synthetic,
+            job.advance_scale,
+            job.placement,
+            subpixel_aa,
+        );
+        if is_color && emoji_mode == EmojiMode::Monochrome {
+            to_coverage(&mut image);
+            return (rect, image, false);
+        }
+        if let Some(darkening) = darkening.as_ref().filter(|_| !is_color) {
+            for pixel in image.iter_mut() {
+                *pixel = u32::from_le_bytes(pixel.to_le_bytes().map(|c| darkening[c as usize]));
+            }
+        }
+        (rect, image, is_color)
+    };
+
+    let Some(deadline) = deadline else {
+        return jobs
+            .iter()
+            .map(|job| (job.0, Some(rasterize(job))))
+            .collect();
+    };
+
+    // At least one glyph is rasterized each frame so that progress is always made.
+    let next = AtomicUsize::new(0);
+    let work = || {
+        let mut done = vec![];
+        loop {
+            let idx = next.fetch_add(1, Ordering::Relaxed);
+            if idx >= jobs.len() || (idx > 0 && Instant::now() >= deadline) {
+                break done;
+            }
+            done.push((idx, rasterize(&jobs[idx])));
+        }
+    };
+
+    // Threads aren't available on every platform, e.g. the web.
+    let workers = std::thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(jobs.len());
+    let done = if workers > 1 {
+        std::thread::scope(|scope| {
+            let handles = (0..workers).map(|_| scope.spawn(work)).collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Vec<_>>()
+        })
+    } else {
+        work()
+    };
+
+    let mut rasterized = jobs
+        .iter()
+        .map(|(key, ..)| (*key, None))
+        .collect::<Vec<_>>();
+    for (idx, image) in done {
+        rasterized[idx].1 = Some(image);
+    }
+    rasterized
+}
+
+/// Styles which are synthesized during rasterization because the font lacks a
+/// matching face.
+#[derive(Debug, Clone, Copy)]
+struct Synthetic {
+    bold: bool,
+    italic: bool,
+    italic_overhang: bool,
+}
+
+/// Where a glyph is rendered relative to its cell.
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    /// The scaled advance of the glyph.
+    advance_width: u32,
+    /// The width of the cell(s) the glyph is scaled to fit.
+    cell_width: u32,
+    /// The width of the box the glyph is rendered into, before it is centered
+    /// within the (wider or narrower) cell(s).
+    box_width: u32,
+    /// The height of the font.
+    font_height: u32,
+    /// The offset from the left of the cell to the left of the box.
+    padding_left: i32,
+    /// The offset from the top of the cell to the top of the font.
+    padding_top: i32,
+    /// The fraction of a pixel the glyph is moved right by.
+    subpixel_offset: f32,
+}
+
+/// The size in physical pixels fonts of `font_size` logical pixels are
+/// rasterized at.
+pub(super) fn physical_font_size(
+    font_size: f32,
+    scale_factor: f64,
+) -> u32 {
+    (font_size as f64 * scale_factor).round().max(1.0) as u32
+}
+
+/// Split the distance between a glyph's exact position and the pixel it is
+/// drawn at into whole pixels and the nearest subpixel phase.
+fn subpixel_phase(remainder: f32) -> (i32, u8) {
+    let steps = (remainder * f32::from(SUBPIXEL_PHASES)).round() as i32;
+    (
+        steps.div_euclid(i32::from(SUBPIXEL_PHASES)),
+        steps.rem_euclid(i32::from(SUBPIXEL_PHASES)) as u8,
+    )
+}
+
+/// The metrics and scale underlines of glyphs from `metrics` are drawn with,
+/// and how far the glyphs are moved down to sit on the baseline `mode` picks.
+fn baseline_metrics<'f>(
+    fonts: &'f Fonts,
+    mode: BaselineMode,
+    metrics: &'f rustybuzz::Face<'f>,
+) -> (&'f rustybuzz::Face<'f>, f32, i32) {
+    let scale = fonts.height_px() as f32 / metrics.height() as f32;
+    match mode {
+        BaselineMode::PerFont => (metrics, scale, 0),
+        BaselineMode::Primary => {
+            // Fonts are aligned by their ascender, so moving a glyph onto the primary
+            // baseline is the difference between the two.
+            let primary = fonts.primary().font();
+            let primary_scale = fonts.height_px() as f32 / primary.height() as f32;
+            (
+                primary,
+                primary_scale,
+                (primary.ascender() as f32 * primary_scale).round() as i32
+                    - (metrics.ascender() as f32 * scale).round() as i32,
+            )
+        }
+    }
+}
+
+/// Rasterize a glyph into an image the size of the cached entry. The returned
+/// flag indicates whether the image contains color data which should be used
+/// as-is rather than tinted with the foreground color.
+fn rasterize_glyph(
+    cached: Entry,
+    metrics: &rustybuzz::Face,
+    info: &rustybuzz::GlyphInfo,
+    synthetic: Synthetic,
+    advance_scale: f32,
+    placement: Placement,
+    subpixel_aa: Option<SubpixelLayout>,
+) -> (CacheRect, Vec<u32>, bool) {
+    if placement.font_height != cached.height
+        || placement.box_width != cached.width
+        || placement.padding_top != 0
+    {
+        // Render into a box the size of the font, then center that within the
+        // (larger or smaller) cell.
+        let glyph_box = Entry::Uncached(CacheRect {
+            width: placement.box_width,
+            height: placement.font_height,
+            ..*cached
+        });
+        let (_, glyph, is_color) = rasterize_glyph(
+            glyph_box,
+            metrics,
+            info,
+            synthetic,
+            advance_scale,
+            Placement {
+                padding_left: 0,
+                padding_top: 0,
+                ..placement
+            },
+            subpixel_aa,
+        );
+
+        let width = cached.width as usize;
+        let box_width = placement.box_width as usize;
+        // The columns of the cell covered by the box, and where they start within it.
+        let start = placement.padding_left.max(0) as usize;
+        let src_start = (-placement.padding_left).max(0) as usize;
+        let len = (width - start).min(box_width.saturating_sub(src_start));
+        let mut image = vec![0u32; width * cached.height as usize];
+        for (y, row) in image.chunks_mut(width).enumerate() {
+            let src = y as i32 - placement.padding_top;
+            if (0..placement.font_height as i32).contains(&src) {
+                let src = src as usize * box_width + src_start;
+                row[start..start + len].copy_from_slice(&glyph[src..src + len]);
+            }
+        }
+
+        return (*cached, image, is_color);
+    }
+
+    let Placement {
+        advance_width,
+        cell_width,
+        ..
+    } = placement;
+
+    let scale = cell_width as f32 / advance_width as f32;
+    let computed_offset_x = -(cell_width as f32 * (1.0 - scale));
+    let computed_offset_y = cached.height as f32 * (1.0 - scale);
+    let scale = scale * advance_scale * 2.0;
+
+    let skew = if synthetic.italic {
+        // When the glyph can't overhang, shift it back so that it stays (mostly)
+        // within its cell.
+        let shift = if synthetic.italic_overhang {
+            0.0
+        } else {
+            -SYNTHETIC_ITALIC_SKEW * cell_width as f32
+        };
+        Transform::new(
+            /* scale x */ 1.0,
+            /* skew x */ 0.0,
+            /* skew y */ -SYNTHETIC_ITALIC_SKEW,
+            /* scale y */ 1.0,
+            /* translate x */ shift,
+            /* translate y */ 0.0,
+        )
+    } else {
+        Transform::default()
+    };
+
+    let mut image = vec![0u32; cached.width as usize * 2 * cached.height as usize * 2];
+    let mut target = DrawTarget::from_backing(
+        cached.width as i32 * 2,
+        cached.height as i32 * 2,
+        &mut image[..],
+    );
+
+    let mut painter = Painter::new(
+        metrics,
+        &mut target,
+        skew,
+        scale,
+        metrics.ascender() as f32 * scale + computed_offset_y,
+        computed_offset_x,
+    );
+    if metrics
+        .paint_color_glyph(
+            GlyphId(info.glyph_id as _),
+            0,
+            RgbaColor::new(255, 255, 255, 255),
+            &mut painter,
+        )
+        .is_some()
+    {
+        let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
+        final_image.draw_image_with_size_at(
+            cached.width as f32,
+            cached.height as f32,
+            0.,
+            0.,
+            &raqote::Image {
+                width: cached.width as i32 * 2,
+                height: cached.height as i32 * 2,
+                data: &image,
+            },
+            &DrawOptions {
+                blend_mode: raqote::BlendMode::Src,
+                antialias: raqote::AntialiasMode::None,
+                ..Default::default()
+            },
+        );
+
+        let mut final_image = final_image.into_vec();
+        for argb in final_image.iter_mut() {
+            let [a, r, g, b] = argb.to_be_bytes();
+            *argb = u32::from_le_bytes([r, g, b, a]);
+        }
+
+        return (*cached, final_image, true);
+    }
+
+    if let Some(raster) = metrics.glyph_raster_image(GlyphId(info.glyph_id as _), u16::MAX) {
+        if let Some((rect, image)) = extract_color_image(&mut image, raster, cached) {
+            return (rect, image, true);
+        }
+    }
+
+    let mut render = Outline::default();
+    if let Some(bounds) = metrics.outline_glyph(GlyphId(info.glyph_id as _), &mut render) {
+        let path = render.finish();
+
+        // Some fonts return bounds that are entirely negative. I'm not sure why this
+        // is, but it means the glyph won't render at all. We check for this here and
+        // offset it if so. This seems to let those fonts render correctly.
+        let x_off = if bounds.x_max < 0 {
+            -bounds.x_min as f32
+        } else {
             0.
         };
-        let x_off = x_off * scale + computed_offset_x;
-        let y_off = metrics.ascender() as f32 * scale + computed_offset_y;
+        // The target is twice the size of the entry.
+        let x_off = x_off * scale + computed_offset_x + placement.subpixel_offset * 2.0;
+        let y_off = metrics.ascender() as f32 * scale + computed_offset_y;
+
+        // Subpixel rendering samples each of the three subpixels separately, so we
+        // render at triple the horizontal resolution.
+        let h_scale = if subpixel_aa.is_some() { 3 } else { 1 };
+        image.resize(
+            cached.width as usize * 2 * h_scale * cached.height as usize * 2,
+            0,
+        );
+
+        let mut target = DrawTarget::from_backing(
+            cached.width as i32 * 2 * h_scale as i32,
+            cached.height as i32 * 2,
+            &mut image[..],
+        );
+        target.set_transform(
+            &Transform::scale(scale, -scale)
+                .then(&skew)
+                .then_translate((x_off, y_off).into())
+                .then_scale(h_scale as f32, 1.0),
+        );
+
+        target.fill(
+            &path,
+            &raqote::Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
+            &DrawOptions::default(),
+        );
+
+        if synthetic.bold {
+            target.stroke(
+                &path,
+                &raqote::Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
+                &StrokeStyle {
+                    width: 1.5,
+                    ..Default::default()
+                },
+                &DrawOptions::new(),
+            );
+        }
+
+        let mut final_image =
+            DrawTarget::new(cached.width as i32 * h_scale as i32, cached.height as i32);
+        final_image.draw_image_with_size_at(
+            cached.width as f32 * h_scale as f32,
+            cached.height as f32,
+            0.,
+            0.,
+            &raqote::Image {
+                width: cached.width as i32 * 2 * h_scale as i32,
+                height: cached.height as i32 * 2,
+                data: &image,
+            },
+            &DrawOptions {
+                blend_mode: raqote::BlendMode::Src,
+                antialias: raqote::AntialiasMode::None,
+                ..Default::default()
+            },
+        );
+
+        if let Some(layout) = subpixel_aa {
+            return (
+                *cached,
+                subpixel_coverage(&final_image.into_vec(), cached.width, layout),
+                false,
+            );
+        }
+
+        return (*cached, final_image.into_vec(), false);
+    }
+
+    if let Some(raster) = metrics.glyph_raster_image(GlyphId(info.glyph_id as _), u16::MAX) {
+        if let Some((rect, image)) = extract_bw_image(&mut image, raster, cached, advance_scale) {
+            return (rect, image, false);
+        }
+    }
+
+    (
+        *cached,
+        vec![0u32; cached.width as usize * cached.height as usize],
+        false,
+    )
+}
+
+/// Convert a color glyph into coverage so that it is tinted like any other
+/// glyph. The coverage of each pixel is its (premultiplied) luminance, which
+/// keeps the glyph's details visible.
+fn to_coverage(image: &mut [u32]) {
+    for rgba in image.iter_mut() {
+        let [r, g, b, _] = rgba.to_le_bytes();
+        let luma = ((u32::from(r) * 54 + u32::from(g) * 183 + u32::from(b) * 19) >> 8) as u8;
+        *rgba = u32::from_le_bytes([luma; 4]);
+    }
+}
+
+/// Weights used to spread coverage across neighboring subpixels, out of 256.
+/// This matches FreeType's default LCD filter and reduces color fringing.
+const LCD_FILTER: [u32; 5] = [8, 77, 86, 77, 8];
+
+/// Convert an image rendered at 3x horizontal resolution into per-channel
+/// subpixel coverage.
+fn subpixel_coverage(
+    image: &[u32],
+    width: u32,
+    layout: SubpixelLayout,
+) -> Vec<u32> {
+    let samples = width as usize * 3;
+    let mut coverage = Vec::with_capacity(image.len() / 3);
+
+    for row in image.chunks(samples) {
+        let filtered = (0..samples)
+            .map(|idx| {
+                let sum = LCD_FILTER
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(tap, weight)| {
+                        let idx = (idx + tap).checked_sub(LCD_FILTER.len() / 2)?;
+                        row.get(idx).map(|argb| (argb >> 24) * weight)
+                    })
+                    .sum::<u32>();
+                (sum / 256).min(255) as u8
+            })
+            .collect::<Vec<_>>();
+
+        coverage.extend(filtered.chunks(3).map(|px| {
+            let [r, g, b] = match layout {
+                SubpixelLayout::Rgb => [px[0], px[1], px[2]],
+                SubpixelLayout::Bgr => [px[2], px[1], px[0]],
+            };
+            u32::from_le_bytes([r, g, b, r.max(g).max(b)])
+        }));
+    }
+
+    coverage
+}
+
+fn extract_color_image(
+    image: &mut Vec<u32>,
+    raster: RasterGlyphImage,
+    cached: Entry,
+) -> Option<(CacheRect, Vec<u32>)> {
+    match raster.format {
+        RasterImageFormat::PNG => {
+            #[cfg(feature = "png")]
+            {
+                let decoder = png::Decoder::new(std::io::Cursor::new(raster.data));
+                if let Ok(mut info) = decoder.read_info() {
+                    image.resize(
+                        info.output_buffer_size().unwrap_or_default() / size_of::<u32>(),
+                        0,
+                    );
+                    if info.next_frame(bytemuck::cast_slice_mut(image)).is_err() {
+                        return None;
+                    }
+
+                    for rgba in image.iter_mut() {
+                        let [r, g, b, a] = rgba.to_be_bytes();
+                        *rgba = u32::from_be_bytes([a, r, g, b]);
+                    }
+                } else {
+                    return None;
+                }
+            }
+            #[cfg(not(feature = "png"))]
+            return None;
+        }
+        RasterImageFormat::BitmapPremulBgra32 => {
+            image.resize(raster.width as usize * raster.height as usize, 0);
+            for (y, row) in raster.data.chunks(raster.width as usize * 4).enumerate() {
+                for (x, pixel) in row.chunks(4).enumerate() {
+                    let pixel: &[u8; 4] = pixel.try_into().expect("Invalid chunk size");
+                    let [b, g, r, a] = *pixel;
+                    let unmultiply = |c: u8| {
+                        if a == 0 {
+                            0
+                        } else {
+                            (c as u16 * 255 / a as u16).min(255) as u8
+                        }
+                    };
+                    let pixel =
+                        u32::from_be_bytes([a, unmultiply(r), unmultiply(g), unmultiply(b)]);
+                    image[y * raster.width as usize + x] = pixel;
+                }
+            }
+        }
+        _ => return None,
+    }
+
+    // Color bitmaps are usually stored in a strike much larger than the cell, so
+    // fit them to the box while preserving their aspect ratio.
+    let fit = (cached.width as f32 / raster.width.max(1) as f32)
+        .min(cached.height as f32 / raster.height.max(1) as f32);
+    let width = raster.width as f32 * fit;
+    let height = raster.height as f32 * fit;
+
+    let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
+    final_image.draw_image_with_size_at(
+        width,
+        height,
+        (cached.width as f32 - width) / 2.0,
+        (cached.height as f32 - height) / 2.0,
+        &raqote::Image {
+            width: raster.width as i32,
+            height: raster.height as i32,
+            data: &*image,
+        },
+        &DrawOptions {
+            blend_mode: raqote::BlendMode::Src,
+            antialias: raqote::AntialiasMode::None,
+            ..Default::default()
+        },
+    );
+
+    let mut final_image = final_image.into_vec();
+    for argb in final_image.iter_mut() {
+        let [a, r, g, b] = argb.to_be_bytes();
+        *argb = u32::from_le_bytes([r, g, b, a]);
+    }
+
+    Some((*cached, final_image))
+}
+
+fn extract_bw_image(
+    image: &mut Vec<u32>,
+    raster: RasterGlyphImage,
+    cached: Entry,
+    scale: f32,
+) -> Option<(CacheRect, Vec<u32>)> {
+    image.resize(raster.width as usize * raster.height as usize, 0);
+
+    match raster.format {
+        RasterImageFormat::BitmapMono => {
+            from_gray_unpacked::<1, 2>(image, raster, LUT_1);
+        }
+        RasterImageFormat::BitmapMonoPacked => {
+            from_gray_packed::<1, 2>(image, raster, LUT_1);
+        }
+        RasterImageFormat::BitmapGray2 => {
+            from_gray_unpacked::<2, 4>(image, raster, LUT_2);
+        }
+        RasterImageFormat::BitmapGray2Packed => {
+            from_gray_packed::<2, 4>(image, raster, LUT_2);
+        }
+        RasterImageFormat::BitmapGray4 => {
+            from_gray_unpacked::<4, 16>(image, raster, LUT_4);
+        }
+        RasterImageFormat::BitmapGray4Packed => {
+            from_gray_packed::<4, 16>(image, raster, LUT_4);
+        }
+        RasterImageFormat::BitmapGray8 => {
+            for (byte, dst) in raster.data.iter().zip(image.iter_mut()) {
+                *dst = u32::from_be_bytes([*byte, 255, 255, 255]);
+            }
+        }
+        _ => return None,
+    }
+
+    let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
+    final_image.draw_image_with_size_at(
+        cached.width as f32,
+        cached.height as f32,
+        raster.x as f32 * scale,
+        raster.y as f32 * scale,
+        &raqote::Image {
+            width: raster.width as i32,
+            height: raster.height as i32,
+            data: &*image,
+        },
+        &DrawOptions {
+            blend_mode: raqote::BlendMode::Src,
+            antialias: raqote::AntialiasMode::None,
+            ..Default::default()
+        },
+    );
+
+    let mut final_image = final_image.into_vec();
+    for argb in final_image.iter_mut() {
+        let [a, r, g, b] = argb.to_be_bytes();
+        *argb = u32::from_le_bytes([r, g, b, a]);
+    }
+
+    Some((*cached, final_image))
+}
+
+fn from_gray_unpacked<const BITS: usize, const ENTRIES: usize>(
+    image: &mut [u32],
+    raster: RasterGlyphImage,
+    steps: [u8; ENTRIES],
+) {
+    for (bits, dst) in raster
+        .data
+        .chunks((raster.width as usize / (8 / BITS)) + 1)
+        .zip(image.chunks_mut(raster.width as usize))
+    {
+        let bits = BitSlice::<_, Lsb0>::from_slice(bits);
+        for (bits, dst) in bits.chunks(BITS).zip(dst.iter_mut()) {
+            let mut index = 0;
+            for idx in bits.iter_ones() {
+                index |= 1 << (BITS - idx - 1);
+            }
+            let value = steps[index as usize];
+            *dst = u32::from_be_bytes([value, 255, 255, 255]);
+        }
+    }
+}
+
+fn from_gray_packed<const BITS: usize, const ENTRIES: usize>(
+    image: &mut [u32],
+    raster: RasterGlyphImage,
+    steps: [u8; ENTRIES],
+) {
+    let bits = BitSlice::<_, Lsb0>::from_slice(raster.data);
+    for (bits, dst) in bits.chunks(BITS).zip(image.iter_mut()) {
+        let mut index = 0;
+        for idx in bits.iter_ones() {
+            index |= 1 << (BITS - idx - 1);
+        }
+        let value = steps[index as usize];
+        *dst = u32::from_be_bytes([value, 255, 255, 255]);
+    }
+}
+
+const LUT_1: [u8; 2] = [0, 255];
+const LUT_2: [u8; 4] = [0, 255 / 3, 2 * (255 / 3), 255];
+const LUT_4: [u8; 16] = [
+    0,
+    (255 / 15),
+    2 * (255 / 15),
+    3 * (255 / 15),
+    4 * (255 / 15),
+    5 * (255 / 15),
+    6 * (255 / 15),
+    7 * (255 / 15),
+    8 * (255 / 15),
+    9 * (255 / 15),
+    10 * (255 / 15),
+    11 * (255 / 15),
+    12 * (255 / 15),
+    13 * (255 / 15),
+    14 * (255 / 15),
+    255,
+];
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use image::load_from_memory;
+    use image::GenericImageView;
+    use image::ImageBuffer;
+    use image::Rgba;
+    use ratatui::backend::Backend;
+    use ratatui::buffer::Buffer;
+    use ratatui::buffer::Cell;
+    use ratatui::layout::Constraint;
+    use ratatui::layout::Layout;
+    use ratatui::layout::Position;
+    use ratatui::layout::Rect;
+    use ratatui::layout::Size;
+    use ratatui::style::Color;
+    use ratatui::style::Modifier;
+    use ratatui::style::Style;
+    use ratatui::style::Styled;
+    use ratatui::style::Stylize;
+    use ratatui::text::Line;
+    use ratatui::text::Span;
+    use ratatui::widgets::Block;
+    use ratatui::widgets::Paragraph;
+    use ratatui::widgets::Wrap;
+    use ratatui::Terminal;
+    use rustybuzz::ttf_parser::RasterGlyphImage;
+    use rustybuzz::ttf_parser::RasterImageFormat;
+    use serial_test::serial;
+    use wgpu::wgt::PollType;
+    use wgpu::Backends;
+    use wgpu::CommandEncoder;
+    use wgpu::CommandEncoderDescriptor;
+    use wgpu::Device;
+    use wgpu::Extent3d;
+    use wgpu::FilterMode;
+    use wgpu::PowerPreference;
+    use wgpu::Queue;
+    use wgpu::SurfaceConfiguration;
+    use wgpu::TextureFormat;
+    use wgpu::TextureView;
+
+    use crate::backend::private::Token;
+    use crate::backend::wgpu_backend::extract_bw_image;
+    use crate::backend::wgpu_backend::WgpuBackend;
+    use crate::backend::wgpu_backend::LUT_2;
+    use crate::backend::wgpu_backend::LUT_4;
+    use crate::backend::ClearPolicy;
+    use crate::backend::FrameStats;
+    use crate::backend::HeadlessSurface;
+    use crate::backend::ImageDesc;
+    use crate::backend::ImagePlacement;
+    use crate::backend::PostProcessor;
+    use crate::backend::RenderCallback;
+    use crate::backend::RenderSurface;
+    use crate::backend::TextureSurface;
+    use crate::shaders::BloomPostProcessor;
+    use crate::shaders::BloomSettings;
+    use crate::shaders::BlurPostProcessor;
+    use crate::shaders::Chain;
+    use crate::shaders::ChromaticAberrationPostProcessor;
+    use crate::shaders::CrtPostProcessor;
+    use crate::shaders::CrtSettings;
+    use crate::shaders::DefaultPostProcessor;
+    use crate::shaders::Lut;
+    use crate::shaders::LutPostProcessor;
+    use crate::shaders::PersistencePostProcessor;
+    use crate::shaders::ScanlinePostProcessor;
+    use crate::shaders::ScanlineSettings;
+    use crate::shaders::VignettePostProcessor;
+    use crate::shaders::VignetteSettings;
+    use crate::utils::text_atlas::CacheRect;
+    use crate::utils::text_atlas::Entry;
+    use crate::BaselineMode;
+    use crate::Builder;
+    use crate::CursorStyle;
+    use crate::Dimensions;
+    use crate::EmojiMode;
+    use crate::Error;
+    use crate::Font;
+    use crate::Fonts;
+    use crate::Selection;
+    use crate::SubpixelLayout;
+    use crate::UnderlineStyle;
+    use crate::Viewport;
+    use crate::XTERM_PALETTE;
+
+    fn tex2buffer(
+        device: &Device,
+        queue: &Queue,
+        surface: &HeadlessSurface,
+    ) {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            surface.texture.as_ref().unwrap().as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: surface.buffer.as_ref().unwrap(),
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(surface.buffer_width),
+                    rows_per_image: Some(surface.height),
+                },
+            },
+            Extent3d {
+                width: surface.width,
+                height: surface.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Copy the current contents of the surface into an image.
+    fn read_image<P: PostProcessor>(
+        backend: &WgpuBackend<'_, 'static, P, HeadlessSurface>
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        ImageBuffer::from_raw(
+            backend.surface.width,
+            backend.surface.height,
+            backend.read_pixels().unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Copy the current contents of an rgba texture.
+    fn read_texture<P: PostProcessor>(
+        backend: &WgpuBackend<'_, 'static, P, HeadlessSurface>,
+        texture: &wgpu::Texture,
+    ) -> Vec<u8> {
+        let row_len = texture.width() * 4;
+        let bytes_per_row = row_len.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = backend.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (bytes_per_row * texture.height()) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = backend
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        backend.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (send, recv) = oneshot::channel();
+        slice.map_async(wgpu::MapMode::Read, move |data| {
+            send.send(data).unwrap();
+        });
+        backend
+            .device
+            .poll(PollType::Wait {
+                submission_index: None,
+                timeout: None,
+            })
+            .unwrap();
+        recv.recv().unwrap().unwrap();
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks_exact(bytes_per_row as usize)
+            .flat_map(|row| &row[..row_len as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+        pixels
+    }
+
+    #[test]
+    #[serial]
+    fn a_z() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                let block = Block::bordered();
+                let area = block.inner(f.area());
+                f.render_widget(block, f.area());
+                f.render_widget(Paragraph::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ"), area);
+            })
+            .unwrap();
+
+        let surface = &terminal.backend().surface;
+        tex2buffer(
+            &terminal.backend().device,
+            &terminal.backend().queue,
+            surface,
+        );
+        {
+            let buffer = surface.buffer.as_ref().unwrap().slice(..);
+
+            let (send, recv) = oneshot::channel();
+            buffer.map_async(wgpu::MapMode::Read, move |data| {
+                send.send(data).unwrap();
+            });
+            terminal
+                .backend()
+                .device
+                .poll(PollType::Wait {
+                    submission_index: None,
+                    timeout: None,
+                })
+                .unwrap();
+            recv.recv().unwrap().unwrap();
+
+            let data = buffer.get_mapped_range();
+            let image =
+                ImageBuffer::<Rgba<u8>, _>::from_raw(surface.width, surface.height, data).unwrap();
+
+            let pixels = image.pixels().copied().collect::<Vec<_>>();
+            let golden = load_from_memory(include_bytes!("goldens/a_z.png")).unwrap();
+            let golden_pixels = golden.pixels().map(|(_, _, px)| px).collect::<Vec<_>>();
+
+            assert!(
+                pixels == golden_pixels,
+                "Rendered image differs from golden"
+            );
+        }
+
+        surface.buffer.as_ref().unwrap().unmap();
+    }
+
+    #[test]
+    #[serial]
+    fn a_z_no_synthetic_bold() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .with_synthetic_bold(false)
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                let block = Block::bordered();
+                let area = block.inner(f.area());
+                f.render_widget(block, f.area());
+                f.render_widget(Paragraph::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ".bold()), area);
+            })
+            .unwrap();
+
+        let surface = &terminal.backend().surface;
+        tex2buffer(
+            &terminal.backend().device,
+            &terminal.backend().queue,
+            surface,
+        );
+        {
+            let buffer = surface.buffer.as_ref().unwrap().slice(..);
+
+            let (send, recv) = oneshot::channel();
+            buffer.map_async(wgpu::MapMode::Read, move |data| {
+                send.send(data).unwrap();
+            });
+            terminal
+                .backend()
+                .device
+                .poll(PollType::Wait {
+                    submission_index: None,
+                    timeout: None,
+                })
+                .unwrap();
+            recv.recv().unwrap().unwrap();
+
+            let data = buffer.get_mapped_range();
+            let image =
+                ImageBuffer::<Rgba<u8>, _>::from_raw(surface.width, surface.height, data).unwrap();
+
+            let pixels = image.pixels().copied().collect::<Vec<_>>();
+            let golden = load_from_memory(include_bytes!("goldens/a_z.png")).unwrap();
+            let golden_pixels = golden.pixels().map(|(_, _, px)| px).collect::<Vec<_>>();
+
+            assert!(
+                pixels == golden_pixels,
+                "Rendered image differs from golden"
+            );
+        }
+
+        surface.buffer.as_ref().unwrap().unmap();
+    }
+
+    #[test]
+    #[serial]
+    fn italic_overhang() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let cell_width = Fonts::new(font.clone(), 24).min_width_px();
+
+        for overhang in [false, true] {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(font.clone())
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(cell_width * 16).unwrap(),
+                            height: NonZeroU32::new(24).unwrap(),
+                        })
+                        .with_synthetic_italic_overhang(overhang)
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("M".italic()), f.area());
+                })
+                .unwrap();
+
+            let image = read_image(terminal.backend());
+
+            let second_cell_inked = image
+                .enumerate_pixels()
+                .filter(|(x, _, _)| (cell_width..cell_width * 2).contains(x))
+                .any(|(_, _, px)| px.0 != [255, 255, 255, 255]);
+
+            assert_eq!(second_cell_inked, overhang);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn subpixel_aa() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let cell_width = Fonts::new(font.clone(), 24).min_width_px();
+
+        for layout in [None, Some(SubpixelLayout::Rgb), Some(SubpixelLayout::Bgr)] {
+            let mut builder = Builder::<DefaultPostProcessor>::from_font(font.clone())
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(cell_width * 16).unwrap(),
+                    height: NonZeroU32::new(24).unwrap(),
+                });
+            if let Some(layout) = layout {
+                builder = builder.with_subpixel_aa(layout);
+            }
+
+            let mut terminal =
+                Terminal::new(futures_lite::future::block_on(builder.build_headless()).unwrap())
+                    .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("Hello"), f.area());
+                })
+                .unwrap();
+
+            let image = read_image(terminal.backend());
+
+            let inked = image.pixels().any(|px| px.0[..3] != [255, 255, 255]);
+            assert!(inked);
+
+            // Black on white, so a pixel which is more red than blue has less coverage
+            // on its red subpixel.
+            let red_edges = image.pixels().filter(|px| px.0[0] > px.0[2]).count();
+            let blue_edges = image.pixels().filter(|px| px.0[2] > px.0[0]).count();
+            match layout {
+                None => assert_eq!((red_edges, blue_edges), (0, 0)),
+                Some(_) => assert!(red_edges > 0 && blue_edges > 0),
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn text_gamma() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let cell_width = Fonts::new(font.clone(), 24).min_width_px();
+
+        let mut images = vec![];
+        for gamma in [1.0, 2.2] {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(font.clone())
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(cell_width * 16).unwrap(),
+                            height: NonZeroU32::new(24).unwrap(),
+                        })
+                        .with_fg_color(Color::Rgb(255, 255, 255))
+                        .with_bg_color(Color::Rgb(0, 0, 0))
+                        .with_text_gamma(gamma)
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("O"), f.area());
+                })
+                .unwrap();
+
+            images.push(
+                read_image(terminal.backend())
+                    .pixels()
+                    .map(|px| px.0[0])
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        // White on black, so the gamma 1.0 value is the glyph coverage.
+        let mut edges = 0;
+        for (&linear, &corrected) in images[0].iter().zip(images[1].iter()) {
+            if linear == 0 || linear == 255 {
+                assert_eq!(linear, corrected);
+                continue;
+            }
+
+            edges += 1;
+            let expected = ((linear as f32 / 255.0).powf(1.0 / 2.2) * 255.0).round();
+            assert!(
+                (corrected as f32 - expected).abs() <= 2.0,
+                "Expected {expected} for coverage {linear}, got {corrected}"
+            );
+        }
+        assert!(edges > 0);
+    }
+
+    #[test]
+    #[serial]
+    fn strikethrough() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let cell_width = Fonts::new(font.clone(), 24).min_width_px();
+
+        for (style, bands) in [
+            (Style::new().crossed_out(), 1),
+            (Style::new().underlined(), 1),
+            (Style::new().crossed_out().underlined(), 2),
+        ] {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(font.clone())
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(cell_width * 16).unwrap(),
+                            height: NonZeroU32::new(24).unwrap(),
+                        })
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new(" ".set_style(style)), f.area());
+                })
+                .unwrap();
+
+            let image = read_image(terminal.backend());
+
+            let inked = (0..image.height())
+                .map(|y| image.get_pixel(cell_width / 2, y).0 != [255, 255, 255, 255])
+                .collect::<Vec<_>>();
+            let found =
+                inked.windows(2).filter(|rows| !rows[0] && rows[1]).count() + usize::from(inked[0]);
+
+            assert_eq!(found, bands, "{style:?}");
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn underline_thickness_and_offset() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let underline_rows = |thickness: Option<f32>, offset: Option<f32>| {
+            let mut builder = Builder::<DefaultPostProcessor>::from_font(font.clone())
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                });
+            if let Some(thickness) = thickness {
+                builder = builder.with_underline_thickness(thickness);
+            }
+            if let Some(offset) = offset {
+                builder = builder.with_underline_offset(offset);
+            }
+            let mut terminal =
+                Terminal::new(futures_lite::future::block_on(builder.build_headless()).unwrap())
+                    .unwrap();
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("    ".underlined()), f.area()))
+                .unwrap();
+
+            let image = read_image(terminal.backend());
+            (0..image.height())
+                .filter(|&y| image.get_pixel(2, y).0 != [255, 255, 255, 255])
+                .collect::<Vec<_>>()
+        };
+
+        let font_rows = underline_rows(None, None);
+        assert!(!font_rows.is_empty());
+
+        let rows = underline_rows(Some(3.0), Some(2.0));
+        assert_eq!(
+            rows,
+            (font_rows[0] + 2..font_rows[0] + 5).collect::<Vec<_>>()
+        );
+
+        let rows = underline_rows(None, Some(-4.0));
+        assert_eq!(rows, font_rows.iter().map(|y| y - 4).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[serial]
+    fn underline_styles() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let cell_width = Fonts::new(font.clone(), 24).min_width_px();
+
+        for style in [
+            UnderlineStyle::Straight,
+            UnderlineStyle::Curly,
+            UnderlineStyle::Double,
+            UnderlineStyle::Dotted,
+            UnderlineStyle::Dashed,
+        ] {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(font.clone())
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(cell_width * 16).unwrap(),
+                            height: NonZeroU32::new(24).unwrap(),
+                        })
+                        .with_underline_style(style)
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("    ".underlined()), f.area());
+                })
+                .unwrap();
+
+            let image = read_image(terminal.backend());
+
+            let inked = |x: u32| {
+                (0..image.height())
+                    .filter(|y| image.get_pixel(x, *y).0 != [255, 255, 255, 255])
+                    .collect::<Vec<_>>()
+            };
+            let columns = (0..cell_width * 4).map(inked).collect::<Vec<_>>();
+
+            match style {
+                UnderlineStyle::Straight => {
+                    assert!(columns.iter().all(|rows| rows == &columns[0]));
+                    assert!(columns[0].windows(2).all(|y| y[1] == y[0] + 1));
+                }
+                UnderlineStyle::Curly => {
+                    assert!(columns.iter().all(|rows| !rows.is_empty()));
+                    assert!(columns.iter().any(|rows| rows[0] != columns[0][0]));
+                }
+                UnderlineStyle::Double => {
+                    assert!(columns.iter().all(|rows| rows == &columns[0]));
+                    let gaps = columns[0].windows(2).filter(|y| y[1] != y[0] + 1).count();
+                    assert_eq!(gaps, 1);
+                }
+                _ => {
+                    // The pattern continues across cell boundaries, so every complete
+                    // dot (or dash) and gap has the same width.
+                    let mut runs = vec![];
+                    for column in &columns {
+                        match runs.last_mut() {
+                            Some((on, len)) if *on != column.is_empty() => *len += 1,
+                            _ => runs.push((!column.is_empty(), 1)),
+                        }
+                    }
+                    runs.pop();
+
+                    let on = runs.iter().filter(|(on, _)| *on).collect::<Vec<_>>();
+                    let off = runs.iter().filter(|(on, _)| !*on).collect::<Vec<_>>();
+                    assert!(on.len() > 1 && off.len() > 1, "{runs:?}");
+                    assert!(on.iter().all(|run| run.1 == on[0].1), "{runs:?}");
+                    assert!(off.iter().all(|run| run.1 == off[0].1), "{runs:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_styles() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.min_width_px(), fonts.cell_height_px());
+        let white = Rgba([255, 255, 255, 255]);
+
+        for style in [CursorStyle::Block, CursorStyle::Bar, CursorStyle::Underline] {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(font.clone())
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(cell_width * 16).unwrap(),
+                            height: NonZeroU32::new(cell_height).unwrap(),
+                        })
+                        .with_cursor_style(style)
+                        .with_cursor_color(Color::Rgb(255, 0, 0))
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("    "), f.area());
+                    f.set_cursor_position((1, 0));
+                })
+                .unwrap();
+
+            let image = read_image(terminal.backend());
+            let red = |x: u32, y: u32| image.get_pixel(x, y).0 == [255, 0, 0, 255];
+            assert_eq!(*image.get_pixel(cell_width / 2, cell_height / 2), white);
+            match style {
+                CursorStyle::Block => {
+                    assert!(red(cell_width + cell_width / 2, cell_height / 2));
+                }
+                CursorStyle::Bar => {
+                    assert!(red(cell_width, cell_height / 2));
+                    assert!(!red(cell_width + cell_width / 2, cell_height / 2));
+                }
+                CursorStyle::Underline => {
+                    assert!(red(cell_width + cell_width / 2, cell_height - 1));
+                    assert!(!red(cell_width + cell_width / 2, cell_height / 2));
+                }
+            }
+
+            // Moving the cursor erases it from the old cell, and hiding it erases it
+            // entirely.
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("    "), f.area());
+                    f.set_cursor_position((3, 0));
+                })
+                .unwrap();
+            let image = read_image(terminal.backend());
+            assert!((cell_width..cell_width * 2)
+                .all(|x| (0..cell_height).all(|y| *image.get_pixel(x, y) == white)));
+            assert!((cell_width * 3..cell_width * 4)
+                .any(|x| (0..cell_height).any(|y| *image.get_pixel(x, y) != white)));
+
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("    "), f.area()))
+                .unwrap();
+            let image = read_image(terminal.backend());
+            assert!(image.pixels().all(|px| *px == white));
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn needs_redraw() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let build = |builder: Builder<'static, DefaultPostProcessor>| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    builder
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(512).unwrap(),
+                            height: NonZeroU32::new(72).unwrap(),
+                        })
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+                .unwrap();
+            terminal
+        };
+
+        let mut terminal = build(Builder::from_font(font.clone()));
+        let backend = terminal.backend_mut();
+        assert!(!backend.needs_redraw());
+        assert_eq!(backend.next_redraw_at(), None);
+
+        // Drawing, moving the cursor and selecting all need a frame.
+        let cell = Cell::new("x");
+        backend.draw([(0, 0, &cell)].into_iter()).unwrap();
+        assert!(backend.needs_redraw());
+        assert!(backend.next_redraw_at().unwrap() <= Instant::now());
+        backend.flush().unwrap();
+        assert!(!backend.needs_redraw());
+
+        // The terminal hid the cursor, which is shown again here.
+        backend.show_cursor().unwrap();
+        assert!(backend.needs_redraw());
+        backend.flush().unwrap();
+        backend.set_cursor_position((3, 1)).unwrap();
+        assert!(backend.needs_redraw());
+        backend.flush().unwrap();
+        assert!(!backend.needs_redraw());
+
+        backend.set_selection(Some(Selection::Block(Rect::new(0, 0, 2, 1))));
+        assert!(backend.needs_redraw());
+        backend.flush().unwrap();
+        assert!(!backend.needs_redraw());
+        assert_eq!(backend.next_redraw_at(), None);
+
+        // Blinking is scheduled without needing a frame until it is due.
+        let mut terminal = build(
+            Builder::from_font(font.clone()).with_cursor_blink(Some(Duration::from_millis(200))),
+        );
+        let backend = terminal.backend_mut();
+        assert_eq!(backend.next_redraw_at(), None);
+        backend.show_cursor().unwrap();
+        backend.flush().unwrap();
+        let blink = backend.next_redraw_at().unwrap();
+        assert!(!backend.needs_redraw());
+        assert!(blink > Instant::now());
+        assert!(blink <= Instant::now() + Duration::from_millis(200));
+        std::thread::sleep(blink.saturating_duration_since(Instant::now()));
+        assert!(backend.needs_redraw());
+        backend.flush().unwrap();
+        assert!(backend.next_redraw_at().unwrap() > blink);
+
+        // Changes wait for the frame interval to pass.
+        let mut terminal = build(Builder::from_font(font).with_max_fps(10));
+        let backend = terminal.backend_mut();
+        backend.draw([(0, 0, &cell)].into_iter()).unwrap();
+        let presented = backend.last_present.unwrap();
+        assert!(backend.next_redraw_at().unwrap() >= presented + Duration::from_millis(100));
+    }
+
+    #[test]
+    #[serial]
+    fn render_callbacks() {
+        fn clear_with(
+            color: wgpu::Color,
+            count: Arc<AtomicUsize>,
+        ) -> RenderCallback {
+            Box::new(move |encoder, view| {
+                count.fetch_add(1, Ordering::Relaxed);
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(color),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    })],
+                    ..Default::default()
+                });
+            })
+        }
+
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+        let cell = Cell::new("x");
+
+        // The overlay is drawn over the post processed frame.
+        let overlays = Arc::new(AtomicUsize::new(0));
+        backend.set_overlay_callback(Some(clear_with(wgpu::Color::RED, overlays.clone())));
+        backend.draw([(0, 0, &cell)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(overlays.load(Ordering::Relaxed), 1);
+        assert!(read_image(&backend)
+            .pixels()
+            .all(|&Rgba([r, g, b, _])| r == 255 && g == 0 && b == 0));
+
+        // Frames without changes aren't rendered.
+        backend.flush().unwrap();
+        assert_eq!(overlays.load(Ordering::Relaxed), 1);
+
+        // The pre process callback draws into the text the post processor reads.
+        let pre_processed = Arc::new(AtomicUsize::new(0));
+        backend.set_overlay_callback(None);
+        backend
+            .set_pre_process_callback(Some(clear_with(wgpu::Color::GREEN, pre_processed.clone())));
+        backend.draw([(1, 0, &cell)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(pre_processed.load(Ordering::Relaxed), 1);
+        assert_eq!(overlays.load(Ordering::Relaxed), 1);
+        assert!(read_image(&backend)
+            .pixels()
+            .all(|&Rgba([r, g, b, _])| r < 50 && g > 200 && b < 50));
+    }
+
+    #[test]
+    #[serial]
+    fn draw_image() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+        let (cell_width, cell_height) = (
+            backend.fonts.cell_width_px(),
+            backend.fonts.cell_height_px(),
+        );
+        let black = Cell::new(" ").set_bg(Color::Rgb(0, 0, 0)).clone();
+        backend
+            .draw([(0, 0, &black), (1, 0, &black), (2, 0, &black)].into_iter())
+            .unwrap();
+        let center = |backend: &WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>,
+                      column: u32| {
+            *read_image(backend).get_pixel(column * cell_width + cell_width / 2, cell_height / 2)
+        };
+
+        // A red and a blue pixel stretched over two cells.
+        backend
+            .draw_image(
+                Rect::new(0, 0, 2, 1),
+                &[255, 0, 0, 255, 0, 0, 255, 255],
+                2,
+                1,
+            )
+            .unwrap();
+        backend.flush().unwrap();
+        let Rgba([r, g, b, _]) = center(&backend, 0);
+        assert!(r > 200 && g < 50 && b < 50, "{r} {g} {b}");
+        let Rgba([r, g, b, _]) = center(&backend, 1);
+        assert!(r < 50 && g < 50 && b > 200, "{r} {g} {b}");
+
+        // The image survives frames which redraw other cells.
+        backend.draw([(2, 0, &black)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let Rgba([r, _, _, _]) = center(&backend, 0);
+        assert!(r > 200);
+
+        // Text drawn into a cell replaces the image there.
+        let text = Cell::new("█")
+            .set_fg(Color::Rgb(0, 255, 0))
+            .set_bg(Color::Rgb(0, 0, 0))
+            .clone();
+        backend.draw([(1, 0, &text)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let Rgba([r, _, _, _]) = center(&backend, 0);
+        assert!(r > 200);
+        let Rgba([r, g, b, _]) = center(&backend, 1);
+        assert!(r < 50 && g > 200 && b < 50, "{r} {g} {b}");
+
+        // Clearing the images restores the text beneath them.
+        backend.clear_images();
+        assert!(backend.needs_redraw());
+        backend.flush().unwrap();
+        let Rgba([r, g, b, _]) = center(&backend, 0);
+        assert!(r < 50 && g < 50 && b < 50, "{r} {g} {b}");
+        assert!(backend.images.is_empty());
+
+        assert!(matches!(
+            backend.draw_image(Rect::new(0, 0, 1, 1), &[0; 7], 1, 2),
+            Err(crate::Error::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn image_layer() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+        let (cell_width, cell_height) = (
+            backend.fonts.cell_width_px(),
+            backend.fonts.cell_height_px(),
+        );
+        let black = Cell::new(" ").set_bg(Color::Rgb(0, 0, 0)).clone();
+        let text = Cell::new("█")
+            .set_fg(Color::Rgb(0, 255, 0))
+            .set_bg(Color::Rgb(0, 0, 0))
+            .clone();
+        backend
+            .draw([(0, 0, &black), (1, 0, &black), (2, 0, &black)].into_iter())
+            .unwrap();
+        let center = |backend: &WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>,
+                      column: u32| {
+            let Rgba([r, g, b, _]) = *read_image(backend)
+                .get_pixel(column * cell_width + cell_width / 2, cell_height / 2);
+            [r, g, b].map(|c| c > 127)
+        };
+        let image = |rgba, placement, z_index| ImageDesc {
+            rgba,
+            width: 1,
+            height: 1,
+            placement,
+            z_index,
+        };
+        const RED: &[u8] = &[255, 0, 0, 255];
+        const BLUE: &[u8] = &[0, 0, 255, 255];
+
+        // Images under the text show through blank cells.
+        backend
+            .add_image(
+                1,
+                image(RED, ImagePlacement::Cells(Rect::new(0, 0, 1, 1)), -1),
+            )
+            .unwrap();
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [true, false, false]);
+
+        backend.draw([(0, 0, &text)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [false, true, false]);
+
+        // Images over the text cover it until they are removed.
+        backend
+            .add_image(
+                2,
+                image(BLUE, ImagePlacement::Cells(Rect::new(0, 0, 1, 1)), 0),
+            )
+            .unwrap();
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [false, false, true]);
+        assert!(backend.remove_image(2));
+        assert!(!backend.remove_image(2));
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [false, true, false]);
+
+        // The layer survives the text being replaced and cleared.
+        backend.draw([(0, 0, &black)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [true, false, false]);
+        backend.clear().unwrap();
+        backend
+            .draw([(0, 0, &black), (1, 0, &black), (2, 0, &black)].into_iter())
+            .unwrap();
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [true, false, false]);
+
+        // Replacing an image by id, now placed in pixels and clipped by the grid.
+        backend
+            .add_image(
+                1,
+                image(
+                    BLUE,
+                    ImagePlacement::Pixels {
+                        x: -5,
+                        y: 0,
+                        width: cell_width * 2 + 5,
+                        height: cell_height,
+                    },
+                    1,
+                ),
+            )
+            .unwrap();
+        backend.flush().unwrap();
+        assert_eq!(center(&backend, 0), [false, false, true]);
+        assert_eq!(center(&backend, 1), [false, false, true]);
+        assert_ne!(center(&backend, 2), [false, false, true]);
+        assert_eq!(backend.layer_images.len(), 1);
+
+        assert!(matches!(
+            backend.add_image(
+                3,
+                ImageDesc {
+                    width: 2,
+                    ..image(RED, ImagePlacement::Cells(Rect::new(0, 0, 1, 1)), 0)
+                }
+            ),
+            Err(crate::Error::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn msaa() {
+        /// Draws a white triangle with a diagonal edge over a black surface.
+        struct Triangle {
+            format: TextureFormat,
+            pipeline: wgpu::RenderPipeline,
+        }
+
+        fn build_triangle(
+            device: &Device,
+            format: TextureFormat,
+            sample_count: u32,
+        ) -> wgpu::RenderPipeline {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(
+                    "@vertex fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) \
+                     vec4<f32> {
+                        let corners = array(vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(1.0, -1.0));
+                        return vec4(corners[i], 0.0, 1.0);
+                    }
+                    @fragment fn fs_main() -> @location(0) vec4<f32> {
+                        return vec4(1.0);
+                    }"
+                    .into(),
+                ),
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: None,
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: None,
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: None,
+                    compilation_options: Default::default(),
+                    targets: &[Some(format.into())],
+                }),
+                multiview_mask: None,
+                cache: None,
+            })
+        }
+
+        impl PostProcessor for Triangle {
+            type UserData = ();
+
+            fn compile(
+                device: &Device,
+                _text_view: &TextureView,
+                surface_config: &SurfaceConfiguration,
+                _user_data: Self::UserData,
+            ) -> Self {
+                Self {
+                    format: surface_config.format,
+                    pipeline: build_triangle(device, surface_config.format, 1),
+                }
+            }
+
+            fn resize(
+                &mut self,
+                _device: &Device,
+                _text_view: &TextureView,
+                _surface_config: &SurfaceConfiguration,
+            ) {
+            }
+
+            fn process(
+                &mut self,
+                encoder: &mut CommandEncoder,
+                _queue: &Queue,
+                _text_view: &TextureView,
+                _surface_config: &SurfaceConfiguration,
+                surface_view: &TextureView,
+            ) {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: surface_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    })],
+                    ..Default::default()
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.draw(0..3, 0..1);
+            }
+
+            fn enable_multisampling(
+                &mut self,
+                device: &Device,
+                _surface_config: &SurfaceConfiguration,
+                sample_count: u32,
+            ) -> bool {
+                self.pipeline = build_triangle(device, self.format, sample_count);
+                true
+            }
+        }
+
+        let render = |sample_count: u32| {
+            let mut backend = futures_lite::future::block_on(
+                Builder::<Triangle>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(64).unwrap(),
+                    height: NonZeroU32::new(64).unwrap(),
+                })
+                .with_msaa(sample_count)
+                .build_headless(),
+            )
+            .unwrap();
+            backend.draw([(0, 0, &Cell::new("x"))].into_iter()).unwrap();
+            backend.flush().unwrap();
+            let partial = read_image(&backend)
+                .pixels()
+                .filter(|&&Rgba([r, ..])| r != 0 && r != 255)
+                .count();
+            (backend.sample_count, partial)
+        };
+
+        assert_eq!(render(1), (1, 0));
+        // Unsupported counts fall back to a single sample.
+        assert_eq!(render(3), (1, 0));
+        let (sample_count, partial) = render(4);
+        assert_eq!(sample_count, 4);
+        assert!(partial > 0);
+    }
+
+    #[test]
+    #[serial]
+    fn frame_stats() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+        assert_eq!(backend.frame_stats(), FrameStats::default());
+
+        let a = Cell::new("a");
+        let b = Cell::new("b");
+        backend.draw([(0, 0, &a), (1, 0, &b)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let cells = backend.cells.len();
+        let stats = backend.frame_stats();
+        assert!(stats.cpu_time > Duration::ZERO);
+        // The blank cells have a glyph as well.
+        assert_eq!(stats.atlas_uploads, 3);
+        assert_eq!(stats.dirty_cells, cells);
+
+        // Only the changed row is composited and its glyphs are already cached.
+        backend.draw([(2, 0, &a)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let stats = backend.frame_stats();
+        assert_eq!(stats.atlas_uploads, 0);
+        assert_eq!(stats.dirty_cells, backend.size().unwrap().width as usize);
+        assert!(stats.dirty_cells < cells);
+
+        // Frames which aren't rendered keep the stats.
+        backend.flush().unwrap();
+        assert_eq!(backend.frame_stats(), stats);
+
+        if backend.gpu_timer.is_none() {
+            assert_eq!(stats.gpu_time, None);
+            return;
+        }
+        backend.device.poll(PollType::wait_indefinitely()).unwrap();
+        backend.draw([(3, 0, &b)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert!(backend.frame_stats().gpu_time.is_some());
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_snaps_to_cluster_start() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+        let bounds = backend.size().unwrap();
+        let cjk = Cell::new("中");
+        let family = Cell::new("👨\u{200d}👩\u{200d}👧");
+        let letter = Cell::new("x");
+        backend
+            .draw([(0, 0, &cjk), (2, 0, &family), (4, 0, &letter)].into_iter())
+            .unwrap();
+        backend.show_cursor().unwrap();
+
+        for (column, start) in [(0, 0), (1, 0), (2, 2), (3, 2), (4, 4), (5, 5)] {
+            backend.set_cursor_position((column, 0)).unwrap();
+            assert_eq!(backend.cursor_cell(bounds), Some(start), "column {column}");
+            // The reported position is where the cursor was placed.
+            assert_eq!(
+                backend.get_cursor_position().unwrap(),
+                Position::new(column, 0)
+            );
+        }
+
+        // The cursor covers the whole cluster it snapped to.
+        let cell_width = backend.fonts.cell_width_px() as f32;
+        for start in [0, 2] {
+            let cursor =
+                backend.cursor_instance(start, CursorStyle::Block, Color::Reset, bounds.width);
+            assert_eq!(cursor.position, [start as f32 * cell_width, 0.0]);
+            assert_eq!(cursor.size[0], 2.0 * cell_width);
+        }
+
+        backend.set_extra_cursors(&[
+            (3, 0, CursorStyle::Bar, Color::Reset),
+            (1, 1, CursorStyle::Bar, Color::Reset),
+        ]);
+        assert_eq!(
+            backend
+                .extra_cursor_cells(bounds)
+                .iter()
+                .map(|&(index, ..)| index)
+                .collect::<Vec<_>>(),
+            vec![2, bounds.width as usize + 1]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn fixed_grid() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(256).unwrap(),
+            })
+            .with_fixed_grid((10, 3))
+            .with_clear_color(Color::Rgb(0, 0, 255))
+            .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+        let (cell_width, cell_height) = (
+            backend.fonts.cell_width_px(),
+            backend.fonts.cell_height_px(),
+        );
+        assert_eq!(backend.size().unwrap(), Size::new(10, 3));
+
+        let red = Cell::new(" ").set_bg(Color::Rgb(255, 0, 0)).clone();
+        let check =
+            |backend: &mut WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>| {
+                backend.draw([(9, 2, &red)].into_iter()).unwrap();
+                backend.flush().unwrap();
+
+                let bounds = backend.cell_bounds(9, 2);
+                let (center_x, center_y) = (
+                    bounds.x as u32 + bounds.width as u32 / 2,
+                    bounds.y as u32 + bounds.height as u32 / 2,
+                );
+                assert_eq!(
+                    backend.pixel_to_cell(center_x as f64, center_y as f64),
+                    Some((9, 2))
+                );
+                let image = read_image(backend);
+                assert_eq!(image.get_pixel(center_x, center_y).0, [255, 0, 0, 255]);
+                // The letterbox around the grid.
+                assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+                bounds
+            };
+
+        // The grid keeps its size and scales by whole factors.
+        let bounds = check(&mut backend);
+        let scale = (512 / (10 * cell_width)).min(256 / (3 * cell_height));
+        assert_eq!(bounds.width as u32, cell_width * scale);
+        assert_eq!(bounds.height as u32, cell_height * scale);
+
+        assert_eq!(
+            backend.resize(10 * cell_width * 3 + 7, 3 * cell_height * 3 + 5),
+            Size::new(10, 3)
+        );
+        let bounds = check(&mut backend);
+        assert_eq!(bounds.width as u32, cell_width * 3);
+        assert_eq!(bounds.x as u32, 3 + 9 * cell_width * 3);
+    }
+
+    #[test]
+    #[serial]
+    fn font_metrics() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(144).unwrap(),
+            })
+            .with_font_size_px(12)
+            .with_scale_factor(2.0)
+            .build_headless(),
+        )
+        .unwrap();
+
+        let metrics = backend.font_metrics();
+        assert_eq!(metrics.scale_factor, 2.0);
+        assert_eq!(metrics.cell_width, backend.fonts.cell_width_px());
+        assert_eq!(metrics.cell_height, backend.fonts.cell_height_px());
+        assert!(metrics.ascent > 0.0 && metrics.descent > 0.0);
+        assert!(metrics.line_gap >= 0.0);
+        // The ascent and descent span the physical font size.
+        assert!((metrics.ascent + metrics.descent - 24.0).abs() < 0.5);
+        assert!(metrics.baseline > 0.0 && metrics.baseline < metrics.cell_height as f32);
+
+        backend.set_scale_factor(1.0);
+        let rescaled = backend.font_metrics();
+        assert_eq!(rescaled.scale_factor, 1.0);
+        assert!((rescaled.ascent * 2.0 - metrics.ascent).abs() < 0.01);
+        assert!(rescaled.cell_height < metrics.cell_height);
+    }
+
+    #[test]
+    #[serial]
+    fn zero_width_symbols() {
+        // A yellow square, standing in for the emoji presentation of 'O'.
+        let emoji = with_color_layers(
+            include_bytes!("fonts/CascadiaMono-Regular.ttf"),
+            'O',
+            &[('█', [255, 255, 0, 255])],
+        );
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(font.clone())
+                .with_regular_fonts([font])
+                .with_fallback_fonts([Font::new(&emoji).expect("Invalid font file")])
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(256).unwrap(),
+                    height: NonZeroU32::new(64).unwrap(),
+                })
+                .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+        let render =
+            |backend: &mut WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>,
+             symbols: &[&str]| {
+                backend.clear().unwrap();
+                let cells = symbols
+                    .iter()
+                    .map(|symbol| {
+                        let mut cell = Cell::default();
+                        cell.set_symbol(symbol)
+                            .set_fg(Color::Rgb(255, 255, 255))
+                            .set_bg(Color::Rgb(0, 0, 0));
+                        cell
+                    })
+                    .collect::<Vec<_>>();
+                backend
+                    .draw(
+                        cells
+                            .iter()
+                            .enumerate()
+                            .map(|(x, cell)| (x as u16, 0, cell)),
+                    )
+                    .unwrap();
+                backend.flush().unwrap();
+                read_image(backend)
+            };
+
+        // A combining mark in a cell of its own is drawn over the character before
+        // it, and leaves its cell blank.
+        let separate = render(&mut backend, &["e", "\u{301}", "x"]);
+        assert_eq!(backend.laid_out[0].symbol(), "e\u{301}");
+        assert_eq!(backend.laid_out[1].symbol(), " ");
+        assert!(separate == render(&mut backend, &["e\u{301}", " ", "x"]));
+        assert!(separate != render(&mut backend, &["e", " ", "x"]));
+
+        // Drawing the mark again doesn't stack it.
+        let mut mark = Cell::new("\u{301}");
+        mark.set_fg(Color::Rgb(255, 255, 255))
+            .set_bg(Color::Rgb(0, 0, 0));
+        render(&mut backend, &["e", "\u{301}", "x"]);
+        backend.draw([(1, 0, &mark)].into_iter()).unwrap();
+        assert_eq!(backend.laid_out[0].symbol(), "e\u{301}");
+
+        // The cells keep what was drawn into them, so a later change to either the
+        // mark's cell or the base's cell composes the row again.
+        let cell = |symbol: &str| {
+            let mut cell = Cell::default();
+            cell.set_symbol(symbol)
+                .set_fg(Color::Rgb(255, 255, 255))
+                .set_bg(Color::Rgb(0, 0, 0));
+            cell
+        };
+        render(&mut backend, &["e", "\u{301}", "x"]);
+        backend.draw([(1, 0, &cell("y"))].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(backend.laid_out[0].symbol(), "e");
+        assert_eq!(backend.laid_out[1].symbol(), "y");
+        assert!(read_image(&backend) == render(&mut backend, &["e", "y", "x"]));
+
+        render(&mut backend, &["e", "\u{301}", "x"]);
+        backend.draw([(0, 0, &cell("a"))].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(backend.laid_out[0].symbol(), "a\u{301}");
+        assert!(read_image(&backend) == render(&mut backend, &["a\u{301}", " ", "x"]));
+
+        // Invisible formatting characters don't change the font or the drawing.
+        let plain = render(&mut backend, &["a", "b"]);
+        assert!(
+            render(&mut backend, &["a", "\u{200B}", "b"]) == render(&mut backend, &["a", " ", "b"])
+        );
+        assert_eq!(
+            backend.fonts.font_for_cell(&backend.laid_out[0]).0.id(),
+            backend.fonts.primary().id()
+        );
+        assert!(plain != render(&mut backend, &["a", " ", "b"]));
+
+        // The emoji presentation selector switches to the color glyph.
+        let yellow = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            image
+                .pixels()
+                .any(|&Rgba([r, g, b, _])| r > 200 && g > 200 && b < 64)
+        };
+        assert!(!yellow(&render(&mut backend, &["O"])));
+        assert!(yellow(&render(&mut backend, &["O", "\u{FE0F}"])));
+        assert!(yellow(&render(&mut backend, &["O\u{FE0F}"])));
+        assert!(!yellow(&render(&mut backend, &["O\u{FE0E}"])));
+    }
+
+    #[test]
+    #[serial]
+    fn conflicting_options() {
+        let build = |builder: Builder<'static, DefaultPostProcessor>| {
+            futures_lite::future::block_on(
+                builder
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(128).unwrap(),
+                        height: NonZeroU32::new(64).unwrap(),
+                    })
+                    .build_headless(),
+            )
+        };
+        let builder = || {
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+        };
+
+        for conflicting in [
+            builder()
+                .with_subpixel_aa(SubpixelLayout::Rgb)
+                .with_transparent_background(true),
+            builder()
+                .with_subpixel_aa(SubpixelLayout::Rgb)
+                .with_background_alpha(Color::Blue, 128),
+            builder()
+                .with_fixed_grid((10, 3))
+                .with_viewport(Viewport::uniform_padding(4)),
+            builder().with_fixed_grid((10, 3)).with_min_cells((20, 5)),
+        ] {
+            assert!(matches!(
+                build(conflicting),
+                Err(crate::Error::ConflictingOptions(_))
+            ));
+        }
+
+        // Each option on its own, or with compatible ones, is fine.
+        for compatible in [
+            builder().with_subpixel_aa(SubpixelLayout::Rgb),
+            builder()
+                .with_subpixel_aa(SubpixelLayout::Rgb)
+                .with_background_alpha(Color::Blue, 255),
+            builder()
+                .with_transparent_background(true)
+                .with_premultiplied_alpha(true),
+            builder()
+                .with_fixed_grid((10, 3))
+                .with_viewport(Viewport::Full),
+        ] {
+            assert!(build(compatible).is_ok());
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn cell_bleed() {
+        let render = |bleed| {
+            let mut backend = futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                // A whole number of cells, so the text isn't stretched.
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(120).unwrap(),
+                    height: NonZeroU32::new(48).unwrap(),
+                })
+                .with_cell_bleed(bleed)
+                .build_headless(),
+            )
+            .unwrap();
+            backend.hide_cursor().unwrap();
+            let mut cell = Cell::new("│");
+            cell.set_fg(Color::Rgb(255, 255, 255))
+                .set_bg(Color::Rgb(255, 0, 0));
+            let mut before = Cell::new(" ");
+            before.set_bg(Color::Rgb(0, 255, 0));
+            let mut after = Cell::new(" ");
+            after.set_bg(Color::Rgb(0, 0, 255));
+            backend
+                .draw([(0, 0, &before), (1, 0, &cell), (2, 0, &after)].into_iter())
+                .unwrap();
+            backend.flush().unwrap();
+            backend
+        };
+
+        let plain = render(0.0);
+        let bled = render(1.0);
+        let (cell_width, cell_height) = (
+            plain.fonts.cell_width_px() as f32,
+            plain.fonts.cell_height_px() as f32,
+        );
+
+        // Only the backgrounds change, the glyphs keep their size.
+        assert_eq!(plain.bg_instances[1].size, [cell_width, cell_height]);
+        assert!(bled.bg_instances.len() > plain.bg_instances.len());
+        assert!(
+            bytemuck::cast_slice::<_, u8>(&plain.text_vertices)
+                == bytemuck::cast_slice::<_, u8>(&bled.text_vertices)
+        );
+
+        // The background reaches into the cell before it, which is drawn first, and
+        // is covered by the cell after it.
+        let pixel = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>, x: f32| {
+            *image.get_pixel(x as u32, cell_height as u32 / 2)
+        };
+        let (plain, bled) = (read_image(&plain), read_image(&bled));
+        assert_eq!(pixel(&plain, cell_width - 1.0), Rgba([0, 255, 0, 255]));
+        assert_eq!(pixel(&bled, cell_width - 1.0), Rgba([255, 0, 0, 255]));
+        assert_eq!(pixel(&bled, cell_width * 2.0 - 1.0), Rgba([0, 0, 255, 255]));
+        assert_eq!(pixel(&bled, cell_width * 2.0), Rgba([0, 0, 255, 255]));
+
+        assert_eq!(render(f32::NAN).cell_bleed, 0.0);
+        assert_eq!(render(4.0).cell_bleed, 1.0);
+    }
+
+    #[test]
+    #[serial]
+    fn cell_bleed_incremental() {
+        let build = || {
+            let mut backend = futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(120).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .with_cell_bleed(1.0)
+                .build_headless(),
+            )
+            .unwrap();
+            backend.hide_cursor().unwrap();
+            backend
+        };
+        let cell = |symbol: &str, bg: Color| {
+            let mut cell = Cell::default();
+            cell.set_symbol(symbol).set_bg(bg);
+            cell
+        };
+        let grid = (0..3u16)
+            .flat_map(|y| (0..10u16).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let shade = (x * 25) as u8;
+                (
+                    x,
+                    y,
+                    cell("x", Color::Rgb(shade, 80 * y as u8, 255 - shade)),
+                )
+            })
+            .collect::<Vec<_>>();
+        let changed = cell("o", Color::Rgb(255, 255, 0));
+        let full = grid
+            .iter()
+            .map(|(x, y, cell)| (*x, *y, if (*x, *y) == (4, 1) { &changed } else { cell }))
+            .collect::<Vec<_>>();
+
+        // Redrawing only the changed cell repaints the edges its neighbors bleed
+        // over, and its own bleed over them, the same as drawing everything.
+        let mut incremental = build();
+        incremental
+            .draw(grid.iter().map(|(x, y, cell)| (*x, *y, cell)))
+            .unwrap();
+        incremental.flush().unwrap();
+        incremental.draw([(4, 1, &changed)].into_iter()).unwrap();
+        incremental.flush().unwrap();
+
+        let mut fresh = build();
+        fresh.draw(full.into_iter()).unwrap();
+        fresh.flush().unwrap();
+
+        assert!(read_image(&incremental) == read_image(&fresh));
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_blink() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.min_width_px(), fonts.cell_height_px());
+
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(cell_width * 16).unwrap(),
+                        height: NonZeroU32::new(cell_height).unwrap(),
+                    })
+                    .with_cursor_color(Color::Rgb(255, 0, 0))
+                    .with_cursor_blink(Some(Duration::from_millis(200)))
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let cursor_at = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>, x: u32| {
+            image
+                .get_pixel(x * cell_width + cell_width / 2, cell_height / 2)
+                .0
+                == [255, 0, 0, 255]
+        };
+
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("    "), f.area());
+                f.set_cursor_position((1, 0));
+            })
+            .unwrap();
+        assert!(cursor_at(&read_image(terminal.backend()), 1));
+        assert!(!terminal.backend().needs_update());
+
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(terminal.backend().needs_update());
+        Backend::flush(terminal.backend_mut()).unwrap();
+        assert!(!cursor_at(&read_image(terminal.backend()), 1));
+
+        // Moving the cursor shows it immediately.
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("    "), f.area());
+                f.set_cursor_position((2, 0));
+            })
+            .unwrap();
+        let image = read_image(terminal.backend());
+        assert!(cursor_at(&image, 2));
+        assert!(!cursor_at(&image, 1));
+    }
+
+    #[test]
+    #[serial]
+    fn transparent_background() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.min_width_px(), fonts.cell_height_px());
+
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(cell_width * 16).unwrap(),
+                        height: NonZeroU32::new(cell_height).unwrap(),
+                    })
+                    .with_transparent_background(true)
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(
+                    Paragraph::new(Line::from(vec!["H".into(), " ".on_blue()])),
+                    f.area(),
+                )
+            })
+            .unwrap();
+
+        let image = read_image(terminal.backend());
+        let cell = |x: u32| {
+            (x * cell_width..(x + 1) * cell_width)
+                .flat_map(|x| (0..cell_height).map(move |y| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+
+        // Text is drawn over a transparent background with premultiplied alpha.
+        let text = cell(0);
+        assert!(text.iter().any(|px| px[3] == 0));
+        assert!(text.iter().any(|px| px[3] == 255));
+        assert!(text.iter().all(|px| px[..3].iter().all(|c| *c <= px[3])));
+
+        assert!(cell(1).iter().all(|px| px[3] == 255 && px[2] > px[0]));
+        assert!(cell(2).iter().all(|px| *px == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    #[serial]
+    fn read_pixels_unaligned() {
+        let render = |format| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(501).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless_with_format(format),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new(Line::from(vec!["Hello ".red(), "World!".blue()])),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+
+            terminal.backend().read_pixels().unwrap()
+        };
+
+        let rgba = render(TextureFormat::Rgba8Unorm);
+        let bgra = render(TextureFormat::Bgra8Unorm);
+
+        assert_eq!(rgba.len(), 501 * 72 * 4);
+        assert!(rgba == bgra, "Bgra readback differs from Rgba readback");
+    }
+
+    #[test]
+    #[serial]
+    fn srgb_surface_gray() {
+        let render = |format| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(256).unwrap(),
+                    })
+                    .with_bg_color(Color::Rgb(128, 128, 128))
+                    .build_headless_with_format(format),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Block::new().style(Style::new().bg(Color::Rgb(128, 128, 128))),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+
+            terminal.backend().read_pixels().unwrap()
+        };
+
+        for format in [TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb] {
+            let pixels = render(format);
+            assert!(
+                pixels.chunks_exact(4).all(|px| px == [128, 128, 128, 255]),
+                "{format:?} doesn't round trip mid gray"
+            );
+        }
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(feature = "testing")]
+    fn assert_frame_matches() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(256).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let draw = |terminal: &mut Terminal<_>, text: &'static str| {
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new(text.green()), f.area()))
+                .unwrap();
+        };
+
+        let dir = std::env::temp_dir();
+        let path = dir.join("ratatui_wgpu_snapshot.png");
+        let actual = dir.join("ratatui_wgpu_snapshot.actual.png");
+        let diff = dir.join("ratatui_wgpu_snapshot.diff.png");
+        let mismatch =
+            |backend: &WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>,
+             tolerance| {
+                let _ = std::fs::remove_file(&actual);
+                let _ = std::fs::remove_file(&diff);
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    backend.assert_frame_matches(&path, tolerance)
+                }))
+                .is_err()
+            };
+
+        // Without a reference, the frame is written out for review.
+        let _ = std::fs::remove_file(&path);
+        draw(&mut terminal, "Hello World!");
+        assert!(mismatch(terminal.backend(), 0));
+        assert!(actual.exists() && !diff.exists());
+
+        std::fs::rename(&actual, &path).unwrap();
+        assert!(!mismatch(terminal.backend(), 0));
+
+        // A different frame writes the frame and highlights the difference.
+        draw(&mut terminal, "Hello There!");
+        assert!(mismatch(terminal.backend(), 0));
+        let highlighted = image::open(&diff).unwrap().into_rgba8();
+        assert!(highlighted.pixels().any(|&px| px == Rgba([255, 0, 0, 255])));
+        assert!(
+            image::open(&actual).unwrap().into_rgba8().into_raw()
+                == terminal.backend().read_pixels().unwrap()
+        );
+
+        // Any difference is within the largest tolerance.
+        assert!(!mismatch(terminal.backend(), u8::MAX));
+
+        for file in [&path, &actual, &diff] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(feature = "png")]
+    fn save_png() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(501).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless_with_format(TextureFormat::Bgra8Unorm),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("Hello World!".green()), f.area());
+            })
+            .unwrap();
+
+        let path = std::env::temp_dir().join("ratatui_wgpu_save_png.png");
+        terminal.backend().save_png(&path).unwrap();
+        let saved = image::open(&path).unwrap().into_rgba8();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved.dimensions(), (501, 72));
+        assert!(
+            saved.into_raw() == terminal.backend().read_pixels().unwrap(),
+            "Saved png differs from readback"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn build_with_device() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let draw = |f: &mut ratatui::Frame| {
+            f.render_widget(Paragraph::new("Hello World!".yellow()), f.area());
+        };
+
+        let mut owned = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        owned.draw(draw).unwrap();
+
+        let config = owned.backend().surface_config.clone();
+        let (device, queue) = futures_lite::future::block_on(async {
+            let adapter = wgpu::Instance::default()
+                .request_adapter(&wgpu::RequestAdapterOptions::default())
+                .await
+                .unwrap();
+            adapter
+                .request_device(&wgpu::DeviceDescriptor::default())
+                .await
+                .unwrap()
+        });
+
+        let (device, queue) = (Arc::new(device), Arc::new(queue));
+
+        // Srgb text formats need a capability only the adapter reports.
+        assert!(matches!(
+            Builder::<DefaultPostProcessor>::from_font(font.clone())
+                .with_text_format(TextureFormat::Rgba8UnormSrgb)
+                .build_with_device(
+                    device.clone(),
+                    queue.clone(),
+                    HeadlessSurface::default(),
+                    config.clone(),
+                ),
+            Err(crate::Error::UnsupportedTextFormat(
+                TextureFormat::Rgba8UnormSrgb
+            ))
+        ));
+
+        let mut shared = Terminal::new(
+            Builder::<DefaultPostProcessor>::from_font(font)
+                .build_with_device(device, queue, HeadlessSurface::default(), config)
+                .unwrap(),
+        )
+        .unwrap();
+        shared.draw(draw).unwrap();
+
+        assert!(
+            read_image(shared.backend()) == read_image(owned.backend()),
+            "Shared device rendering differs"
+        );
+        assert!(owned.backend().adapter_info().is_some());
+        assert!(shared.backend().adapter_info().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn build_with_texture() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let draw = |f: &mut ratatui::Frame| {
+            f.render_widget(Paragraph::new("Hello World!".cyan()), f.area());
+        };
+
+        let mut headless = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        headless.draw(draw).unwrap();
+
+        // Use a second headless surface purely as storage for the caller owned
+        // texture so it can be read back.
+        let backend = headless.backend();
+        let mut external = HeadlessSurface::default();
+        external.configure(&backend.device, &backend.surface_config, Token);
+        let view = external
+            .texture
+            .as_ref()
+            .unwrap()
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut textured = Terminal::new(
+            Builder::<DefaultPostProcessor>::from_font(font)
+                .build_with_texture(
+                    Arc::new(backend.device.clone()),
+                    Arc::new(backend.queue.clone()),
+                    TextureSurface::new(view, 512, 72, TextureFormat::Rgba8Unorm),
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        textured.draw(draw).unwrap();
+
+        let backend = headless.backend();
+        let pixels = RenderSurface::read_pixels(
+            &external,
+            &backend.device,
+            &backend.queue,
+            Rect::new(0, 0, 512, 72),
+            Token,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(
+            pixels == backend.read_pixels().unwrap(),
+            "Texture rendering differs"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn text_texture_view() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.min_width_px(), fonts.cell_height_px());
+
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(cell_width * 64 + 3).unwrap(),
+                        height: NonZeroU32::new(cell_height * 2).unwrap(),
+                    })
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let texture = terminal.backend().text_texture_view().texture();
+        assert_eq!(texture.format(), TextureFormat::Rgba8Unorm);
+        assert_eq!(
+            (texture.width(), texture.height()),
+            (cell_width * 64, cell_height * 2)
+        );
+
+        terminal
+            .backend_mut()
+            .resize(cell_width * 32, cell_height * 3);
+        let texture = terminal.backend().text_texture_view().texture();
+        assert_eq!(
+            (texture.width(), texture.height()),
+            (cell_width * 32, cell_height * 3)
+        );
+    }
+
+    #[test]
+    fn select_present_mode() {
+        use wgpu::PresentMode;
+
+        use crate::backend::select_present_mode;
+
+        let fifo = [PresentMode::Fifo];
+        let mailbox = [PresentMode::Fifo, PresentMode::Mailbox];
+        let immediate = [PresentMode::Fifo, PresentMode::Immediate];
+
+        assert_eq!(
+            select_present_mode(PresentMode::Immediate, &immediate),
+            PresentMode::Immediate
+        );
+        assert_eq!(
+            select_present_mode(PresentMode::Immediate, &mailbox),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            select_present_mode(PresentMode::Immediate, &fifo),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            select_present_mode(PresentMode::AutoNoVsync, &fifo),
+            PresentMode::AutoNoVsync
+        );
+        assert_eq!(
+            select_present_mode(PresentMode::Mailbox, &[]),
+            PresentMode::Mailbox
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn chain() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let dimensions = || Dimensions {
+            width: NonZeroU32::new(512).unwrap(),
+            height: NonZeroU32::new(72).unwrap(),
+        };
+        let draw = |f: &mut ratatui::Frame| {
+            let block = Block::bordered();
+            let area = block.inner(f.area());
+            f.render_widget(block, f.area());
+            f.render_widget(Paragraph::new("Hello World!".magenta()), area);
+        };
+
+        let mut single = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(dimensions())
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        single.draw(draw).unwrap();
+
+        let mut chained = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<Chain<DefaultPostProcessor, DefaultPostProcessor>>::from_font_and_user_data(
+                    font,
+                    ((), ()),
+                )
+                .with_width_and_height(dimensions())
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        chained.draw(draw).unwrap();
+
+        assert!(
+            read_image(chained.backend()) == read_image(single.backend()),
+            "Chained blits differ from a single blit"
+        );
+
+        // The intermediate texture must follow the surface size.
+        single.backend_mut().resize(256, 48);
+        chained.backend_mut().resize(256, 48);
+        single.draw(draw).unwrap();
+        chained.draw(draw).unwrap();
+
+        assert!(
+            read_image(chained.backend()) == read_image(single.backend()),
+            "Chained blits differ from a single blit after resizing"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn crt_post_processor() {
+        let render = |settings: CrtSettings| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<CrtPostProcessor>::from_font_and_user_data(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                        settings,
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(256).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Block::new().on_white(), f.area());
+                })
+                .unwrap();
+
+            (
+                read_image(terminal.backend()),
+                terminal.backend().post_processor().needs_update(),
+            )
+        };
+
+        let (plain, needs_update) = render(CrtSettings::default());
+        assert!(!needs_update);
+
+        // The input is solid white, so every pixel should be affected.
+        assert_eq!(plain.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_ne!(plain.get_pixel(256, 128).0, [255, 255, 255, 255]);
+        assert_ne!(plain.get_pixel(256, 128).0[..3], [0, 0, 0]);
+
+        let luma = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>, x, y| {
+            image.get_pixel(x, y).0[..3]
+                .iter()
+                .map(|c| u32::from(*c))
+                .sum::<u32>()
+        };
+
+        let (vignetted, _) = render(CrtSettings {
+            vignette_strength: 1.0,
+            ..Default::default()
+        });
+        assert!(luma(&vignetted, 40, 128) < luma(&plain, 40, 128));
+        assert!(luma(&vignetted, 40, 128) < luma(&vignetted, 256, 128));
+
+        let (_, needs_update) = render(CrtSettings {
+            flicker: 0.05,
+            ..Default::default()
+        });
+        assert!(needs_update);
+    }
+
+    #[test]
+    #[serial]
+    fn blur_post_processor() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let dimensions = || Dimensions {
+            width: NonZeroU32::new(512).unwrap(),
+            height: NonZeroU32::new(72).unwrap(),
+        };
+        let draw = |f: &mut ratatui::Frame| {
+            let [left, right] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(f.area());
+            f.render_widget(Block::new().on_white(), left);
+            f.render_widget(Block::new().on_black(), right);
+        };
+
+        let mut sharp = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(dimensions())
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        sharp.draw(draw).unwrap();
+        let sharp = read_image(sharp.backend());
+
+        let mut blurred = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<BlurPostProcessor>::from_font_and_user_data(font, 8.0)
+                    .with_width_and_height(dimensions())
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        blurred.draw(draw).unwrap();
+        let image = read_image(blurred.backend());
+
+        // Uniform areas are unaffected while the edge between them is smoothed.
+        assert_eq!(image.get_pixel(64, 36), sharp.get_pixel(64, 36));
+        assert_eq!(image.get_pixel(448, 36), sharp.get_pixel(448, 36));
+        let edge = image.get_pixel(256, 36).0[0];
+        assert!(edge > 0 && edge < 255, "Edge not blurred: {edge}");
+
+        blurred.backend_mut().post_processor_mut().radius = 0.0;
+        assert!(blurred.backend().needs_update());
+        Backend::flush(blurred.backend_mut()).unwrap();
+        assert!(!blurred.backend().needs_update());
+        assert!(
+            read_image(blurred.backend()) == sharp,
+            "Zero radius blur differs from a blit"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn scanline_post_processor() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<ScanlinePostProcessor>::from_font_and_user_data(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                    ScanlineSettings::default(),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal.hide_cursor().unwrap();
+        terminal
+            .draw(|f| {
+                f.render_widget(Block::new().bg(Color::Rgb(255, 255, 255)), f.area());
+            })
+            .unwrap();
+        assert!(!terminal.backend().needs_update());
+
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(64, 10).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(64, 11).0, [191, 191, 191, 255]);
+
+        terminal
+            .backend_mut()
+            .post_processor_mut()
+            .settings
+            .intensity = 0.0;
+        assert!(terminal.backend().needs_update());
+        Backend::flush(terminal.backend_mut()).unwrap();
+        assert!(!terminal.backend().needs_update());
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(64, 11).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    #[serial]
+    fn lut_post_processor() {
+        fn render<P: PostProcessor>(user_data: P::UserData) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<P>::from_font_and_user_data(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                        user_data,
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new("Hello World!".fg(Color::Rgb(255, 128, 0)).on_black()),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+
+            read_image(terminal.backend())
+        }
+
+        let cube = |map: fn(f32) -> f32| {
+            let mut cube = "TITLE \"test\"\n# comment\nLUT_3D_SIZE 2\n".to_string();
+            for b in [0.0, 1.0] {
+                for g in [0.0, 1.0] {
+                    for r in [0.0, 1.0] {
+                        cube += &format!("{} {} {}\n", map(r), map(g), map(b));
+                    }
+                }
+            }
+            Lut::from_cube(&cube).unwrap()
+        };
+
+        let sharp = render::<DefaultPostProcessor>(());
+        let identity = render::<LutPostProcessor>(cube(|c| c));
+        let inverted = render::<LutPostProcessor>(cube(|c| 1.0 - c));
+
+        let close = |l: u8, r: u8| l.abs_diff(r) <= 1;
+        for ((sharp, identity), inverted) in
+            sharp.pixels().zip(identity.pixels()).zip(inverted.pixels())
+        {
+            for c in 0..3 {
+                assert!(close(sharp.0[c], identity.0[c]), "{sharp:?} {identity:?}");
+                assert!(
+                    close(255 - sharp.0[c], inverted.0[c]),
+                    "{sharp:?} {inverted:?}"
+                );
+            }
+            assert_eq!(inverted.0[3], 255);
+        }
+    }
+
+    #[test]
+    fn lut_parsing() {
+        let invalid =
+            |source: &str| matches!(Lut::from_cube(source), Err(crate::Error::InvalidLut(_)));
+        assert!(invalid(""));
+        assert!(invalid("LUT_3D_SIZE 1\n0 0 0\n"));
+        assert!(invalid("LUT_3D_SIZE 300\n"));
+        assert!(invalid("LUT_1D_SIZE 2\n"));
+        assert!(invalid("0 0 0\nLUT_3D_SIZE 2\n"));
+        assert!(invalid(&format!("LUT_3D_SIZE 2\n{}", "0 0 0\n".repeat(7))));
+        assert!(invalid(&format!(
+            "LUT_3D_SIZE 2\n{}0 0\n",
+            "0 0 0\n".repeat(7)
+        )));
+        assert!(invalid(&format!(
+            "LUT_3D_SIZE 2\n{}0 0 x\n",
+            "0 0 0\n".repeat(7)
+        )));
+        assert!(invalid(&format!(
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 1 1 1\nDOMAIN_MAX 0 0 0\n{}",
+            "0 0 0\n".repeat(8)
+        )));
+
+        let lut = Lut::from_cube(&format!(
+            "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 2\nUNKNOWN_KEYWORD 1\n{}",
+            "0.5 1.5 -1\n".repeat(8)
+        ))
+        .unwrap();
+        assert_eq!(lut.size(), 2);
+
+        assert!(matches!(
+            Lut::from_strip(4, 3, &[0; 48]),
+            Err(crate::Error::InvalidLut(_))
+        ));
+        assert!(matches!(
+            Lut::from_strip(4, 2, &[0; 31]),
+            Err(crate::Error::InvalidLut(_))
+        ));
+
+        // A strip lays out the blue slices side by side.
+        let mut strip = vec![];
+        for g in 0..2u8 {
+            for b in 0..2u8 {
+                for r in 0..2u8 {
+                    strip.extend([r * 255, g * 255, b * 255, 255]);
+                }
+            }
+        }
+        let mut cube = "LUT_3D_SIZE 2\n".to_string();
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    cube += &format!("{r} {g} {b}\n");
+                }
+            }
+        }
+        assert_eq!(
+            Lut::from_strip(4, 2, &strip).unwrap(),
+            Lut::from_cube(&cube).unwrap()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn chromatic_aberration_post_processor() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let dimensions = || Dimensions {
+            width: NonZeroU32::new(fonts.min_width_px() * 64).unwrap(),
+            height: NonZeroU32::new(fonts.cell_height_px() * 3).unwrap(),
+        };
+        fn render<P: PostProcessor>(
+            font: &Font<'static>,
+            dimensions: Dimensions,
+            user_data: P::UserData,
+        ) -> Terminal<WgpuBackend<'static, 'static, P, HeadlessSurface>> {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<P>::from_font_and_user_data(font.clone(), user_data)
+                        .with_width_and_height(dimensions)
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| {
+                    let area = f.area();
+                    f.render_widget(Block::new().bg(Color::Rgb(0, 0, 0)), area);
+                    let right = Rect::new(area.width * 3 / 4, 0, area.width / 4, area.height);
+                    f.render_widget(Block::new().bg(Color::Rgb(255, 255, 255)), right);
+                })
+                .unwrap();
+            terminal
+        }
+
+        let sharp = read_image(render::<DefaultPostProcessor>(&font, dimensions(), ()).backend());
+        let mut aberrated = render::<ChromaticAberrationPostProcessor>(&font, dimensions(), 8.0);
+        let image = read_image(aberrated.backend());
+
+        // The red channel is moved outwards, so it bleeds past the left edge of the
+        // box on the right side of the screen.
+        let edge = dimensions().width.get() * 3 / 4;
+        let y = dimensions().height.get() / 2;
+        assert_eq!(sharp.get_pixel(edge - 2, y).0, [0, 0, 0, 255]);
+        let fringe = image.get_pixel(edge - 2, y).0;
+        assert!(fringe[0] > 200 && fringe[2] < 50, "{fringe:?}");
+        assert_eq!(image.get_pixel(edge + 16, y), sharp.get_pixel(edge + 16, y));
+
+        aberrated.backend_mut().post_processor_mut().offset = 0.0;
+        assert!(aberrated.backend().needs_update());
+        Backend::flush(aberrated.backend_mut()).unwrap();
+        assert!(!aberrated.backend().needs_update());
+        assert!(read_image(aberrated.backend()) == sharp);
+
+        // Chained with scanlines, both effects apply.
+        let chained = render::<Chain<ChromaticAberrationPostProcessor, ScanlinePostProcessor>>(
+            &font,
+            dimensions(),
+            (8.0, ScanlineSettings::default()),
+        );
+        let image = read_image(chained.backend());
+        let fringe = image.get_pixel(edge - 2, y).0;
+        assert!(fringe[0] > 100 && fringe[2] < 50, "{fringe:?}");
+        assert_ne!(
+            image.get_pixel(edge + 16, 10).0,
+            image.get_pixel(edge + 16, 11).0
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn vignette_post_processor() {
+        fn render<P: PostProcessor>(user_data: P::UserData) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<P>::from_font_and_user_data(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                        user_data,
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(256).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| {
+                    f.render_widget(Block::new().bg(Color::Rgb(255, 255, 255)), f.area());
+                })
+                .unwrap();
+
+            read_image(terminal.backend())
+        }
+
+        let image = render::<VignettePostProcessor>(VignetteSettings::default());
+        assert_eq!(image.get_pixel(256, 128).0, [255, 255, 255, 255]);
+        let corner = image.get_pixel(0, 0).0;
+        assert!(corner[0] < 128 && corner[0] == corner[2], "{corner:?}");
+        assert!(image.get_pixel(0, 128).0[0] > corner[0]);
+
+        // As the tail of a chain, fading to a color.
+        let image = render::<Chain<DefaultPostProcessor, VignettePostProcessor>>((
+            (),
+            VignetteSettings {
+                color: [255, 0, 0],
+                ..Default::default()
+            },
+        ));
+        assert_eq!(image.get_pixel(256, 128).0, [255, 255, 255, 255]);
+        let corner = image.get_pixel(511, 255).0;
+        assert!(corner[0] == 255 && corner[2] < 128, "{corner:?}");
+    }
+
+    #[test]
+    #[serial]
+    fn persistence_post_processor() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<PersistencePostProcessor>::from_font_and_user_data(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                    0.5,
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal.hide_cursor().unwrap();
+        let draw = |terminal: &mut Terminal<_>, lit: bool| {
+            terminal
+                .draw(|f| {
+                    f.render_widget(Block::new().bg(Color::Rgb(0, 0, 0)), f.area());
+                    if lit {
+                        f.render_widget(
+                            Block::new().bg(Color::Rgb(255, 255, 255)),
+                            Rect::new(0, 0, 4, 1),
+                        );
+                    }
+                })
+                .unwrap();
+        };
+
+        draw(&mut terminal, true);
+        assert_eq!(
+            read_image(terminal.backend()).get_pixel(4, 4).0,
+            [255, 255, 255, 255]
+        );
+
+        // The block leaves a trail which fades out without further drawing.
+        draw(&mut terminal, false);
+        let trail = read_image(terminal.backend()).get_pixel(4, 4).0;
+        assert!((126..=128).contains(&trail[0]), "{trail:?}");
+        let mut frames = 0;
+        while terminal.backend().needs_update() {
+            Backend::flush(terminal.backend_mut()).unwrap();
+            frames += 1;
+            assert!(frames <= 8);
+        }
+        assert_eq!(
+            read_image(terminal.backend()).get_pixel(4, 4).0,
+            [0, 0, 0, 255]
+        );
+
+        // Resizing drops the history.
+        draw(&mut terminal, true);
+        terminal.backend_mut().resize(256, 72);
+        draw(&mut terminal, false);
+        assert_eq!(
+            read_image(terminal.backend()).get_pixel(4, 4).0,
+            [0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn bloom_post_processor() {
+        fn render<P: PostProcessor>(user_data: P::UserData) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<P>::from_font_and_user_data(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                        user_data,
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("Hello World!".white().on_black()), f.area());
+                })
+                .unwrap();
+
+            read_image(terminal.backend())
+        }
+
+        let sharp = render::<DefaultPostProcessor>(());
+        let bloom = render::<BloomPostProcessor>(BloomSettings::default());
+
+        let pairs = || sharp.pixels().zip(bloom.pixels());
+        assert!(
+            pairs().any(|(sharp, bloom)| bloom.0[0] > sharp.0[0]),
+            "Bright text doesn't glow"
+        );
+        assert!(
+            pairs().all(|(sharp, bloom)| bloom.0[0] >= sharp.0[0]),
+            "Bloom darkened the output"
+        );
+
+        let unlit = render::<BloomPostProcessor>(BloomSettings {
+            threshold: 2.0,
+            ..Default::default()
+        });
+        assert!(unlit == sharp, "Text below the threshold glows");
+    }
+
+    #[test]
+    #[serial]
+    fn persistent_buffers() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let build = || {
+            Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(font.clone())
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(512).unwrap(),
+                            height: NonZeroU32::new(72).unwrap(),
+                        })
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let draw = |text: &'static str| {
+            move |f: &mut ratatui::Frame| {
+                f.render_widget(Paragraph::new(text).on_blue(), f.area());
+            }
+        };
+
+        let mut terminal = build();
+        terminal.draw(draw("Hello World!")).unwrap();
+        let buffers = |terminal: &Terminal<WgpuBackend<'_, 'static, _, _>>| {
+            let backend = terminal.backend();
+            [
+                backend.bg_instance_buffer.buffer().cloned(),
+                backend.text_vertex_buffer.buffer().cloned(),
+                backend.index_buffer.buffer().cloned(),
+            ]
+        };
+        let full = buffers(&terminal);
+        assert!(full.iter().all(Option::is_some));
+
+        // A smaller update reuses the buffers from the full redraw.
+        terminal.draw(draw("Hello Earth!")).unwrap();
+        assert!(buffers(&terminal) == full, "Buffers were reallocated");
+
+        let mut fresh = build();
+        fresh.draw(draw("Hello Earth!")).unwrap();
+        assert!(
+            read_image(terminal.backend()) == read_image(fresh.backend()),
+            "Incremental update differs from a full redraw"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn full_screen_bg_instances() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(1280).unwrap(),
+                    height: NonZeroU32::new(720).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| f.render_widget(Block::new().bg(Color::Blue), f.area()))
+            .unwrap();
+
+        let backend = terminal.backend();
+        let size = backend.size().unwrap();
+        // One instance per cell, rather than the four vertices per cell needed
+        // before the background was drawn instanced.
+        assert_eq!(
+            backend.bg_instances.len(),
+            size.width as usize * size.height as usize
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn atlas_eviction() {
+        let build = || {
+            Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_atlas_size(256)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(1024).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut terminal = build();
+        let capacity = terminal.backend().cached.capacity() as usize;
+        let glyphs = ('!'..='~').chain('¡'..='ÿ').collect::<Vec<_>>();
+        let first = glyphs[..capacity / 3].iter().collect::<String>();
+        let second = glyphs[capacity / 3..][..capacity / 2]
+            .iter()
+            .collect::<String>();
+        let third = glyphs[capacity / 3 + capacity / 2..][..capacity / 2]
+            .iter()
+            .collect::<String>();
+        assert!(
+            first.chars().count() + second.chars().count() + third.chars().count() > capacity,
+            "The atlas must overflow"
+        );
+
+        let draw = |bottom: &str| {
+            let text = vec![Line::from(first.as_str()), Line::from(bottom.to_string())];
+            move |f: &mut ratatui::Frame| {
+                f.render_widget(Paragraph::new(text), f.area());
+            }
+        };
+
+        terminal.draw(draw(&second)).unwrap();
+        // The first line is left untouched, but its glyphs are the least recently
+        // used and are evicted to make room for the new line.
+        terminal.draw(draw(&third)).unwrap();
+        // Repaint every cell from the atlas.
+        terminal.backend_mut().update_palette(XTERM_PALETTE);
+        terminal.draw(draw(&third)).unwrap();
+
+        let mut fresh = build();
+        fresh.draw(draw(&third)).unwrap();
+        assert!(
+            read_image(terminal.backend()) == read_image(fresh.backend()),
+            "Evicted glyphs were not rasterized again"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn atlas_pages() {
+        let build = |atlas: Option<(u32, u32)>| {
+            let builder = Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(1024).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            });
+            let builder = if let Some((size, pages)) = atlas {
+                builder.with_atlas_size(size).with_atlas_pages(pages)
+            } else {
+                builder
+            };
+            Terminal::new(futures_lite::future::block_on(builder.build_headless()).unwrap())
+                .unwrap()
+        };
+
+        let mut terminal = build(Some((256, 2)));
+        let per_page = terminal.backend().cached.capacity() as usize / 2;
+        let glyphs = ('!'..='~').chain('¡'..='ÿ').collect::<Vec<_>>();
+        let text = vec![
+            Line::from(glyphs[..per_page].iter().collect::<String>()),
+            Line::from(
+                glyphs[per_page..][..per_page / 2]
+                    .iter()
+                    .collect::<String>(),
+            ),
+        ];
+        let draw = |f: &mut ratatui::Frame| {
+            f.render_widget(Paragraph::new(text.clone()), f.area());
+        };
+
+        terminal.draw(draw).unwrap();
+        assert_eq!(terminal.backend().atlas_pages.len(), 2);
+        assert!(!terminal.backend_mut().cached.take_evicted());
+
+        let mut single = build(None);
+        single.draw(draw).unwrap();
+        assert_eq!(single.backend().atlas_pages.len(), 1);
+        assert!(
+            read_image(terminal.backend()) == read_image(single.backend()),
+            "Glyphs on the second page rendered incorrectly"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn pixel_to_cell() {
+        let backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(256).unwrap(),
+            })
+            .with_viewport(Viewport::Shrink {
+                width: 100,
+                height: 50,
+            })
+            .build_headless(),
+        )
+        .unwrap();
+
+        let cell_width = backend.fonts.min_width_px() as f64;
+        let cell_height = backend.fonts.cell_height_px() as f64;
+        let size = backend.size().unwrap();
+
+        assert_eq!(backend.pixel_to_cell(0.0, 0.0), Some((0, 0)));
+        assert_eq!(
+            backend.pixel_to_cell(cell_width * 2.5, cell_height - 0.5),
+            Some((2, 0))
+        );
+        assert_eq!(
+            backend.pixel_to_cell(
+                size.width as f64 * cell_width - 0.5,
+                size.height as f64 * cell_height - 0.5
+            ),
+            Some((size.width - 1, size.height - 1))
+        );
+
+        // Past the last cell, in the shrunk area, and off the surface.
+        assert_eq!(
+            backend.pixel_to_cell(size.width as f64 * cell_width, 0.0),
+            None
+        );
+        assert_eq!(backend.pixel_to_cell(0.0, 230.0), None);
+        assert_eq!(backend.pixel_to_cell(-1.0, 0.0), None);
+        assert_eq!(backend.pixel_to_cell(f64::NAN, 0.0), None);
+    }
+
+    #[test]
+    #[serial]
+    fn cell_bounds() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .with_viewport(Viewport::Shrink {
+                    width: 100,
+                    height: 50,
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("a文b"), f.area()))
+            .unwrap();
+
+        let backend = terminal.backend();
+        let cell_width = backend.fonts.min_width_px() as u16;
+        let cell_height = backend.fonts.cell_height_px() as u16;
+
+        assert_eq!(
+            backend.cell_bounds(0, 0),
+            Rect::new(0, 0, cell_width, cell_height)
+        );
+        assert_eq!(
+            backend.cell_bounds(1, 0),
+            Rect::new(cell_width, 0, cell_width * 2, cell_height)
+        );
+        assert_eq!(
+            backend.cell_bounds(3, 2),
+            Rect::new(cell_width * 3, cell_height * 2, cell_width, cell_height)
+        );
+
+        for (column, row) in [(0, 0), (1, 0), (3, 2)] {
+            let bounds = backend.cell_bounds(column, row);
+            assert_eq!(
+                backend.pixel_to_cell(bounds.x as f64, bounds.y as f64),
+                Some((column, row))
+            );
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn centered_viewport() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .with_viewport(Viewport::Centered {
+                    width: 200,
+                    height: 100,
+                })
+                .with_clear_color(Color::Rgb(255, 0, 0))
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("a"), f.area());
+            })
+            .unwrap();
+
+        let backend = terminal.backend();
+        let image = read_image(backend);
+        let red = Rgba([255, 0, 0, 255]);
+        assert_eq!(*image.get_pixel(5, 5), red);
+        assert_eq!(*image.get_pixel(155, 77), red);
+        assert_eq!(*image.get_pixel(356, 178), red);
+        assert_ne!(*image.get_pixel(200, 120), red);
+
+        let cell_width = backend.fonts.min_width_px();
+        let cell_height = backend.fonts.cell_height_px();
+        let size = backend.size().unwrap();
+        assert_eq!(size.width as u32, 200 / cell_width);
+        assert_eq!(size.height as u32, 100 / cell_height);
+
+        assert_eq!(backend.pixel_to_cell(155.0, 78.0), None);
+        assert_eq!(backend.pixel_to_cell(156.0, 78.0), Some((0, 0)));
+        assert_eq!(
+            backend.pixel_to_cell(156.0 + cell_width as f64 * 1.5, 78.0),
+            Some((1, 0))
+        );
+
+        assert_eq!(
+            backend.cell_bounds(1, 1),
+            Rect {
+                x: (156 + cell_width) as u16,
+                y: (78 + cell_height) as u16,
+                width: cell_width as u16,
+                height: cell_height as u16,
+            }
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn integer_scale_viewport() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .with_viewport(Viewport::IntegerScale {
+                    width: 200,
+                    height: 100,
+                })
+                .with_clear_color(Color::Rgb(255, 0, 0))
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(
+                    Paragraph::new(" ").bg(Color::Rgb(0, 0, 255)),
+                    Rect::new(0, 0, 1, 1),
+                );
+            })
+            .unwrap();
+
+        let backend = terminal.backend();
+        let cell_width = backend.fonts.min_width_px();
+        let cell_height = backend.fonts.cell_height_px();
+        let size = backend.size().unwrap();
+        assert_eq!(size.width as u32, 200 / cell_width);
+        assert_eq!(size.height as u32, 100 / cell_height);
+
+        let text_width = size.width as u32 * cell_width;
+        let text_height = size.height as u32 * cell_height;
+        let scale = (512 / text_width).min(256 / text_height);
+        assert!(scale >= 2);
+        let x = (512 - text_width * scale) / 2;
+        let y = (256 - text_height * scale) / 2;
+
+        let cell = backend.cell_bounds(0, 0);
+        assert_eq!(
+            cell,
+            Rect {
+                x: x as u16,
+                y: y as u16,
+                width: (cell_width * scale) as u16,
+                height: (cell_height * scale) as u16,
+            }
+        );
+        assert_eq!(backend.pixel_to_cell(x as f64 - 1.0, y as f64), None);
+        assert_eq!(backend.pixel_to_cell(x as f64, y as f64), Some((0, 0)));
+        assert_eq!(
+            backend.pixel_to_cell((x + cell_width * scale) as f64, y as f64),
+            Some((1, 0))
+        );
+
+        let image = read_image(backend);
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        assert_eq!(*image.get_pixel(0, 0), red);
+        assert_eq!(*image.get_pixel(x - 1, y), red);
+        assert_eq!(*image.get_pixel(x, y), blue);
+        assert_eq!(
+            *image.get_pixel(x + cell_width * scale - 1, y + cell_height * scale - 1),
+            blue
+        );
+        assert_ne!(*image.get_pixel(x + cell_width * scale, y), blue);
+    }
+
+    #[test]
+    #[serial]
+    fn padded_viewport() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .with_viewport(Viewport::Padded {
+                    top: 10,
+                    right: 20,
+                    bottom: 30,
+                    left: 40,
+                })
+                .with_clear_color(Color::Rgb(255, 0, 0))
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("a"), f.area());
+            })
+            .unwrap();
+
+        let backend = terminal.backend();
+        let cell_width = backend.fonts.min_width_px();
+        let cell_height = backend.fonts.cell_height_px();
+        let size = backend.size().unwrap();
+        assert_eq!(size.width as u32, (512 - 60) / cell_width);
+        assert_eq!(size.height as u32, (256 - 40) / cell_height);
+
+        assert_eq!(backend.pixel_to_cell(39.0, 10.0), None);
+        assert_eq!(backend.pixel_to_cell(40.0, 10.0), Some((0, 0)));
+        assert_eq!(
+            backend.cell_bounds(1, 1),
+            Rect {
+                x: (40 + cell_width) as u16,
+                y: (10 + cell_height) as u16,
+                width: cell_width as u16,
+                height: cell_height as u16,
+            }
+        );
+
+        let image = read_image(backend);
+        let red = Rgba([255, 0, 0, 255]);
+        assert_eq!(*image.get_pixel(39, 100), red);
+        assert_eq!(*image.get_pixel(100, 9), red);
+        assert_eq!(*image.get_pixel(492, 100), red);
+        assert_eq!(*image.get_pixel(100, 226), red);
+        assert_ne!(*image.get_pixel(40, 10), red);
+
+        // Padding larger than the surface leaves a single cell.
+        terminal.backend_mut().viewport = Viewport::uniform_padding(1000);
+        terminal.backend_mut().resize(300, 200);
+        assert_eq!(
+            terminal.backend().size().unwrap(),
+            Size {
+                width: 1,
+                height: 1
+            }
+        );
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("a"), f.area());
+            })
+            .unwrap();
+        assert_eq!(terminal.backend().pixel_to_cell(0.0, 0.0), None);
+    }
+
+    #[test]
+    #[serial]
+    fn wide_column_alignment() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(
+                    Paragraph::new(vec![Line::from("a文b日c"), Line::from("abcdefg")]),
+                    f.area(),
+                )
+            })
+            .unwrap();
+
+        let backend = terminal.backend();
+        let cell_width = backend.fonts.min_width_px() as i32;
+        let width = backend.size().unwrap().width as usize;
+
+        let columns = |index: usize| {
+            backend.rendered[index]
+                .iter()
+                .map(|(&(x, _, _), info)| (x, info.cached.width as i32))
+                .collect::<Vec<_>>()
+        };
+
+        // Wide glyphs span two cells, the trailing cell draws nothing.
+        for (column, wide) in [(0, false), (1, true), (3, false), (4, true), (6, false)] {
+            let expected_width = if wide { 2 } else { 1 } * cell_width;
+            assert_eq!(
+                columns(column),
+                vec![(column as i32 * cell_width, expected_width)]
+            );
+            assert_eq!(
+                columns(width + column),
+                vec![(column as i32 * cell_width, cell_width)]
+            );
+        }
+        assert!(columns(2).is_empty());
+        assert!(columns(5).is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn wide_last_column() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(256).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+
+        let size = backend.size().unwrap();
+        let cell = Cell::new("文");
+        backend
+            .draw([(size.width - 1, size.height - 1, &cell)].into_iter())
+            .unwrap();
+        backend.flush().unwrap();
+
+        let last = size.width as usize * size.height as usize - 1;
+        assert_eq!(backend.rendered[last].len(), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn multi_code_point_clusters() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| {
+                f.render_widget(
+                    Paragraph::new(vec![
+                        Line::from("e\u{301}x"),
+                        Line::from("👨\u{200D}👩\u{200D}👧x"),
+                    ]),
+                    f.area(),
+                )
+            })
+            .unwrap();
+
+        let backend = terminal.backend();
+        let cell_width = backend.fonts.min_width_px() as i32;
+        let width = backend.size().unwrap().width as usize;
+        let origins = |index: usize| {
+            backend.rendered[index]
+                .keys()
+                .map(|&(x, _, _)| x)
+                .collect::<Vec<_>>()
+        };
+
+        // The combining mark is drawn on its base glyph.
+        assert!(!origins(0).is_empty());
+        assert!(origins(0).iter().all(|&x| x == 0));
+        assert_eq!(origins(1), vec![cell_width]);
+
+        // The font has no glyph for the sequence, so a glyph is drawn per code
+        // point for as long as they fit in its two cells.
+        assert!(!origins(width).is_empty());
+        assert!(origins(width).iter().all(|&x| x == 0));
+        assert!(!origins(width + 1).is_empty());
+        assert!(origins(width + 1).iter().all(|&x| x == cell_width));
+        assert_eq!(origins(width + 2), vec![cell_width * 2]);
+        for column in 3..width {
+            assert_eq!(origins(width + column), vec![column as i32 * cell_width]);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn ligatures() {
+        // The glyphs of the test font.
+        const EQUAL: u32 = 2;
+        const GREATER: u32 = 3;
+        const ARROW: u32 = 4;
+        const EQUAL_BEFORE_GREATER: u32 = 5;
+
+        for ligatures in [false, true] {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/LigatureTest.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(256).unwrap(),
+                    })
+                    .with_ligatures(ligatures)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("=>="), f.area()))
+                .unwrap();
+
+            let backend = terminal.backend();
+            let cell_width = backend.fonts.min_width_px() as i32;
+            let glyphs = |index: usize| {
+                backend.rendered[index]
+                    .iter()
+                    .map(|(&(x, _, _), info)| (x, info.key.glyph, info.cached.width as i32))
+                    .collect::<Vec<_>>()
+            };
+
+            if ligatures {
+                // The ligature covers both cells, the following cell is unaffected.
+                assert_eq!(glyphs(0), vec![(0, ARROW, cell_width * 2)]);
+                assert!(glyphs(1).is_empty());
+            } else {
+                // Contextual alternates stay within their cells, so they're kept.
+                assert_eq!(glyphs(0), vec![(0, EQUAL_BEFORE_GREATER, cell_width)]);
+                assert_eq!(glyphs(1), vec![(cell_width, GREATER, cell_width)]);
+            }
+            assert_eq!(glyphs(2), vec![(cell_width * 2, EQUAL, cell_width)]);
+            assert_eq!(
+                backend.pixel_to_cell(cell_width as f64 * 1.5, 0.0),
+                Some((1, 0))
+            );
+            assert_eq!(backend.get_text().lines().next().unwrap().trim_end(), "=>=");
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn builtin_box_drawing() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_builtin_box_drawing(true)
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(256).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+
+        let mut block = Cell::new("█");
+        block.set_fg(Color::Rgb(255, 0, 0));
+        let mut line = Cell::new("─");
+        line.set_fg(Color::Rgb(255, 0, 0));
+        backend
+            .draw([(0, 0, &block), (1, 0, &block), (0, 1, &line), (1, 1, &line)].into_iter())
+            .unwrap();
+        backend.hide_cursor().unwrap();
+        backend.flush().unwrap();
+
+        assert!(backend.rendered[0]
+            .values()
+            .all(|info| info.key.font == crate::utils::box_drawing::BUILTIN_FONT_ID));
+
+        let cell_width = backend.fonts.min_width_px();
+        let cell_height = backend.fonts.cell_height_px();
+        let image = read_image(&backend);
+        let red = |x: u32, y: u32| image.get_pixel(x, y).0 == [255, 0, 0, 255];
+
+        // Full blocks cover their cells exactly, with no seam between them.
+        assert!((0..cell_width * 2).all(|x| (0..cell_height).all(|y| red(x, y))));
+        assert!(!red(cell_width * 2, cell_height / 2));
+
+        // Horizontal lines run edge to edge through the middle of the cell.
+        let line = (cell_height..cell_height * 2)
+            .filter(|y| red(0, *y))
+            .collect::<Vec<_>>();
+        assert!(!line.is_empty() && line.len() < cell_height as usize / 2);
+        assert!(line.iter().all(|y| (0..cell_width * 2).all(|x| red(x, *y))));
+    }
+
+    #[test]
+    #[serial]
+    fn builtin_powerline() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_builtin_powerline(true)
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(256).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+
+        let red = Color::Rgb(255, 0, 0);
+        let blue = Color::Rgb(0, 0, 255);
+        let mut before = Cell::new(" ");
+        before.set_bg(red);
+        let mut separator = Cell::new("\u{e0b0}");
+        separator.set_fg(red).set_bg(blue);
+        let mut after = Cell::new(" ");
+        after.set_bg(blue);
+        let block = Cell::new("█");
+        backend
+            .draw(
+                [
+                    (0, 0, &before),
+                    (1, 0, &separator),
+                    (2, 0, &after),
+                    (0, 1, &block),
+                ]
+                .into_iter(),
+            )
+            .unwrap();
+        backend.hide_cursor().unwrap();
+        backend.flush().unwrap();
+
+        assert!(backend.rendered[1]
+            .values()
+            .all(|info| info.key.font == crate::utils::box_drawing::BUILTIN_FONT_ID));
+        // Box drawing is toggled separately.
+        let row = backend.size().unwrap().width as usize;
+        assert!(backend.rendered[row]
+            .values()
+            .all(|info| info.key.font != crate::utils::box_drawing::BUILTIN_FONT_ID));
+
+        let cell_width = backend.fonts.min_width_px();
+        let cell_height = backend.fonts.cell_height_px();
+        let image = read_image(&backend);
+        let pixel = |x: u32, y: u32| image.get_pixel(x, y).0;
+
+        // The base of the triangle continues the background before it, and its
+        // apex meets the background after it.
+        assert!((1..cell_height - 1).all(|y| pixel(cell_width, y) == [255, 0, 0, 255]));
+        assert_eq!(pixel(cell_width * 2 - 1, 0), [0, 0, 255, 255]);
+        assert!(pixel(cell_width * 2 - 1, cell_height / 2)[0] > 0);
+    }
+
+    #[test]
+    #[serial]
+    fn background_alpha() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.min_width_px(), fonts.cell_height_px());
+        let blue = Color::Rgb(0, 0, 255);
+
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(cell_width * 16).unwrap(),
+                        height: NonZeroU32::new(cell_height).unwrap(),
+                    })
+                    .with_transparent_background(true)
+                    .with_background_alpha(blue, 128)
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let ui = |f: &mut ratatui::Frame| {
+            f.render_widget(Paragraph::new(Line::from(" H ".bg(blue))), f.area());
+            f.set_cursor_position((10, 0));
+        };
+        terminal.draw(ui).unwrap();
+
+        let image = read_image(terminal.backend());
+        let cell = |x: u32| {
+            (x * cell_width..(x + 1) * cell_width)
+                .flat_map(|x| (0..cell_height).map(move |y| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+
+        // The background is stored premultiplied with the requested alpha.
+        assert!(cell(0).iter().all(|px| *px == [0, 0, 128, 128]));
+        // Text is blended over it, staying premultiplied.
+        let text = cell(1);
+        assert!(text.contains(&[0, 0, 128, 128]));
+        assert!(text.iter().any(|px| px[3] == 255));
+        assert!(text.iter().all(|px| px[3] >= 128));
+        assert!(text.iter().all(|px| px[..3].iter().all(|c| *c <= px[3])));
+        assert!(cell(3).iter().all(|px| *px == [0, 0, 0, 0]));
+
+        terminal.backend_mut().set_background_alpha(blue, 255);
+        terminal.draw(ui).unwrap();
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    #[serial]
+    fn hdr_formats() {
+        let render = |format| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_hdr(true)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(501).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless_with_format(format),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new(Line::from(vec!["Hello ".red(), "World!".on_blue()])),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+
+            terminal.backend().read_pixels().unwrap()
+        };
+
+        let rgba = render(TextureFormat::Rgba8Unorm);
+        for format in [TextureFormat::Rgb10a2Unorm, TextureFormat::Rgba16Float] {
+            let wide = render(format);
+            assert_eq!(wide.len(), rgba.len());
+            // Colors are the same as on an 8-bit surface, give or take rounding.
+            assert!(
+                rgba.iter().zip(&wide).all(|(a, b)| a.abs_diff(*b) <= 2),
+                "{format:?} readback differs from Rgba readback"
+            );
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn text_format() {
+        let build = |format| {
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(501).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .with_text_format(format)
+                .build_headless(),
+            )
+        };
+        let render = |format| {
+            let mut terminal = Terminal::new(build(format).unwrap()).unwrap();
+
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new(Line::from(vec!["Hello ".red(), "World!".on_blue()])),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+
+            terminal.backend().read_pixels().unwrap()
+        };
+
+        let rgba = render(TextureFormat::Rgba8Unorm);
+        for format in [TextureFormat::Rgba16Float, TextureFormat::Rgba8UnormSrgb] {
+            // Srgb formats aren't available on every adapter.
+            if matches!(build(format), Err(crate::Error::UnsupportedTextFormat(_))) {
+                assert!(format.is_srgb(), "{format:?} should be supported");
+                continue;
+            }
+            let text = render(format);
+            assert_eq!(text.len(), rgba.len());
+            // Text is blended against the known cell background, so only rounding differs.
+            assert!(
+                rgba.iter().zip(&text).all(|(a, b)| a.abs_diff(*b) <= 2),
+                "{format:?} text differs from Rgba text"
+            );
+        }
+
+        assert!(matches!(
+            build(TextureFormat::Rgba8Uint),
+            Err(crate::Error::UnsupportedTextFormat(
+                TextureFormat::Rgba8Uint
+            ))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn text_format_intermediates() {
+        fn render<P: PostProcessor>(
+            user_data: P::UserData,
+            format: TextureFormat,
+        ) -> Vec<u8> {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<P>::from_font_and_user_data(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                        user_data,
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_text_format(format)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("Hello World!".on_blue()), f.area()))
+                .unwrap();
+            terminal.backend().read_pixels().unwrap()
+        }
+
+        // The blur's intermediate texture takes the text format, and chains keep
+        // passing srgb encoded colors along with float text.
+        let close = |a: Vec<u8>, b: Vec<u8>| {
+            a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| a.abs_diff(*b) <= 2)
+        };
+        assert!(close(
+            render::<BlurPostProcessor>(1.0, TextureFormat::Rgba8Unorm),
+            render::<BlurPostProcessor>(1.0, TextureFormat::Rgba16Float),
+        ));
+        assert!(close(
+            render::<Chain<BlurPostProcessor, DefaultPostProcessor>>(
+                (1.0, ()),
+                TextureFormat::Rgba8Unorm
+            ),
+            render::<Chain<BlurPostProcessor, DefaultPostProcessor>>(
+                (1.0, ()),
+                TextureFormat::Rgba16Float
+            ),
+        ));
+
+        // Float bloom levels aren't clamped while they're added up, so only check
+        // that they render.
+        render::<BloomPostProcessor>(BloomSettings::default(), TextureFormat::Rgba16Float);
+    }
+
+    #[test]
+    #[serial]
+    fn clear_color() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .with_bg_color(Color::Rgb(0, 0, 255))
+                .with_viewport(Viewport::Shrink {
+                    width: 100,
+                    height: 50,
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        let image = read_image(terminal.backend());
+        // The excluded area is opaque black rather than the default background.
+        assert_eq!(image.get_pixel(511, 255).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(511, 0).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 255).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 100).0, [0, 0, 255, 255]);
+
+        terminal
+            .backend_mut()
+            .set_clear_color(Color::Rgb(255, 0, 0));
+        terminal.backend_mut().flush().unwrap();
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(511, 255).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 100).0, [0, 0, 255, 255]);
+
+        terminal.backend_mut().set_clear_color(Color::Reset);
+        terminal.backend_mut().flush().unwrap();
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(511, 255).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    #[serial]
+    fn budgeted_rasterization() {
+        let render = |budgeted_rasterization| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_budgeted_rasterization(budgeted_rasterization)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(256).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            // Only one glyph is rasterized per flush.
+            terminal.backend_mut().rasterization_budget = Duration::ZERO;
+
+            let text = (b'!'..=b'~').map(char::from).collect::<String>();
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new(text.as_str()).wrap(Wrap { trim: false }),
+                        f.area(),
+                    )
+                })
+                .unwrap();
+
+            // Flush until every glyph has been rasterized.
+            assert_eq!(
+                terminal.backend().unrasterized.is_empty(),
+                !budgeted_rasterization
+            );
+            for _ in 0..200 {
+                if terminal.backend().unrasterized.is_empty() {
+                    break;
+                }
+                terminal.backend_mut().flush().unwrap();
+            }
+            assert!(terminal.backend().unrasterized.is_empty());
+
+            terminal.backend().read_pixels().unwrap()
+        };
+
+        assert!(render(true) == render(false));
+    }
+
+    #[test]
+    #[serial]
+    fn resize_grid() {
+        let build = |width, height| {
+            Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(width).unwrap(),
+                        height: NonZeroU32::new(height).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut terminal = build(512, 256);
+        let cell_width = terminal.backend().fonts.min_width_px();
+        let cell_height = terminal.backend().fonts.cell_height_px();
+        terminal
+            .draw(|f| {
+                f.render_widget(
+                    Paragraph::new("X".repeat(1000)).wrap(Wrap { trim: false }),
+                    f.area(),
+                )
+            })
+            .unwrap();
+
+        let size = terminal.backend_mut().resize(300, 100);
+        assert_eq!(
+            size,
+            Size {
+                width: (300 / cell_width) as u16,
+                height: (100 / cell_height) as u16,
+            }
+        );
+        assert_eq!(terminal.backend().grid_size(), size);
+        terminal
+            .resize(Rect::new(0, 0, size.width, size.height))
+            .unwrap();
+
+        fn draw(
+            terminal: &mut Terminal<
+                WgpuBackend<'_, 'static, DefaultPostProcessor, crate::backend::HeadlessSurface>,
+            >
+        ) -> Vec<u8> {
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("Y"), f.area()))
+                .unwrap();
+            terminal.backend().read_pixels().unwrap()
+        }
+
+        // Nothing from the larger grid remains.
+        let resized = draw(&mut terminal);
+        assert_eq!(
+            terminal.backend().cells.len(),
+            size.width as usize * size.height as usize
+        );
+        assert!(resized == draw(&mut build(300, 100)));
+    }
+
+    #[test]
+    #[serial]
+    fn resize_to_current_size() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        let before = terminal.backend().read_pixels().unwrap();
+
+        let size = terminal.backend().grid_size();
+        assert_eq!(terminal.backend_mut().resize(512, 256), size);
+        assert_eq!(terminal.backend_mut().resize(0, 256), size);
+        assert!(terminal.backend().dirty_rows.iter().all(|d| !d));
+        assert!(!terminal.backend().cells.is_empty());
+
+        terminal.backend_mut().flush().unwrap();
+        assert!(terminal.backend().read_pixels().unwrap() == before);
+    }
+
+    #[test]
+    #[serial]
+    fn recover_missing_texture() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        let before = terminal.backend().read_pixels().unwrap();
+
+        let backend = terminal.backend_mut();
+        backend.surface.texture = None;
+        backend.surface.buffer = None;
+        backend.colors_dirty = true;
+        backend.flush().unwrap();
+
+        assert!(backend.surface.texture.is_some());
+        assert!(backend.read_pixels().unwrap() == before);
+    }
+
+    #[test]
+    #[serial]
+    fn max_fps() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .with_max_fps(1)
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        let hello = terminal.backend().read_pixels().unwrap();
+        assert!(!terminal.backend().needs_update());
+        assert_eq!(terminal.backend().frame_time(), None);
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("World"), f.area()))
+            .unwrap();
+        assert!(terminal.backend().read_pixels().unwrap() == hello);
+        assert!(terminal.backend().needs_update());
+
+        let backend = terminal.backend_mut();
+        backend.last_present = Some(Instant::now() - Duration::from_secs(1));
+        backend.flush().unwrap();
+        assert!(!backend.needs_update());
+        assert!(backend.frame_time().unwrap() >= Duration::from_secs(1));
+
+        let mut expected = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(256).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        expected
+            .draw(|f| f.render_widget(Paragraph::new("World"), f.area()))
+            .unwrap();
+        assert!(backend.read_pixels().unwrap() == expected.backend().read_pixels().unwrap());
+    }
+
+    #[test]
+    #[serial]
+    fn cell_width_scale() {
+        fn render(scale: f32) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, Size, u32, u32) {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_cell_width_scale(scale)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("H"), f.area()))
+                .unwrap();
+
+            let backend = terminal.backend_mut();
+            let size = backend.grid_size();
+            let (width, height) = (backend.fonts.min_width_px(), backend.fonts.cell_height_px());
+            (read_image(backend), size, width, height)
+        }
+
+        let (plain, plain_size, width, height) = render(1.0);
+        let (wide, wide_size, _, _) = render(2.0);
+        assert_eq!(wide_size.width, plain_size.width / 2);
+        assert_eq!(wide_size.height, plain_size.height);
+
+        // Narrower cells crop the glyph instead.
+        let (_, narrow_size, _, _) = render(0.5);
+        assert!(narrow_size.width >= plain_size.width * 2);
+
+        let padding = width / 2;
+        let background = *plain.get_pixel(0, 0);
+        for y in 0..height {
+            for x in 0..padding {
+                assert_eq!(*wide.get_pixel(x, y), background);
+            }
+            for x in 0..width {
+                assert_eq!(wide.get_pixel(x + padding, y), plain.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn subpixel_positioning() {
+        fn render(
+            scale: f32,
+            positioning: bool,
+        ) -> (f32, u32) {
+            let mut backend = futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .with_cell_width_scale(scale)
+                .with_subpixel_positioning(positioning)
+                .build_headless(),
+            )
+            .unwrap();
+            backend.hide_cursor().unwrap();
+            let mut glyph = Cell::new("l");
+            glyph
+                .set_fg(Color::Rgb(255, 255, 255))
+                .set_bg(Color::Rgb(0, 0, 0));
+            let mut blank = Cell::new(" ");
+            blank.set_bg(Color::Rgb(0, 0, 0));
+            backend
+                .draw([(0, 0, &glyph), (1, 0, &blank)].into_iter())
+                .unwrap();
+            backend.flush().unwrap();
+
+            // The horizontal center of the glyph's coverage, within the two black
+            // cells.
+            let width = backend.fonts.min_width_px();
+            let image = read_image(&backend);
+            let (mut total, mut weighted) = (0.0, 0.0);
+            for (x, _, Rgba([r, ..])) in image
+                .enumerate_pixels()
+                .filter(|(x, y, _)| *x < width * 2 && *y < backend.fonts.cell_height_px())
+            {
+                total += *r as f32;
+                weighted += *r as f32 * (x as f32 + 0.5);
+            }
+            (weighted / total, width)
+        }
+
+        let (plain, width) = render(1.0, true);
+        // One pixel wider cells center the glyph half a pixel further right.
+        let scale = (width + 1) as f32 / width as f32;
+        let (snapped, _) = render(scale, false);
+        let (positioned, _) = render(scale, true);
+        assert!(
+            (snapped - plain).abs() < 0.05,
+            "Snapped glyph moved by {}",
+            snapped - plain
+        );
+        // The nearest of three phases to half a pixel is two thirds.
+        assert!(
+            (0.4..0.9).contains(&(positioned - plain)),
+            "Positioned glyph moved by {}",
+            positioned - plain
+        );
+        assert_eq!(render(1.0, false).0, plain);
+    }
+
+    #[test]
+    #[serial]
+    fn with_backends() {
+        let build = |backends: Backends| {
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_backends(backends)
+                .build_headless(),
+            )
+        };
+
+        let backend = build(Backends::all()).unwrap();
+        let info = backend.adapter_info().unwrap().clone();
+
+        let restricted = build(info.backend.into()).unwrap();
+        assert_eq!(restricted.adapter_info().unwrap().backend, info.backend);
+
+        assert!(matches!(
+            build(Backends::empty()),
+            Err(crate::Error::AdapterRequestFailed(_))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn adapter_options() {
+        let builder = || {
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+        };
+
+        let low_power = futures_lite::future::block_on(
+            builder()
+                .with_power_preference(PowerPreference::LowPower)
+                .build_headless(),
+        );
+        assert!(low_power.is_ok());
+
+        // Not every machine has a fallback adapter, but requesting one must not
+        // silently pick a hardware adapter instead.
+        match futures_lite::future::block_on(
+            builder().with_force_fallback_adapter(true).build_headless(),
+        ) {
+            Ok(backend) => assert_eq!(
+                backend.adapter_info().unwrap().device_type,
+                wgpu::DeviceType::Cpu
+            ),
+            Err(err) => assert!(matches!(err, crate::Error::AdapterRequestFailed(_))),
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn unsupported_headless_format() {
+        let backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .build_headless_with_format(TextureFormat::Depth32Float),
+        );
+        assert!(matches!(
+            backend,
+            Err(crate::Error::UnsupportedSurfaceFormat(
+                TextureFormat::Depth32Float
+            ))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn wide_cursor() {
+        fn render(
+            style: CursorStyle,
+            column: Option<u16>,
+        ) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, u32) {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_cursor_style(style)
+                    .with_cursor_color(Color::Rgb(255, 0, 0))
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("世界"), f.area());
+                    if let Some(column) = column {
+                        f.set_cursor_position((column, 0));
+                    }
+                })
+                .unwrap();
+
+            let backend = terminal.backend_mut();
+            let width = backend.fonts.min_width_px();
+            (read_image(backend), width)
+        }
+
+        for style in [CursorStyle::Block, CursorStyle::Underline] {
+            let (leading, width) = render(style, Some(0));
+            let (trailing, _) = render(style, Some(1));
+            let (hidden, _) = render(style, None);
+            assert!(
+                leading == trailing,
+                "{style:?} cursor didn't snap to the leading cell"
+            );
+
+            // The right half of the character is covered as well.
+            let right_half = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+                image
+                    .view(width, 0, width, image.height() / 3)
+                    .pixels()
+                    .map(|(_, _, px)| px)
+                    .collect::<Vec<_>>()
+            };
+            assert!(
+                right_half(&leading) != right_half(&hidden),
+                "{style:?} cursor doesn't span the wide cell"
+            );
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn dim_factor() {
+        fn brightest(modifier: Modifier) -> u8 {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_dim_factor(0.25)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new("#").style(
+                            Style::new()
+                                .fg(Color::Rgb(255, 255, 255))
+                                .bg(Color::Rgb(0, 0, 0))
+                                .add_modifier(modifier),
+                        ),
+                        f.area(),
+                    )
+                })
+                .unwrap();
+
+            read_image(terminal.backend())
+                .pixels()
+                .map(|px| px.0[0])
+                .max()
+                .unwrap()
+        }
+
+        assert_eq!(brightest(Modifier::empty()), 255);
+        assert_eq!(brightest(Modifier::BOLD), 255);
+        // Dimming scales the foreground color, and bold text is dimmed the same way.
+        assert_eq!(brightest(Modifier::DIM), 64);
+        assert_eq!(brightest(Modifier::DIM | Modifier::BOLD), 64);
+    }
+
+    #[test]
+    #[serial]
+    fn reversed() {
+        let render = |transparent: bool, style: Style| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_transparent_background(transparent)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("H").style(style), f.area()))
+                .unwrap();
+
+            let backend = terminal.backend_mut();
+            let (width, height) = (backend.fonts.min_width_px(), backend.fonts.cell_height_px());
+            let image = read_image(backend);
+            (0..width)
+                .flat_map(|x| (0..height).map(move |y| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+
+        // The glyph is drawn in the background color over the foreground color.
+        let cell = render(
+            false,
+            Style::new()
+                .fg(Color::Rgb(255, 0, 0))
+                .bg(Color::Rgb(0, 0, 255))
+                .reversed(),
+        );
+        assert_eq!(cell[0], [255, 0, 0, 255]);
+        assert!(cell.contains(&[0, 0, 255, 255]));
+
+        // The default foreground stays opaque when it becomes the background.
+        let cell = render(true, Style::new().reversed());
+        assert!(cell.iter().all(|px| px[3] == 255));
+        assert_eq!(cell[0], [0, 0, 0, 255]);
+        assert!(cell.contains(&[255, 255, 255, 255]));
+    }
+
+    #[test]
+    #[serial]
+    fn hidden() {
+        let render = |hidden: bool, cursor: bool| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_cursor_color(Color::Rgb(255, 0, 0))
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            let style = Style::new()
+                .fg(Color::Rgb(255, 255, 255))
+                .bg(Color::Rgb(0, 0, 255));
+            let style = if hidden { style.hidden() } else { style };
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new("H").style(style), f.area());
+                    if cursor {
+                        f.set_cursor_position((0, 0));
+                    }
+                })
+                .unwrap();
+
+            let backend = terminal.backend_mut();
+            let (width, height) = (backend.fonts.min_width_px(), backend.fonts.cell_height_px());
+            let image = read_image(backend);
+            (0..width)
+                .flat_map(|x| (0..height).map(move |y| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(render(false, false).iter().any(|px| px[0] > 200));
+        assert!(render(true, false).iter().all(|px| *px == [0, 0, 255, 255]));
+        // The cursor is still drawn over hidden text.
+        assert!(render(true, true).iter().all(|px| *px == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    #[serial]
+    fn text_blink() {
+        let render = |enabled: bool| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_rapid_blink_millis(0)
+                    .with_text_blink(enabled)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new("HH").style(
+                            Style::new()
+                                .fg(Color::Rgb(255, 255, 255))
+                                .bg(Color::Rgb(0, 0, 255))
+                                .rapid_blink(),
+                        ),
+                        f.area(),
+                    )
+                })
+                .unwrap();
+            let needs_update = terminal.backend().needs_update();
+
+            let backend = terminal.backend_mut();
+            let width = backend.fonts.min_width_px();
+            let height = backend.fonts.cell_height_px();
+            let image = read_image(backend);
+            let visible = (0..2)
+                .map(|cell| {
+                    (cell * width..(cell + 1) * width)
+                        .flat_map(|x| (0..height).map(move |y| (x, y)))
+                        .any(|(x, y)| image.get_pixel(x, y).0[0] > 200)
+                })
+                .collect::<Vec<_>>();
+            (needs_update, visible)
+        };
+
+        // Both cells toggle together on the first flush.
+        let (needs_update, visible) = render(true);
+        assert!(needs_update);
+        assert_eq!(visible, [false, false]);
+
+        let (needs_update, visible) = render(false);
+        assert!(!needs_update);
+        assert_eq!(visible, [true, true]);
+    }
+
+    #[test]
+    #[serial]
+    fn capture_region() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello World!".green()), f.area()))
+            .unwrap();
+
+        let full = read_image(terminal.backend());
+        // An odd width exercises the row padding.
+        let region = Rect::new(10, 5, 37, 20);
+        let captured = terminal.backend().capture_region(region).unwrap();
+        let expected = full
+            .view(10, 5, 37, 20)
+            .pixels()
+            .flat_map(|(_, _, px)| px.0)
+            .collect::<Vec<_>>();
+        assert!(captured == expected);
+
+        assert!(matches!(
+            terminal.backend().capture_region(Rect::new(500, 0, 13, 1)),
+            Err(crate::Error::RegionOutOfBounds(_))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(feature = "underline-color")]
+    fn underline_color() {
+        let render = |style: UnderlineStyle, underline_color: Option<Color>| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_underline_style(style)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            let text = Style::new()
+                .fg(Color::Rgb(255, 255, 255))
+                .bg(Color::Rgb(0, 0, 0))
+                .underlined();
+            let text = match underline_color {
+                Some(color) => text.underline_color(color),
+                None => text,
+            };
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("___").style(text), f.area()))
+                .unwrap();
+
+            read_image(terminal.backend())
+                .pixels()
+                .any(|px| px.0[0] > 200 && px.0[1] < 60 && px.0[2] < 60)
+        };
+
+        for style in [
+            UnderlineStyle::Straight,
+            UnderlineStyle::Curly,
+            UnderlineStyle::Double,
+        ] {
+            assert!(
+                render(style, Some(Color::Rgb(255, 0, 0))),
+                "{style:?} underline isn't red"
+            );
+            assert!(
+                !render(style, None),
+                "{style:?} underline isn't the text color"
+            );
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn tab_width() {
+        let terminal = || {
+            Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .with_tab_width(4)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let render = |terminal: &mut Terminal<
+            WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>,
+        >,
+                      text: &str| {
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new(text), f.area());
+                    if let Some(x) = text.find('|') {
+                        f.buffer_mut()[(x as u16, 0)].set_symbol("\t");
+                    }
+                })
+                .unwrap();
+
+            let symbols = terminal.backend().laid_out[..8]
+                .iter()
+                .map(|cell| cell.symbol().to_string())
+                .collect::<String>();
+            (symbols, read_image(terminal.backend()))
+        };
+
+        let (symbols, tabbed) = render(&mut terminal(), "01|34567");
+        assert_eq!(symbols, "01  4567");
+
+        let (symbols, expanded) = render(&mut terminal(), "01  4567");
+        assert_eq!(symbols, "01  4567");
+        assert!(tabbed == expanded);
+
+        // The cells a tab covers keep their content, so changing only the tab's
+        // cell brings the covered cells back, and changing a covered cell doesn't
+        // draw over the tab.
+        let mut tabs = terminal();
+        render(&mut tabs, "01|34567");
+        let (symbols, replaced) = render(&mut tabs, "01x34567");
+        assert_eq!(symbols, "01x34567");
+        assert!(replaced == render(&mut terminal(), "01x34567").1);
+
+        render(&mut tabs, "01|34567");
+        let (symbols, covered) = render(&mut tabs, "01|z4567");
+        assert_eq!(symbols, "01  4567");
+        assert!(covered == expanded);
+    }
+
+    #[test]
+    #[serial]
+    fn headless_software() {
+        let backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless_software(TextureFormat::Rgba8Unorm),
+        )
+        .unwrap();
+
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("hello"), f.area());
+            })
+            .unwrap();
+
+        let image = read_image(terminal.backend());
+        assert!(image.pixels().any(|px| px.0[..3] != [0, 0, 0]));
+    }
+
+    #[test]
+    #[serial]
+    fn extra_cursors() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (width, height) = (fonts.min_width_px(), fonts.cell_height_px());
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(width * 16).unwrap(),
+                        height: NonZeroU32::new(height).unwrap(),
+                    })
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal.hide_cursor().unwrap();
+        let style = Style::new()
+            .fg(Color::Rgb(255, 255, 255))
+            .bg(Color::Rgb(0, 0, 255));
+        let green = [0, 255, 0, 255];
+        let red = [255, 0, 0, 255];
+
+        terminal.backend_mut().set_extra_cursors(&[
+            (1, 0, CursorStyle::Block, Color::Rgb(0, 255, 0)),
+            (3, 0, CursorStyle::Bar, Color::Rgb(255, 0, 0)),
+            (500, 0, CursorStyle::Bar, Color::Rgb(255, 0, 0)),
+        ]);
+        terminal
+            .draw(|f| {
+                f.render_widget(Paragraph::new("abcd").style(style), f.area());
+            })
+            .unwrap();
+
+        let backend = terminal.backend_mut();
+        let cell = |image: &ImageBuffer<Rgba<u8>, Vec<u8>>, column: u32| {
+            (column * width..(column + 1) * width)
+                .flat_map(|x| (0..height).map(move |y| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+        let image = read_image(backend);
+        assert!(cell(&image, 0).iter().all(|px| *px != green && *px != red));
+        // The block swaps the colors of the cell, so the glyph stays visible.
+        assert_eq!(image.get_pixel(width + 1, 1).0, green);
+        assert!(cell(&image, 1).contains(&[0, 0, 255, 255]));
+        assert_eq!(image.get_pixel(3 * width, height / 2).0, red);
+        assert_ne!(image.get_pixel(3 * width + width / 2, 1).0, red);
+
+        backend.set_extra_cursors(&[]);
+        terminal.draw(|_| {}).unwrap();
+        let image = read_image(terminal.backend());
+        for column in 0..4 {
+            assert!(cell(&image, column)
+                .iter()
+                .all(|px| *px != green && *px != red));
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn selection() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (width, height) = (fonts.min_width_px(), fonts.cell_height_px());
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(width * 16).unwrap(),
+                        height: NonZeroU32::new(height * 2).unwrap(),
+                    })
+                    .with_selection_color(Color::Rgb(255, 0, 0), 128)
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal.hide_cursor().unwrap();
+        let style = Style::new()
+            .fg(Color::Rgb(255, 255, 255))
+            .bg(Color::Rgb(0, 0, 255));
+
+        let mut highlighted = |selection: Option<Selection>, text: &'static str| {
+            terminal.backend_mut().set_selection(selection);
+            terminal
+                .draw(|f| {
+                    f.render_widget(Paragraph::new(text).style(style), f.area());
+                })
+                .unwrap();
+            let image = read_image(terminal.backend());
+            (0..2)
+                .flat_map(|row| (0..16).map(move |column| (column, row)))
+                .filter(|&(column, row)| {
+                    image.get_pixel(column * width + 1, row * height + 1).0 == [128, 0, 127, 255]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            highlighted(Some(Selection::Block(Rect::new(1, 0, 1, 2))), "ab"),
+            [(1, 0), (1, 1)]
+        );
+        assert_eq!(
+            highlighted(
+                Some(Selection::Lines {
+                    start: Position::new(1, 1),
+                    end: Position::new(14, 0),
+                }),
+                "ab"
+            ),
+            [(14, 0), (15, 0), (0, 1), (1, 1)]
+        );
+        // Selecting the trailing half of a wide character highlights all of it.
+        assert_eq!(
+            highlighted(Some(Selection::Block(Rect::new(2, 0, 1, 1))), "a世b"),
+            [(1, 0), (2, 0)]
+        );
+        assert_eq!(highlighted(None, "a世b"), []);
+    }
+
+    #[test]
+    #[serial]
+    fn max_texture_dimension() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_limits(wgpu::Limits {
+                max_texture_dimension_2d: 2048,
+                ..Default::default()
+            })
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(4096).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .with_atlas_size(4096)
+            .build_headless(),
+        )
+        .unwrap();
+
+        assert_eq!(backend.max_texture_dimension(), 2048);
+        assert_eq!(backend.surface_config.width, 2048);
+        assert_eq!(backend.atlas_pages[0].cache.width(), 2048);
+        assert!(backend.wgpu_state.text_dest_view.texture().width() <= 2048);
+        backend.resize(8192, 72);
+        assert_eq!(backend.surface_config.width, 2048);
+    }
+
+    #[test]
+    #[serial]
+    fn incremental_atlas_upload() {
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                        .expect("Invalid font file"),
+                )
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("A"), f.area()))
+            .unwrap();
+        let first = read_texture(terminal.backend(), &terminal.backend().atlas_pages[0].cache);
+        assert!(
+            first.iter().any(|c| *c != 0),
+            "The first glyph wasn't uploaded"
+        );
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("AB"), f.area()))
+            .unwrap();
+        let second = read_texture(terminal.backend(), &terminal.backend().atlas_pages[0].cache);
+
+        assert!(first != second, "The second glyph wasn't uploaded");
+        // Only texels which were still blank may have changed.
+        assert!(
+            first
+                .chunks_exact(4)
+                .zip(second.chunks_exact(4))
+                .all(|(before, after)| before == after || before == [0; 4]),
+            "Adding a glyph disturbed the first glyph"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn preload_glyphs() {
+        let build = || {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+        };
+        let draw = |terminal: &mut Terminal<_>| {
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("AB ─"), f.area()))
+                .unwrap();
+        };
+
+        let mut preloaded = build();
+        preloaded.backend_mut().preload_glyphs("AB ─");
+        let atlas = read_texture(
+            preloaded.backend(),
+            &preloaded.backend().atlas_pages[0].cache,
+        );
+        assert!(atlas.iter().any(|c| *c != 0), "No glyph was uploaded");
+
+        // Preloading cached glyphs again doesn't change anything.
+        preloaded.backend_mut().preload_glyphs("BA");
+        assert!(
+            atlas
+                == read_texture(
+                    preloaded.backend(),
+                    &preloaded.backend().atlas_pages[0].cache
+                )
+        );
+
+        // Drawing the glyphs uses the preloaded entries rather than adding new ones.
+        draw(&mut preloaded);
+        assert!(
+            atlas
+                == read_texture(
+                    preloaded.backend(),
+                    &preloaded.backend().atlas_pages[0].cache
+                ),
+            "Drawing preloaded glyphs changed the atlas"
+        );
+
+        let mut plain = build();
+        draw(&mut plain);
+        assert!(
+            read_image(preloaded.backend()) == read_image(plain.backend()),
+            "Preloaded glyphs are drawn differently"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn min_cells() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.cell_width_px(), fonts.cell_height_px());
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font)
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(cell_width * 4).unwrap(),
+                        height: NonZeroU32::new(cell_height * 2).unwrap(),
+                    })
+                    .with_min_cells((10, 4))
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal.hide_cursor().unwrap();
+        assert_eq!(terminal.size().unwrap(), Size::new(10, 4));
+
+        // The middle of the grid, columns 3 to 6 and rows 1 and 2, is shown.
+        terminal
+            .draw(|f| {
+                f.render_widget(Block::new().bg(Color::Rgb(0, 0, 0)), f.area());
+                f.render_widget(
+                    Block::new().bg(Color::Rgb(255, 0, 0)),
+                    Rect::new(3, 1, 1, 1),
+                );
+                f.render_widget(
+                    Block::new().bg(Color::Rgb(0, 0, 255)),
+                    Rect::new(6, 2, 1, 1),
+                );
+            })
+            .unwrap();
+        let image = read_image(terminal.backend());
+        assert_eq!(image.dimensions(), (cell_width * 4, cell_height * 2));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(cell_width, 0).0, [0, 0, 0, 255]);
+        assert_eq!(
+            image.get_pixel(cell_width * 4 - 1, cell_height * 2 - 1).0,
+            [0, 0, 255, 255]
+        );
+
+        let backend = terminal.backend();
+        assert_eq!(backend.pixel_to_cell(0.0, 0.0), Some((3, 1)));
+        assert_eq!(
+            backend.cell_bounds(3, 1),
+            Rect::new(0, 0, cell_width as u16, cell_height as u16)
+        );
+
+        // Growing the surface past the minimum no longer crops the grid.
+        let size = terminal
+            .backend_mut()
+            .resize(cell_width * 12, cell_height * 5);
+        assert_eq!(size, Size::new(12, 5));
+    }
+
+    #[test]
+    #[serial]
+    fn set_fonts() {
+        let cascadia: &[u8] = include_bytes!("fonts/CascadiaMono-Regular.ttf");
+        let fairfax: &[u8] = include_bytes!("fonts/Fairfax.ttf");
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(
+                    Font::new(cascadia).expect("Invalid font file"),
+                )
+                .with_font_size_px(24)
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(512).unwrap(),
+                    height: NonZeroU32::new(72).unwrap(),
+                })
+                .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        terminal.hide_cursor().unwrap();
+
+        let render = |terminal: &mut Terminal<_>| {
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("Hello World!"), f.area()))
+                .unwrap();
+            read_image(terminal.backend())
+        };
+        let original_size = terminal.size().unwrap();
+        let original = render(&mut terminal);
+
+        let size = terminal.backend_mut().set_fonts(fairfax, 0).unwrap();
+        let cell_width = Fonts::new(Font::new(fairfax).unwrap(), 24).cell_width_px();
+        assert_eq!(size.width as u32, 512 / cell_width);
+        assert_eq!(size, terminal.backend().grid_size());
+        terminal
+            .resize(Rect::new(0, 0, size.width, size.height))
+            .unwrap();
+        let swapped = render(&mut terminal);
+        assert!(original != swapped, "The new font wasn't used");
+
+        let size = terminal.backend_mut().set_fonts(cascadia, 0).unwrap();
+        assert_eq!(size, original_size);
+        terminal
+            .resize(Rect::new(0, 0, size.width, size.height))
+            .unwrap();
+        assert!(
+            original == render(&mut terminal),
+            "Swapping back didn't restore the original rendering"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn set_fonts_invalid_data() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(512).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .build_headless(),
+        )
+        .unwrap();
+
+        let cell = backend.cell_bounds(0, 0);
+        let size = backend.grid_size();
+        assert!(matches!(
+            backend.set_fonts(b"not a font".as_slice(), 0),
+            Err(Error::FontParsingFailed(_))
+        ));
+        assert!(matches!(
+            backend.set_fonts(b"not a font".to_vec(), 0),
+            Err(Error::FontParsingFailed(_))
+        ));
+        assert_eq!(backend.cell_bounds(0, 0), cell);
+        assert_eq!(backend.grid_size(), size);
+    }
+
+    #[test]
+    #[serial]
+    fn render_text() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(font)
+                .with_width_and_height(Dimensions {
+                    width: NonZeroU32::new(fonts.min_width_px() * 20).unwrap(),
+                    height: NonZeroU32::new(fonts.height_px() * 3).unwrap(),
+                })
+                .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 20, 3));
+        buffer.set_string(
+            1,
+            1,
+            "Hello World!",
+            Style::new()
+                .fg(Color::Rgb(255, 255, 0))
+                .bg(Color::Rgb(0, 0, 128)),
+        );
+        let content = || {
+            buffer
+                .content()
+                .iter()
+                .enumerate()
+                .map(|(index, cell)| ((index % 20) as u16, (index / 20) as u16, cell))
+        };
 
-        let mut target = DrawTarget::from_backing(
-            cached.width as i32 * 2,
-            cached.height as i32 * 2,
-            &mut image[..],
+        let text = backend.text_texture_view().texture().clone();
+        let target = backend.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: text.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: text.format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let mut encoder = backend
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        backend
+            .render_text(
+                &mut encoder,
+                &target.create_view(&Default::default()),
+                ClearPolicy::default(),
+                content(),
+            )
+            .unwrap();
+        backend.queue.submit(Some(encoder.finish()));
+        let layered = read_texture(&backend, &target);
+
+        // Flushing afterwards renders the same grid through the regular path.
+        backend.draw(content()).unwrap();
+        backend.flush().unwrap();
+        assert!(
+            layered == backend.read_pixels().unwrap(),
+            "render_text differs from flush"
         );
-        target.set_transform(
-            &Transform::scale(scale, -scale)
-                .then(&skew)
-                .then_translate((x_off, y_off).into()),
+        assert!(
+            layered
+                .chunks_exact(4)
+                .any(|px| px[0] > 200 && px[1] > 200 && px[2] < 100),
+            "No text was rendered"
         );
+    }
 
-        target.fill(
-            &path,
-            &raqote::Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
-            &DrawOptions::default(),
+    #[test]
+    #[serial]
+    fn render_text_clear_policy() {
+        let mut backend = futures_lite::future::block_on(
+            Builder::<DefaultPostProcessor>::from_font(
+                Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                    .expect("Invalid font file"),
+            )
+            .with_width_and_height(Dimensions {
+                width: NonZeroU32::new(240).unwrap(),
+                height: NonZeroU32::new(72).unwrap(),
+            })
+            .with_transparent_background(true)
+            .with_premultiplied_alpha(true)
+            .build_headless(),
+        )
+        .unwrap();
+        backend.hide_cursor().unwrap();
+
+        // Text and a blank cell over the default, transparent background, and an
+        // opaque blue cell.
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 20, 3));
+        buffer.set_string(1, 1, "Hi", Style::new().fg(Color::Rgb(255, 255, 255)));
+        buffer.set_string(5, 1, " ", Style::new().bg(Color::Rgb(0, 0, 255)));
+
+        let text = backend.text_texture_view().texture().clone();
+        let target = backend.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: text.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: text.format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = target.create_view(&Default::default());
+        let (cell_width, cell_height) = (
+            backend.fonts.cell_width_px(),
+            backend.fonts.cell_height_px(),
         );
 
-        if fake_bold {
-            target.stroke(
-                &path,
-                &raqote::Source::Solid(SolidSource::from_unpremultiplied_argb(255, 255, 255, 255)),
-                &StrokeStyle {
-                    width: 1.5,
-                    ..Default::default()
-                },
-                &DrawOptions::new(),
-            );
-        }
-
-        let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
-        final_image.draw_image_with_size_at(
-            cached.width as f32,
-            cached.height as f32,
-            0.,
-            0.,
-            &raqote::Image {
-                width: cached.width as i32 * 2,
-                height: cached.height as i32 * 2,
-                data: &image,
-            },
-            &DrawOptions {
-                blend_mode: raqote::BlendMode::Src,
-                antialias: raqote::AntialiasMode::None,
+        let mut render = |clear| {
+            // The caller's scene is solid green.
+            let mut encoder = backend
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor::default());
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
                 ..Default::default()
-            },
-        );
+            });
+            backend
+                .render_text(
+                    &mut encoder,
+                    &view,
+                    clear,
+                    buffer
+                        .content()
+                        .iter()
+                        .enumerate()
+                        .map(|(index, cell)| ((index % 20) as u16, (index / 20) as u16, cell)),
+                )
+                .unwrap();
+            backend.queue.submit(Some(encoder.finish()));
+            let pixels = read_texture(&backend, &target);
+            let pixel = |x: u32, y: u32| {
+                let index = (y * target.width() + x) as usize * 4;
+                <[u8; 4]>::try_from(&pixels[index..index + 4]).unwrap()
+            };
+            let has_text = pixels
+                .chunks_exact(4)
+                .any(|px| px[0] > 200 && px[1] > 200 && px[2] > 200);
+            (
+                pixel(cell_width / 2, cell_height / 2),
+                pixel(
+                    cell_width * 5 + cell_width / 2,
+                    cell_height + cell_height / 2,
+                ),
+                has_text,
+            )
+        };
 
-        return (*cached, final_image.into_vec());
+        // Loading keeps the scene behind transparent cells.
+        assert_eq!(
+            render(ClearPolicy::Load),
+            ([0, 255, 0, 255], [0, 0, 255, 255], true)
+        );
+        // Clearing replaces it, by default with transparent black.
+        assert_eq!(
+            render(ClearPolicy::default()),
+            ([0, 0, 0, 0], [0, 0, 255, 255], true)
+        );
+        assert_eq!(
+            render(ClearPolicy::Clear(Color::Rgb(255, 0, 0))),
+            ([255, 0, 0, 255], [0, 0, 255, 255], true)
+        );
     }
 
-    if let Some(raster) = metrics.glyph_raster_image(GlyphId(info.glyph_id as _), u16::MAX) {
-        if let Some(value) = extract_bw_image(&mut image, raster, cached, advance_scale) {
-            return value;
-        }
-    }
+    #[test]
+    #[serial]
+    fn atlas_filter() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let stretched = |filter| {
+            let mut backend = futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(fonts.min_width_px() * 10).unwrap(),
+                        height: NonZeroU32::new(fonts.height_px() * 3).unwrap(),
+                    })
+                    .with_atlas_filter(filter)
+                    .build_headless(),
+            )
+            .unwrap();
+            backend.hide_cursor().unwrap();
+
+            let mut buffer = Buffer::empty(Rect::new(0, 0, 10, 3));
+            buffer.set_string(
+                1,
+                1,
+                "@Wgpu&",
+                Style::new()
+                    .fg(Color::Rgb(255, 255, 255))
+                    .bg(Color::Rgb(0, 0, 0)),
+            );
 
-    (
-        *cached,
-        vec![0u32; cached.width as usize * cached.height as usize],
-    )
-}
+            // Rendering into a target twice the size of the text texture
+            // stretches each glyph pixel over two pixels in either direction.
+            let text = backend.text_texture_view().texture().clone();
+            let size = text.size();
+            let target = backend.device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: Extent3d {
+                    width: size.width * 2,
+                    height: size.height * 2,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: text.format(),
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let mut encoder = backend
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor::default());
+            backend
+                .render_text(
+                    &mut encoder,
+                    &target.create_view(&Default::default()),
+                    ClearPolicy::default(),
+                    buffer
+                        .content()
+                        .iter()
+                        .enumerate()
+                        .map(|(index, cell)| ((index % 10) as u16, (index / 10) as u16, cell)),
+                )
+                .unwrap();
+            backend.queue.submit(Some(encoder.finish()));
+            read_texture(&backend, &target)
+        };
 
-fn extract_color_image(
-    image: &mut Vec<u32>,
-    raster: RasterGlyphImage,
-    cached: Entry,
-    scale: f32,
-) -> Option<(CacheRect, Vec<u32>)> {
-    match raster.format {
-        RasterImageFormat::PNG => {
-            #[cfg(feature = "png")]
-            {
-                let decoder = png::Decoder::new(std::io::Cursor::new(raster.data));
-                if let Ok(mut info) = decoder.read_info() {
-                    image.resize(
-                        info.output_buffer_size().unwrap_or_default() / size_of::<u32>(),
-                        0,
-                    );
-                    if info.next_frame(bytemuck::cast_slice_mut(image)).is_err() {
-                        return None;
-                    }
+        // Count the horizontal pairs of stretched pixels which differ, i.e.
+        // were interpolated between two glyph pixels.
+        let interpolated = |pixels: &[u8]| {
+            pixels
+                .chunks_exact(8)
+                .filter(|pair| pair[0].abs_diff(pair[4]) > 2)
+                .count()
+        };
 
-                    for rgba in image.iter_mut() {
-                        let [r, g, b, a] = rgba.to_be_bytes();
-                        *rgba = u32::from_be_bytes([a, r, g, b]);
-                    }
-                } else {
-                    return None;
-                }
-            }
-            #[cfg(not(feature = "png"))]
-            return None;
-        }
-        RasterImageFormat::BitmapPremulBgra32 => {
-            image.resize(raster.width as usize * raster.height as usize, 0);
-            for (y, row) in raster.data.chunks(raster.width as usize * 4).enumerate() {
-                for (x, pixel) in row.chunks(4).enumerate() {
-                    let pixel: &[u8; 4] = pixel.try_into().expect("Invalid chunk size");
-                    let [b, g, r, a] = *pixel;
-                    let pixel = u32::from_be_bytes([
-                        a,
-                        r.saturating_mul(255 / a),
-                        g.saturating_mul(255 / a),
-                        b.saturating_mul(255 / a),
-                    ]);
-                    image[y * raster.width as usize + x] = pixel;
-                }
-            }
-        }
-        _ => return None,
+        let nearest = stretched(FilterMode::Nearest);
+        assert!(
+            nearest.chunks_exact(4).any(|px| px[0] > 200),
+            "No text was rendered"
+        );
+        assert_eq!(interpolated(&nearest), 0, "Nearest filtering blurs edges");
+
+        let linear = stretched(FilterMode::Linear);
+        assert!(
+            interpolated(&linear) > 0,
+            "Linear filtering doesn't interpolate"
+        );
+        assert!(
+            interpolated(&linear) > nearest.len() / 8 / 100,
+            "Linear filtering barely interpolates"
+        );
     }
 
-    let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
-    final_image.draw_image_with_size_at(
-        cached.width as f32,
-        cached.height as f32,
-        raster.x as f32 * scale,
-        raster.y as f32 * scale,
-        &raqote::Image {
-            width: raster.width as i32,
-            height: raster.height as i32,
-            data: &*image,
-        },
-        &DrawOptions {
-            blend_mode: raqote::BlendMode::Src,
-            antialias: raqote::AntialiasMode::None,
-            ..Default::default()
-        },
-    );
+    #[test]
+    #[serial]
+    fn baseline_alignment() {
+        let cascadia =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fairfax = Font::new(include_bytes!("fonts/Fairfax.ttf")).expect("Invalid font file");
+        let render = |mode| {
+            // Regular text uses Fairfax, the primary font, and bold text Cascadia.
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(cascadia.clone())
+                        .with_regular_fonts([fairfax.clone()])
+                        .with_bold_fonts([cascadia.clone()])
+                        // Large enough for the baselines to be several pixels apart.
+                        .with_font_size_px(72)
+                        .with_width_and_height(Dimensions {
+                            width: NonZeroU32::new(512).unwrap(),
+                            height: NonZeroU32::new(72).unwrap(),
+                        })
+                        .with_baseline_alignment(mode)
+                        .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
 
-    let mut final_image = final_image.into_vec();
-    for argb in final_image.iter_mut() {
-        let [a, r, g, b] = argb.to_be_bytes();
-        *argb = u32::from_le_bytes([r, g, b, a]);
-    }
+            let style = Style::new()
+                .fg(Color::Rgb(255, 255, 255))
+                .bg(Color::Rgb(0, 0, 0));
+            terminal
+                .draw(|f| {
+                    f.render_widget(Block::new().style(style), f.area());
+                    f.render_widget(
+                        Paragraph::new(Line::from(vec![
+                            Span::styled("H", style),
+                            Span::styled("H", style.bold()),
+                            Span::styled("  ", style),
+                            Span::styled("H", style.underlined()),
+                            Span::styled("H", style.bold().underlined()),
+                        ])),
+                        f.area(),
+                    );
+                })
+                .unwrap();
 
-    Some((*cached, final_image))
-}
+            let cell_width = terminal.backend().fonts.cell_width_px();
+            let image = read_image(terminal.backend());
+            let (x, y, _) = terminal.backend().viewport_placement();
+            let inked = |cell: u32, row: u32| {
+                (0..cell_width)
+                    .any(|col| image.get_pixel(x + cell * cell_width + col, y + row)[0] > 128)
+            };
+            let rows = 0..terminal.backend().fonts.cell_height_px();
+            // The lowest row with ink, which is the glyph's baseline.
+            let bottom = |cell: u32| rows.clone().rev().find(|&row| inked(cell, row)).unwrap();
+            // The rows only inked in the underlined copy of a cell.
+            let underline = |cell: u32, plain: u32| {
+                rows.clone()
+                    .filter(|&row| inked(cell, row) && !inked(plain, row))
+                    .collect::<Vec<_>>()
+            };
+            (bottom(0), bottom(1), underline(4, 0), underline(5, 1))
+        };
 
-fn extract_bw_image(
-    image: &mut Vec<u32>,
-    raster: RasterGlyphImage,
-    cached: Entry,
-    scale: f32,
-) -> Option<(CacheRect, Vec<u32>)> {
-    image.resize(raster.width as usize * raster.height as usize, 0);
+        let (regular, bold, regular_underline, bold_underline) = render(BaselineMode::PerFont);
+        assert!(
+            regular.abs_diff(bold) > 1,
+            "The fonts' baselines need to differ for the test to be meaningful"
+        );
+        assert!(!regular_underline.is_empty() && !bold_underline.is_empty());
+        assert_ne!(regular_underline, bold_underline);
 
-    match raster.format {
-        RasterImageFormat::BitmapMono => {
-            from_gray_unpacked::<1, 2>(image, raster, LUT_1);
-        }
-        RasterImageFormat::BitmapMonoPacked => {
-            from_gray_packed::<1, 2>(image, raster, LUT_1);
-        }
-        RasterImageFormat::BitmapGray2 => {
-            from_gray_unpacked::<2, 4>(image, raster, LUT_2);
+        let (regular, bold, regular_underline, bold_underline) = render(BaselineMode::Primary);
+        assert!(
+            regular.abs_diff(bold) <= 1,
+            "Regular text ends at row {regular}, bold text at row {bold}"
+        );
+        // The bold glyph may cover the underline's top row.
+        assert_eq!(regular_underline.last(), bold_underline.last());
+        assert!(regular_underline[0].abs_diff(bold_underline[0]) <= 1);
+    }
+
+    /// Add a COLR/CPAL color glyph to `font`, drawing `base` as the given
+    /// layers of other glyphs in their RGBA colors.
+    fn with_color_layers(
+        font: &[u8],
+        base: char,
+        layers: &[(char, [u8; 4])],
+    ) -> Vec<u8> {
+        let face = rustybuzz::ttf_parser::Face::parse(font, 0).expect("Invalid font file");
+        let glyph = |ch| face.glyph_index(ch).expect("Missing glyph").0;
+
+        let mut colr = vec![];
+        colr.extend_from_slice(&0u16.to_be_bytes());
+        colr.extend_from_slice(&1u16.to_be_bytes());
+        colr.extend_from_slice(&14u32.to_be_bytes());
+        colr.extend_from_slice(&20u32.to_be_bytes());
+        colr.extend_from_slice(&(layers.len() as u16).to_be_bytes());
+        for value in [glyph(base), 0, layers.len() as u16] {
+            colr.extend_from_slice(&value.to_be_bytes());
         }
-        RasterImageFormat::BitmapGray2Packed => {
-            from_gray_packed::<2, 4>(image, raster, LUT_2);
+        for (idx, (ch, _)) in layers.iter().enumerate() {
+            colr.extend_from_slice(&glyph(*ch).to_be_bytes());
+            colr.extend_from_slice(&(idx as u16).to_be_bytes());
         }
-        RasterImageFormat::BitmapGray4 => {
-            from_gray_unpacked::<4, 16>(image, raster, LUT_4);
+
+        let mut cpal = vec![];
+        for value in [0, layers.len() as u16, 1, layers.len() as u16] {
+            cpal.extend_from_slice(&value.to_be_bytes());
         }
-        RasterImageFormat::BitmapGray4Packed => {
-            from_gray_packed::<4, 16>(image, raster, LUT_4);
+        cpal.extend_from_slice(&14u32.to_be_bytes());
+        cpal.extend_from_slice(&0u16.to_be_bytes());
+        for (_, [r, g, b, a]) in layers {
+            cpal.extend_from_slice(&[*b, *g, *r, *a]);
         }
-        RasterImageFormat::BitmapGray8 => {
-            for (byte, dst) in raster.data.iter().zip(image.iter_mut()) {
-                *dst = u32::from_be_bytes([*byte, 255, 255, 255]);
-            }
+
+        // Rebuild the font with the new tables added to its table directory.
+        let num_tables = u16::from_be_bytes([font[4], font[5]]) as usize;
+        let mut tables = (0..num_tables)
+            .map(|idx| {
+                let record = &font[12 + idx * 16..28 + idx * 16];
+                let offset = u32::from_be_bytes(record[8..12].try_into().unwrap()) as usize;
+                let len = u32::from_be_bytes(record[12..16].try_into().unwrap()) as usize;
+                (
+                    <[u8; 4]>::try_from(&record[..4]).unwrap(),
+                    &font[offset..offset + len],
+                )
+            })
+            .collect::<Vec<_>>();
+        tables.push((*b"COLR", &colr));
+        tables.push((*b"CPAL", &cpal));
+        tables.sort_by_key(|(tag, _)| *tag);
+
+        let mut out = font[..12].to_vec();
+        out[4..6].copy_from_slice(&(tables.len() as u16).to_be_bytes());
+        let mut offset = 12 + tables.len() * 16;
+        let mut data = vec![];
+        for (tag, table) in &tables {
+            out.extend_from_slice(tag);
+            out.extend_from_slice(&0u32.to_be_bytes());
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            data.extend_from_slice(table);
+            data.resize(data.len().next_multiple_of(4), 0);
+            offset = 12 + tables.len() * 16 + data.len();
         }
-        _ => return None,
+        out.extend_from_slice(&data);
+        out
     }
 
-    let mut final_image = DrawTarget::new(cached.width as i32, cached.height as i32);
-    final_image.draw_image_with_size_at(
-        cached.width as f32,
-        cached.height as f32,
-        raster.x as f32 * scale,
-        raster.y as f32 * scale,
-        &raqote::Image {
-            width: raster.width as i32,
-            height: raster.height as i32,
-            data: &*image,
-        },
-        &DrawOptions {
-            blend_mode: raqote::BlendMode::Src,
-            antialias: raqote::AntialiasMode::None,
-            ..Default::default()
-        },
-    );
+    #[test]
+    #[serial]
+    fn emoji_mode() {
+        // A yellow square with a blue 'O' on top, standing in for an emoji.
+        let font = with_color_layers(
+            include_bytes!("fonts/CascadiaMono-Regular.ttf"),
+            'O',
+            &[('█', [255, 255, 0, 255]), ('O', [0, 0, 255, 255])],
+        );
+        let render = |mode| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(&font).expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(64).unwrap(),
+                        height: NonZeroU32::new(32).unwrap(),
+                    })
+                    .with_emoji_mode(mode)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new("O")
+                            .fg(Color::Rgb(255, 0, 0))
+                            .bg(Color::Rgb(0, 0, 0)),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+            read_image(terminal.backend())
+        };
 
-    let mut final_image = final_image.into_vec();
-    for argb in final_image.iter_mut() {
-        let [a, r, g, b] = argb.to_be_bytes();
-        *argb = u32::from_le_bytes([r, g, b, a]);
+        // Color glyphs keep their own colors, regardless of the foreground.
+        let image = render(EmojiMode::Color);
+        assert!(image
+            .pixels()
+            .any(|&Rgba([r, g, b, _])| r > 200 && g > 200 && b < 64));
+        assert!(image
+            .pixels()
+            .any(|&Rgba([r, g, b, _])| r < 64 && g < 64 && b > 200));
+
+        // Monochrome glyphs are coverage tinted with the foreground, like text.
+        let image = render(EmojiMode::Monochrome);
+        assert!(image.pixels().all(|&Rgba([_, g, b, _])| g < 16 && b < 16));
+        assert!(image.pixels().any(|&Rgba([r, ..])| r > 128));
     }
 
-    Some((*cached, final_image))
-}
+    #[test]
+    #[serial]
+    fn stem_darkening() {
+        let render = |amount| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
+                            .expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(64).unwrap(),
+                        height: NonZeroU32::new(24).unwrap(),
+                    })
+                    .with_fg_color(Color::Rgb(255, 255, 255))
+                    .with_bg_color(Color::Rgb(0, 0, 0))
+                    // Blend the coverage directly so that it can be read back.
+                    .with_text_gamma(1.0)
+                    .with_stem_darkening(amount)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| f.render_widget(Paragraph::new("O"), f.area()))
+                .unwrap();
+            read_image(terminal.backend())
+                .pixels()
+                .map(|px| px[0])
+                .collect::<Vec<_>>()
+        };
 
-fn from_gray_unpacked<const BITS: usize, const ENTRIES: usize>(
-    image: &mut [u32],
-    raster: RasterGlyphImage,
-    steps: [u8; ENTRIES],
-) {
-    for (bits, dst) in raster
-        .data
-        .chunks((raster.width as usize / (8 / BITS)) + 1)
-        .zip(image.chunks_mut(raster.width as usize))
-    {
-        let bits = BitSlice::<_, Lsb0>::from_slice(bits);
-        for (bits, dst) in bits.chunks(BITS).zip(dst.iter_mut()) {
-            let mut index = 0;
-            for idx in bits.iter_ones() {
-                index |= 1 << (BITS - idx - 1);
+        let plain = render(0.0);
+        let mut previous = plain.clone();
+        for amount in [0.5, 1.0] {
+            let darkened = render(amount);
+            let mut edges = 0;
+            for ((&plain, &lighter), &darker) in plain.iter().zip(&previous).zip(&darkened) {
+                if plain == 0 || plain == 255 {
+                    assert_eq!(plain, darker);
+                    continue;
+                }
+                assert!(darker >= lighter, "{darker} is lighter than {lighter}");
+                edges += usize::from(darker > lighter);
             }
-            let value = steps[index as usize];
-            *dst = u32::from_be_bytes([value, 255, 255, 255]);
+            assert!(edges > 0, "Darkening by {amount} had no effect");
+            previous = darkened;
         }
     }
-}
 
-fn from_gray_packed<const BITS: usize, const ENTRIES: usize>(
-    image: &mut [u32],
-    raster: RasterGlyphImage,
-    steps: [u8; ENTRIES],
-) {
-    let bits = BitSlice::<_, Lsb0>::from_slice(raster.data);
-    for (bits, dst) in bits.chunks(BITS).zip(image.iter_mut()) {
-        let mut index = 0;
-        for idx in bits.iter_ones() {
-            index |= 1 << (BITS - idx - 1);
+    #[test]
+    #[serial]
+    fn premultiplied_alpha() {
+        // A yellow 'O' with antialiased edges, next to regular text.
+        let font = with_color_layers(
+            include_bytes!("fonts/CascadiaMono-Regular.ttf"),
+            'O',
+            &[('O', [255, 255, 0, 255])],
+        );
+        let render = |premultiplied| {
+            let mut terminal = Terminal::new(
+                futures_lite::future::block_on(
+                    Builder::<DefaultPostProcessor>::from_font(
+                        Font::new(&font).expect("Invalid font file"),
+                    )
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(64).unwrap(),
+                        height: NonZeroU32::new(32).unwrap(),
+                    })
+                    .with_text_gamma(1.0)
+                    .with_premultiplied_alpha(premultiplied)
+                    .build_headless(),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            terminal.hide_cursor().unwrap();
+            terminal
+                .draw(|f| {
+                    f.render_widget(
+                        Paragraph::new("Ox")
+                            .fg(Color::Rgb(255, 255, 255))
+                            .bg(Color::Rgb(0, 0, 0)),
+                        f.area(),
+                    );
+                })
+                .unwrap();
+            let cell_width = terminal.backend().fonts.cell_width_px();
+            let (x, y, _) = terminal.backend().viewport_placement();
+            let image = read_image(terminal.backend());
+            let cell = |cell: u32| {
+                (0..terminal.backend().fonts.cell_height_px())
+                    .flat_map(|row| {
+                        (0..cell_width).map(move |col| (x + cell * cell_width + col, y + row))
+                    })
+                    .map(|(x, y)| image.get_pixel(x, y)[0])
+                    .collect::<Vec<_>>()
+            };
+            (cell(0), cell(1))
+        };
+
+        let (straight_glyph, straight_text) = render(false);
+        let (glyph, text) = render(true);
+
+        // Regular text is blended the same either way.
+        for (straight, premultiplied) in straight_text.iter().zip(&text) {
+            assert!(straight.abs_diff(*premultiplied) <= 1);
         }
-        let value = steps[index as usize];
-        *dst = u32::from_be_bytes([value, 255, 255, 255]);
+
+        // The color glyph's edges are no longer multiplied with their alpha twice.
+        let mut edges = 0;
+        for (&straight, &premultiplied) in straight_glyph.iter().zip(&glyph) {
+            assert!(premultiplied >= straight.saturating_sub(1));
+            edges += usize::from(premultiplied > straight.saturating_add(8));
+        }
+        assert!(edges > 0, "No edge got lighter");
     }
-}
 
-const LUT_1: [u8; 2] = [0, 255];
-const LUT_2: [u8; 4] = [0, 255 / 3, 2 * (255 / 3), 255];
-const LUT_4: [u8; 16] = [
-    0,
-    (255 / 15),
-    2 * (255 / 15),
-    3 * (255 / 15),
-    4 * (255 / 15),
-    5 * (255 / 15),
-    6 * (255 / 15),
-    7 * (255 / 15),
-    8 * (255 / 15),
-    9 * (255 / 15),
-    10 * (255 / 15),
-    11 * (255 / 15),
-    12 * (255 / 15),
-    13 * (255 / 15),
-    14 * (255 / 15),
-    255,
-];
+    #[test]
+    #[serial]
+    fn update_palette() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let fonts = Fonts::new(font.clone(), 24);
+        let (cell_width, cell_height) = (fonts.min_width_px(), fonts.cell_height_px());
 
-#[cfg(test)]
-mod tests {
-    use std::num::NonZeroU32;
+        let mut palette = XTERM_PALETTE;
+        palette[1] = [0, 255, 0];
 
-    use image::load_from_memory;
-    use image::GenericImageView;
-    use image::ImageBuffer;
-    use image::Rgba;
-    use ratatui::style::Color;
-    use ratatui::style::Stylize;
-    use ratatui::text::Line;
-    use ratatui::widgets::Block;
-    use ratatui::widgets::Paragraph;
-    use ratatui::Terminal;
-    use rustybuzz::ttf_parser::RasterGlyphImage;
-    use rustybuzz::ttf_parser::RasterImageFormat;
-    use serial_test::serial;
-    use wgpu::wgt::PollType;
-    use wgpu::CommandEncoderDescriptor;
-    use wgpu::Device;
-    use wgpu::Extent3d;
-    use wgpu::Queue;
-    use wgpu::TextureFormat;
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(cell_width * 16).unwrap(),
+                        height: NonZeroU32::new(cell_height).unwrap(),
+                    })
+                    .with_palette(palette)
+                    .build_headless_with_format(TextureFormat::Rgba8Unorm),
+            )
+            .unwrap(),
+        )
+        .unwrap();
 
-    use crate::backend::wgpu_backend::extract_bw_image;
-    use crate::backend::wgpu_backend::LUT_2;
-    use crate::backend::wgpu_backend::LUT_4;
-    use crate::backend::HeadlessSurface;
-    use crate::shaders::DefaultPostProcessor;
-    use crate::utils::text_atlas::CacheRect;
-    use crate::utils::text_atlas::Entry;
-    use crate::Builder;
-    use crate::Dimensions;
-    use crate::Font;
+        terminal
+            .draw(|f| {
+                f.render_widget(
+                    Paragraph::new(" ".bg(Color::Indexed(1)).fg(Color::Indexed(2))),
+                    f.area(),
+                )
+            })
+            .unwrap();
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(0, 0).0, [0, 255, 0, 255]);
 
-    fn tex2buffer(
-        device: &Device,
-        queue: &Queue,
-        surface: &HeadlessSurface,
-    ) {
-        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
-        encoder.copy_texture_to_buffer(
-            surface.texture.as_ref().unwrap().as_image_copy(),
-            wgpu::TexelCopyBufferInfo {
-                buffer: surface.buffer.as_ref().unwrap(),
-                layout: wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(surface.buffer_width),
-                    rows_per_image: Some(surface.height),
-                },
-            },
-            Extent3d {
-                width: surface.width,
-                height: surface.height,
-                depth_or_array_layers: 1,
-            },
+        palette[1] = [0, 0, 255];
+        terminal.backend_mut().update_palette(palette);
+        Backend::flush(terminal.backend_mut()).unwrap();
+
+        let image = read_image(terminal.backend());
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    #[serial]
+    fn set_font_size() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let mut terminal = Terminal::new(
+            futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(72).unwrap(),
+                    })
+                    .build_headless(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let size = terminal.backend_mut().set_font_size(36.0);
+        assert_eq!(
+            size,
+            Size {
+                width: (512 / Fonts::new(font.clone(), 36).min_width_px()) as u16,
+                height: 2,
+            }
         );
-        queue.submit(Some(encoder.finish()));
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        assert_eq!(terminal.size().unwrap(), size);
+
+        let size = terminal.backend_mut().set_font_size(1000.0);
+        assert_eq!(
+            size,
+            Size {
+                width: 1,
+                height: 1,
+            }
+        );
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        assert_eq!(terminal.get_frame().area().as_size(), size);
     }
 
     #[test]
     #[serial]
-    fn a_z() {
+    fn scale_factor() {
+        let font =
+            Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf")).expect("Invalid font file");
+        let build = |size, scale_factor| {
+            let mut backend = futures_lite::future::block_on(
+                Builder::<DefaultPostProcessor>::from_font(font.clone())
+                    .with_width_and_height(Dimensions {
+                        width: NonZeroU32::new(512).unwrap(),
+                        height: NonZeroU32::new(144).unwrap(),
+                    })
+                    .with_font_size_px(size)
+                    .with_scale_factor(scale_factor)
+                    .build_headless(),
+            )
+            .unwrap();
+            backend.hide_cursor().unwrap();
+            backend
+        };
+        let render =
+            |backend: &mut WgpuBackend<'_, 'static, DefaultPostProcessor, HeadlessSurface>| {
+                let mut cell = Cell::new("@");
+                cell.set_fg(Color::Rgb(255, 255, 255))
+                    .set_bg(Color::Rgb(0, 0, 0));
+                backend.draw([(0, 0, &cell)].into_iter()).unwrap();
+                backend.flush().unwrap();
+                read_image(backend)
+            };
+
+        // Logical font sizes are scaled to physical pixels.
+        let mut physical = build(24, 1.0);
+        let mut scaled = build(12, 2.0);
+        assert_eq!(scaled.scale_factor(), 2.0);
+        assert_eq!(scaled.fonts.height_px(), 24);
+        assert_eq!(scaled.grid_size(), physical.grid_size());
+        assert!(render(&mut scaled) == render(&mut physical));
+
+        // Moving to another monitor rescales from the logical size, without
+        // drifting.
+        let mut logical = build(12, 1.0);
+        assert_eq!(scaled.set_scale_factor(1.5), build(18, 1.0).grid_size());
+        assert_eq!(scaled.fonts.height_px(), 18);
+        assert_eq!(scaled.set_scale_factor(1.0), logical.grid_size());
+        assert!(render(&mut scaled) == render(&mut logical));
+
+        // The font size stays logical.
+        scaled.set_scale_factor(2.0);
+        assert_eq!(scaled.set_font_size(18.0), build(36, 1.0).grid_size());
+        assert_eq!(scaled.fonts.height_px(), 36);
+
+        // Invalid scale factors are ignored.
+        assert_eq!(
+            scaled.set_scale_factor(f64::NAN),
+            build(18, 1.0).grid_size()
+        );
+        assert_eq!(scaled.scale_factor(), 1.0);
+        assert_eq!(build(24, -1.0).fonts.height_px(), 24);
+    }
+
+    #[test]
+    #[serial]
+    fn line_height_scale() {
         let mut terminal = Terminal::new(
             futures_lite::future::block_on(
                 Builder::<DefaultPostProcessor>::from_font(
                     Font::new(include_bytes!("fonts/CascadiaMono-Regular.ttf"))
                         .expect("Invalid font file"),
                 )
+                .with_font_size_px(18)
+                .with_line_height_scale(2.0)
                 .with_width_and_height(Dimensions {
                     width: NonZeroU32::new(512).unwrap(),
                     height: NonZeroU32::new(72).unwrap(),
@@ -1410,53 +10277,19 @@ mod tests {
         )
         .unwrap();
 
+        assert_eq!(terminal.size().unwrap().height, 2);
+
         terminal
-            .draw(|f| {
-                let block = Block::bordered();
-                let area = block.inner(f.area());
-                f.render_widget(block, f.area());
-                f.render_widget(Paragraph::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ"), area);
-            })
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
             .unwrap();
 
-        let surface = &terminal.backend().surface;
-        tex2buffer(
-            &terminal.backend().device,
-            &terminal.backend().queue,
-            surface,
-        );
-        {
-            let buffer = surface.buffer.as_ref().unwrap().slice(..);
-
-            let (send, recv) = oneshot::channel();
-            buffer.map_async(wgpu::MapMode::Read, move |data| {
-                send.send(data).unwrap();
-            });
-            terminal
-                .backend()
-                .device
-                .poll(PollType::Wait {
-                    submission_index: None,
-                    timeout: None,
-                })
-                .unwrap();
-            recv.recv().unwrap().unwrap();
-
-            let data = buffer.get_mapped_range();
-            let image =
-                ImageBuffer::<Rgba<u8>, _>::from_raw(surface.width, surface.height, data).unwrap();
-
-            let pixels = image.pixels().copied().collect::<Vec<_>>();
-            let golden = load_from_memory(include_bytes!("goldens/a_z.png")).unwrap();
-            let golden_pixels = golden.pixels().map(|(_, _, px)| px).collect::<Vec<_>>();
-
-            assert!(
-                pixels == golden_pixels,
-                "Rendered image differs from golden"
-            );
-        }
+        let size = terminal.backend_mut().set_line_height_scale(1.0);
+        assert_eq!(size.height, 4);
 
-        surface.buffer.as_ref().unwrap().unmap();
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("Hello"), f.area()))
+            .unwrap();
+        assert_eq!(terminal.get_frame().area().as_size(), size);
     }
 
     #[test]
@@ -2076,12 +10909,12 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: golden.width(),
                 height: golden.height(),
             }),
-            1.0,
         )
         .expect("Didn't extract png")
         .1;
@@ -2119,12 +10952,12 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 1,
                 height: 1,
             }),
-            1.0,
         )
         .expect("Didn't extract bgra")
         .1;
@@ -2135,6 +10968,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bgra_transparent() {
+        use crate::backend::wgpu_backend::extract_color_image;
+
+        let data = [16, 16, 16, 0];
+        let raster = RasterGlyphImage {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+            pixels_per_em: 0,
+            format: RasterImageFormat::BitmapPremulBgra32,
+            data: &data,
+        };
+
+        let mut image = vec![];
+        let extracted = extract_color_image(
+            &mut image,
+            raster,
+            Entry::Cached(CacheRect {
+                page: 0,
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            }),
+        )
+        .expect("Didn't extract bgra")
+        .1;
+
+        assert_eq!(bytemuck::bytes_of(&extracted[0]), [0, 0, 0, 0]);
+    }
+
     #[test]
     fn bmp1() {
         let data0 = 0b1000_0001;
@@ -2154,6 +11020,7 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 4,
@@ -2202,6 +11069,7 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 8,
@@ -2260,6 +11128,7 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 6,
@@ -2316,6 +11185,7 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 6,
@@ -2384,6 +11254,7 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 1,
@@ -2422,6 +11293,7 @@ mod tests {
             &mut image,
             raster,
             Entry::Cached(CacheRect {
+                page: 0,
                 x: 0,
                 y: 0,
                 width: 2,
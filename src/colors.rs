@@ -1,6 +1,7 @@
 use ratatui::style::Color;
 
-pub(crate) type Rgb = [u8; 3];
+/// A color as red, green, and blue components.
+pub type Rgb = [u8; 3];
 
 #[allow(non_snake_case)]
 #[derive(Debug, Default, Clone)]
@@ -23,36 +24,63 @@ pub struct ColorTable {
     pub WHITE: Rgb,
 }
 
-impl ColorTable {
+/// The colors used during rendering, mapping ratatui's named colors through a
+/// [`ColorTable`] and indexed colors through a 256 color palette.
+#[derive(Debug, Clone)]
+pub(crate) struct Colors {
+    pub(crate) table: ColorTable,
+    pub(crate) palette: [Rgb; 256],
+}
+
+impl Colors {
     pub(crate) fn c2c(
         &self,
         color: Color,
         reset: Rgb,
     ) -> Rgb {
+        let table = &self.table;
         match color {
             Color::Reset => reset,
-            Color::Black => self.BLACK,
-            Color::Red => self.RED,
-            Color::Green => self.GREEN,
-            Color::Yellow => self.YELLOW,
-            Color::Blue => self.BLUE,
-            Color::Magenta => self.MAGENTA,
-            Color::Cyan => self.CYAN,
-            Color::Gray => self.GRAY,
-            Color::DarkGray => self.DARKGRAY,
-            Color::LightRed => self.LIGHTRED,
-            Color::LightGreen => self.LIGHTGREEN,
-            Color::LightYellow => self.LIGHTYELLOW,
-            Color::LightBlue => self.LIGHTBLUE,
-            Color::LightMagenta => self.LIGHTMAGENTA,
-            Color::LightCyan => self.LIGHTCYAN,
-            Color::White => self.WHITE,
+            Color::Black => table.BLACK,
+            Color::Red => table.RED,
+            Color::Green => table.GREEN,
+            Color::Yellow => table.YELLOW,
+            Color::Blue => table.BLUE,
+            Color::Magenta => table.MAGENTA,
+            Color::Cyan => table.CYAN,
+            Color::Gray => table.GRAY,
+            Color::DarkGray => table.DARKGRAY,
+            Color::LightRed => table.LIGHTRED,
+            Color::LightGreen => table.LIGHTGREEN,
+            Color::LightYellow => table.LIGHTYELLOW,
+            Color::LightBlue => table.LIGHTBLUE,
+            Color::LightMagenta => table.LIGHTMAGENTA,
+            Color::LightCyan => table.LIGHTCYAN,
+            Color::White => table.WHITE,
             Color::Rgb(r, g, b) => [r, g, b],
-            Color::Indexed(idx) => ANSI_TO_RGB[idx as usize],
+            Color::Indexed(idx) => self.palette[idx as usize],
         }
     }
 }
 
+/// Decodes an srgb encoded channel in `0.0..=1.0` to linear.
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear channel in `0.0..=1.0` as srgb.
+pub(crate) fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 /// <https://www.w3.org/TR/SVG11/types.html#ColorKeywords>
 pub(crate) mod named {
     use crate::colors::ColorTable;
@@ -77,10 +105,12 @@ pub(crate) mod named {
     };
 }
 
+/// The standard xterm 256 color palette used for [`Color::Indexed`] colors.
+///
 /// This could be split into `[standard table]` + `[high intensity table]` +
 /// `<6x6x6 cube fn>` + `<grayscale step fn>`, but a lookup table is only 768
 /// bytes and way simpler to implement.
-const ANSI_TO_RGB: [Rgb; 256] = [
+pub const XTERM_PALETTE: [Rgb; 256] = [
     [0x00, 0x00, 0x00],
     [0x80, 0x00, 0x00],
     [0x00, 0x80, 0x00],
@@ -1,16 +1,28 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::hash::BuildHasher;
+use std::hash::DefaultHasher;
 use std::hash::Hasher;
 use std::hash::RandomState;
 
 use ratatui::buffer::Cell;
 use ratatui::style::Modifier;
+use rustybuzz::ttf_parser;
+use rustybuzz::ttf_parser::GlyphId;
+use rustybuzz::ttf_parser::RasterImageFormat;
+use rustybuzz::ttf_parser::Tag;
 use rustybuzz::Face;
 
+use crate::Error;
+use crate::RandomState as CacheState;
+use crate::Result;
+
 /// A Font which can be used for rendering.
 #[derive(Clone)]
 pub struct Font<'a> {
     font: Face<'a>,
     advance: f32,
+    data_id: u64,
     id: u64,
 }
 
@@ -18,23 +30,103 @@ impl<'a> Font<'a> {
     /// Create a new Font from data. Returns [`None`] if the font cannot
     /// be parsed.
     pub fn new(data: &'a [u8]) -> Option<Self> {
+        Self::from_bytes(data, 0).ok()
+    }
+
+    /// Create a new Font from TTF/OTF data, or from the face at `face_index`
+    /// for a font collection (.ttc/.otc). Use `0` for fonts which aren't
+    /// collections.
+    ///
+    /// Returns [`Error::FontParsingFailed`] if the data isn't a valid font or
+    /// the collection doesn't contain the requested face.
+    pub fn from_bytes(
+        data: &'a [u8],
+        face_index: u32,
+    ) -> Result<Self> {
+        let font = ttf_parser::Face::parse(data, face_index).map_err(Error::FontParsingFailed)?;
+        let font = Face::from_face(font);
+
         let mut hasher = RandomState::new().build_hasher();
         hasher.write(data);
+        hasher.write_u32(face_index);
+        let id = hasher.finish();
 
-        Face::from_slice(data, 0).map(|font| {
-            let advance = font
-                .glyph_hor_advance(font.glyph_index('m').unwrap_or_default())
-                .unwrap_or_default() as f32;
-            Self {
-                font,
-                advance,
-                id: hasher.finish(),
-            }
+        Ok(Self {
+            advance: Self::advance_of(&font),
+            font,
+            data_id: id,
+            id,
         })
     }
 }
 
+impl Font<'static> {
+    /// Create a new Font from owned TTF/OTF data, e.g. a font downloaded at
+    /// runtime. See [`Font::from_bytes`] for details on `face_index`.
+    ///
+    /// <div class="warning">
+    ///
+    /// Fonts borrow their data for their entire lifetime, so the data is
+    /// leaked in order to produce a `'static` font and is never freed, even
+    /// after the font is dropped. Every call leaks another copy, so avoid
+    /// calling this repeatedly, e.g. whenever the user switches fonts. Load
+    /// each font once and keep it, or borrow the data with
+    /// [`Font::from_bytes`] instead.
+    ///
+    /// </div>
+    pub fn from_owned_bytes(
+        data: Vec<u8>,
+        face_index: u32,
+    ) -> Result<Self> {
+        // Validate before leaking so that invalid data is freed.
+        ttf_parser::Face::parse(&data, face_index).map_err(Error::FontParsingFailed)?;
+        Self::from_bytes(Vec::leak(data), face_index)
+    }
+
+    /// The number of faces in a font collection, or [`None`] if the data isn't
+    /// a font collection.
+    pub fn faces_in_collection(data: &[u8]) -> Option<u32> {
+        ttf_parser::fonts_in_collection(data)
+    }
+}
+
 impl Font<'_> {
+    /// Instantiate this font at the provided variation axis coordinates, e.g.
+    /// `(Tag::from_bytes(b"wght"), 350.0)`. Axes which aren't provided are
+    /// reset to their default values, and axes the font doesn't support are
+    /// ignored. This has no effect on non-variable fonts.
+    pub(crate) fn set_variable_axes(
+        &mut self,
+        axes: &[(Tag, f32)],
+    ) {
+        if !self.font.is_variable() {
+            return;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u64(self.data_id);
+        for axis in self.font.variation_axes() {
+            let value = axes
+                .iter()
+                .rev()
+                .find(|(tag, _)| *tag == axis.tag)
+                .map(|(_, value)| *value)
+                .unwrap_or(axis.def_value);
+            self.font.set_variation(axis.tag, value);
+
+            hasher.write_u32(axis.tag.0);
+            hasher.write_u32(value.to_bits());
+        }
+
+        self.advance = Self::advance_of(&self.font);
+        self.id = hasher.finish();
+    }
+
+    fn advance_of(font: &Face) -> f32 {
+        font.glyph_hor_advance(font.glyph_index('m').unwrap_or_default())
+            .unwrap_or_default() as f32
+    }
+
     pub(crate) fn id(&self) -> u64 {
         self.id
     }
@@ -60,6 +152,8 @@ impl Font<'_> {
 pub struct Fonts<'a> {
     char_width: u32,
     char_height: u32,
+    line_height_scale: f32,
+    cell_width_scale: f32,
 
     last_resort: Font<'a>,
 
@@ -67,6 +161,25 @@ pub struct Fonts<'a> {
     bold: Vec<Font<'a>>,
     italic: Vec<Font<'a>>,
     bold_italic: Vec<Font<'a>>,
+    fallback: Vec<Font<'a>>,
+
+    axes: Vec<(Tag, f32)>,
+    resolved: RefCell<HashMap<(char, Modifier), Resolved, CacheState>>,
+}
+
+/// The font chosen for a code point along with whether bold/italic must be
+/// faked.
+type Resolved = (Slot, bool, bool);
+
+/// Identifies a font within a [`Fonts`] collection without borrowing it.
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    LastResort,
+    Regular(usize),
+    Bold(usize),
+    Italic(usize),
+    BoldItalic(usize),
+    Fallback(usize),
 }
 
 impl<'a> Fonts<'a> {
@@ -81,14 +194,20 @@ impl<'a> Fonts<'a> {
         font: Font<'a>,
         size_px: u32,
     ) -> Self {
+        let size_px = size_px.max(1);
         Self {
             char_width: font.char_width(size_px),
             char_height: size_px,
+            line_height_scale: 1.0,
+            cell_width_scale: 1.0,
             last_resort: font,
             regular: vec![],
             bold: vec![],
             italic: vec![],
             bold_italic: vec![],
+            fallback: vec![],
+            axes: vec![],
+            resolved: RefCell::default(),
         }
     }
 
@@ -98,12 +217,53 @@ impl<'a> Fonts<'a> {
         self.char_height
     }
 
+    /// The scale applied to the font height to compute the height of a cell.
+    #[inline]
+    pub fn line_height_scale(&self) -> f32 {
+        self.line_height_scale
+    }
+
+    /// Set the scale applied to the font height to compute the height of a
+    /// cell, e.g. `1.2` for 20% taller cells. Glyphs are vertically centered
+    /// within the cell. Defaults to `1.0`.
+    pub fn set_line_height_scale(
+        &mut self,
+        scale: f32,
+    ) {
+        self.line_height_scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        };
+    }
+
+    /// The scale applied to the font width to compute the width of a cell.
+    #[inline]
+    pub fn cell_width_scale(&self) -> f32 {
+        self.cell_width_scale
+    }
+
+    /// Set the scale applied to the font width to compute the width of a
+    /// cell, e.g. `1.1` for 10% wider columns. Glyphs keep their size and are
+    /// horizontally centered within the cell. Defaults to `1.0`.
+    pub fn set_cell_width_scale(
+        &mut self,
+        scale: f32,
+    ) {
+        self.cell_width_scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        };
+    }
+
     /// Change the height of all fonts in this collection to the specified
     /// height in pixels.
     pub fn set_size_px(
         &mut self,
         height_px: u32,
     ) {
+        let height_px = height_px.max(1);
         self.char_height = height_px;
 
         self.char_width = std::iter::once(&self.last_resort)
@@ -111,11 +271,36 @@ impl<'a> Fonts<'a> {
             .chain(self.bold.iter())
             .chain(self.italic.iter())
             .chain(self.bold_italic.iter())
+            .chain(self.fallback.iter())
             .map(|font| font.char_width(height_px))
             .min()
             .unwrap_or_default();
     }
 
+    /// Set the variation axis coordinates used to instantiate variable fonts in
+    /// this collection, e.g. `(Tag::from_bytes(b"wght"), 350.0)`. These apply
+    /// to all current fonts as well as fonts added later. Axes which aren't
+    /// provided use the font's default value, and fonts which don't support an
+    /// axis ignore it.
+    pub fn set_variable_axes(
+        &mut self,
+        axes: &[(Tag, f32)],
+    ) {
+        self.axes = axes.to_vec();
+
+        for font in std::iter::once(&mut self.last_resort)
+            .chain(self.regular.iter_mut())
+            .chain(self.bold.iter_mut())
+            .chain(self.italic.iter_mut())
+            .chain(self.bold_italic.iter_mut())
+            .chain(self.fallback.iter_mut())
+        {
+            font.set_variable_axes(&self.axes);
+        }
+
+        self.set_size_px(self.char_height);
+    }
+
     /// Add a collection of fonts for various styles. They will automatically be
     /// added to the appropriate fallback font list based on the font's
     /// bold/italic properties. Note that this will automatically organize fonts
@@ -129,8 +314,10 @@ impl<'a> Fonts<'a> {
         let italic_len = self.italic.len();
         let bold_len = self.bold.len();
         let regular_len = self.regular.len();
+        self.resolved.get_mut().clear();
 
-        for font in fonts {
+        for mut font in fonts {
+            font.set_variable_axes(&self.axes);
             if !font.font().is_monospaced() {
                 warn!("Non monospace font used in add_fonts, this may cause unexpected rendering.");
             }
@@ -159,9 +346,11 @@ impl<'a> Fonts<'a> {
         &mut self,
         fonts: impl IntoIterator<Item = Font<'a>>,
     ) {
+        self.resolved.get_mut().clear();
         self.char_width = self.char_width.min(Self::add_fonts_internal(
             &mut self.regular,
             fonts,
+            &self.axes,
             self.char_height,
         ));
     }
@@ -176,9 +365,11 @@ impl<'a> Fonts<'a> {
         &mut self,
         fonts: impl IntoIterator<Item = Font<'a>>,
     ) {
+        self.resolved.get_mut().clear();
         self.char_width = self.char_width.min(Self::add_fonts_internal(
             &mut self.bold,
             fonts,
+            &self.axes,
             self.char_height,
         ));
     }
@@ -194,9 +385,11 @@ impl<'a> Fonts<'a> {
         &mut self,
         fonts: impl IntoIterator<Item = Font<'a>>,
     ) {
+        self.resolved.get_mut().clear();
         self.char_width = self.char_width.min(Self::add_fonts_internal(
             &mut self.italic,
             fonts,
+            &self.axes,
             self.char_height,
         ));
     }
@@ -211,9 +404,33 @@ impl<'a> Fonts<'a> {
         &mut self,
         fonts: impl IntoIterator<Item = Font<'a>>,
     ) {
+        self.resolved.get_mut().clear();
         self.char_width = self.char_width.min(Self::add_fonts_internal(
             &mut self.bold_italic,
             fonts,
+            &self.axes,
+            self.char_height,
+        ));
+    }
+
+    /// Add a new collection of fallback fonts. These fonts are consulted for
+    /// every style, after all of the style specific fonts and before the
+    /// last-resort font, in the order provided. They will come _after_
+    /// previously provided fallback fonts.
+    ///
+    /// This is useful for fonts covering scripts which your primary fonts
+    /// don't, e.g. CJK or Cyrillic, and which usually don't ship with separate
+    /// bold or italic variants. Bold and italic text will be faked for these
+    /// fonts where appropriate.
+    pub fn add_fallback_fonts(
+        &mut self,
+        fonts: impl IntoIterator<Item = Font<'a>>,
+    ) {
+        self.resolved.get_mut().clear();
+        self.char_width = self.char_width.min(Self::add_fonts_internal(
+            &mut self.fallback,
+            fonts,
+            &self.axes,
             self.char_height,
         ));
     }
@@ -222,93 +439,239 @@ impl<'a> Fonts<'a> {
 impl<'a> Fonts<'a> {
     /// The minimum width (in pixels) across all fonts.
     pub(crate) fn min_width_px(&self) -> u32 {
-        self.char_width
+        self.char_width.max(1)
+    }
+
+    /// The width (in pixels) of a cell, after applying the cell width scale.
+    pub(crate) fn cell_width_px(&self) -> u32 {
+        ((self.min_width_px() as f32 * self.cell_width_scale).round() as u32).max(1)
+    }
+
+    /// The offset (in pixels) from the left of a cell to the left of the
+    /// font's advance, used to horizontally center glyphs in the cell.
+    pub(crate) fn padding_left_px(&self) -> i32 {
+        (self.cell_width_px() as i32 - self.min_width_px() as i32) / 2
+    }
+
+    /// The offset (in pixels) [`Fonts::padding_left_px`] is rounded from.
+    pub(crate) fn exact_padding_left_px(&self) -> f32 {
+        (self.cell_width_px() as f32 - self.min_width_px() as f32) / 2.0
+    }
+
+    /// The height (in pixels) of a cell, after applying the line height scale.
+    pub(crate) fn cell_height_px(&self) -> u32 {
+        ((self.char_height as f32 * self.line_height_scale).round() as u32).max(1)
+    }
+
+    /// The offset (in pixels) from the top of a cell to the top of the font's
+    /// line box, used to vertically center glyphs in the cell.
+    pub(crate) fn padding_top_px(&self) -> i32 {
+        (self.cell_height_px() as i32 - self.char_height as i32) / 2
+    }
+
+    /// The font other fonts are aligned to with [`BaselineMode::Primary`].
+    ///
+    /// [`BaselineMode::Primary`]: crate::BaselineMode::Primary
+    pub(crate) fn primary(&self) -> &Font<'a> {
+        self.regular.first().unwrap_or(&self.last_resort)
     }
 
     pub(crate) fn count(&self) -> usize {
-        1 + self.bold.len() + self.italic.len() + self.bold_italic.len() + self.regular.len()
+        1 + self.bold.len()
+            + self.italic.len()
+            + self.bold_italic.len()
+            + self.regular.len()
+            + self.fallback.len()
+    }
+
+    /// Look up a font of this collection by its [`Font::id`].
+    pub(crate) fn font_by_id(
+        &self,
+        id: u64,
+    ) -> Option<&Font<'a>> {
+        std::iter::once(&self.last_resort)
+            .chain(&self.regular)
+            .chain(&self.bold)
+            .chain(&self.italic)
+            .chain(&self.bold_italic)
+            .chain(&self.fallback)
+            .find(|font| font.id() == id)
     }
 
     pub(crate) fn font_for_cell(
         &'_ self,
         cell: &Cell,
     ) -> (&'_ Font<'_>, bool, bool) {
-        if cell.modifier.contains(Modifier::BOLD | Modifier::ITALIC) {
+        let style = cell.modifier & (Modifier::BOLD | Modifier::ITALIC);
+        let cluster = cell.symbol();
+
+        let mut chars = cluster.chars();
+        let (slot, fake_bold, fake_italic) = match (chars.next(), chars.next()) {
+            (Some(ch), None) => *self
+                .resolved
+                .borrow_mut()
+                .entry((ch, style))
+                .or_insert_with(|| self.resolve(cluster, style)),
+            _ => self.resolve(cluster, style),
+        };
+
+        (self.font_in_slot(slot), fake_bold, fake_italic)
+    }
+
+    fn resolve(
+        &self,
+        cluster: &str,
+        style: Modifier,
+    ) -> Resolved {
+        let fallback = |fake_bold, fake_italic| {
+            self.fallback
+                .iter()
+                .enumerate()
+                .map(move |(idx, f)| (Slot::Fallback(idx), f, fake_bold, fake_italic))
+        };
+
+        if style.contains(Modifier::BOLD | Modifier::ITALIC) {
             self.select_font(
-                cell.symbol(),
+                cluster,
                 self.bold_italic
                     .iter()
-                    .map(|f| (f, false, false))
-                    .chain(self.italic.iter().map(|f| (f, true, false)))
-                    .chain(self.bold.iter().map(|f| (f, false, true)))
-                    .chain(self.regular.iter().map(|f| (f, true, true))),
+                    .enumerate()
+                    .map(|(idx, f)| (Slot::BoldItalic(idx), f, false, false))
+                    .chain(
+                        self.italic
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, f)| (Slot::Italic(idx), f, true, false)),
+                    )
+                    .chain(
+                        self.bold
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, f)| (Slot::Bold(idx), f, false, true)),
+                    )
+                    .chain(
+                        self.regular
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, f)| (Slot::Regular(idx), f, true, true)),
+                    )
+                    .chain(fallback(true, true)),
                 true,
                 true,
             )
-        } else if cell.modifier.contains(Modifier::BOLD) {
+        } else if style.contains(Modifier::BOLD) {
             self.select_font(
-                cell.symbol(),
+                cluster,
                 self.bold
                     .iter()
-                    .map(|f| (f, false, false))
-                    .chain(self.regular.iter().map(|f| (f, true, false))),
+                    .enumerate()
+                    .map(|(idx, f)| (Slot::Bold(idx), f, false, false))
+                    .chain(
+                        self.regular
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, f)| (Slot::Regular(idx), f, true, false)),
+                    )
+                    .chain(fallback(true, false)),
                 true,
                 false,
             )
-        } else if cell.modifier.contains(Modifier::ITALIC) {
+        } else if style.contains(Modifier::ITALIC) {
             self.select_font(
-                cell.symbol(),
+                cluster,
                 self.italic
                     .iter()
-                    .map(|f| (f, false, false))
-                    .chain(self.regular.iter().map(|f| (f, false, true))),
+                    .enumerate()
+                    .map(|(idx, f)| (Slot::Italic(idx), f, false, false))
+                    .chain(
+                        self.regular
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, f)| (Slot::Regular(idx), f, false, true)),
+                    )
+                    .chain(fallback(false, true)),
                 false,
                 true,
             )
         } else {
             self.select_font(
-                cell.symbol(),
-                self.regular.iter().map(|f| (f, false, false)),
+                cluster,
+                self.regular
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, f)| (Slot::Regular(idx), f, false, false))
+                    .chain(fallback(false, false)),
                 false,
                 false,
             )
         }
     }
 
+    fn font_in_slot(
+        &self,
+        slot: Slot,
+    ) -> &Font<'a> {
+        match slot {
+            Slot::LastResort => &self.last_resort,
+            Slot::Regular(idx) => &self.regular[idx],
+            Slot::Bold(idx) => &self.bold[idx],
+            Slot::Italic(idx) => &self.italic[idx],
+            Slot::BoldItalic(idx) => &self.bold_italic[idx],
+            Slot::Fallback(idx) => &self.fallback[idx],
+        }
+    }
+
     fn select_font<'fonts>(
         &'fonts self,
         cluster: &str,
-        fonts: impl IntoIterator<Item = (&'fonts Font<'a>, bool, bool)>,
+        fonts: impl IntoIterator<Item = (Slot, &'fonts Font<'a>, bool, bool)>,
         last_resort_fake_bold: bool,
         last_resort_fake_italic: bool,
-    ) -> (&'fonts Font<'a>, bool, bool) {
-        let mut max = 0;
+    ) -> Resolved {
+        // Variation selectors and other invisible formatting characters don't need a
+        // glyph of their own, but an emoji or text presentation selector prefers fonts
+        // which draw the base character in color or as text.
+        let presentation = if cluster.contains('\u{FE0F}') {
+            Some(true)
+        } else if cluster.contains('\u{FE0E}') {
+            Some(false)
+        } else {
+            None
+        };
+        let total = cluster.chars().filter(|&ch| !is_ignorable(ch)).count();
+
+        let mut max = (0, presentation.is_none());
         let mut font = None;
-        for (candidate, fake_bold, fake_italic) in fonts.into_iter().chain(std::iter::once((
+        for (slot, candidate, fake_bold, fake_italic) in fonts.into_iter().chain(std::iter::once((
+            Slot::LastResort,
             &self.last_resort,
             last_resort_fake_bold,
             last_resort_fake_italic,
         ))) {
-            let (count, last_idx) =
+            let face = candidate.font();
+            let count = cluster
+                .chars()
+                .filter(|&ch| !is_ignorable(ch) && face.glyph_index(ch).is_some())
+                .count();
+            let presented = presentation.is_none_or(|color| {
                 cluster
                     .chars()
-                    .enumerate()
-                    .fold((0, 0), |(mut count, _), (idx, ch)| {
-                        count += usize::from(candidate.font().glyph_index(ch).is_some());
-                        (count, idx)
-                    });
-            if count > max {
-                max = count;
-                font = Some((candidate, fake_bold, fake_italic));
+                    .next()
+                    .and_then(|ch| face.glyph_index(ch))
+                    .is_some_and(|glyph| is_color_glyph(face, glyph) == color)
+            });
+            if (count, presented) > max {
+                max = (count, presented);
+                font = Some((slot, fake_bold, fake_italic));
             }
 
-            if count == last_idx + 1 {
+            if count == total && presented {
                 break;
             }
         }
 
         *font.get_or_insert((
-            &self.last_resort,
+            Slot::LastResort,
             last_resort_fake_bold,
             last_resort_fake_italic,
         ))
@@ -317,10 +680,14 @@ impl<'a> Fonts<'a> {
     fn add_fonts_internal(
         target: &mut Vec<Font<'a>>,
         fonts: impl IntoIterator<Item = Font<'a>>,
+        axes: &[(Tag, f32)],
         char_height: u32,
     ) -> u32 {
         let len = target.len();
-        target.extend(fonts);
+        target.extend(fonts.into_iter().map(|mut font| {
+            font.set_variable_axes(axes);
+            font
+        }));
 
         target[len..]
             .iter()
@@ -329,3 +696,90 @@ impl<'a> Fonts<'a> {
             .unwrap_or(u32::MAX)
     }
 }
+
+/// Whether the glyph is sourced from a color table (COLR/CPAL) or a color
+/// bitmap strike (CBDT/sbix). These glyphs are rendered with their own colors
+/// rather than being tinted with the foreground color.
+pub(crate) fn is_color_glyph(
+    metrics: &Face,
+    glyph: GlyphId,
+) -> bool {
+    metrics.is_color_glyph(glyph)
+        || metrics
+            .glyph_raster_image(glyph, u16::MAX)
+            .is_some_and(|raster| {
+                matches!(
+                    raster.format,
+                    RasterImageFormat::PNG | RasterImageFormat::BitmapPremulBgra32
+                )
+            })
+}
+
+/// Whether the character is invisible formatting, such as a variation selector
+/// or a zero width space, which fonts needn't have a glyph for.
+fn is_ignorable(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{200B}' | '\u{200C}' | '\u{2060}' | '\u{FEFF}' | '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::buffer::Cell;
+    use ratatui::style::Modifier;
+    use rustybuzz::ttf_parser::FaceParsingError;
+
+    use crate::Error;
+    use crate::Font;
+    use crate::Fonts;
+
+    #[test]
+    fn from_bytes() {
+        let data = include_bytes!("backend/fonts/CascadiaMono-Regular.ttf");
+        assert!(Font::from_bytes(data, 0).is_ok());
+        assert!(Font::from_owned_bytes(data.to_vec(), 0).is_ok());
+
+        assert!(matches!(
+            Font::from_bytes(b"definitely not a font", 0),
+            Err(Error::FontParsingFailed(FaceParsingError::UnknownMagic))
+        ));
+        assert!(matches!(
+            Font::from_owned_bytes(data[..64].to_vec(), 0),
+            Err(Error::FontParsingFailed(_))
+        ));
+        assert!(Font::new(b"").is_none());
+    }
+
+    #[test]
+    fn fallback_resolution() {
+        let cascadia = Font::new(include_bytes!("backend/fonts/CascadiaMono-Regular.ttf"))
+            .expect("Invalid font file");
+        let fairfax =
+            Font::new(include_bytes!("backend/fonts/Fairfax.ttf")).expect("Invalid font file");
+
+        let mut fonts = Fonts::new(cascadia.clone(), 24);
+        fonts.add_regular_fonts([cascadia.clone()]);
+        fonts.add_fallback_fonts([fairfax.clone()]);
+
+        let mut cell = Cell::new("a");
+        assert_eq!(fonts.font_for_cell(&cell).0.id(), cascadia.id());
+        assert_eq!(fonts.font_for_cell(&cell).0.id(), cascadia.id());
+
+        cell.set_symbol("ƀ");
+        assert_eq!(fonts.font_for_cell(&cell).0.id(), fairfax.id());
+
+        // Invisible formatting characters don't need a glyph.
+        cell.set_symbol("a\u{200B}\u{FE00}");
+        assert_eq!(fonts.font_for_cell(&cell).0.id(), cascadia.id());
+        cell.set_symbol("ƀ");
+
+        cell.modifier = Modifier::BOLD;
+        let (font, fake_bold, fake_italic) = fonts.font_for_cell(&cell);
+        assert_eq!(font.id(), fairfax.id());
+        assert!(fake_bold);
+        assert!(!fake_italic);
+
+        assert_eq!(fonts.resolved.borrow().len(), 3);
+    }
+}
@@ -6,8 +6,9 @@
 //! to a [`Terminal`](ratatui::Terminal). After that, rendering can be done as
 //! normal using the ratatui library. If you need custom shader post-processing,
 //! see the [`PostProcessor`] trait or the
-//! [`DefaultPostProcessor`](shaders::DefaultPostProcessor) implementation for
-//! guidance.
+//! [`DefaultPostProcessor`](shaders::DefaultPostProcessor) and
+//! [`ScanlinePostProcessor`](shaders::ScanlinePostProcessor) implementations
+//! for guidance.
 //!
 //! Here's a short example using winit on native with the default post processor
 //! implementation:
@@ -111,10 +112,7 @@
 //! ```
 //!
 //! # Limitations
-//! 1. No cursor rendering.
-//!     - The location of the cursor is tracked, and operations using it should
-//!       behave as expected, but the cursor is not rendered to the screen.
-//! 2. Attempting to render more unique (utf8 character * BOLD|ITALIC)
+//! 1. Attempting to render more unique (utf8 character * BOLD|ITALIC)
 //!    characters than can fit in the cache in a single draw call will cause
 //!    incorrect rendering. This is ~3750 characters at the default font size
 //!    with most fonts. If you need more than this, file a bug and I'll do the
@@ -153,6 +151,60 @@ pub enum Error {
     /// couldn't be loaded.
     #[error("Failed to get default Surface configuration from wgpu.")]
     SurfaceConfigurationRequestFailed,
+    /// Backend creation failed because the adapter can't render to the
+    /// surface's format, or a [`HeadlessSurface`] can't read it back.
+    #[error("Unsupported surface format: {0:?}")]
+    UnsupportedSurfaceFormat(wgpu::TextureFormat),
+    /// Backend creation failed because the format set with
+    /// [`Builder::with_text_format`] can't be rendered to, blended, or sampled
+    /// with filtering by the adapter, or doesn't have four channels.
+    #[error("Unsupported text format: {0:?}")]
+    UnsupportedTextFormat(wgpu::TextureFormat),
+    /// Backend creation failed because the [`Builder`] was given options
+    /// which can't be combined, see its documentation for the combinations.
+    #[error("Conflicting builder options: {0}")]
+    ConflictingOptions(&'static str),
+    /// Acquiring the next frame from the surface failed because the gpu ran
+    /// out of memory. Other surface errors are considered transient and only
+    /// cause the frame to be skipped.
+    #[error("{0}")]
+    SurfaceTextureAcquisitionFailed(wgpu::SurfaceError),
+    /// Font creation failed because the data isn't a valid font, or the
+    /// requested face doesn't exist in a font collection.
+    #[error("Failed to parse font data: {0}")]
+    FontParsingFailed(rustybuzz::ttf_parser::FaceParsingError),
+    /// Reading the rendered image back from the gpu failed because the
+    /// readback buffer couldn't be mapped.
+    #[error("{0}")]
+    BufferMapFailed(wgpu::BufferAsyncError),
+    /// Reading the rendered image back from the gpu failed while waiting for
+    /// the device to finish rendering.
+    #[error("{0}")]
+    DevicePollFailed(wgpu::PollError),
+    /// The backend's surface doesn't support reading back rendered frames.
+    /// Only headless surfaces can be read back.
+    #[error("The surface does not support readback")]
+    ReadbackUnsupported,
+    /// The region to read back doesn't lie within the surface.
+    #[error("Region {0} exceeds the surface bounds")]
+    RegionOutOfBounds(ratatui::layout::Rect),
+    /// An image passed to
+    /// [`WgpuBackend::draw_image`](crate::WgpuBackend::draw_image) doesn't
+    /// match its dimensions, or is too large for the device.
+    #[error("Invalid image: {0}")]
+    InvalidImage(String),
+    /// A color lookup table for the
+    /// [`LutPostProcessor`](shaders::LutPostProcessor) is malformed.
+    #[error("Invalid color lookup table: {0}")]
+    InvalidLut(String),
+    /// Writing a png failed.
+    #[cfg(feature = "png")]
+    #[error("Failed to write png: {0}")]
+    PngEncodingFailed(png::EncodingError),
+    /// Reading a png failed.
+    #[cfg(feature = "png")]
+    #[error("Failed to read png: {0}")]
+    PngDecodingFailed(png::DecodingError),
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -164,11 +216,30 @@ type RandomState = std::hash::RandomState;
 
 pub use backend::builder::Builder;
 pub use backend::wgpu_backend::WgpuBackend;
+pub use backend::BaselineMode;
+pub use backend::ClearPolicy;
+pub use backend::CursorStyle;
 pub use backend::Dimensions;
+pub use backend::EmojiMode;
+pub use backend::FontMetrics;
+pub use backend::FrameStats;
+pub use backend::HeadlessSurface;
+pub use backend::HeadlessTarget;
+pub use backend::ImageDesc;
+pub use backend::ImagePlacement;
 pub use backend::PostProcessor;
+pub use backend::RenderCallback;
 pub use backend::RenderSurface;
 pub use backend::RenderTexture;
+pub use backend::Selection;
+pub use backend::SubpixelLayout;
+pub use backend::TextureSurface;
+pub use backend::TextureTarget;
+pub use backend::UnderlineStyle;
 pub use backend::Viewport;
 pub use colors::ColorTable;
+pub use colors::Rgb;
+pub use colors::XTERM_PALETTE;
 pub use fonts::Font;
 pub use fonts::Fonts;
+pub use rustybuzz::ttf_parser::Tag;
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
 use std::mem::size_of;
 use std::num::NonZeroU64;
 
@@ -12,6 +14,10 @@ use wgpu::BindGroupLayoutDescriptor;
 use wgpu::BindGroupLayoutEntry;
 use wgpu::BindingResource;
 use wgpu::BindingType;
+use wgpu::BlendComponent;
+use wgpu::BlendFactor;
+use wgpu::BlendOperation;
+use wgpu::BlendState;
 use wgpu::Buffer;
 use wgpu::BufferBindingType;
 use wgpu::BufferDescriptor;
@@ -43,6 +49,9 @@ use wgpu::RenderPipelineDescriptor;
 use wgpu::Sampler;
 use wgpu::SamplerBindingType;
 use wgpu::SamplerDescriptor;
+use wgpu::ShaderModule;
+use wgpu::ShaderModuleDescriptor;
+use wgpu::ShaderSource;
 use wgpu::ShaderStages;
 use wgpu::StoreOp;
 use wgpu::SurfaceConfiguration;
@@ -60,7 +69,25 @@ use wgpu::{
     self,
 };
 
+use crate::backend::expects_linear;
 use crate::backend::PostProcessor;
+use crate::Error;
+use crate::Result;
+use crate::Rgb;
+
+/// Like [`include_wgsl!`], but prepends the helpers from `surface.wgsl` which
+/// the post-processing shaders share.
+macro_rules! include_post_wgsl {
+    ($path:literal) => {
+        ShaderModuleDescriptor {
+            label: Some($path),
+            source: ShaderSource::Wgsl(Cow::Borrowed(concat!(
+                include_str!("shaders/surface.wgsl"),
+                include_str!($path)
+            ))),
+        }
+    };
+}
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -146,7 +173,7 @@ impl<const PRESERVE_ASPECT: bool> PostProcessor for DefaultPostProcessor<PRESERV
             ],
         });
 
-        let shader = device.create_shader_module(include_wgsl!("shaders/blit.wgsl"));
+        let shader = device.create_shader_module(include_post_wgsl!("shaders/blit.wgsl"));
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Text Blit Layout"),
@@ -238,7 +265,7 @@ impl<const PRESERVE_ASPECT: bool> PostProcessor for DefaultPostProcessor<PRESERV
             uniforms.copy_from_slice(bytemuck::bytes_of(&Uniforms {
                 screen_size: [surface_config.width as f32, surface_config.height as f32],
                 preserve_aspect: u32::from(PRESERVE_ASPECT),
-                use_srgb: u32::from(surface_config.format.is_srgb()),
+                use_srgb: u32::from(expects_linear(surface_config.format)),
             }));
         }
 
@@ -306,6 +333,192 @@ fn build_blitter(
     })
 }
 
+/// A post-processor which runs `A` and then feeds its output to `B`, e.g. to
+/// stack several effects without each of them having to know about the
+/// others. `A` renders into an intermediate texture the size of the surface,
+/// which `B` then samples in place of the composited text before rendering to
+/// the surface. The intermediate has the same format as the text, except for
+/// float formats. Processors write linear colors to float surfaces, while `B`
+/// expects srgb encoded ones, so those chain through
+/// [`TextureFormat::Rgba8Unorm`] instead.
+///
+/// Chains may be nested to combine more than two effects. The user data is a
+/// tuple of the user data for `A` and `B`.
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+    intermediate_config: SurfaceConfiguration,
+    intermediate: TextureView,
+}
+
+impl<A, B> Chain<A, B> {
+    /// Get the post-processor which renders first.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Get a mutable reference to the post-processor which renders first.
+    pub fn first_mut(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    /// Get the post-processor which renders to the surface.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// Get a mutable reference to the post-processor which renders to the
+    /// surface.
+    pub fn second_mut(&mut self) -> &mut B {
+        &mut self.second
+    }
+}
+
+impl<A: PostProcessor, B: PostProcessor> PostProcessor for Chain<A, B> {
+    type UserData = (A::UserData, B::UserData);
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        (first_data, second_data): Self::UserData,
+    ) -> Self {
+        let intermediate_config = build_intermediate_config(text_view, surface_config);
+        let intermediate = build_intermediate(device, &intermediate_config);
+
+        let first = A::compile(device, text_view, &intermediate_config, first_data);
+        let second = B::compile(device, &intermediate, surface_config, second_data);
+
+        Self {
+            first,
+            second,
+            intermediate_config,
+            intermediate,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.intermediate_config = build_intermediate_config(text_view, surface_config);
+        self.intermediate = build_intermediate(device, &self.intermediate_config);
+
+        self.first
+            .resize(device, text_view, &self.intermediate_config);
+        self.second
+            .resize(device, &self.intermediate, surface_config);
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        self.first.process(
+            encoder,
+            queue,
+            text_view,
+            &self.intermediate_config,
+            &self.intermediate,
+        );
+        self.second.process(
+            encoder,
+            queue,
+            &self.intermediate,
+            surface_config,
+            surface_view,
+        );
+    }
+
+    /// Only the second processor renders to the surface view, so only it is
+    /// asked to render multisampled.
+    fn enable_multisampling(
+        &mut self,
+        device: &Device,
+        surface_config: &SurfaceConfiguration,
+        sample_count: u32,
+    ) -> bool {
+        self.second
+            .enable_multisampling(device, surface_config, sample_count)
+    }
+
+    fn needs_update(&self) -> bool {
+        self.first.needs_update() || self.second.needs_update()
+    }
+}
+
+/// The format of textures holding the same colors as `text_view`, which
+/// keeps the precision chosen with
+/// [`Builder::with_text_format`](crate::Builder::with_text_format) through
+/// every pass.
+fn intermediate_format(text_view: &TextureView) -> TextureFormat {
+    text_view.texture().format().remove_srgb_suffix()
+}
+
+/// The config of the texture a [`Chain`] renders its first processor into.
+fn build_intermediate_config(
+    text_view: &TextureView,
+    surface_config: &SurfaceConfiguration,
+) -> SurfaceConfiguration {
+    let format = intermediate_format(text_view);
+    SurfaceConfiguration {
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        format: if expects_linear(format) {
+            TextureFormat::Rgba8Unorm
+        } else {
+            format
+        },
+        view_formats: vec![],
+        ..surface_config.clone()
+    }
+}
+
+/// The texture the horizontal pass of a [`BlurPostProcessor`] renders into.
+/// The blur converts colors itself, so it keeps the text format either way.
+fn build_blur_intermediate(
+    device: &Device,
+    text_view: &TextureView,
+    surface_config: &SurfaceConfiguration,
+) -> TextureView {
+    build_intermediate(
+        device,
+        &SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            format: intermediate_format(text_view),
+            view_formats: vec![],
+            ..surface_config.clone()
+        },
+    )
+}
+
+fn build_intermediate(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> TextureView {
+    device
+        .create_texture(&TextureDescriptor {
+            label: Some("Post Process Chain Intermediate"),
+            size: Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        })
+        .create_view(&TextureViewDescriptor::default())
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct CrtUniforms {
@@ -325,7 +538,10 @@ struct CrtUniforms {
     mask_strength: f32,
     use_srgb: i32,
     milliseconds: u32,
-    _pad1: [f32; 2],
+    vignette_strength: f32,
+    flicker: f32,
+    seconds: f32,
+    _pad1: [f32; 3],
 }
 
 /// Settings for the CRT post-processor.
@@ -382,6 +598,16 @@ pub struct CrtSettings {
     /// transitions.
     /// Defaults to 0.0.
     pub slow_fade: f32,
+    /// How strongly to darken the edges of the screen.
+    /// A good range of values is 0.0 to 1.0.
+    /// Defaults to 0.0.
+    pub vignette_strength: f32,
+    /// How much the brightness of the screen flickers over time. Any value
+    /// above 0.0 animates the effect, so `flush` must be called continuously
+    /// for it to be visible, see [`PostProcessor::needs_update`].
+    /// A good range of values is 0.0 to 0.1.
+    /// Defaults to 0.0.
+    pub flicker: f32,
 }
 
 impl Default for CrtSettings {
@@ -398,6 +624,8 @@ impl Default for CrtSettings {
             mask_type: 3.0,
             mask_strength: 0.2,
             slow_fade: 0.0,
+            vignette_strength: 0.0,
+            flicker: 0.0,
         }
     }
 }
@@ -425,6 +653,7 @@ pub struct CrtPostProcessor {
     width: u32,
     height: u32,
     timer: Instant,
+    start: Instant,
 
     settings: CrtSettings,
 }
@@ -733,6 +962,7 @@ impl PostProcessor for CrtPostProcessor {
             width: drawable_width,
             height: drawable_height,
             timer: Instant::now(),
+            start: Instant::now(),
             settings: user_data,
         }
     }
@@ -745,10 +975,12 @@ impl PostProcessor for CrtPostProcessor {
     ) {
         let settings = self.settings.clone();
         let timer = self.timer;
+        let start = self.start;
 
         *self = Self::compile(device, text_view, surface_config, settings);
 
         self.timer = timer;
+        self.start = start;
     }
 
     fn process(
@@ -844,9 +1076,12 @@ impl PostProcessor for CrtPostProcessor {
                 corner_radius: self.settings.corner_radius_factor,
                 mask_type: self.settings.mask_type,
                 mask_strength: self.settings.mask_strength,
-                use_srgb: i32::from(surface_config.format.is_srgb()),
+                use_srgb: i32::from(expects_linear(surface_config.format)),
                 milliseconds: self.timer.elapsed().as_millis() as u32,
-                _pad1: [0.0; 2],
+                vignette_strength: self.settings.vignette_strength,
+                flicker: self.settings.flicker,
+                seconds: self.start.elapsed().as_secs_f32(),
+                _pad1: [0.0; 3],
             }));
         }
         self.timer = Instant::now();
@@ -892,7 +1127,2247 @@ impl PostProcessor for CrtPostProcessor {
     }
 
     fn needs_update(&self) -> bool {
-        self.settings.slow_fade == 1.0
+        self.settings.slow_fade == 1.0 || self.settings.flicker > 0.0
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GaussianBlurUniforms {
+    screen_size: [f32; 2],
+    direction: [f32; 2],
+    radius: f32,
+    use_srgb: u32,
+    _pad: [f32; 2],
+}
+
+/// A post-processor which applies a gaussian blur to the output, e.g. for
+/// "frosted glass" overlays or focus effects. The blur is separable, so it is
+/// rendered as a horizontal pass into an intermediate texture followed by a
+/// vertical pass to the surface.
+///
+/// The user data is the initial blur radius in pixels.
+pub struct BlurPostProcessor {
+    /// The radius of the blur in pixels. This may be changed at any time and
+    /// takes effect the next time the backend is flushed. Radii above 64 are
+    /// clamped. A radius of 0.0 disables the blur.
+    pub radius: f32,
+    rendered_radius: f32,
+
+    sampler: Sampler,
+    layout: BindGroupLayout,
+    horizontal_pipeline: RenderPipeline,
+    vertical_pipeline: RenderPipeline,
+
+    horizontal_uniforms: Buffer,
+    vertical_uniforms: Buffer,
+
+    intermediate: TextureView,
+    horizontal_pass: RenderBundle,
+    vertical_pass: RenderBundle,
+}
+
+impl PostProcessor for BlurPostProcessor {
+    type UserData = f32;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        radius: Self::UserData,
+    ) -> Self {
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let horizontal_uniforms = device.create_buffer(&BufferDescriptor {
+            label: Some("Gaussian Blur Horizontal Uniforms"),
+            size: size_of::<GaussianBlurUniforms>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let vertical_uniforms = device.create_buffer(&BufferDescriptor {
+            label: Some("Gaussian Blur Vertical Uniforms"),
+            size: size_of::<GaussianBlurUniforms>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let layout = build_gaussian_blur_layout(device);
+        let format = intermediate_format(text_view);
+        let horizontal_pipeline = build_gaussian_blur(device, &layout, format);
+        let vertical_pipeline = build_gaussian_blur(device, &layout, surface_config.format);
+
+        let intermediate = build_blur_intermediate(device, text_view, surface_config);
+
+        let horizontal_pass = build_gaussian_blur_pass(
+            device,
+            &layout,
+            &horizontal_pipeline,
+            text_view,
+            &sampler,
+            &horizontal_uniforms,
+            format,
+        );
+        let vertical_pass = build_gaussian_blur_pass(
+            device,
+            &layout,
+            &vertical_pipeline,
+            &intermediate,
+            &sampler,
+            &vertical_uniforms,
+            surface_config.format,
+        );
+
+        Self {
+            radius,
+            rendered_radius: radius,
+            sampler,
+            layout,
+            horizontal_pipeline,
+            vertical_pipeline,
+            horizontal_uniforms,
+            vertical_uniforms,
+            intermediate,
+            horizontal_pass,
+            vertical_pass,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.intermediate = build_blur_intermediate(device, text_view, surface_config);
+
+        self.horizontal_pass = build_gaussian_blur_pass(
+            device,
+            &self.layout,
+            &self.horizontal_pipeline,
+            text_view,
+            &self.sampler,
+            &self.horizontal_uniforms,
+            intermediate_format(text_view),
+        );
+        self.vertical_pass = build_gaussian_blur_pass(
+            device,
+            &self.layout,
+            &self.vertical_pipeline,
+            &self.intermediate,
+            &self.sampler,
+            &self.vertical_uniforms,
+            surface_config.format,
+        );
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        let screen_size = [surface_config.width as f32, surface_config.height as f32];
+        for (buffer, direction, use_srgb) in [
+            (&self.horizontal_uniforms, [1.0, 0.0], false),
+            (
+                &self.vertical_uniforms,
+                [0.0, 1.0],
+                expects_linear(surface_config.format),
+            ),
+        ] {
+            let mut uniforms = queue
+                .write_buffer_with(
+                    buffer,
+                    0,
+                    NonZeroU64::new(size_of::<GaussianBlurUniforms>() as u64).unwrap(),
+                )
+                .unwrap();
+            uniforms.copy_from_slice(bytemuck::bytes_of(&GaussianBlurUniforms {
+                screen_size,
+                direction,
+                radius: self.radius.max(0.0),
+                use_srgb: u32::from(use_srgb),
+                _pad: [0.0; 2],
+            }));
+        }
+        self.rendered_radius = self.radius;
+
+        for (view, bundle) in [
+            (&self.intermediate, &self.horizontal_pass),
+            (surface_view, &self.vertical_pass),
+        ] {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Gaussian Blur Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+
+            pass.execute_bundles(Some(bundle));
+        }
+    }
+
+    fn needs_update(&self) -> bool {
+        self.radius != self.rendered_radius
+    }
+}
+
+/// A single pass which samples the composited text with uniforms of type `U`
+/// and renders straight to the surface. This is shared by the lightweight
+/// effects, which only differ in their uniforms and shader.
+struct FullscreenPass<U> {
+    label: &'static str,
+
+    uniforms: Buffer,
+    bindings: BindGroupLayout,
+    sampler: Sampler,
+    pipeline: RenderPipeline,
+
+    blitter: RenderBundle,
+
+    _uniforms: PhantomData<U>,
+}
+
+impl<U: bytemuck::Pod> FullscreenPass<U> {
+    fn new(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        label: &'static str,
+        shader: ShaderModuleDescriptor,
+        filter: FilterMode,
+    ) -> Self {
+        let uniforms = device.create_buffer(&BufferDescriptor {
+            label: Some(&format!("{label} Uniforms")),
+            size: size_of::<U>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&format!("{label} Bindings Layout")),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(size_of::<U>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(shader);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(&format!("{label} Layout")),
+            bind_group_layouts: &[&layout],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&format!("{label} Pipeline")),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: surface_config.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let blitter = build_blitter(
+            device,
+            &layout,
+            text_view,
+            &sampler,
+            &uniforms,
+            surface_config,
+            &pipeline,
+        );
+
+        Self {
+            label,
+            uniforms,
+            bindings: layout,
+            sampler,
+            pipeline,
+            blitter,
+            _uniforms: PhantomData,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.blitter = build_blitter(
+            device,
+            &self.bindings,
+            text_view,
+            &self.sampler,
+            &self.uniforms,
+            surface_config,
+            &self.pipeline,
+        );
+    }
+
+    fn process(
+        &self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        surface_view: &TextureView,
+        uniforms: &U,
+    ) {
+        queue
+            .write_buffer_with(
+                &self.uniforms,
+                0,
+                NonZeroU64::new(size_of::<U>() as u64).unwrap(),
+            )
+            .unwrap()
+            .copy_from_slice(bytemuck::bytes_of(uniforms));
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("{} Pass", self.label)),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+
+        pass.execute_bundles(Some(&self.blitter));
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScanlineUniforms {
+    screen_size: [f32; 2],
+    use_srgb: u32,
+    period: u32,
+    intensity: f32,
+    _pad: f32,
+}
+
+/// Settings for the scanline post-processor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanlineSettings {
+    /// The height in pixels of one light and one dark scanline. The second half
+    /// of each period is darkened, so a period of 2 darkens every other row.
+    /// Periods below 2 are treated as 2.
+    /// Defaults to 2.
+    pub period: u32,
+    /// How much to darken the dark scanlines, from 0.0 (not at all) to 1.0
+    /// (black).
+    /// Defaults to 0.25.
+    pub intensity: f32,
+}
+
+impl Default for ScanlineSettings {
+    fn default() -> Self {
+        Self {
+            period: 2,
+            intensity: 0.25,
+        }
+    }
+}
+
+/// A lightweight post-processor which darkens alternating rows of pixels for a
+/// retro look, without the cost of the full [`CrtPostProcessor`]. The text is
+/// rendered directly to the surface in a single pass. The effect is static, so
+/// it only needs an update after its settings change.
+///
+/// The user data is the initial [`ScanlineSettings`].
+pub struct ScanlinePostProcessor {
+    /// The scanline settings. These may be changed at any time and take effect
+    /// the next time the backend is flushed.
+    pub settings: ScanlineSettings,
+    rendered_settings: ScanlineSettings,
+
+    pass: FullscreenPass<ScanlineUniforms>,
+}
+
+impl PostProcessor for ScanlinePostProcessor {
+    type UserData = ScanlineSettings;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        settings: Self::UserData,
+    ) -> Self {
+        let pass = FullscreenPass::new(
+            device,
+            text_view,
+            surface_config,
+            "Scanline",
+            include_post_wgsl!("shaders/scanline.wgsl"),
+            FilterMode::Nearest,
+        );
+
+        Self {
+            settings,
+            rendered_settings: settings,
+            pass,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.pass.resize(device, text_view, surface_config);
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        self.pass.process(
+            encoder,
+            queue,
+            surface_view,
+            &ScanlineUniforms {
+                screen_size: [surface_config.width as f32, surface_config.height as f32],
+                use_srgb: u32::from(expects_linear(surface_config.format)),
+                period: self.settings.period.max(2),
+                intensity: self.settings.intensity.clamp(0.0, 1.0),
+                _pad: 0.0,
+            },
+        );
+        self.rendered_settings = self.settings;
+    }
+
+    fn needs_update(&self) -> bool {
+        self.settings != self.rendered_settings
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ChromaticAberrationUniforms {
+    screen_size: [f32; 2],
+    offset: f32,
+    use_srgb: u32,
+}
+
+/// A post-processor which splits the red and blue channels of the output
+/// apart, moving them radially away from the center of the screen like the
+/// fringes of a cheap lens. This pairs well with the [`CrtPostProcessor`] or
+/// [`ScanlinePostProcessor`] in a [`Chain`].
+///
+/// The user data is the initial offset in pixels.
+pub struct ChromaticAberrationPostProcessor {
+    /// How far in pixels the red and blue channels are moved at the middle of
+    /// each edge of the screen, shrinking towards the center. This may be
+    /// changed at any time and takes effect the next time the backend is
+    /// flushed. An offset of 0.0 disables the effect.
+    pub offset: f32,
+    rendered_offset: f32,
+
+    pass: FullscreenPass<ChromaticAberrationUniforms>,
+}
+
+impl PostProcessor for ChromaticAberrationPostProcessor {
+    type UserData = f32;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        offset: Self::UserData,
+    ) -> Self {
+        // The channels are offset by fractions of a pixel, so they're filtered.
+        let pass = FullscreenPass::new(
+            device,
+            text_view,
+            surface_config,
+            "Chromatic Aberration",
+            include_post_wgsl!("shaders/chromatic_aberration.wgsl"),
+            FilterMode::Linear,
+        );
+
+        Self {
+            offset,
+            rendered_offset: offset,
+            pass,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.pass.resize(device, text_view, surface_config);
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        self.pass.process(
+            encoder,
+            queue,
+            surface_view,
+            &ChromaticAberrationUniforms {
+                screen_size: [surface_config.width as f32, surface_config.height as f32],
+                offset: self.offset,
+                use_srgb: u32::from(expects_linear(surface_config.format)),
+            },
+        );
+        self.rendered_offset = self.offset;
+    }
+
+    fn needs_update(&self) -> bool {
+        self.offset != self.rendered_offset
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniforms {
+    screen_size: [f32; 2],
+    radius: f32,
+    softness: f32,
+    color: [f32; 3],
+    use_srgb: u32,
+}
+
+/// Settings for the vignette post-processor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VignetteSettings {
+    /// How far from the center the vignette starts, as a fraction of the
+    /// distance from the center to the corners.
+    /// Defaults to 0.6.
+    pub radius: f32,
+    /// How far the vignette fades in past `radius`, as a fraction of the
+    /// distance from the center to the corners. Small values give a hard edge.
+    /// Defaults to 0.5.
+    pub softness: f32,
+    /// The color the edges of the screen fade to.
+    /// Defaults to black.
+    pub color: Rgb,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.6,
+            softness: 0.5,
+            color: [0, 0, 0],
+        }
+    }
+}
+
+/// A post-processor which fades the edges of the screen toward a color,
+/// darkening the corners the most. This is mainly useful at the end of a
+/// [`Chain`] of other effects.
+///
+/// The user data is the initial [`VignetteSettings`].
+pub struct VignettePostProcessor {
+    /// The vignette settings. These may be changed at any time and take effect
+    /// the next time the backend is flushed.
+    pub settings: VignetteSettings,
+    rendered_settings: VignetteSettings,
+
+    pass: FullscreenPass<VignetteUniforms>,
+}
+
+impl PostProcessor for VignettePostProcessor {
+    type UserData = VignetteSettings;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        settings: Self::UserData,
+    ) -> Self {
+        let pass = FullscreenPass::new(
+            device,
+            text_view,
+            surface_config,
+            "Vignette",
+            include_post_wgsl!("shaders/vignette.wgsl"),
+            FilterMode::Nearest,
+        );
+
+        Self {
+            settings,
+            rendered_settings: settings,
+            pass,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.pass.resize(device, text_view, surface_config);
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        self.pass.process(
+            encoder,
+            queue,
+            surface_view,
+            &VignetteUniforms {
+                screen_size: [surface_config.width as f32, surface_config.height as f32],
+                radius: self.settings.radius,
+                softness: self.settings.softness,
+                color: self.settings.color.map(|c| f32::from(c) / 255.0),
+                use_srgb: u32::from(expects_linear(surface_config.format)),
+            },
+        );
+        self.rendered_settings = self.settings;
+    }
+
+    fn needs_update(&self) -> bool {
+        self.settings != self.rendered_settings
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PersistenceUniforms {
+    screen_size: [f32; 2],
+    decay: f32,
+    use_srgb: u32,
+}
+
+/// A post-processor which simulates the persistence of CRT phosphors: text
+/// which disappears leaves a trail that fades out over the following frames.
+///
+/// The previous output is kept in a history texture and blended with each new
+/// frame. While a trail is fading, [`PostProcessor::needs_update`] returns
+/// `true` so the animation completes even if nothing else is drawn. Trails fade
+/// by frame rather than by time, so they fade faster when the backend is
+/// flushed more often.
+///
+/// The user data is the initial decay.
+pub struct PersistencePostProcessor {
+    /// The fraction of its brightness a trail keeps from one frame to the
+    /// next. This may be changed at any time and takes effect the next time
+    /// the backend is flushed. Values are clamped to 0.0..=0.99. A decay of
+    /// 0.0 disables trails.
+    pub decay: f32,
+    /// The number of frames until trails of the most recent change have faded
+    /// out completely.
+    fading: u32,
+
+    uniforms: Buffer,
+    bindings: BindGroupLayout,
+    sampler: Sampler,
+    pipeline: RenderPipeline,
+
+    history: PersistenceHistory,
+    pass: RenderBundle,
+}
+
+struct PersistenceHistory {
+    /// The output of the previous frame, which the shader reads.
+    input: Texture,
+    input_view: TextureView,
+    /// The output of the current frame, copied to `input` afterwards.
+    output: Texture,
+    output_view: TextureView,
+}
+
+impl PersistencePostProcessor {
+    /// The number of frames until a full brightness trail has faded below the
+    /// smallest visible step.
+    fn frames_to_fade(&self) -> u32 {
+        let decay = self.decay.clamp(0.0, 0.99);
+        if decay == 0.0 {
+            0
+        } else {
+            ((1.0f32 / 255.0).ln() / decay.ln()).ceil() as u32
+        }
+    }
+}
+
+impl PostProcessor for PersistencePostProcessor {
+    type UserData = f32;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        decay: Self::UserData,
+    ) -> Self {
+        let uniforms = device.create_buffer(&BufferDescriptor {
+            label: Some("Persistence Uniforms"),
+            size: size_of::<PersistenceUniforms>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Persistence Bindings Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(size_of::<PersistenceUniforms>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(include_post_wgsl!("shaders/persistence.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Persistence Layout"),
+            bind_group_layouts: &[&layout],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Persistence Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[
+                    Some(ColorTargetState {
+                        format: surface_config.format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                    Some(ColorTargetState {
+                        format: TextureFormat::Rgba8Unorm,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let history = build_persistence_history(device, surface_config);
+        let pass = build_persistence_pass(
+            device,
+            &PersistenceResources {
+                uniforms: &uniforms,
+                sampler: &sampler,
+                layout: &layout,
+                pipeline: &pipeline,
+            },
+            text_view,
+            &history,
+            surface_config.format,
+        );
+
+        Self {
+            decay,
+            fading: 0,
+            uniforms,
+            bindings: layout,
+            sampler,
+            pipeline,
+            history,
+            pass,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        // New textures are zeroed, so trails of the old size don't end up stretched
+        // over the new one.
+        self.history = build_persistence_history(device, surface_config);
+        self.fading = 0;
+        self.pass = build_persistence_pass(
+            device,
+            &PersistenceResources {
+                uniforms: &self.uniforms,
+                sampler: &self.sampler,
+                layout: &self.bindings,
+                pipeline: &self.pipeline,
+            },
+            text_view,
+            &self.history,
+            surface_config.format,
+        );
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        // Frames rendered while trails are fading count down the fade. The final
+        // frame drops the history entirely, so trails which were cut short by
+        // changes during the fade don't linger.
+        self.fading = match self.fading {
+            0 => self.frames_to_fade(),
+            fading => fading - 1,
+        };
+        let decay = if self.fading == 0 {
+            0.0
+        } else {
+            self.decay.clamp(0.0, 0.99)
+        };
+
+        {
+            let mut uniforms = queue
+                .write_buffer_with(
+                    &self.uniforms,
+                    0,
+                    NonZeroU64::new(size_of::<PersistenceUniforms>() as u64).unwrap(),
+                )
+                .unwrap();
+            uniforms.copy_from_slice(bytemuck::bytes_of(&PersistenceUniforms {
+                screen_size: [surface_config.width as f32, surface_config.height as f32],
+                decay,
+                use_srgb: u32::from(expects_linear(surface_config.format)),
+            }));
+        }
+
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Persistence Pass"),
+                color_attachments: &[
+                    Some(RenderPassColorAttachment {
+                        view: surface_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Color::TRANSPARENT),
+                            store: StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    }),
+                    Some(RenderPassColorAttachment {
+                        view: &self.history.output_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Color::TRANSPARENT),
+                            store: StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    }),
+                ],
+                ..Default::default()
+            });
+
+            pass.execute_bundles(Some(&self.pass));
+        }
+
+        encoder.copy_texture_to_texture(
+            self.history.output.as_image_copy(),
+            self.history.input.as_image_copy(),
+            self.history.output.size(),
+        );
+    }
+
+    fn needs_update(&self) -> bool {
+        self.fading > 0
+    }
+}
+
+fn build_persistence_history(
+    device: &Device,
+    surface_config: &SurfaceConfiguration,
+) -> PersistenceHistory {
+    let size = Extent3d {
+        width: surface_config.width,
+        height: surface_config.height,
+        depth_or_array_layers: 1,
+    };
+
+    let input = device.create_texture(&TextureDescriptor {
+        label: Some("Persistence History In"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let output = device.create_texture(&TextureDescriptor {
+        label: Some("Persistence History Out"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    PersistenceHistory {
+        input_view: input.create_view(&TextureViewDescriptor::default()),
+        input,
+        output_view: output.create_view(&TextureViewDescriptor::default()),
+        output,
+    }
+}
+
+struct PersistenceResources<'a> {
+    uniforms: &'a Buffer,
+    sampler: &'a Sampler,
+    layout: &'a BindGroupLayout,
+    pipeline: &'a RenderPipeline,
+}
+
+fn build_persistence_pass(
+    device: &Device,
+    resources: &PersistenceResources,
+    text_view: &TextureView,
+    history: &PersistenceHistory,
+    format: TextureFormat,
+) -> RenderBundle {
+    let bindings = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Persistence Bindings"),
+        layout: resources.layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(text_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(resources.sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: resources.uniforms.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(&history.input_view),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
+        label: Some("Persistence Pass Encoder"),
+        color_formats: &[Some(format), Some(TextureFormat::Rgba8Unorm)],
+        depth_stencil: None,
+        sample_count: 1,
+        multiview: None,
+    });
+
+    encoder.set_pipeline(resources.pipeline);
+    encoder.set_bind_group(0, &bindings, &[]);
+    encoder.draw(0..3, 0..1);
+
+    encoder.finish(&RenderBundleDescriptor {
+        label: Some("Persistence Pass Bundle"),
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LutUniforms {
+    screen_size: [f32; 2],
+    use_srgb: u32,
+    lut_size: f32,
+    domain_min: [f32; 4],
+    domain_max: [f32; 4],
+}
+
+/// The largest supported edge length of a [`Lut`], which is the smallest limit
+/// for 3d textures wgpu guarantees.
+const MAX_LUT_SIZE: u32 = 256;
+
+/// A 3d color lookup table for the [`LutPostProcessor`].
+///
+/// Entries are stored with red changing fastest, then green, then blue, the
+/// same as in `.cube` files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    data: Vec<[u8; 4]>,
+}
+
+impl Lut {
+    /// Parse a table from the contents of an Adobe/Resolve `.cube` file. Only
+    /// 3d tables are supported, with an edge length of 2 to 256 entries.
+    ///
+    /// Returns [`Error::InvalidLut`] if the file is malformed.
+    pub fn from_cube(source: &str) -> Result<Self> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = vec![];
+
+        for (line_number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid =
+                |reason: &str| Error::InvalidLut(format!("line {}: {reason}", line_number + 1));
+            let mut parts = line.split_whitespace();
+            let keyword = parts.next().unwrap_or_default();
+            let values = |parts: std::str::SplitWhitespace<'_>| {
+                parts
+                    .map(str::parse::<f32>)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|_| invalid("expected a number"))
+            };
+
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err(invalid("1d tables are not supported")),
+                "LUT_3D_SIZE" => {
+                    let parsed = parts
+                        .next()
+                        .and_then(|size| size.parse::<u32>().ok())
+                        .filter(|_| parts.next().is_none())
+                        .ok_or_else(|| invalid("expected a single size"))?;
+                    if !(2..=MAX_LUT_SIZE).contains(&parsed) {
+                        return Err(invalid("size must be between 2 and 256"));
+                    }
+                    size = Some(parsed);
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let values: [f32; 3] = values(parts)?
+                        .try_into()
+                        .map_err(|_| invalid("expected three values"))?;
+                    if keyword == "DOMAIN_MIN" {
+                        domain_min = values;
+                    } else {
+                        domain_max = values;
+                    }
+                }
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max]: [f32; 2] = values(parts)?
+                        .try_into()
+                        .map_err(|_| invalid("expected two values"))?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    // Other keywords, e.g. from newer revisions of the format,
+                    // don't affect the table.
+                }
+                _ => {
+                    if size.is_none() {
+                        return Err(invalid("table data before LUT_3D_SIZE"));
+                    }
+                    let [r, g, b]: [f32; 3] = values(line.split_whitespace())?
+                        .try_into()
+                        .map_err(|_| invalid("expected three values"))?;
+                    data.push([r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| Error::InvalidLut("missing LUT_3D_SIZE".to_string()))?;
+        if data.len() != size.pow(3) as usize {
+            return Err(Error::InvalidLut(format!(
+                "expected {} entries, found {}",
+                size.pow(3),
+                data.len()
+            )));
+        }
+        if domain_min
+            .iter()
+            .zip(domain_max)
+            .any(|(min, max)| *min >= max)
+        {
+            return Err(Error::InvalidLut(
+                "DOMAIN_MIN must be less than DOMAIN_MAX".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            data: data.into_iter().map(|[r, g, b]| [r, g, b, 255]).collect(),
+        })
+    }
+
+    /// Build a table from a 2d strip of rgba8 pixels, as exported by many image
+    /// editors and game engines. A strip for a table with an edge length of `n`
+    /// is `n * n` pixels wide and `n` pixels tall. It holds a tile per blue
+    /// value from left to right, with red increasing to the right and green
+    /// increasing downwards within each tile.
+    ///
+    /// Returns [`Error::InvalidLut`] if the dimensions don't describe a strip
+    /// with an edge length of 2 to 256, or don't match the length of `rgba`.
+    pub fn from_strip(
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> Result<Self> {
+        let size = height;
+        if !(2..=MAX_LUT_SIZE).contains(&size) || width != size * size {
+            return Err(Error::InvalidLut(format!(
+                "a {width}x{height} image is not a lookup table strip"
+            )));
+        }
+        if rgba.len() != (width * height * 4) as usize {
+            return Err(Error::InvalidLut(format!(
+                "expected {} bytes of strip data, found {}",
+                width * height * 4,
+                rgba.len()
+            )));
+        }
+
+        let size = size as usize;
+        let mut data = Vec::with_capacity(size.pow(3));
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let offset = (g * size * size + b * size + r) * 4;
+                    let [r, g, b, _]: [u8; 4] = rgba[offset..offset + 4].try_into().unwrap();
+                    data.push([r, g, b, 255]);
+                }
+            }
+        }
+
+        Ok(Self {
+            size: size as u32,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data,
+        })
+    }
+
+    /// The number of entries along each edge of the table.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// A post-processor which color grades the output with a 3d [`Lut`], e.g. to
+/// match a color scheme or a film look. The table is applied to the colors as
+/// they would be displayed, before conversion for srgb surfaces, and entries
+/// are interpolated linearly.
+///
+/// The user data is the lookup table.
+pub struct LutPostProcessor {
+    uniforms: Buffer,
+    bindings: BindGroupLayout,
+    nearest: Sampler,
+    linear: Sampler,
+    lut: Texture,
+    lut_view: TextureView,
+    /// The table contents, uploaded during the first call to `process`.
+    lut_upload: Option<(Buffer, u32)>,
+    lut_size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    pipeline: RenderPipeline,
+
+    grader: RenderBundle,
+}
+
+impl PostProcessor for LutPostProcessor {
+    type UserData = Lut;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        lut: Self::UserData,
+    ) -> Self {
+        let uniforms = device.create_buffer(&BufferDescriptor {
+            label: Some("Lut Uniforms"),
+            size: size_of::<LutUniforms>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let nearest = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let linear = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let lut_texture = device.create_texture(&TextureDescriptor {
+            label: Some("Lut"),
+            size: Extent3d {
+                width: lut.size,
+                height: lut.size,
+                depth_or_array_layers: lut.size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let lut_view = lut_texture.create_view(&TextureViewDescriptor::default());
+
+        // Compiling doesn't have access to the queue, so the table is staged in a
+        // mapped buffer and copied into the texture before its first use.
+        let row_size = lut.size as usize * size_of::<[u8; 4]>();
+        let bytes_per_row = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let lut_upload = device.create_buffer(&BufferDescriptor {
+            label: Some("Lut Upload"),
+            size: (bytes_per_row * lut.size.pow(2) as usize) as u64,
+            usage: BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        {
+            let mut mapped = lut_upload.slice(..).get_mapped_range_mut();
+            for (dest, row) in mapped
+                .chunks_exact_mut(bytes_per_row)
+                .zip(lut.data.chunks_exact(lut.size as usize))
+            {
+                dest[..row_size].copy_from_slice(bytemuck::cast_slice(row));
+            }
+        }
+        lut_upload.unmap();
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Lut Bindings Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(size_of::<LutUniforms>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(include_post_wgsl!("shaders/lut.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Lut Layout"),
+            bind_group_layouts: &[&layout],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Lut Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: surface_config.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let grader = build_lut_pass(
+            device,
+            &LutResources {
+                uniforms: &uniforms,
+                nearest: &nearest,
+                linear: &linear,
+                layout: &layout,
+                pipeline: &pipeline,
+                lut: &lut_view,
+            },
+            text_view,
+            surface_config.format,
+        );
+
+        Self {
+            uniforms,
+            bindings: layout,
+            nearest,
+            linear,
+            lut: lut_texture,
+            lut_view,
+            lut_upload: Some((lut_upload, bytes_per_row as u32)),
+            lut_size: lut.size,
+            domain_min: lut.domain_min,
+            domain_max: lut.domain_max,
+            pipeline,
+            grader,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.grader = build_lut_pass(
+            device,
+            &LutResources {
+                uniforms: &self.uniforms,
+                nearest: &self.nearest,
+                linear: &self.linear,
+                layout: &self.bindings,
+                pipeline: &self.pipeline,
+                lut: &self.lut_view,
+            },
+            text_view,
+            surface_config.format,
+        );
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        if let Some((upload, bytes_per_row)) = self.lut_upload.take() {
+            let size = Extent3d {
+                width: self.lut_size,
+                height: self.lut_size,
+                depth_or_array_layers: self.lut_size,
+            };
+            encoder.copy_buffer_to_texture(
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &upload,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(self.lut_size),
+                    },
+                },
+                self.lut.as_image_copy(),
+                size,
+            );
+        }
+
+        {
+            let mut uniforms = queue
+                .write_buffer_with(
+                    &self.uniforms,
+                    0,
+                    NonZeroU64::new(size_of::<LutUniforms>() as u64).unwrap(),
+                )
+                .unwrap();
+            let [min_r, min_g, min_b] = self.domain_min;
+            let [max_r, max_g, max_b] = self.domain_max;
+            uniforms.copy_from_slice(bytemuck::bytes_of(&LutUniforms {
+                screen_size: [surface_config.width as f32, surface_config.height as f32],
+                use_srgb: u32::from(expects_linear(surface_config.format)),
+                lut_size: self.lut_size as f32,
+                domain_min: [min_r, min_g, min_b, 0.0],
+                domain_max: [max_r, max_g, max_b, 1.0],
+            }));
+        }
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Lut Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+
+        pass.execute_bundles(Some(&self.grader));
+    }
+}
+
+struct LutResources<'a> {
+    uniforms: &'a Buffer,
+    nearest: &'a Sampler,
+    linear: &'a Sampler,
+    layout: &'a BindGroupLayout,
+    pipeline: &'a RenderPipeline,
+    lut: &'a TextureView,
+}
+
+fn build_lut_pass(
+    device: &Device,
+    resources: &LutResources,
+    text_view: &TextureView,
+    format: TextureFormat,
+) -> RenderBundle {
+    let bindings = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Lut Bindings"),
+        layout: resources.layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(text_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(resources.nearest),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: resources.uniforms.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(resources.lut),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: BindingResource::Sampler(resources.linear),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
+        label: Some("Lut Pass Encoder"),
+        color_formats: &[Some(format)],
+        depth_stencil: None,
+        sample_count: 1,
+        multiview: None,
+    });
+
+    encoder.set_pipeline(resources.pipeline);
+    encoder.set_bind_group(0, &bindings, &[]);
+    encoder.draw(0..3, 0..1);
+
+    encoder.finish(&RenderBundleDescriptor {
+        label: Some("Lut Pass Bundle"),
+    })
+}
+
+fn build_gaussian_blur_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("Gaussian Blur Bindings Layout"),
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(size_of::<GaussianBlurUniforms>() as u64),
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn build_gaussian_blur(
+    device: &Device,
+    layout: &BindGroupLayout,
+    format: TextureFormat,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(include_post_wgsl!("shaders/gaussian_blur.wgsl"));
+
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Gaussian Blur Layout"),
+        bind_group_layouts: &[layout],
+        immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Gaussian Blur Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleStrip,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview_mask: None,
+        cache: None,
+    })
+}
+
+fn build_gaussian_blur_pass(
+    device: &Device,
+    layout: &BindGroupLayout,
+    pipeline: &RenderPipeline,
+    source: &TextureView,
+    sampler: &Sampler,
+    uniforms: &Buffer,
+    format: TextureFormat,
+) -> RenderBundle {
+    let bindings = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Gaussian Blur Bindings"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(source),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: uniforms.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
+        label: Some("Gaussian Blur Pass Encoder"),
+        color_formats: &[Some(format)],
+        depth_stencil: None,
+        sample_count: 1,
+        multiview: None,
+    });
+
+    encoder.set_pipeline(pipeline);
+    encoder.set_bind_group(0, &bindings, &[]);
+    encoder.draw(0..3, 0..1);
+
+    encoder.finish(&RenderBundleDescriptor {
+        label: Some("Gaussian Blur Pass Bundle"),
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniforms {
+    screen_size: [f32; 2],
+    threshold: f32,
+    intensity: f32,
+    use_srgb: u32,
+    _pad: [f32; 3],
+}
+
+/// The maximum number of downsampled textures used by the bloom effect.
+const BLOOM_LEVELS: usize = 5;
+
+/// Settings for the bloom post-processor.
+///
+/// See struct members for more information on each setting.
+#[derive(Clone, Debug)]
+pub struct BloomSettings {
+    /// The luminance above which pixels start to glow.
+    /// A good range of values is 0.3 to 0.9.
+    /// Defaults to 0.6.
+    pub threshold: f32,
+    /// How strongly the glow is added back over the text.
+    /// A good range of values is 0.5 to 3.0.
+    /// Defaults to 1.0.
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.6,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// A post-processor which makes bright text glow. Pixels brighter than the
+/// threshold are extracted into a chain of progressively downsampled textures,
+/// blurred while upsampling them again, and added back over the composited
+/// text.
+///
+/// The chain uses up to five textures in the same format as the composited
+/// text, the first of which is half the size of the surface. This costs
+/// roughly a third of the memory of a surface-sized texture in addition to the
+/// surface itself.
+pub struct BloomPostProcessor {
+    settings: BloomSettings,
+
+    uniforms: Buffer,
+    nearest: Sampler,
+    linear: Sampler,
+    layout: BindGroupLayout,
+    bloom_layout: BindGroupLayout,
+
+    extract_pipeline: RenderPipeline,
+    downsample_pipeline: RenderPipeline,
+    upsample_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+
+    passes: BloomPasses,
+}
+
+impl PostProcessor for BloomPostProcessor {
+    type UserData = BloomSettings;
+
+    fn compile(
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        user_data: Self::UserData,
+    ) -> Self {
+        let uniforms = device.create_buffer(&BufferDescriptor {
+            label: Some("Bloom Uniforms"),
+            size: size_of::<BloomUniforms>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let nearest = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let linear = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let texture_entries = [
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Bloom Bindings Layout"),
+            entries: &[
+                texture_entries[0],
+                texture_entries[1],
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(size_of::<BloomUniforms>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bloom_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Bloom Composite Bindings Layout"),
+            entries: &texture_entries,
+        });
+
+        let shader = device.create_shader_module(include_post_wgsl!("shaders/bloom.wgsl"));
+        let format = intermediate_format(text_view);
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+
+        let extract_pipeline =
+            build_bloom_pipeline(device, &shader, &[&layout], "fs_extract", format, None);
+        let downsample_pipeline =
+            build_bloom_pipeline(device, &shader, &[&layout], "fs_downsample", format, None);
+        let upsample_pipeline = build_bloom_pipeline(
+            device,
+            &shader,
+            &[&layout],
+            "fs_upsample",
+            format,
+            Some(BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+        );
+        let composite_pipeline = build_bloom_pipeline(
+            device,
+            &shader,
+            &[&layout, &bloom_layout],
+            "fs_composite",
+            surface_config.format,
+            None,
+        );
+
+        let passes = build_bloom_passes(
+            device,
+            text_view,
+            surface_config,
+            &BloomResources {
+                uniforms: &uniforms,
+                nearest: &nearest,
+                linear: &linear,
+                layout: &layout,
+                bloom_layout: &bloom_layout,
+                extract_pipeline: &extract_pipeline,
+                downsample_pipeline: &downsample_pipeline,
+                upsample_pipeline: &upsample_pipeline,
+                composite_pipeline: &composite_pipeline,
+            },
+        );
+
+        Self {
+            settings: user_data,
+            uniforms,
+            nearest,
+            linear,
+            layout,
+            bloom_layout,
+            extract_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            passes,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        device: &Device,
+        text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+    ) {
+        self.passes = build_bloom_passes(
+            device,
+            text_view,
+            surface_config,
+            &BloomResources {
+                uniforms: &self.uniforms,
+                nearest: &self.nearest,
+                linear: &self.linear,
+                layout: &self.layout,
+                bloom_layout: &self.bloom_layout,
+                extract_pipeline: &self.extract_pipeline,
+                downsample_pipeline: &self.downsample_pipeline,
+                upsample_pipeline: &self.upsample_pipeline,
+                composite_pipeline: &self.composite_pipeline,
+            },
+        );
+    }
+
+    fn process(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        _text_view: &TextureView,
+        surface_config: &SurfaceConfiguration,
+        surface_view: &TextureView,
+    ) {
+        {
+            let mut uniforms = queue
+                .write_buffer_with(
+                    &self.uniforms,
+                    0,
+                    NonZeroU64::new(size_of::<BloomUniforms>() as u64).unwrap(),
+                )
+                .unwrap();
+            uniforms.copy_from_slice(bytemuck::bytes_of(&BloomUniforms {
+                screen_size: [surface_config.width as f32, surface_config.height as f32],
+                threshold: self.settings.threshold,
+                intensity: self.settings.intensity,
+                use_srgb: u32::from(expects_linear(surface_config.format)),
+                _pad: [0.0; 3],
+            }));
+        }
+
+        let BloomPasses {
+            levels,
+            extract,
+            downsample,
+            upsample,
+            composite,
+        } = &self.passes;
+
+        let mut passes = vec![(&levels[0], LoadOp::Clear(Color::BLACK), extract)];
+        passes.extend(
+            downsample
+                .iter()
+                .enumerate()
+                .map(|(level, pass)| (&levels[level + 1], LoadOp::Clear(Color::BLACK), pass)),
+        );
+        passes.extend(
+            upsample
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(level, pass)| (&levels[level], LoadOp::Load, pass)),
+        );
+        passes.push((surface_view, LoadOp::Clear(Color::TRANSPARENT), composite));
+
+        for (view, load, bundle) in passes {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Bloom Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load,
+                        store: StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+
+            pass.execute_bundles(Some(bundle));
+        }
+    }
+}
+
+/// The textures and render bundles which depend on the surface size.
+struct BloomPasses {
+    /// Progressively halved textures, starting at half the surface size.
+    levels: Vec<TextureView>,
+    /// Renders the bright parts of the text into `levels[0]`.
+    extract: RenderBundle,
+    /// Pass `i` renders `levels[i]` into `levels[i + 1]`.
+    downsample: Vec<RenderBundle>,
+    /// Pass `i` adds `levels[i + 1]` onto `levels[i]`.
+    upsample: Vec<RenderBundle>,
+    /// Renders the text and `levels[0]` to the surface.
+    composite: RenderBundle,
+}
+
+struct BloomResources<'a> {
+    uniforms: &'a Buffer,
+    nearest: &'a Sampler,
+    linear: &'a Sampler,
+    layout: &'a BindGroupLayout,
+    bloom_layout: &'a BindGroupLayout,
+    extract_pipeline: &'a RenderPipeline,
+    downsample_pipeline: &'a RenderPipeline,
+    upsample_pipeline: &'a RenderPipeline,
+    composite_pipeline: &'a RenderPipeline,
+}
+
+fn build_bloom_pipeline(
+    device: &Device,
+    shader: &ShaderModule,
+    layouts: &[&BindGroupLayout],
+    entry_point: &str,
+    format: TextureFormat,
+    blend: Option<BlendState>,
+) -> RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Bloom Layout"),
+        bind_group_layouts: layouts,
+        immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Bloom Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleStrip,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: Some(entry_point),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview_mask: None,
+        cache: None,
+    })
+}
+
+fn build_bloom_passes(
+    device: &Device,
+    text_view: &TextureView,
+    surface_config: &SurfaceConfiguration,
+    resources: &BloomResources,
+) -> BloomPasses {
+    let format = intermediate_format(text_view);
+    let mut levels = vec![];
+    let mut size = (
+        (surface_config.width / 2).max(1),
+        (surface_config.height / 2).max(1),
+    );
+    loop {
+        levels.push(
+            device
+                .create_texture(&TextureDescriptor {
+                    label: Some("Bloom Level"),
+                    size: Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&TextureViewDescriptor::default()),
+        );
+
+        if levels.len() == BLOOM_LEVELS || size.0 < 2 || size.1 < 2 {
+            break;
+        }
+        size = (size.0 / 2, size.1 / 2);
+    }
+
+    let bindings = |view: &TextureView, sampler: &Sampler| {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Bloom Bindings"),
+            layout: resources.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: resources.uniforms.as_entire_binding(),
+                },
+            ],
+        })
+    };
+
+    let bundle = |pipeline: &RenderPipeline, groups: &[&BindGroup], format: TextureFormat| {
+        let mut encoder = device.create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
+            label: Some("Bloom Pass Encoder"),
+            color_formats: &[Some(format)],
+            depth_stencil: None,
+            sample_count: 1,
+            multiview: None,
+        });
+
+        encoder.set_pipeline(pipeline);
+        for (index, group) in groups.iter().enumerate() {
+            encoder.set_bind_group(index as u32, *group, &[]);
+        }
+        encoder.draw(0..3, 0..1);
+
+        encoder.finish(&RenderBundleDescriptor {
+            label: Some("Bloom Pass Bundle"),
+        })
+    };
+
+    let text_bindings = bindings(text_view, resources.nearest);
+    let extract = bundle(resources.extract_pipeline, &[&text_bindings], format);
+
+    let downsample = levels
+        .windows(2)
+        .map(|pair| {
+            bundle(
+                resources.downsample_pipeline,
+                &[&bindings(&pair[0], resources.linear)],
+                format,
+            )
+        })
+        .collect();
+
+    let upsample = levels
+        .windows(2)
+        .map(|pair| {
+            bundle(
+                resources.upsample_pipeline,
+                &[&bindings(&pair[1], resources.linear)],
+                format,
+            )
+        })
+        .collect();
+
+    let bloom_bindings = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Bloom Composite Bindings"),
+        layout: resources.bloom_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&levels[0]),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(resources.linear),
+            },
+        ],
+    });
+    let composite = bundle(
+        resources.composite_pipeline,
+        &[&text_bindings, &bloom_bindings],
+        surface_config.format,
+    );
+
+    BloomPasses {
+        levels,
+        extract,
+        downsample,
+        upsample,
+        composite,
     }
 }
 
@@ -975,7 +3450,7 @@ fn build_crt(
     texture_layout: &BindGroupLayout,
     crt_uniforms_buffer: &Buffer,
 ) -> (RenderPipeline, BindGroup) {
-    let shader = device.create_shader_module(include_wgsl!("shaders/crt.wgsl"));
+    let shader = device.create_shader_module(include_post_wgsl!("shaders/crt.wgsl"));
 
     let uniforms_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("CRT Fragment Uniforms Binding Layout"),
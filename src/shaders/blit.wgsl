@@ -30,9 +30,7 @@ var<uniform> uniforms: Uniforms;
 fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
     let target_size = select(vec2<f32>(textureDimensions(Texture)), uniforms.screen_size, uniforms.preserve_aspect == 0u);
     let uv = gl_Position.xy / target_size;
-    let factor = select(2.2, 1.0, uniforms.use_srgb == 0u);
-
-    let color = pow(textureSample(Texture, Sampler, uv), vec4(vec3(factor), 1.0));
+    let color = to_surface(textureSample(Texture, Sampler, uv), uniforms.use_srgb != 0u);
 
     return FragmentOutput(select(color, vec4(0.0, 0.0, 0.0, 0.0), uv.x > 1.0 || uv.y > 1.0));
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2(f32((index << 1) & 2), f32(index & 2));
+    return VertexOutput(vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var tex_s: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    threshold: f32,
+    intensity: f32,
+    use_srgb: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+@group(1) @binding(0)
+var bloom: texture_2d<f32>;
+@group(1) @binding(1)
+var bloom_s: sampler;
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+// Keep the part of the color which is brighter than the threshold.
+@fragment
+fn fs_extract(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / (uniforms.screen_size / 2.0);
+    let color = textureSample(tex, tex_s, uv).rgb;
+    let luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    let bright = max(luma - uniforms.threshold, 0.0) / max(luma, 0.0001);
+
+    return FragmentOutput(vec4(color * bright, 1.0));
+}
+
+// Sample the next larger level with a 2x2 box filter using bilinear taps.
+@fragment
+fn fs_downsample(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy * 2.0 / vec2<f32>(textureDimensions(tex));
+    let texel = 1.0 / vec2<f32>(textureDimensions(tex));
+
+    var color = textureSample(tex, tex_s, uv + texel * vec2(-1.0, -1.0));
+    color += textureSample(tex, tex_s, uv + texel * vec2(1.0, -1.0));
+    color += textureSample(tex, tex_s, uv + texel * vec2(-1.0, 1.0));
+    color += textureSample(tex, tex_s, uv + texel * vec2(1.0, 1.0));
+
+    return FragmentOutput(vec4(color.rgb / 4.0, 1.0));
+}
+
+// Sample the next smaller level with a tent filter. The result is added to the
+// current level by the blend state.
+@fragment
+fn fs_upsample(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let texel = 1.0 / vec2<f32>(textureDimensions(tex));
+    let uv = gl_Position.xy * texel / 2.0;
+
+    var color = textureSample(tex, tex_s, uv) * 4.0;
+    color += textureSample(tex, tex_s, uv + texel * vec2(-1.0, 0.0)) * 2.0;
+    color += textureSample(tex, tex_s, uv + texel * vec2(1.0, 0.0)) * 2.0;
+    color += textureSample(tex, tex_s, uv + texel * vec2(0.0, -1.0)) * 2.0;
+    color += textureSample(tex, tex_s, uv + texel * vec2(0.0, 1.0)) * 2.0;
+    color += textureSample(tex, tex_s, uv + texel * vec2(-1.0, -1.0));
+    color += textureSample(tex, tex_s, uv + texel * vec2(1.0, -1.0));
+    color += textureSample(tex, tex_s, uv + texel * vec2(-1.0, 1.0));
+    color += textureSample(tex, tex_s, uv + texel * vec2(1.0, 1.0));
+
+    return FragmentOutput(vec4(color.rgb / 16.0, 1.0));
+}
+
+// Add the blurred highlights back over the composited text.
+@fragment
+fn fs_composite(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+    let text = textureSample(tex, tex_s, uv);
+    let glow = textureSample(bloom, bloom_s, uv).rgb * uniforms.intensity;
+    let color = vec4(min(text.rgb + glow, vec3(1.0)), text.a);
+
+    return FragmentOutput(to_surface(color, uniforms.use_srgb != 0u));
+}
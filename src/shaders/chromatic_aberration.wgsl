@@ -0,0 +1,43 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) Index: u32) -> VertexOutput {
+    let vertex = vec2(f32((Index << 1u) & 2u), f32(Index & 2u));
+    return VertexOutput(vec4(vertex * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var Texture: texture_2d<f32>;
+@group(0) @binding(1)
+var Sampler: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    offset: f32,
+    use_srgb: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+@fragment
+fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+
+    // The offset grows from nothing at the center to the full amount at the middle
+    // of each edge. Red is pushed outwards and blue inwards. The sampler clamps, so
+    // the edges don't wrap around.
+    let shift = (uv - 0.5) * 2.0 * uniforms.offset / uniforms.screen_size;
+    let red = textureSample(Texture, Sampler, uv + shift);
+    let center = textureSample(Texture, Sampler, uv);
+    let blue = textureSample(Texture, Sampler, uv - shift);
+
+    let color = vec4(red.r, center.g, blue.b, center.a);
+    return FragmentOutput(to_surface(color, uniforms.use_srgb != 0u));
+}
@@ -43,6 +43,9 @@ struct Uniforms {
     mask_strength: f32,
     use_srgb: i32,
     milliseconds: u32,
+    vignette_strength: f32,
+    flicker: f32,
+    seconds: f32,
 }
 
 @group(3) @binding(0)
@@ -144,7 +147,6 @@ fn accumulate(uv: vec2<f32>) -> vec4<f32> {
 fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
     let uv = gl_Position.xy / uniforms.resolution;
 
-    let factor = select(2.2, 1.0, uniforms.use_srgb == 0);
     let acc = accumulate(uv);
 
     // Curve
@@ -181,11 +183,18 @@ fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
     // Mask
     col *= mask(uv * uniforms.resolution, 1.0 - uniforms.mask_strength);
 
+    // Vignette
+    let vignette = 16.0 * curved_uv.x * curved_uv.y * (1.0 - curved_uv.x) * (1.0 - curved_uv.y);
+    col *= mix(1.0, pow(clamp(vignette, 0.0, 1.0), 0.3), uniforms.vignette_strength);
+
+    // Flicker
+    col *= 1.0 - uniforms.flicker * (0.5 + 0.5 * sin(uniforms.seconds * 110.0));
+
     var distance = distance(curved_uv) - uniforms.corner_radius;
     distance = smoothstep(0.0, 100.0, distance);
 
     let crtColor = vec4(mix(col * vec3(uniforms.modulate_crt), vec3(0.0, 0.0, 0.0), vec3(distance)), 1.0);
     let clampedCrt = select(crtColor, vec4(vec3(0.0), 1.0), curved_uv.x < 0.0 || curved_uv.x > 1.0 || curved_uv.y < 0.0 || curved_uv.y > 1.0);
 
-    return FragmentOutput(pow(clampedCrt, vec4(factor)), acc);
+    return FragmentOutput(to_surface(clampedCrt, uniforms.use_srgb != 0), acc);
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2(f32((index << 1) & 2), f32(index & 2));
+    return VertexOutput(vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var tex_s: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    direction: vec2<f32>,
+    radius: f32,
+    use_srgb: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+// Keeps the number of samples per pass bounded for very large radii.
+const MAX_RADIUS: i32 = 64;
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@fragment
+fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+    let step = uniforms.direction / uniforms.screen_size;
+    let radius = min(i32(ceil(uniforms.radius)), MAX_RADIUS);
+    let sigma = max(uniforms.radius / 2.0, 0.0001);
+
+    var color = vec4(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        color += textureSample(tex, tex_s, uv + step * f32(i)) * weight;
+        total += weight;
+    }
+
+    return FragmentOutput(to_surface(color / total, uniforms.use_srgb != 0u));
+}
@@ -0,0 +1,53 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) Index: u32) -> VertexOutput {
+    let vertex = vec2(f32((Index << 1u) & 2u), f32(Index & 2u));
+    return VertexOutput(vec4(vertex * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var Texture: texture_2d<f32>;
+@group(0) @binding(1)
+var Sampler: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    use_srgb: u32,
+    lut_size: f32,
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+@group(0) @binding(3)
+var Lut: texture_3d<f32>;
+@group(0) @binding(4)
+var LutSampler: sampler;
+
+@fragment
+fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+    let color = textureSample(Texture, Sampler, uv);
+
+    // The composited text is premultiplied, while the table maps straight colors.
+    let straight = select(color.rgb / color.a, vec3(0.0), color.a == 0.0);
+    let domain = uniforms.domain_max.xyz - uniforms.domain_min.xyz;
+    let coords = clamp((straight - uniforms.domain_min.xyz) / domain, vec3(0.0), vec3(1.0));
+
+    // Sample between the centers of the outermost texels so the table's end points
+    // map exactly to the ends of the domain.
+    let size = uniforms.lut_size;
+    let graded = textureSample(Lut, LutSampler, coords * (size - 1.0) / size + 0.5 / size).rgb;
+
+    let surface = select(graded, srgb_to_linear(graded), uniforms.use_srgb != 0u);
+    return FragmentOutput(vec4(surface * color.a, color.a));
+}
@@ -0,0 +1,43 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) Index: u32) -> VertexOutput {
+    let vertex = vec2(f32((Index << 1u) & 2u), f32(Index & 2u));
+    return VertexOutput(vec4(vertex * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+    @location(1) History: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var Texture: texture_2d<f32>;
+@group(0) @binding(1)
+var Sampler: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    decay: f32,
+    use_srgb: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+@group(0) @binding(3)
+var History: texture_2d<f32>;
+
+@fragment
+fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+    let current = textureSample(Texture, Sampler, uv);
+    let previous = textureSample(History, Sampler, uv);
+
+    // Lit phosphors stay lit, and fade out slowly once they aren't excited anymore.
+    let color = max(current, previous * uniforms.decay);
+
+    return FragmentOutput(to_surface(color, uniforms.use_srgb != 0u), color);
+}
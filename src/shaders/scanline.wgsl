@@ -0,0 +1,41 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) Index: u32) -> VertexOutput {
+    let vertex = vec2(f32((Index << 1u) & 2u), f32(Index & 2u));
+    return VertexOutput(vec4(vertex * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var Texture: texture_2d<f32>;
+@group(0) @binding(1)
+var Sampler: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    use_srgb: u32,
+    period: u32,
+    intensity: f32,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+@fragment
+fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+    let color = to_surface(textureSample(Texture, Sampler, uv), uniforms.use_srgb != 0u);
+
+    // The second half of every period is darkened.
+    let row = u32(gl_Position.y);
+    let dark = row % uniforms.period >= uniforms.period / 2u;
+    let scale = select(1.0, 1.0 - uniforms.intensity, dark);
+
+    return FragmentOutput(vec4(color.rgb * scale, color.a));
+}
@@ -0,0 +1,12 @@
+// Helpers shared by the post-processing shaders, prepended to them when their
+// modules are created.
+
+// The composited text holds srgb encoded colors. Surfaces with an srgb format
+// expect linear colors, which they encode again when they're written.
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(pow((color + 0.055) / 1.055, vec3(2.4)), color / 12.92, color <= vec3(0.04045));
+}
+
+fn to_surface(color: vec4<f32>, use_srgb: bool) -> vec4<f32> {
+    return select(color, vec4(srgb_to_linear(color.rgb), color.a), use_srgb);
+}
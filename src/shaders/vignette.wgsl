@@ -0,0 +1,44 @@
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) Index: u32) -> VertexOutput {
+    let vertex = vec2(f32((Index << 1u) & 2u), f32(Index & 2u));
+    return VertexOutput(vec4(vertex * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0));
+}
+
+struct FragmentOutput {
+    @location(0) FragColor: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var Texture: texture_2d<f32>;
+@group(0) @binding(1)
+var Sampler: sampler;
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    radius: f32,
+    softness: f32,
+    color: vec3<f32>,
+    use_srgb: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> uniforms: Uniforms;
+
+@fragment
+fn fs_main(@builtin(position) gl_Position: vec4<f32>) -> FragmentOutput {
+    let uv = gl_Position.xy / uniforms.screen_size;
+    let color = textureSample(Texture, Sampler, uv);
+
+    // 0.0 at the center of the screen and 1.0 in the corners.
+    let distance = length((uv - 0.5) * 2.0) / sqrt(2.0);
+    let amount = smoothstep(uniforms.radius, uniforms.radius + max(uniforms.softness, 0.0001), distance);
+
+    // The composited text is premultiplied, so the vignette color is as well.
+    let shaded = vec4(mix(color.rgb, uniforms.color * color.a, amount), color.a);
+
+    return FragmentOutput(to_surface(shaded, uniforms.use_srgb != 0u));
+}
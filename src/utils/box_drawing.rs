@@ -0,0 +1,663 @@
+//! Procedurally generated glyphs for the box drawing (U+2500 - U+257F),
+//! block element (U+2580 - U+259F) and powerline separator (U+E0B0 - U+E0B3)
+//! characters, sized to fill a cell exactly so that neighboring glyphs connect
+//! without gaps.
+
+/// Atlas font id used for generated glyphs. Font ids are hashes of the font
+/// data, so this will not collide with a real font in practice.
+pub(crate) const BUILTIN_FONT_ID: u64 = u64::MAX;
+
+/// The weight of a line leaving the center of the cell in one direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Weight {
+    None,
+    Light,
+    Heavy,
+    Double,
+}
+
+/// The lines of a box drawing character, as (up, right, down, left).
+type Lines = [Weight; 4];
+
+const UP: usize = 0;
+const RIGHT: usize = 1;
+const DOWN: usize = 2;
+const LEFT: usize = 3;
+
+/// Whether `ch` is a box drawing or block element character.
+pub(crate) fn is_box_drawing(ch: char) -> bool {
+    ('\u{2500}'..='\u{259f}').contains(&ch)
+}
+
+/// Whether `ch` is one of the powerline triangle and arrow separators.
+pub(crate) fn is_powerline(ch: char) -> bool {
+    ('\u{e0b0}'..='\u{e0b3}').contains(&ch)
+}
+
+/// Render `ch` into a `width` by `height` image of premultiplied white
+/// coverage, in the same format as rasterized font glyphs. Returns `None` if
+/// the character isn't a box drawing, block element or powerline character.
+pub(crate) fn rasterize(
+    ch: char,
+    width: u32,
+    height: u32,
+) -> Option<Vec<u32>> {
+    if !is_box_drawing(ch) && !is_powerline(ch) {
+        return None;
+    }
+
+    let mut canvas = Canvas::new(width.max(1), height.max(1));
+    match ch as u32 {
+        0x2500..=0x2503 | 0x250c..=0x254b | 0x2550..=0x256c | 0x2574..=0x257f => {
+            canvas.lines(lines(ch));
+        }
+        0x2504..=0x250b | 0x254c..=0x254f => canvas.dashes(ch),
+        0x256d..=0x2570 => canvas.arc(ch),
+        0x2571..=0x2573 => canvas.diagonals(ch),
+        0xe0b0..=0xe0b3 => canvas.powerline(ch),
+        _ => canvas.block(ch),
+    }
+
+    Some(canvas.pixels)
+}
+
+/// Look up the lines of the solid box drawing characters.
+fn lines(ch: char) -> Lines {
+    // Each entry lists the weight of the up, right, down and left lines, with 1
+    // for light, 2 for heavy and 3 for double lines.
+    const SOLID: [&[u8; 4]; 4] = [b"0101", b"0202", b"1010", b"2020"];
+    const CORNERS_AND_TEES: [&[u8; 4]; 64] = [
+        b"0110", b"0210", b"0120", b"0220", b"0011", b"0012", b"0021", b"0022", // ┌ - ┓
+        b"1100", b"1200", b"2100", b"2200", b"1001", b"1002", b"2001", b"2002", // └ - ┛
+        b"1110", b"1210", b"2110", b"1120", b"2120", b"2210", b"1220", b"2220", // ├ - ┣
+        b"1011", b"1012", b"2011", b"1021", b"2021", b"2012", b"1022", b"2022", // ┤ - ┫
+        b"0111", b"0112", b"0211", b"0212", b"0121", b"0122", b"0221", b"0222", // ┬ - ┳
+        b"1101", b"1102", b"1201", b"1202", b"2101", b"2102", b"2201", b"2202", // ┴ - ┻
+        b"1111", b"1112", b"1211", b"1212", b"2111", b"1121", b"2121", b"2112", // ┼ - ╃
+        b"2211", b"1122", b"1221", b"2212", b"1222", b"2122", b"2221", b"2222", // ╄ - ╋
+    ];
+    const DOUBLE: [&[u8; 4]; 29] = [
+        b"0303", b"3030", b"0310", b"0130", b"0330", b"0013", b"0031", b"0033", // ═ - ╗
+        b"1300", b"3100", b"3300", b"1003", b"3001", b"3003", b"1310", b"3130", // ╘ - ╟
+        b"3330", b"1013", b"3031", b"3033", b"0313", b"0131", b"0333", b"1303", // ╠ - ╧
+        b"3101", b"3303", b"1313", b"3131", b"3333", // ╨ - ╬
+    ];
+    const HALVES: [&[u8; 4]; 12] = [
+        b"0001", b"1000", b"0100", b"0010", b"0002", b"2000", b"0200", b"0020", // ╴ - ╻
+        b"0201", b"1020", b"0102", b"2010", // ╼ - ╿
+    ];
+
+    let code = ch as usize;
+    let entry = match code {
+        0x2500..=0x2503 => SOLID[code - 0x2500],
+        0x250c..=0x254b => CORNERS_AND_TEES[code - 0x250c],
+        0x2550..=0x256c => DOUBLE[code - 0x2550],
+        0x2574..=0x257f => HALVES[code - 0x2574],
+        _ => b"0000",
+    };
+
+    entry.map(|weight| match weight {
+        b'1' => Weight::Light,
+        b'2' => Weight::Heavy,
+        b'3' => Weight::Double,
+        _ => Weight::None,
+    })
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    /// The thickness of a light line, heavy lines are twice as thick and double
+    /// lines are two light lines separated by a light line's thickness.
+    light: u32,
+    pixels: Vec<u32>,
+}
+
+impl Canvas {
+    fn new(
+        width: u32,
+        height: u32,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            light: (width.min(height) / 8).max(1),
+            pixels: vec![0; width as usize * height as usize],
+        }
+    }
+
+    fn thickness(
+        &self,
+        weight: Weight,
+    ) -> u32 {
+        match weight {
+            Weight::None => 0,
+            Weight::Light => self.light,
+            Weight::Heavy => self.light * 2,
+            Weight::Double => self.light * 3,
+        }
+    }
+
+    /// Fill the rectangle from (x0, y0) up to (x1, y1) with the given coverage.
+    fn fill(
+        &mut self,
+        x0: u32,
+        y0: u32,
+        x1: u32,
+        y1: u32,
+        coverage: u8,
+    ) {
+        let pixel = u32::from_ne_bytes([coverage; 4]);
+        for y in y0.min(self.height)..y1.min(self.height) {
+            let row = (y * self.width) as usize;
+            self.pixels[row + x0.min(self.width) as usize..row + x1.min(self.width) as usize]
+                .fill(pixel);
+        }
+    }
+
+    /// The start of a band of `thickness` centered in `size`.
+    fn band(
+        size: u32,
+        thickness: u32,
+    ) -> u32 {
+        size.saturating_sub(thickness) / 2
+    }
+
+    fn lines(
+        &mut self,
+        lines: Lines,
+    ) {
+        let (width, height, light) = (self.width, self.height, self.light);
+
+        // The extent of the perpendicular lines at the center, which lines
+        // joining them extend into so that corners are closed.
+        let vertical = self.thickness(lines[UP]).max(self.thickness(lines[DOWN]));
+        let horizontal = self
+            .thickness(lines[LEFT])
+            .max(self.thickness(lines[RIGHT]));
+        let has_double_vertical = lines[UP] == Weight::Double || lines[DOWN] == Weight::Double;
+        let has_double_horizontal = lines[LEFT] == Weight::Double || lines[RIGHT] == Weight::Double;
+
+        // Start of the outer line of double lines along each axis.
+        let double_x = Self::band(width, light * 3);
+        let double_y = Self::band(height, light * 3);
+
+        for (direction, weight) in lines.into_iter().enumerate() {
+            let horizontal_line = direction == LEFT || direction == RIGHT;
+            let (size, cross) = if horizontal_line {
+                (width, height)
+            } else {
+                (height, width)
+            };
+
+            // The range covered along the line's own axis, from the edge of the
+            // cell to the center.
+            let span = |stop: u32, toward_end: bool| {
+                if toward_end {
+                    (stop, size)
+                } else {
+                    (0, stop)
+                }
+            };
+            let toward_end = direction == RIGHT || direction == DOWN;
+            let (perpendicular, perpendicular_double, double_start) = if horizontal_line {
+                (vertical, has_double_vertical, double_x)
+            } else {
+                (horizontal, has_double_horizontal, double_y)
+            };
+
+            let mut bands = vec![];
+            match weight {
+                Weight::None => continue,
+                Weight::Light | Weight::Heavy => {
+                    let thickness = self.thickness(weight);
+                    let opposite = lines[(direction + 2) % 4];
+                    let stop = if perpendicular_double && opposite != Weight::None {
+                        // Cross both of the double lines.
+                        if toward_end {
+                            double_start
+                        } else {
+                            double_start + light * 3
+                        }
+                    } else if perpendicular_double {
+                        // Join the nearer line of the double lines.
+                        if toward_end {
+                            double_start + light * 2
+                        } else {
+                            double_start + light
+                        }
+                    } else {
+                        let extent = if perpendicular > 0 {
+                            perpendicular
+                        } else {
+                            thickness
+                        };
+                        let start = Self::band(size, extent);
+                        if toward_end {
+                            start
+                        } else {
+                            start + extent
+                        }
+                    };
+                    let start = Self::band(cross, thickness);
+                    bands.push((start, start + thickness, span(stop, toward_end)));
+                }
+                Weight::Double => {
+                    let cross_start = Self::band(cross, light * 3);
+                    // The perpendicular lines on the lower and upper side of
+                    // this line.
+                    let sides = if horizontal_line {
+                        (lines[UP], lines[DOWN])
+                    } else {
+                        (lines[LEFT], lines[RIGHT])
+                    };
+                    let opposite = lines[(direction + 2) % 4];
+
+                    for (offset, same, other) in
+                        [(0, sides.0, sides.1), (light * 2, sides.1, sides.0)]
+                    {
+                        let stop = if same == Weight::Double {
+                            // The inner line meets the line on the same side.
+                            if toward_end {
+                                double_start + light * 2
+                            } else {
+                                double_start + light
+                            }
+                        } else if other == Weight::Double {
+                            // The outer line wraps around the corner.
+                            if toward_end {
+                                double_start
+                            } else {
+                                double_start + light * 3
+                            }
+                        } else if perpendicular > 0 {
+                            let start = Self::band(size, perpendicular);
+                            if toward_end {
+                                start
+                            } else {
+                                start + perpendicular
+                            }
+                        } else if opposite != Weight::None {
+                            if toward_end {
+                                0
+                            } else {
+                                size
+                            }
+                        } else {
+                            size / 2
+                        };
+
+                        let start = cross_start + offset;
+                        bands.push((start, start + light, span(stop, toward_end)));
+                    }
+                }
+            }
+
+            for (cross0, cross1, (along0, along1)) in bands {
+                if horizontal_line {
+                    self.fill(along0, cross0, along1, cross1, 255);
+                } else {
+                    self.fill(cross0, along0, cross1, along1, 255);
+                }
+            }
+        }
+    }
+
+    fn dashes(
+        &mut self,
+        ch: char,
+    ) {
+        let (count, weight, horizontal) = match ch as u32 {
+            0x2504 => (3, Weight::Light, true),
+            0x2505 => (3, Weight::Heavy, true),
+            0x2506 => (3, Weight::Light, false),
+            0x2507 => (3, Weight::Heavy, false),
+            0x2508 => (4, Weight::Light, true),
+            0x2509 => (4, Weight::Heavy, true),
+            0x250a => (4, Weight::Light, false),
+            0x250b => (4, Weight::Heavy, false),
+            0x254c => (2, Weight::Light, true),
+            0x254d => (2, Weight::Heavy, true),
+            0x254e => (2, Weight::Light, false),
+            _ => (2, Weight::Heavy, false),
+        };
+
+        let thickness = self.thickness(weight);
+        let size = if horizontal { self.width } else { self.height };
+        let cross = Self::band(if horizontal { self.height } else { self.width }, thickness);
+        // Dashes are centered in their segment so the gaps continue evenly into
+        // neighboring cells.
+        let gap = (size / count / 3).max(1);
+
+        for dash in 0..count {
+            let start = dash * size / count + gap / 2;
+            let end = ((dash + 1) * size / count).saturating_sub(gap - gap / 2);
+            if horizontal {
+                self.fill(start, cross, end, cross + thickness, 255);
+            } else {
+                self.fill(cross, start, cross + thickness, end, 255);
+            }
+        }
+    }
+
+    /// Fill pixels by sampling `covered` at 4x4 points per pixel.
+    fn sample(
+        &mut self,
+        covered: impl Fn(f32, f32) -> bool,
+    ) {
+        const SAMPLES: u32 = 4;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let hits = (0..SAMPLES * SAMPLES)
+                    .filter(|sample| {
+                        let sx = x as f32 + (sample % SAMPLES) as f32 / SAMPLES as f32 + 0.125;
+                        let sy = y as f32 + (sample / SAMPLES) as f32 / SAMPLES as f32 + 0.125;
+                        covered(sx, sy)
+                    })
+                    .count() as u32;
+
+                if hits > 0 {
+                    let coverage = (hits * 255 / (SAMPLES * SAMPLES)) as u8;
+                    let pixel = &mut self.pixels[(y * self.width + x) as usize];
+                    let existing = pixel.to_ne_bytes()[0];
+                    *pixel = u32::from_ne_bytes([existing.max(coverage); 4]);
+                }
+            }
+        }
+    }
+
+    fn arc(
+        &mut self,
+        ch: char,
+    ) {
+        let light = self.light;
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        // The centers of the lines the arc joins up.
+        let cx = Self::band(self.width, light) as f32 + light as f32 / 2.0;
+        let cy = Self::band(self.height, light) as f32 + light as f32 / 2.0;
+        let radius = (width - cx).min(cx).min(height - cy).min(cy);
+
+        // Which way the arc opens, towards the lines it connects to.
+        let (right, down) = match ch as u32 {
+            0x256d => (true, true),
+            0x256e => (false, true),
+            0x256f => (false, false),
+            _ => (true, false),
+        };
+        let center_x = if right { cx + radius } else { cx - radius };
+        let center_y = if down { cy + radius } else { cy - radius };
+
+        let half = light as f32 / 2.0;
+        self.sample(|x, y| {
+            let in_quadrant = (x <= center_x) == right && (y <= center_y) == down;
+            let distance = ((x - center_x).powi(2) + (y - center_y).powi(2)).sqrt();
+            in_quadrant && (distance - radius).abs() <= half
+        });
+
+        // Continue the ends of the arc to the edges of the cell.
+        let line_x = Self::band(self.width, light);
+        let line_y = Self::band(self.height, light);
+        let arc_end_x = center_x.round() as u32;
+        let arc_end_y = center_y.round() as u32;
+        if right {
+            self.fill(arc_end_x, line_y, self.width, line_y + light, 255);
+        } else {
+            self.fill(0, line_y, arc_end_x, line_y + light, 255);
+        }
+        if down {
+            self.fill(line_x, arc_end_y, line_x + light, self.height, 255);
+        } else {
+            self.fill(line_x, 0, line_x + light, arc_end_y, 255);
+        }
+    }
+
+    fn diagonals(
+        &mut self,
+        ch: char,
+    ) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let length = (width * width + height * height).sqrt();
+        let half = self.light as f32 / 2.0;
+
+        let rising = matches!(ch as u32, 0x2571 | 0x2573);
+        let falling = matches!(ch as u32, 0x2572 | 0x2573);
+        self.sample(|x, y| {
+            // Distance from the diagonals through the corners of the cell.
+            let to_falling = (height * x - width * y).abs() / length;
+            let to_rising = (height * x + width * y - width * height).abs() / length;
+            (falling && to_falling <= half) || (rising && to_rising <= half)
+        });
+    }
+
+    fn block(
+        &mut self,
+        ch: char,
+    ) {
+        let (width, height) = (self.width, self.height);
+        let eighths_x = |n: u32| (width * n + 4) / 8;
+        let eighths_y = |n: u32| (height * n + 4) / 8;
+        let (mid_x, mid_y) = (width / 2, height / 2);
+
+        match ch as u32 {
+            0x2580 => self.fill(0, 0, width, mid_y, 255),
+            code @ 0x2581..=0x2588 => {
+                self.fill(0, height - eighths_y(code - 0x2580), width, height, 255)
+            }
+            code @ 0x2589..=0x258f => self.fill(0, 0, eighths_x(0x2590 - code), height, 255),
+            0x2590 => self.fill(mid_x, 0, width, height, 255),
+            code @ 0x2591..=0x2593 => {
+                let coverage = ((code - 0x2590) * 64) as u8;
+                self.fill(0, 0, width, height, coverage);
+            }
+            0x2594 => self.fill(0, 0, width, eighths_y(1), 255),
+            0x2595 => self.fill(width - eighths_x(1), 0, width, height, 255),
+            code => {
+                // Quadrants, as upper left, upper right, lower left and lower
+                // right.
+                let quadrants: [bool; 4] = match code {
+                    0x2596 => [false, false, true, false],
+                    0x2597 => [false, false, false, true],
+                    0x2598 => [true, false, false, false],
+                    0x2599 => [true, false, true, true],
+                    0x259a => [true, false, false, true],
+                    0x259b => [true, true, true, false],
+                    0x259c => [true, true, false, true],
+                    0x259d => [false, true, false, false],
+                    0x259e => [false, true, true, false],
+                    _ => [false, true, true, true],
+                };
+
+                let bounds = [
+                    (0, 0, mid_x, mid_y),
+                    (mid_x, 0, width, mid_y),
+                    (0, mid_y, mid_x, height),
+                    (mid_x, mid_y, width, height),
+                ];
+                for (filled, (x0, y0, x1, y1)) in quadrants.into_iter().zip(bounds) {
+                    if filled {
+                        self.fill(x0, y0, x1, y1, 255);
+                    }
+                }
+            }
+        }
+    }
+
+    fn powerline(
+        &mut self,
+        ch: char,
+    ) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let half = self.light as f32 / 2.0;
+        let length = (width * width + height * height / 4.0).sqrt();
+
+        // The separators point right, with the apex in the middle of the right
+        // edge, and are mirrored to point left.
+        let left = matches!(ch as u32, 0xe0b2 | 0xe0b3);
+        let solid = matches!(ch as u32, 0xe0b0 | 0xe0b2);
+        self.sample(|x, y| {
+            let x = if left { width - x } else { x };
+            // Distance from the center line, and the horizontal extent of the
+            // triangle at that height.
+            let dy = (y - height / 2.0).abs();
+            let extent = width * (1.0 - 2.0 * dy / height);
+            if solid {
+                x <= extent
+            } else {
+                // Distance from the edge of the triangle.
+                ((x - extent) * height / 2.0).abs() / length <= half
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::box_drawing::rasterize;
+
+    const WIDTH: u32 = 12;
+    const HEIGHT: u32 = 24;
+
+    fn coverage(ch: char) -> Vec<Vec<u8>> {
+        rasterize(ch, WIDTH, HEIGHT)
+            .unwrap()
+            .chunks(WIDTH as usize)
+            .map(|row| row.iter().map(|px| px.to_ne_bytes()[0]).collect())
+            .collect()
+    }
+
+    fn covered_rows(
+        image: &[Vec<u8>],
+        column: usize,
+    ) -> Vec<usize> {
+        (0..image.len()).filter(|&y| image[y][column] > 0).collect()
+    }
+
+    fn covered_columns(
+        image: &[Vec<u8>],
+        row: usize,
+    ) -> Vec<usize> {
+        (0..image[row].len())
+            .filter(|&x| image[row][x] > 0)
+            .collect()
+    }
+
+    #[test]
+    fn not_builtin() {
+        assert!(rasterize('a', WIDTH, HEIGHT).is_none());
+        assert!(rasterize('\u{25a0}', WIDTH, HEIGHT).is_none());
+    }
+
+    #[test]
+    fn lines_connect() {
+        let horizontal = coverage('─');
+        let cross = coverage('┼');
+        let corner = coverage('┌');
+        let vertical = coverage('│');
+
+        // Horizontal lines span the cell at the same rows, so they connect.
+        let rows = covered_rows(&horizontal, 0);
+        assert!(!rows.is_empty());
+        assert_eq!(rows, covered_rows(&horizontal, WIDTH as usize - 1));
+        assert_eq!(rows, covered_rows(&cross, 0));
+        assert_eq!(rows, covered_rows(&corner, WIDTH as usize - 1));
+
+        // Vertical lines span the cell at the same columns.
+        let columns = covered_columns(&vertical, 0);
+        assert!(!columns.is_empty());
+        assert_eq!(columns, covered_columns(&vertical, HEIGHT as usize - 1));
+        assert_eq!(columns, covered_columns(&cross, 0));
+        assert_eq!(columns, covered_columns(&corner, HEIGHT as usize - 1));
+
+        // The corner doesn't reach the top or left edges.
+        assert!(covered_columns(&corner, 0).is_empty());
+        assert!(covered_rows(&corner, 0).is_empty());
+    }
+
+    #[test]
+    fn heavy_and_double() {
+        let light = covered_rows(&coverage('─'), 0).len();
+        assert_eq!(covered_rows(&coverage('━'), 0).len(), light * 2);
+
+        // Two lines separated by a gap.
+        let double = covered_rows(&coverage('═'), 0);
+        assert_eq!(double.len(), light * 2);
+        assert_eq!(double[light * 2 - 1] - double[0], light * 3 - 1);
+        assert_eq!(double, covered_rows(&coverage('╔'), WIDTH as usize - 1));
+        assert_eq!(double, covered_rows(&coverage('╬'), 0));
+    }
+
+    #[test]
+    fn blocks() {
+        assert!(coverage('█').iter().flatten().all(|&px| px == 255));
+
+        let lower_half = coverage('▄');
+        assert!(lower_half[..HEIGHT as usize / 2]
+            .iter()
+            .flatten()
+            .all(|&px| px == 0));
+        assert!(lower_half[HEIGHT as usize / 2..]
+            .iter()
+            .flatten()
+            .all(|&px| px == 255));
+
+        let left_eighth = coverage('▏');
+        assert_eq!(covered_columns(&left_eighth, 0), vec![0, 1]);
+
+        let shade = coverage('▒');
+        assert!(shade.iter().flatten().all(|&px| px == 128));
+
+        let quadrants = coverage('▚');
+        assert_eq!(quadrants[0][0], 255);
+        assert_eq!(quadrants[0][WIDTH as usize - 1], 0);
+        assert_eq!(quadrants[HEIGHT as usize - 1][0], 0);
+        assert_eq!(quadrants[HEIGHT as usize - 1][WIDTH as usize - 1], 255);
+    }
+
+    #[test]
+    fn arcs_and_diagonals_reach_edges() {
+        let arc = coverage('╭');
+        let horizontal = coverage('─');
+        let vertical = coverage('│');
+        assert_eq!(
+            covered_rows(&arc, WIDTH as usize - 1),
+            covered_rows(&horizontal, 0)
+        );
+        assert_eq!(
+            covered_columns(&arc, HEIGHT as usize - 1),
+            covered_columns(&vertical, 0)
+        );
+
+        let diagonal = coverage('╱');
+        assert!(diagonal[0][WIDTH as usize - 1] > 0);
+        assert!(diagonal[HEIGHT as usize - 1][0] > 0);
+        assert_eq!(diagonal[0][0], 0);
+    }
+
+    #[test]
+    fn powerline_separators_fill_cell() {
+        let right = coverage('\u{e0b0}');
+        // The base covers the whole left edge and the apex the middle of the
+        // right edge, so the separator lines up with neighboring backgrounds.
+        assert!(right.iter().all(|row| row[0] > 0));
+        assert!(right[1..HEIGHT as usize - 1]
+            .iter()
+            .all(|row| row[0] == 255));
+        assert!(right[HEIGHT as usize / 2][WIDTH as usize - 1] > 0);
+        assert_eq!(right[0][WIDTH as usize - 1], 0);
+        assert_eq!(right[HEIGHT as usize - 1][WIDTH as usize - 1], 0);
+
+        let left = coverage('\u{e0b2}');
+        let mirrored = right
+            .iter()
+            .map(|row| row.iter().rev().copied().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(left, mirrored);
+
+        let thin = coverage('\u{e0b1}');
+        assert!(thin[0][0] > 0);
+        assert!(thin[HEIGHT as usize - 1][0] > 0);
+        assert!(thin[HEIGHT as usize / 2][WIDTH as usize - 1] > 0);
+        assert_eq!(thin[HEIGHT as usize / 2][0], 0);
+    }
+}
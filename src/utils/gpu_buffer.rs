@@ -0,0 +1,57 @@
+use wgpu::Buffer;
+use wgpu::BufferDescriptor;
+use wgpu::BufferSlice;
+use wgpu::BufferUsages;
+use wgpu::Device;
+use wgpu::Queue;
+
+/// A gpu buffer which is kept between frames and only reallocated when the
+/// data written to it outgrows its current capacity.
+pub(crate) struct GrowableBuffer {
+    label: &'static str,
+    usage: BufferUsages,
+    buffer: Option<Buffer>,
+}
+
+impl GrowableBuffer {
+    pub(crate) fn new(
+        label: &'static str,
+        usage: BufferUsages,
+    ) -> Self {
+        Self {
+            label,
+            usage: usage | BufferUsages::COPY_DST,
+            buffer: None,
+        }
+    }
+
+    /// Upload `contents` to the start of the buffer, growing it to the next
+    /// power of two if it is too small. Returns the part of the buffer holding
+    /// `contents`.
+    pub(crate) fn write(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        contents: &[u8],
+    ) -> BufferSlice<'_> {
+        let size = (contents.len() as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+
+        let buffer = match self.buffer.take() {
+            Some(buffer) if buffer.size() >= size => buffer,
+            _ => device.create_buffer(&BufferDescriptor {
+                label: Some(self.label),
+                size: size.next_power_of_two().max(wgpu::COPY_BUFFER_ALIGNMENT),
+                usage: self.usage,
+                mapped_at_creation: false,
+            }),
+        };
+
+        queue.write_buffer(&buffer, 0, contents);
+        self.buffer.insert(buffer).slice(..size)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+}
@@ -14,7 +14,11 @@ use raqote::Vector;
 use rustybuzz::ttf_parser::colr::CompositeMode;
 use rustybuzz::Face;
 
+pub(crate) mod box_drawing;
+pub(crate) mod gpu_buffer;
 pub(crate) mod plan_cache;
+#[cfg(feature = "testing")]
+pub(crate) mod snapshot;
 pub(crate) mod text_atlas;
 
 pub(crate) struct Outline {
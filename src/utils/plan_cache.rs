@@ -1,7 +1,9 @@
 use std::num::NonZeroUsize;
 
 use evictor::Lru;
+use rustybuzz::ttf_parser::Tag;
 use rustybuzz::Direction;
+use rustybuzz::Feature;
 use rustybuzz::Script;
 use rustybuzz::ShapePlan;
 use rustybuzz::UnicodeBuffer;
@@ -17,12 +19,29 @@ struct Key {
 
 pub(crate) struct PlanCache {
     lru: Lru<Key, ShapePlan>,
+    features: Vec<Feature>,
 }
 
 impl PlanCache {
-    pub(crate) fn new(capacity: usize) -> Self {
+    /// Create a cache for plans which substitute ligatures spanning several
+    /// cells only if `ligatures` is set. Contextual alternates replace glyphs
+    /// one for one, so they stay within their cells and are always applied.
+    pub(crate) fn new(
+        capacity: usize,
+        ligatures: bool,
+    ) -> Self {
+        let features = if ligatures {
+            vec![]
+        } else {
+            [b"liga", b"clig"]
+                .into_iter()
+                .map(|tag| Feature::new(Tag::from_bytes(tag), 0, ..))
+                .collect()
+        };
+
         Self {
             lru: Lru::new(NonZeroUsize::new(capacity).expect("Capacity must be non-zero")),
+            features,
         }
     }
 
@@ -44,7 +63,7 @@ impl PlanCache {
                 buffer.direction(),
                 Some(buffer.script()),
                 buffer.language().as_ref(),
-                &[],
+                &self.features,
             )
         })
     }
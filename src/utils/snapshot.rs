@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use crate::Error;
+use crate::Result;
+
+/// A tightly packed RGBA image, four bytes per pixel.
+pub(crate) struct RgbaImage {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) pixels: Vec<u8>,
+}
+
+/// Read the png at `path` as RGBA, expanding palette, grayscale and rgb
+/// images and reducing 16 bit channels to 8 bits.
+pub(crate) fn read_png(path: &Path) -> Result<RgbaImage> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| Error::PngDecodingFailed(png::DecodingError::from(e)))?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(Error::PngDecodingFailed)?;
+
+    let mut data = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let info = reader
+        .next_frame(&mut data)
+        .map_err(Error::PngDecodingFailed)?;
+    data.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => data,
+        png::ColorType::Rgb => data
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            data.iter().flat_map(|&g| [g, g, g, u8::MAX]).collect()
+        }
+    };
+
+    Ok(RgbaImage {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+/// Compare `actual` against `reference`, allowing each channel to differ by
+/// `tolerance`. Returns the number of mismatched pixels and an image the size
+/// of `actual` with mismatched pixels in red over a faded copy of `actual`, or
+/// [`None`] if the images match. Pixels outside of `reference` always
+/// mismatch.
+pub(crate) fn diff(
+    actual: &RgbaImage,
+    reference: &RgbaImage,
+    tolerance: u8,
+) -> Option<(usize, RgbaImage)> {
+    let mut mismatched = 0;
+    let mut pixels = Vec::with_capacity(actual.pixels.len());
+    for (index, pixel) in actual.pixels.chunks_exact(4).enumerate() {
+        let (x, y) = (index as u32 % actual.width, index as u32 / actual.width);
+        let expected = (x < reference.width && y < reference.height).then(|| {
+            let start = (y as usize * reference.width as usize + x as usize) * 4;
+            &reference.pixels[start..start + 4]
+        });
+        let matches = expected.is_some_and(|expected| {
+            pixel
+                .iter()
+                .zip(expected)
+                .all(|(a, e)| a.abs_diff(*e) <= tolerance)
+        });
+
+        if matches {
+            let luma = (pixel[0] as u32 * 3 + pixel[1] as u32 * 6 + pixel[2] as u32) / 10;
+            let faded = (luma / 4) as u8;
+            pixels.extend([faded, faded, faded, u8::MAX]);
+        } else {
+            mismatched += 1;
+            pixels.extend([u8::MAX, 0, 0, u8::MAX]);
+        }
+    }
+
+    if mismatched == 0 && actual.width == reference.width && actual.height == reference.height {
+        return None;
+    }
+
+    Some((
+        mismatched,
+        RgbaImage {
+            width: actual.width,
+            height: actual.height,
+            pixels,
+        },
+    ))
+}
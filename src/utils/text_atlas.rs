@@ -11,10 +11,13 @@ pub(crate) struct Key {
     pub(crate) style: Modifier,
     pub(crate) glyph: u32,
     pub(crate) font: u64,
+    /// The horizontal subpixel phase the glyph is rasterized at.
+    pub(crate) phase: u8,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) struct CacheRect {
+    pub(crate) page: u32,
     pub(crate) x: u32,
     pub(crate) y: u32,
     pub(crate) width: u32,
@@ -42,9 +45,18 @@ impl Deref for Entry {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    rect: CacheRect,
+    /// The frame this entry was last requested in.
+    last_used: u64,
+    /// The glyph has been rasterized into the entry.
+    ready: bool,
+}
+
 #[derive(Debug)]
 pub(crate) struct Atlas {
-    lru: Lru<Key, CacheRect>,
+    lru: Lru<Key, Slot>,
     width: u32,
     height: u32,
 
@@ -52,7 +64,16 @@ pub(crate) struct Atlas {
     entry_height: u32,
 
     next_entry: u32,
+    entries_per_page: u32,
+    max_pages: u32,
     max_entries: u32,
+
+    frame: u64,
+    /// An entry from an earlier frame was evicted since the last call to
+    /// [`Atlas::take_evicted`].
+    evicted: bool,
+    /// The last frame which needed more entries than the atlas can hold.
+    overflowed: Option<u64>,
 }
 
 impl Atlas {
@@ -60,11 +81,16 @@ impl Atlas {
         fonts: &Fonts,
         width: u32,
         height: u32,
+        max_pages: u32,
     ) -> Self {
-        let entry_width = fonts.min_width_px() * 2;
-        let entry_height = fonts.height_px();
-        let max_entries = ((width / entry_width) * (height / entry_height)).max(1);
-        debug!("Atlas with WxH {entry_width}x{entry_height} can hold {max_entries}");
+        let entry_width = fonts.cell_width_px() * 2;
+        let entry_height = fonts.cell_height_px();
+        let entries_per_page = ((width / entry_width) * (height / entry_height)).max(1);
+        let max_entries = entries_per_page * max_pages;
+        debug!(
+            "Atlas with WxH {entry_width}x{entry_height} can hold {entries_per_page} on each of \
+             {max_pages} pages"
+        );
 
         Atlas {
             lru: Lru::new(
@@ -75,7 +101,12 @@ impl Atlas {
             entry_width,
             entry_height,
             next_entry: 0,
+            entries_per_page,
+            max_pages,
             max_entries,
+            frame: 0,
+            evicted: false,
+            overflowed: None,
         }
     }
 
@@ -84,26 +115,85 @@ impl Atlas {
         fonts: &Fonts,
     ) {
         self.clear();
-        self.entry_width = fonts.min_width_px() * 2;
-        self.entry_height = fonts.height_px();
-        self.max_entries = (self.width / self.entry_width) * (self.height / self.entry_height);
+        self.entry_width = fonts.cell_width_px() * 2;
+        self.entry_height = fonts.cell_height_px();
+        self.entries_per_page =
+            ((self.width / self.entry_width) * (self.height / self.entry_height)).max(1);
+        self.max_entries = self.entries_per_page * self.max_pages;
+        self.lru = Lru::new(
+            NonZeroUsize::new(self.max_entries as usize).expect("Max entries must be non-zero"),
+        );
 
         debug!(
-            "Atlas with WxH {}x{} can hold {}",
-            self.entry_width, self.entry_height, self.max_entries
+            "Atlas with WxH {}x{} can hold {} on each of {} pages",
+            self.entry_width, self.entry_height, self.entries_per_page, self.max_pages
         );
     }
 
     fn clear(&mut self) {
         self.lru.clear();
         self.next_entry = 0;
+        self.evicted = false;
+    }
+
+    /// Start a new frame. Entries requested during the current frame are only
+    /// evicted if the frame needs more entries than the atlas can hold.
+    pub(crate) fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Returns true if an entry requested in an earlier frame has been evicted
+    /// since the last call. Anything still displaying such an entry must
+    /// request it again.
+    pub(crate) fn take_evicted(&mut self) -> bool {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// The number of pages holding entries. Pages are only used once the ones
+    /// before them are full, and entries are only evicted once every page is
+    /// full.
+    pub(crate) fn pages(&self) -> u32 {
+        self.next_entry.div_ceil(self.entries_per_page).max(1)
+    }
+
+    /// Returns true if `rect` still holds the glyph for `key`.
+    pub(crate) fn contains(
+        &self,
+        key: &Key,
+        rect: &CacheRect,
+    ) -> bool {
+        self.lru.peek(key).is_some_and(|slot| slot.rect == *rect)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn capacity(&self) -> u32 {
+        self.max_entries
     }
 
     pub(crate) fn try_get(
         &mut self,
         key: &Key,
     ) -> Option<Entry> {
-        self.lru.get(key).copied().map(Entry::Cached)
+        let frame = self.frame;
+        self.lru.get_mut(key).map(|slot| {
+            slot.last_used = frame;
+            if slot.ready {
+                Entry::Cached(slot.rect)
+            } else {
+                Entry::Uncached(slot.rect)
+            }
+        })
+    }
+
+    /// Record that the glyph for `key` has been rasterized into its entry.
+    /// Entries are returned as [`Entry::Uncached`] until then.
+    pub(crate) fn mark_ready(
+        &mut self,
+        key: &Key,
+    ) {
+        if let Some(slot) = self.lru.get_mut(key) {
+            slot.ready = true;
+        }
     }
 
     pub(crate) fn get(
@@ -124,14 +214,33 @@ impl Atlas {
 
         self.try_get(key).unwrap_or_else(|| {
             let rect = if self.next_entry == self.max_entries {
-                self.lru.pop().expect("Atlas has zero max entries!").1
+                let (_, slot) = self.lru.pop().expect("Atlas has zero max entries!");
+                if slot.last_used != self.frame {
+                    self.evicted = true;
+                } else if self.overflowed != Some(self.frame) {
+                    self.overflowed = Some(self.frame);
+                    warn!(
+                        "Glyph atlas can't hold all {} glyphs needed for this frame, some glyphs \
+                         will be drawn incorrectly",
+                        self.max_entries
+                    );
+                }
+                // The slot may have held a glyph of a different width.
+                CacheRect { width, ..slot.rect }
             } else {
                 let entry = self.next_entry;
                 self.next_entry += 1;
                 self.slot_to_rect(entry, width)
             };
 
-            self.lru.insert(*key, rect);
+            self.lru.insert(
+                *key,
+                Slot {
+                    rect,
+                    last_used: self.frame,
+                    ready: false,
+                },
+            );
             Entry::Uncached(rect)
         })
     }
@@ -141,9 +250,12 @@ impl Atlas {
         slot: u32,
         width: u32,
     ) -> CacheRect {
+        let page = slot / self.entries_per_page;
+        let slot = slot % self.entries_per_page;
         let x = slot % (self.width / self.entry_width) * self.entry_width;
         let y = slot / (self.width / self.entry_width) * self.entry_height;
         CacheRect {
+            page,
             x,
             y,
             width,
@@ -171,7 +283,7 @@ mod tests {
             .unwrap(),
             24,
         );
-        let mut atlas = Atlas::new(&fonts, 24, 24);
+        let mut atlas = Atlas::new(&fonts, 24, 24, 1);
 
         for idx in 0..atlas.max_entries {
             atlas.get(
@@ -179,6 +291,7 @@ mod tests {
                     style: Modifier::default(),
                     glyph: idx as _,
                     font: idx as _,
+                    phase: 0,
                 },
                 12,
                 24,
@@ -189,6 +302,7 @@ mod tests {
             style: Modifier::default(),
             glyph: u32::MAX,
             font: u32::MAX as _,
+            phase: 0,
         };
 
         let last_inserted = atlas.get(&last_key, 12, 24);
@@ -196,4 +310,66 @@ mod tests {
 
         assert_eq!(*last_inserted, *post_insertion);
     }
+
+    #[test]
+    fn pages() {
+        let fonts = Fonts::new(
+            Font::new(include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/backend/fonts/Fairfax.ttf"
+            )))
+            .unwrap(),
+            24,
+        );
+        let mut atlas = Atlas::new(&fonts, 24, 24, 2);
+        let key = |idx: u32| Key {
+            style: Modifier::default(),
+            glyph: idx,
+            font: 0,
+            phase: 0,
+        };
+
+        let first = atlas.get(&key(0), 12, 24);
+        assert_eq!(atlas.pages(), 1);
+        let second = atlas.get(&key(1), 12, 24);
+        assert_eq!(atlas.pages(), 2);
+        assert_eq!((first.page, second.page), (0, 1));
+        assert_eq!((first.x, first.y), (second.x, second.y));
+
+        // Both pages are full, so the least recently used entry is evicted.
+        atlas.next_frame();
+        atlas.get(&key(1), 12, 24);
+        let third = atlas.get(&key(2), 12, 24);
+        assert_eq!(*third, *first);
+        assert!(atlas.take_evicted());
+    }
+
+    #[test]
+    fn uncached_until_ready() {
+        let fonts = Fonts::new(
+            Font::new(include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/backend/fonts/Fairfax.ttf"
+            )))
+            .unwrap(),
+            24,
+        );
+        let mut atlas = Atlas::new(&fonts, 24, 24, 1);
+        let key = Key {
+            style: Modifier::default(),
+            glyph: 0,
+            font: 0,
+            phase: 0,
+        };
+
+        let first = atlas.get(&key, 12, 24);
+        let second = atlas.get(&key, 12, 24);
+        assert!(!first.cached() && !second.cached());
+        assert_eq!(*first, *second);
+
+        atlas.mark_ready(&key);
+        let ready = atlas.get(&key, 12, 24);
+        assert!(ready.cached());
+        assert_eq!(*ready, *first);
+    }
 }